@@ -1,5 +1,5 @@
 use abstutil::{retain_btreemap, Timer};
-use geom::PolyLine;
+use geom::{LonLat, PolyLine, Pt2D};
 use map_model::raw::{OriginalIntersection, OriginalRoad, RawMap};
 use map_model::IntersectionType;
 
@@ -131,3 +131,35 @@ pub fn clip_map(map: &mut RawMap, timer: &mut Timer) {
 
     timer.stop("clipping map to boundary");
 }
+
+// Only keep the parts of transit routes inside the boundary. Stops outside are removed along with
+// their times, so the remaining schedule still lines up.
+pub fn clip_bus_routes(map: &mut RawMap, timer: &mut Timer) {
+    timer.start("clipping bus routes to boundary");
+    let boundary_polygon = map.boundary_polygon.clone();
+    let gps_bounds = map.gps_bounds.clone();
+    let inside = |gps: LonLat| {
+        Pt2D::from_gps(gps, &gps_bounds)
+            .map(|pt| boundary_polygon.contains_pt(pt))
+            .unwrap_or(false)
+    };
+
+    let num_before = map.bus_routes.len();
+    for route in &mut map.bus_routes {
+        let mut patterns = Vec::new();
+        for mut p in route.patterns.drain(..) {
+            if p.retain_stops(inside) {
+                patterns.push(p);
+            }
+        }
+        route.patterns = patterns;
+    }
+    map.bus_routes.retain(|r| !r.patterns.is_empty());
+    timer.note(format!(
+        "{} of {} bus routes are at least partly inside the boundary",
+        map.bus_routes.len(),
+        num_before
+    ));
+
+    timer.stop("clipping bus routes to boundary");
+}
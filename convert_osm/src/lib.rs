@@ -1,13 +1,16 @@
 mod clip;
 mod neighborhoods;
+mod osm_doc;
 mod osm_reader;
 mod split_ways;
 
+pub use crate::clip::clip_bus_routes;
+pub use crate::osm_reader::check_turn_lanes;
 use abstutil::Timer;
 use geom::{Distance, FindClosest, Line, PolyLine, Pt2D};
 use kml::ExtraShapes;
 use map_model::raw::{OriginalBuilding, OriginalRoad, RawMap};
-use map_model::{osm, LaneID, OffstreetParking, Position};
+use map_model::{osm, LaneID, OffstreetParking, ParkingPolicy, Position, SpeedLimitDefaults};
 
 // Just used for matching hints to different sides of a road.
 const DIRECTED_ROAD_THICKNESS: Distance = Distance::const_meters(2.5);
@@ -20,6 +23,8 @@ pub struct Flags {
     pub gtfs: Option<String>,
     pub neighborhoods: Option<String>,
     pub clip: Option<String>,
+    // A region like "de", or a JSON file with a custom SpeedLimitDefaults. US defaults otherwise.
+    pub speed_limits: Option<String>,
     pub output: String,
 }
 
@@ -29,6 +34,9 @@ pub fn convert(flags: &Flags, timer: &mut abstutil::Timer) -> RawMap {
         timer,
     );
     clip::clip_map(&mut map, timer);
+    if let Some(ref region) = flags.speed_limits {
+        map.speed_limits = load_speed_limits(region, timer);
+    }
 
     // Need to do a first pass of removing cul-de-sacs here, or we wind up with loop PolyLines when
     // doing the parking hint matching.
@@ -48,6 +56,7 @@ pub fn convert(flags: &Flags, timer: &mut abstutil::Timer) -> RawMap {
     if let Some(ref path) = flags.gtfs {
         timer.start("load GTFS");
         map.bus_routes = gtfs::load(path);
+        clip::clip_bus_routes(&mut map, timer);
         timer.stop("load GTFS");
     }
 
@@ -60,6 +69,16 @@ pub fn convert(flags: &Flags, timer: &mut abstutil::Timer) -> RawMap {
     map
 }
 
+fn load_speed_limits(region: &str, timer: &mut Timer) -> SpeedLimitDefaults {
+    if region.ends_with(".json") {
+        return abstutil::read_json(region.to_string(), timer);
+    }
+    match SpeedLimitDefaults::for_region(region) {
+        Ok(defaults) => defaults,
+        Err(err) => panic!("Bad --speed_limits: {}", err),
+    }
+}
+
 fn use_parking_hints(map: &mut RawMap, path: String, timer: &mut Timer) {
     timer.start("apply parking hints");
     let shapes: ExtraShapes = abstutil::read_binary(path, timer);
@@ -191,12 +210,15 @@ fn use_offstreet_parking(map: &mut RawMap, path: &str, timer: &mut Timer) {
                 id, existing.num_stalls, existing.name, num_stalls, name
             );
         }
+        let policy =
+            ParkingPolicy::from_osm_tags(&map.buildings[&id].osm_tags, "parking:condition");
         map.buildings.get_mut(&id).unwrap().parking = Some(OffstreetParking {
             name,
             num_stalls,
             // Temporary values, populate later
             driveway_line: Line::new(Pt2D::new(0.0, 0.0), Pt2D::new(1.0, 1.0)),
             driving_pos: Position::new(LaneID(0), Distance::ZERO),
+            policy,
         });
         None
     });
@@ -11,6 +11,7 @@ fn main() {
         gtfs: args.optional("--gtfs"),
         neighborhoods: args.optional("--neighborhoods"),
         clip: args.optional("--clip"),
+        speed_limits: args.optional("--speed_limits"),
         output: args.required("--output"),
     };
     args.done();
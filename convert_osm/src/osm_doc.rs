@@ -0,0 +1,282 @@
+use crate::osm_reader;
+use abstutil::{FileWithProgress, Timer};
+use geom::{GPSBounds, LonLat};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+
+// Everything extract_osm needs from an OSM file, the same no matter what format it was read from.
+pub struct Document {
+    pub nodes: BTreeMap<i64, Node>,
+    pub ways: BTreeMap<i64, Way>,
+    pub relations: BTreeMap<i64, Relation>,
+}
+
+pub struct Node {
+    pub pt: LonLat,
+    pub tags: BTreeMap<String, String>,
+}
+
+pub struct Way {
+    pub nodes: Vec<i64>,
+    pub tags: BTreeMap<String, String>,
+}
+
+pub struct Relation {
+    // (member, role)
+    pub members: Vec<(Member, String)>,
+    pub tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub enum Member {
+    Node(i64),
+    Way(i64),
+    Relation(i64),
+}
+
+// .osm.pbf files are streamed; anything else is parsed as XML. If clip is specified, roads and
+// buildings without any nodes inside it are skipped, along with the nodes only they use. They'd be
+// clipped away later anyway. Other ways are always kept, since areas can surround the whole clip
+// region, and multipolygons need all of their member ways.
+pub fn read(path: &str, clip: Option<&GPSBounds>, timer: &mut Timer) -> Document {
+    let doc = if path.ends_with(".pbf") {
+        read_pbf(path, clip, timer)
+    } else {
+        read_xml(path, clip, timer)
+    };
+    println!(
+        "OSM doc has {} nodes, {} ways, {} relations",
+        doc.nodes.len(),
+        doc.ways.len(),
+        doc.relations.len()
+    );
+    doc
+}
+
+fn read_xml(path: &str, clip: Option<&GPSBounds>, timer: &mut Timer) -> Document {
+    let (reader, done) = FileWithProgress::new(path).unwrap();
+    let xml = osm_xml::OSM::parse(reader).expect("OSM parsing failed");
+    done(timer);
+
+    let mut ways = BTreeMap::new();
+    let mut needed_nodes = HashSet::new();
+    for way in xml.ways.values() {
+        let nodes: Vec<i64> = way
+            .nodes
+            .iter()
+            .filter_map(|node_ref| match node_ref {
+                osm_xml::UnresolvedReference::Node(id) => Some(*id),
+                _ => None,
+            })
+            .collect();
+        // Don't handle nested ways/relations yet
+        if nodes.len() != way.nodes.len() {
+            continue;
+        }
+        let tags = tags_to_map(way.tags.iter().map(|t| (&t.key, &t.val)));
+        if let Some(bounds) = clip {
+            if clipped_out(&tags, &nodes, |id| {
+                xml.nodes
+                    .get(&id)
+                    .map(|n| bounds.contains(LonLat::new(n.lon, n.lat)))
+                    .unwrap_or(false)
+            }) {
+                continue;
+            }
+        }
+        needed_nodes.extend(nodes.iter().cloned());
+        ways.insert(way.id, Way { nodes, tags });
+    }
+
+    let mut nodes = BTreeMap::new();
+    for node in xml.nodes.values() {
+        let pt = LonLat::new(node.lon, node.lat);
+        if keep_node(node.id, pt, clip, &needed_nodes) {
+            nodes.insert(
+                node.id,
+                Node {
+                    pt,
+                    tags: tags_to_map(node.tags.iter().map(|t| (&t.key, &t.val))),
+                },
+            );
+        }
+    }
+
+    let mut relations = BTreeMap::new();
+    for rel in xml.relations.values() {
+        let tags = tags_to_map(rel.tags.iter().map(|t| (&t.key, &t.val)));
+        if !is_interesting_relation(&tags) {
+            continue;
+        }
+        let members = rel
+            .members
+            .iter()
+            .filter_map(|member| match member {
+                osm_xml::Member::Node(osm_xml::UnresolvedReference::Node(id), ref role) => {
+                    Some((Member::Node(*id), role.clone()))
+                }
+                osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), ref role) => {
+                    Some((Member::Way(*id), role.clone()))
+                }
+                osm_xml::Member::Relation(osm_xml::UnresolvedReference::Relation(id), ref role) => {
+                    Some((Member::Relation(*id), role.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        relations.insert(rel.id, Relation { members, tags });
+    }
+
+    Document {
+        nodes,
+        ways,
+        relations,
+    }
+}
+
+// Regional extracts are too big to hold in memory, so stream through the file. When clipping,
+// this takes three passes: find nodes inside the clip region, then the ways and relations to keep,
+// then the rest of the nodes those ways need.
+fn read_pbf(path: &str, clip: Option<&GPSBounds>, timer: &mut Timer) -> Document {
+    let mut pbf = osmpbfreader::OsmPbfReader::new(
+        File::open(path).unwrap_or_else(|err| panic!("Can't open {}: {}", path, err)),
+    );
+
+    let mut inside: HashSet<i64> = HashSet::new();
+    if let Some(bounds) = clip {
+        timer.start("find OSM nodes inside the clip region");
+        for obj in pbf.iter() {
+            if let osmpbfreader::OsmObj::Node(node) = obj.expect("PBF parsing failed") {
+                if bounds.contains(LonLat::new(node.lon(), node.lat())) {
+                    inside.insert(node.id.0);
+                }
+            }
+        }
+        pbf.rewind().expect("Can't rewind PBF");
+        timer.stop("find OSM nodes inside the clip region");
+    }
+
+    timer.start("read OSM ways and relations");
+    let mut ways = BTreeMap::new();
+    let mut needed_nodes = HashSet::new();
+    let mut relations = BTreeMap::new();
+    // Without clipping, nodes can be read in the same pass, since they come first.
+    let mut nodes = BTreeMap::new();
+    for obj in pbf.iter() {
+        match obj.expect("PBF parsing failed") {
+            osmpbfreader::OsmObj::Node(node) => {
+                if clip.is_none() {
+                    nodes.insert(
+                        node.id.0,
+                        Node {
+                            pt: LonLat::new(node.lon(), node.lat()),
+                            tags: tags_to_map(node.tags.iter()),
+                        },
+                    );
+                }
+            }
+            osmpbfreader::OsmObj::Way(way) => {
+                let way_nodes: Vec<i64> = way.nodes.iter().map(|id| id.0).collect();
+                let tags = tags_to_map(way.tags.iter());
+                if clip.is_some() && clipped_out(&tags, &way_nodes, |id| inside.contains(&id)) {
+                    continue;
+                }
+                needed_nodes.extend(way_nodes.iter().cloned());
+                ways.insert(
+                    way.id.0,
+                    Way {
+                        nodes: way_nodes,
+                        tags,
+                    },
+                );
+            }
+            osmpbfreader::OsmObj::Relation(rel) => {
+                let tags = tags_to_map(rel.tags.iter());
+                if !is_interesting_relation(&tags) {
+                    continue;
+                }
+                let members = rel
+                    .refs
+                    .iter()
+                    .map(|r| {
+                        let member = match r.member {
+                            osmpbfreader::OsmId::Node(id) => Member::Node(id.0),
+                            osmpbfreader::OsmId::Way(id) => Member::Way(id.0),
+                            osmpbfreader::OsmId::Relation(id) => Member::Relation(id.0),
+                        };
+                        (member, r.role.to_string())
+                    })
+                    .collect();
+                relations.insert(rel.id.0, Relation { members, tags });
+            }
+        }
+    }
+    timer.stop("read OSM ways and relations");
+
+    if clip.is_some() {
+        timer.start("read OSM nodes");
+        pbf.rewind().expect("Can't rewind PBF");
+        for obj in pbf.iter() {
+            if let osmpbfreader::OsmObj::Node(node) = obj.expect("PBF parsing failed") {
+                let pt = LonLat::new(node.lon(), node.lat());
+                if keep_node(node.id.0, pt, clip, &needed_nodes) {
+                    nodes.insert(
+                        node.id.0,
+                        Node {
+                            pt,
+                            tags: tags_to_map(node.tags.iter()),
+                        },
+                    );
+                }
+            }
+        }
+        timer.stop("read OSM nodes");
+    }
+
+    Document {
+        nodes,
+        ways,
+        relations,
+    }
+}
+
+// Both readers must agree on this, so the same file in either format gives the same map.
+fn clipped_out<F: Fn(i64) -> bool>(
+    tags: &BTreeMap<String, String>,
+    nodes: &Vec<i64>,
+    inside: F,
+) -> bool {
+    (osm_reader::is_road(tags) || map_model::is_light_rail(tags) || osm_reader::is_bldg(tags))
+        && !nodes.iter().any(|id| inside(*id))
+}
+
+fn keep_node(id: i64, pt: LonLat, clip: Option<&GPSBounds>, needed: &HashSet<i64>) -> bool {
+    match clip {
+        Some(bounds) => bounds.contains(pt) || needed.contains(&id),
+        None => true,
+    }
+}
+
+// extract_osm only looks at these
+fn is_interesting_relation(tags: &BTreeMap<String, String>) -> bool {
+    match tags.get("type").map(|t| t.as_str()) {
+        Some("multipolygon") | Some("restriction") => true,
+        _ => false,
+    }
+}
+
+fn tags_to_map<K: ToString, V: ToString, I: Iterator<Item = (K, V)>>(
+    raw_tags: I,
+) -> BTreeMap<String, String> {
+    raw_tags
+        .filter_map(|(k, v)| {
+            let key = k.to_string();
+            // Toss out really useless metadata.
+            if key.starts_with("tiger:") || key.starts_with("old_name:") {
+                None
+            } else {
+                Some((key, v.to_string()))
+            }
+        })
+        .collect()
+}
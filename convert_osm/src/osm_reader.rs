@@ -1,8 +1,11 @@
-use abstutil::{FileWithProgress, Timer};
+use crate::osm_doc::{self, Member};
+use abstutil::Timer;
 use geom::{GPSBounds, HashablePt2D, LonLat, PolyLine, Polygon, Pt2D, Ring};
 use map_model::raw::{OriginalBuilding, RawArea, RawBuilding, RawMap, RawRoad, RestrictionType};
-use map_model::{osm, AreaType};
-use osm_xml;
+use map_model::{
+    is_light_rail, osm, parse_conditional, parse_turn_lanes, AreaType, LaneType,
+    ReversibleSchedule, RoadSpec, TimeWindows,
+};
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
@@ -19,27 +22,24 @@ pub fn extract_osm(
     HashSet<HashablePt2D>,
     // OSM Node IDs
     HashMap<HashablePt2D, i64>,
-    // Turn restrictions: (restriction type, from way ID, via node ID, to way ID)
-    Vec<(RestrictionType, i64, i64, i64)>,
+    // Turn restrictions: (restriction type, from way ID, via node ID, to way ID, only during
+    // these times)
+    Vec<(RestrictionType, i64, i64, i64, Option<TimeWindows>)>,
     // Amenities (location, name, amenity type)
     Vec<(Pt2D, String, String)>,
 ) {
-    let (reader, done) = FileWithProgress::new(osm_path).unwrap();
-    let doc = osm_xml::OSM::parse(reader).expect("OSM parsing failed");
-    println!(
-        "OSM doc has {} nodes, {} ways, {} relations",
-        doc.nodes.len(),
-        doc.ways.len(),
-        doc.relations.len()
-    );
-    done(timer);
+    let clip = maybe_clip_path
+        .as_ref()
+        .map(|path| read_osmosis_polygon(path));
+    let doc = osm_doc::read(osm_path, clip.as_ref().map(|m| &m.gps_bounds), timer);
 
-    let mut map = if let Some(ref path) = maybe_clip_path {
-        read_osmosis_polygon(path)
+    let mut map = if let Some(m) = clip {
+        m
     } else {
-        let mut m = RawMap::blank(abstutil::basename(osm_path));
+        // Both foo.osm and foo.osm.pbf are named foo
+        let mut m = RawMap::blank(abstutil::basename(osm_path.trim_end_matches(".pbf")));
         for node in doc.nodes.values() {
-            m.gps_bounds.update(LonLat::new(node.lon, node.lat));
+            m.gps_bounds.update(node.pt);
         }
         m.boundary_polygon = m.gps_bounds.to_bounds().get_rectangle();
         m
@@ -52,12 +52,12 @@ pub fn extract_osm(
     let mut amenities = Vec::new();
 
     timer.start_iter("processing OSM nodes", doc.nodes.len());
-    for node in doc.nodes.values() {
+    for (id, node) in &doc.nodes {
         timer.next();
-        let pt = Pt2D::forcibly_from_gps(LonLat::new(node.lon, node.lat), &map.gps_bounds);
-        osm_node_ids.insert(pt.to_hashable(), node.id);
+        let pt = Pt2D::forcibly_from_gps(node.pt, &map.gps_bounds);
+        osm_node_ids.insert(pt.to_hashable(), *id);
 
-        let tags = tags_to_map(&node.tags);
+        let tags = &node.tags;
         if tags.get(osm::HIGHWAY) == Some(&"traffic_signals".to_string()) {
             traffic_signals.insert(pt.to_hashable());
         }
@@ -70,30 +70,26 @@ pub fn extract_osm(
 
     let mut coastline_groups: Vec<Vec<Pt2D>> = Vec::new();
     timer.start_iter("processing OSM ways", doc.ways.len());
-    for way in doc.ways.values() {
+    for (id, way) in &doc.ways {
         timer.next();
 
         let mut valid = true;
         let mut gps_pts = Vec::new();
-        for node_ref in &way.nodes {
-            match doc.resolve_reference(node_ref) {
-                osm_xml::Reference::Node(node) => {
-                    gps_pts.push(LonLat::new(node.lon, node.lat));
-                }
-                // Don't handle nested ways/relations yet
-                _ => {
-                    valid = false;
-                }
+        for node_id in &way.nodes {
+            if let Some(node) = doc.nodes.get(node_id) {
+                gps_pts.push(node.pt);
+            } else {
+                valid = false;
             }
         }
         if !valid {
             continue;
         }
         let pts = map.gps_bounds.forcibly_convert(&gps_pts);
-        let mut tags = tags_to_map(&way.tags);
-        tags.insert(osm::OSM_WAY_ID.to_string(), way.id.to_string());
+        let mut tags = way.tags.clone();
+        tags.insert(osm::OSM_WAY_ID.to_string(), id.to_string());
 
-        if is_road(&tags) {
+        if is_road(&tags) || is_light_rail(&tags) {
             // If there's no parking data in OSM already, then assume no parking and mark that it's
             // inferred.
             if !tags.contains_key(osm::PARKING_LEFT)
@@ -114,6 +110,7 @@ pub fn extract_osm(
                 if tags.get(osm::HIGHWAY) == Some(&"motorway".to_string())
                     || tags.get(osm::HIGHWAY) == Some(&"motorway_link".to_string())
                     || tags.get("junction") == Some(&"roundabout".to_string())
+                    || is_light_rail(&tags)
                 {
                     tags.insert(osm::SIDEWALK.to_string(), "none".to_string());
                 } else if tags.get("oneway") == Some(&"yes".to_string()) {
@@ -126,14 +123,23 @@ pub fn extract_osm(
                 }
             }
 
-            roads.push((
-                way.id,
-                RawRoad {
-                    center_points: pts,
-                    osm_tags: tags,
-                    turn_restrictions: Vec::new(),
-                },
-            ));
+            let mut road = RawRoad {
+                center_points: pts,
+                osm_tags: tags,
+                turn_restrictions: Vec::new(),
+                conditional_turn_restrictions: Vec::new(),
+            };
+            check_turn_lanes(*id, &mut road, timer);
+            if road.osm_tags.get("oneway") == Some(&"reversible".to_string())
+                && ReversibleSchedule::parse(&road.osm_tags).is_none()
+            {
+                timer.warn(format!(
+                    "Way {} is reversible, but has no usable oneway:conditional schedule. Treating \
+                     it as a normal oneway.",
+                    id
+                ));
+            }
+            roads.push((*id, road));
         } else if is_bldg(&tags) {
             let mut deduped = pts.clone();
             deduped.dedup();
@@ -141,7 +147,7 @@ pub fn extract_osm(
                 continue;
             }
             map.buildings.insert(
-                OriginalBuilding { osm_way_id: way.id },
+                OriginalBuilding { osm_way_id: *id },
                 RawBuilding {
                     polygon: Polygon::new(&deduped),
                     osm_tags: tags,
@@ -155,7 +161,7 @@ pub fn extract_osm(
             }
             map.areas.push(RawArea {
                 area_type: at,
-                osm_id: way.id,
+                osm_id: *id,
                 polygon: Polygon::new(&pts),
                 osm_tags: tags,
             });
@@ -163,7 +169,7 @@ pub fn extract_osm(
             coastline_groups.push(pts);
         } else {
             // The way might be part of a relation later.
-            id_to_way.insert(way.id, pts);
+            id_to_way.insert(*id, pts);
         }
     }
 
@@ -171,17 +177,17 @@ pub fn extract_osm(
 
     let mut turn_restrictions = Vec::new();
     timer.start_iter("processing OSM relations", doc.relations.len());
-    for rel in doc.relations.values() {
+    for (rel_id, rel) in &doc.relations {
         timer.next();
-        let mut tags = tags_to_map(&rel.tags);
-        tags.insert(osm::OSM_REL_ID.to_string(), rel.id.to_string());
+        let mut tags = rel.tags.clone();
+        tags.insert(osm::OSM_REL_ID.to_string(), rel_id.to_string());
         if let Some(at) = get_area_type(&tags) {
             if tags.get("type") == Some(&"multipolygon".to_string()) {
                 let mut ok = true;
                 let mut pts_per_way: Vec<Vec<Pt2D>> = Vec::new();
-                for member in &rel.members {
+                for (member, role) in &rel.members {
                     match member {
-                        osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), ref role) => {
+                        Member::Way(id) => {
                             // If the way is clipped out, that's fine
                             if let Some(pts) = id_to_way.get(id) {
                                 if role == "outer" {
@@ -189,22 +195,22 @@ pub fn extract_osm(
                                 } else {
                                     println!(
                                         "Relation {} has unhandled member role {}, ignoring it",
-                                        rel.id, role
+                                        rel_id, role
                                     );
                                 }
                             }
                         }
                         _ => {
-                            println!("Relation {} refers to {:?}", rel.id, member);
+                            println!("Relation {} refers to {:?}", rel_id, member);
                             ok = false;
                         }
                     }
                 }
                 if ok {
-                    for polygon in glue_multipolygon(rel.id, pts_per_way, &boundary) {
+                    for polygon in glue_multipolygon(*rel_id, pts_per_way, &boundary) {
                         map.areas.push(RawArea {
                             area_type: at,
-                            osm_id: rel.id,
+                            osm_id: *rel_id,
                             polygon,
                             osm_tags: tags.clone(),
                         });
@@ -215,9 +221,9 @@ pub fn extract_osm(
             let mut from_way_id: Option<i64> = None;
             let mut via_node_id: Option<i64> = None;
             let mut to_way_id: Option<i64> = None;
-            for member in &rel.members {
+            for (member, role) in &rel.members {
                 match member {
-                    osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), ref role) => {
+                    Member::Way(id) => {
                         if role == "from" {
                             from_way_id = Some(*id);
                         } else if role == "to" {
@@ -225,24 +231,45 @@ pub fn extract_osm(
                         }
                         // TODO Handle 'via' ways
                     }
-                    osm_xml::Member::Node(osm_xml::UnresolvedReference::Node(id), ref role) => {
+                    Member::Node(id) => {
                         if role == "via" {
                             via_node_id = Some(*id);
                         }
                     }
-                    _ => unreachable!(),
+                    Member::Relation(_) => {}
                 }
             }
             if let (Some(from_way_id), Some(via_node_id), Some(to_way_id)) =
                 (from_way_id, via_node_id, to_way_id)
             {
                 if let Some(restriction) = tags.get("restriction") {
-                    turn_restrictions.push((
-                        RestrictionType::new(restriction),
-                        from_way_id,
-                        via_node_id,
-                        to_way_id,
-                    ));
+                    if let Some(rt) = RestrictionType::new(restriction) {
+                        turn_restrictions.push((rt, from_way_id, via_node_id, to_way_id, None));
+                    } else {
+                        timer.warn(format!(
+                            "Relation {} has unknown restriction {}",
+                            rel_id, restriction
+                        ));
+                    }
+                }
+                // Like "no_left_turn @ (Mo-Fr 07:00-09:00)"
+                if let Some(conditional) = tags.get("restriction:conditional") {
+                    for (restriction, windows) in parse_conditional(conditional) {
+                        if let Some(rt) = RestrictionType::new(&restriction) {
+                            turn_restrictions.push((
+                                rt,
+                                from_way_id,
+                                via_node_id,
+                                to_way_id,
+                                Some(windows),
+                            ));
+                        } else {
+                            timer.warn(format!(
+                                "Relation {} has unknown conditional restriction {}",
+                                rel_id, restriction
+                            ));
+                        }
+                    }
                 }
             }
         }
@@ -271,21 +298,49 @@ pub fn extract_osm(
     )
 }
 
-fn tags_to_map(raw_tags: &[osm_xml::Tag]) -> BTreeMap<String, String> {
-    raw_tags
-        .iter()
-        .filter_map(|tag| {
-            // Toss out really useless metadata.
-            if tag.key.starts_with("tiger:") || tag.key.starts_with("old_name:") {
-                None
-            } else {
-                Some((tag.key.clone(), tag.val.clone()))
+// Drop turn:lanes tags that can't be understood or don't match the number of lanes, so the
+// movements at the end of the road fall back to being inferred from geometry.
+pub fn check_turn_lanes(id: i64, road: &mut RawRoad, timer: &mut Timer) {
+    let RoadSpec { fwd, back } = road.get_spec();
+    let count = |lts: Vec<LaneType>| {
+        lts.into_iter()
+            .filter(|lt| *lt == LaneType::Driving || *lt == LaneType::Bus)
+            .count()
+    };
+    let (num_fwd, num_back) = (count(fwd), count(back));
+    for (key, expected) in vec![
+        ("turn:lanes", num_fwd),
+        ("turn:lanes:forward", num_fwd),
+        ("turn:lanes:backward", num_back),
+    ] {
+        let problem = if let Some(value) = road.osm_tags.get(key) {
+            match parse_turn_lanes(value) {
+                Some(per_lane) => {
+                    if per_lane.len() == expected {
+                        None
+                    } else {
+                        Some(format!(
+                            "{}={} has {} lanes, but the way has {}",
+                            key,
+                            value,
+                            per_lane.len(),
+                            expected
+                        ))
+                    }
+                }
+                None => Some(format!("{}={} isn't understood", key, value)),
             }
-        })
-        .collect()
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            timer.warn(format!("Way {}: {}, ignoring it", id, problem));
+            road.osm_tags.remove(key);
+        }
+    }
 }
 
-fn is_road(tags: &BTreeMap<String, String>) -> bool {
+pub(crate) fn is_road(tags: &BTreeMap<String, String>) -> bool {
     if !tags.contains_key(osm::HIGHWAY) {
         return false;
     }
@@ -324,7 +379,7 @@ fn is_road(tags: &BTreeMap<String, String>) -> bool {
     true
 }
 
-fn is_bldg(tags: &BTreeMap<String, String>) -> bool {
+pub(crate) fn is_bldg(tags: &BTreeMap<String, String>) -> bool {
     tags.contains_key("building")
 }
 
@@ -3,7 +3,7 @@ use geom::{HashablePt2D, Pt2D};
 use map_model::raw::{
     OriginalIntersection, OriginalRoad, RawIntersection, RawMap, RawRoad, RestrictionType,
 };
-use map_model::{osm, IntersectionType};
+use map_model::{osm, IntersectionType, TimeWindows};
 use std::collections::{HashMap, HashSet};
 
 pub fn split_up_roads(
@@ -12,7 +12,7 @@ pub fn split_up_roads(
         Vec<(i64, RawRoad)>,
         HashSet<HashablePt2D>,
         HashMap<HashablePt2D, i64>,
-        Vec<(RestrictionType, i64, i64, i64)>,
+        Vec<(RestrictionType, i64, i64, i64, Option<TimeWindows>)>,
         Vec<(Pt2D, String, String)>,
     ),
     timer: &mut Timer,
@@ -97,7 +97,7 @@ pub fn split_up_roads(
 
     // Resolve turn restrictions
     let mut restrictions = Vec::new();
-    for (restriction, from_osm, via_osm, to_osm) in turn_restrictions {
+    for (restriction, from_osm, via_osm, to_osm, windows) in turn_restrictions {
         // TODO Brute less force.
         let mut found = false;
         'OUTER: for r in map.roads.keys() {
@@ -113,7 +113,7 @@ pub fn split_up_roads(
             };
             for r_to in map.roads_per_intersection(i) {
                 if r_to.osm_way_id == to_osm {
-                    restrictions.push((*r, restriction, r_to));
+                    restrictions.push((*r, restriction, r_to, windows.clone()));
                     found = true;
                     break 'OUTER;
                 }
@@ -126,12 +126,13 @@ pub fn split_up_roads(
             ));
         }
     }
-    for (from, rt, to) in restrictions {
-        map.roads
-            .get_mut(&from)
-            .unwrap()
-            .turn_restrictions
-            .push((rt, to));
+    for (from, rt, to, windows) in restrictions {
+        let road = map.roads.get_mut(&from).unwrap();
+        if let Some(windows) = windows {
+            road.conditional_turn_restrictions.push((rt, to, windows));
+        } else {
+            road.turn_restrictions.push((rt, to));
+        }
     }
 
     timer.stop("splitting up roads");
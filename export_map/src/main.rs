@@ -0,0 +1,18 @@
+use abstutil::{CmdArgs, Timer};
+use map_model::Map;
+
+fn main() {
+    let mut args = CmdArgs::new();
+    // Something like ../data/system/maps/montlake.bin
+    let load = args.required_free();
+    // One .geojson file per type of map object goes in this directory.
+    let output = args.optional("--output");
+    args.done();
+
+    let mut timer = Timer::new(format!("export {}", load));
+    let map: Map = abstutil::read_binary(load, &mut timer);
+    let dir = output.unwrap_or_else(|| format!("../data/export/{}", map.get_name()));
+    timer.start("export GeoJSON");
+    map.export_geojson(&dir);
+    timer.stop("export GeoJSON");
+}
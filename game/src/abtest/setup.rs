@@ -177,6 +177,16 @@ fn launch_test(test: &ABTest, ui: &mut UI, ctx: &mut EventCtx) -> ABTestMode {
                                     .sim_flags
                                     .opts
                                     .recalc_lanechanging,
+                                event_log: None,
+                                record_trajectories: None,
+                                reroute_blocked_after: current_flags
+                                    .sim_flags
+                                    .opts
+                                    .reroute_blocked_after,
+                                reroute_around_closures: current_flags
+                                    .sim_flags
+                                    .opts
+                                    .reroute_around_closures,
                             },
                         },
                         ..current_flags.clone()
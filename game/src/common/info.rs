@@ -11,8 +11,8 @@ use ezgui::{
     Key, Line, ManagedWidget, Outcome, Plot, RewriteColor, Series, Text, VerticalAlignment,
 };
 use geom::{Circle, Distance, Duration, Statistic, Time};
-use map_model::{IntersectionID, RoadID};
-use sim::{CarID, TripEnd, TripID, TripMode, TripStart};
+use map_model::{IntersectionID, RoadID, Traversable};
+use sim::{CarID, LevelOfService, TripEnd, TripID, TripMode, TripStart};
 use std::collections::BTreeMap;
 
 pub struct InfoPanel {
@@ -274,6 +274,13 @@ fn info_for(id: ID, ctx: &EventCtx, ui: &UI) -> Vec<ManagedWidget> {
                     )));
                     tr = true;
                 }
+                for (restriction, to, windows) in &r.conditional_turn_restrictions {
+                    txt.add(Line(format!(
+                        "Restriction from this road to {}: {:?} during {}",
+                        to, restriction, windows
+                    )));
+                    tr = true;
+                }
                 if tr {
                     txt.add(Line(""));
                 }
@@ -284,13 +291,34 @@ fn info_for(id: ID, ctx: &EventCtx, ui: &UI) -> Vec<ManagedWidget> {
                     "Has {} parking spots",
                     l.number_parking_spots()
                 )));
+                txt.add(Line(format!("Parking is {}", l.parking_policy.describe())));
                 rows.push(ManagedWidget::draw_text(ctx, txt));
             } else {
                 txt.add(Line(format!("Speed limit: {}", r.get_speed_limit())));
+                if r.is_reversible(l.id) {
+                    txt.add(Line(format!(
+                        "Reversible lane, open {}",
+                        r.reversible.as_ref().unwrap()
+                    )));
+                }
+                if let Some(ref hours) = l.bus_hours {
+                    txt.add(Line(format!("Only for buses {}", hours)));
+                }
                 txt.add(Line(format!(
                     "{} total agents crossed so far",
                     prettyprint_usize(sim.get_analytics().thruput_stats.count_per_road.get(r.id))
                 )));
+                if l.is_sidewalk() {
+                    let los = sim
+                        .get_analytics()
+                        .ped_level_of_service(Time::START_OF_DAY, sim.time())
+                        .remove(&Traversable::Lane(id))
+                        .unwrap_or(LevelOfService::A);
+                    txt.add(Line(format!(
+                        "Worst pedestrian level of service so far: {:?}",
+                        los
+                    )));
+                }
                 rows.push(ManagedWidget::draw_text(ctx, txt));
 
                 rows.push(
@@ -383,6 +411,7 @@ fn info_for(id: ID, ctx: &EventCtx, ui: &UI) -> Vec<ManagedWidget> {
                     Line(format!("{} parking spots via ", p.num_stalls)),
                     Line(&p.name).fg(name_color),
                 ]);
+                txt.add(Line(format!("Parking is {}", p.policy.describe())));
             }
 
             let cnt = sim.count_trips_involving_bldg(id);
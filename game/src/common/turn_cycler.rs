@@ -45,14 +45,13 @@ impl TurnCyclerState {
                 }
             }
             Some(ID::Intersection(i)) => {
-                if let Some(ref signal) = ui.primary.map.maybe_get_traffic_signal(i) {
+                if ui.primary.map.maybe_get_traffic_signal(i).is_some() {
                     if ui
                         .per_obj
                         .action(ctx, Key::F, "show full traffic signal diagram")
                     {
                         ui.primary.current_selection = None;
-                        let (idx, _, _) =
-                            signal.current_phase_and_remaining_time(ui.primary.sim.time());
+                        let (idx, _) = ui.primary.sim.current_signal_phase(i, &ui.primary.map);
                         return Some(Transition::Push(Box::new(ShowTrafficSignal {
                             menu: ModalMenu::new(
                                 "Traffic Signal Diagram",
@@ -286,7 +285,7 @@ fn make_diagram(i: IntersectionID, selected: usize, ui: &UI, ctx: &mut EventCtx)
         col.push(
             ManagedWidget::row(vec![
                 ManagedWidget::draw_text(ctx, Text::from(Line(format!("#{}", idx + 1)))),
-                ManagedWidget::draw_text(ctx, Text::from(Line(phase.duration.to_string()))),
+                ManagedWidget::draw_text(ctx, Text::from(Line(phase.phase_type.to_string()))),
             ])
             .margin(5)
             .evenly_spaced(),
@@ -69,6 +69,7 @@ fn dump_debug(id: ID, map: &Map, sim: &Sim, draw_map: &DrawMap) {
                     PathConstraints::Car,
                     PathConstraints::Bike,
                     PathConstraints::Bus,
+                    PathConstraints::Train,
                 ] {
                     if constraint.can_use(l, map) {
                         println!(
@@ -253,6 +253,16 @@ fn can_change_lane_type(l: LaneID, new_lt: LaneType, map: &Map) -> Option<String
         return None;
     }
 
+    // Tracks aren't part of the street.
+    if map.get_l(l).is_light_rail() {
+        return Some(format!("You can't change light rail tracks"));
+    }
+
+    // Both sides share the same pavement.
+    if r.is_reversible(l) {
+        return Some(format!("You can't change a lane that switches direction"));
+    }
+
     // Only one parking lane per side.
     if proposed_lts
         .iter()
@@ -268,7 +278,9 @@ fn can_change_lane_type(l: LaneID, new_lt: LaneType, map: &Map) -> Option<String
     }
 
     // Don't let players orphan a bus stop.
-    if !r.all_bus_stops(map).is_empty()
+    if r.all_bus_stops(map)
+        .into_iter()
+        .any(|bs| map.get_parent(map.get_bs(bs).driving_pos.lane()).id == r.id)
         && !proposed_lts
             .iter()
             .any(|lt| *lt == LaneType::Driving || *lt == LaneType::Bus)
@@ -387,6 +399,11 @@ fn apply_brush(brush: Brush, map: &Map, l: LaneID) -> Result<Option<EditCmd>, St
             if !lane.lane_type.is_for_moving_vehicles() {
                 return Err(format!("You can't reverse a {:?} lane", lane.lane_type));
             }
+            if map.get_r(lane.parent).reversible.is_some() {
+                return Err(format!(
+                    "This road's lanes already switch direction by time of day"
+                ));
+            }
             if map.get_r(lane.parent).dir_and_offset(l).1 != 0 {
                 return Err(format!(
                     "You can only reverse the lanes next to the road's yellow center line"
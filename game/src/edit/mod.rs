@@ -207,6 +207,8 @@ impl State for EditMode {
                 EditCmd::ChangeTrafficSignal(ss) => ID::Intersection(ss.id),
                 EditCmd::CloseIntersection { id, .. } => ID::Intersection(id),
                 EditCmd::UncloseIntersection(id, _) => ID::Intersection(id),
+                EditCmd::ChangeLaneParkingPolicy { id, .. } => ID::Lane(id),
+                EditCmd::ChangeBldgParkingPolicy { id, .. } => ID::Building(id),
             };
             apply_map_edits(ctx, ui, edits);
             return Transition::Push(Warping::new(
@@ -11,10 +11,12 @@ use abstutil::Timer;
 use ezgui::{
     hotkey, lctrl, Button, Choice, Color, Composite, DrawBoth, EventCtx, EventLoopMode, GeomBatch,
     GfxCtx, HorizontalAlignment, Key, Line, ManagedWidget, ModalMenu, Outcome, RewriteColor, Text,
-    VerticalAlignment,
+    VerticalAlignment, WrappedWizard,
 };
 use geom::{Distance, Duration, Polygon};
-use map_model::{ControlTrafficSignal, EditCmd, IntersectionID, Phase, TurnGroupID, TurnPriority};
+use map_model::{
+    ControlTrafficSignal, EditCmd, IntersectionID, Phase, PhaseType, TurnGroupID, TurnPriority,
+};
 use sim::Sim;
 use std::collections::BTreeSet;
 
@@ -120,11 +122,11 @@ impl State for TrafficSignalEditor {
                     }
                     return Transition::Keep;
                 }
-                x if x.starts_with("change duration of #") => {
-                    let idx = x["change duration of #".len()..].parse::<usize>().unwrap() - 1;
-                    return Transition::Push(change_phase_duration(
+                x if x.starts_with("change timing of #") => {
+                    let idx = x["change timing of #".len()..].parse::<usize>().unwrap() - 1;
+                    return Transition::Push(change_phase_type(
                         idx,
-                        orig_signal.phases[idx].duration,
+                        orig_signal.phases[idx].phase_type.clone(),
                     ));
                 }
                 x if x.starts_with("delete phase #") => {
@@ -458,12 +460,12 @@ fn make_diagram(i: IntersectionID, selected: usize, ui: &UI, ctx: &mut EventCtx)
         let mut row = vec![
             ManagedWidget::draw_text(
                 ctx,
-                Text::from(Line(format!("Phase #{}: {}", idx + 1, phase.duration))),
+                Text::from(Line(format!("Phase #{}: {}", idx + 1, phase.phase_type))),
             ),
             WrappedComposite::svg_button(
                 ctx,
                 "assets/tools/edit.svg",
-                &format!("change duration of #{}", idx + 1),
+                &format!("change timing of #{}", idx + 1),
                 if selected == idx {
                     hotkey(Key::D)
                 } else {
@@ -590,30 +592,94 @@ fn change_traffic_signal(signal: ControlTrafficSignal, ui: &mut UI, ctx: &mut Ev
     apply_map_edits(ctx, ui, edits);
 }
 
-fn change_phase_duration(idx: usize, current_duration: Duration) -> Box<dyn State> {
+fn change_phase_type(idx: usize, current: PhaseType) -> Box<dyn State> {
     WizardState::new(Box::new(move |wiz, ctx, _| {
-        let new_duration = wiz.wrap(ctx).input_something(
-            "How long should this phase be (seconds)?",
-            Some(format!("{}", current_duration.inner_seconds() as usize)),
-            Box::new(|line| {
-                line.parse::<usize>()
-                    .ok()
-                    .and_then(|n| if n != 0 { Some(n) } else { None })
-            }),
-        )?;
+        let mut wizard = wiz.wrap(ctx);
+        let (fixed, actuated) = (
+            "fixed duration".to_string(),
+            "actuated by demand".to_string(),
+        );
+        let new_type =
+            if wizard.choose_string("What kind of timing should this phase have?", || {
+                vec![fixed.clone(), actuated.clone()]
+            })? == fixed
+            {
+                PhaseType::Fixed(input_seconds(
+                    &mut wizard,
+                    "How long should this phase be (seconds)?",
+                    current.simple_duration(),
+                    Duration::seconds(1.0),
+                )?)
+            } else {
+                let (min_green, max_green, extension) = match current.clone() {
+                    PhaseType::Fixed(d) => (d, d, Duration::seconds(5.0)),
+                    PhaseType::Actuated {
+                        min_green,
+                        max_green,
+                        extension,
+                    } => (min_green, max_green, extension),
+                };
+                let min_green = input_seconds(
+                    &mut wizard,
+                    "What's the shortest this phase should last (seconds)?",
+                    min_green,
+                    Duration::seconds(1.0),
+                )?;
+                let max_green = input_seconds(
+                    &mut wizard,
+                    "What's the longest this phase should last (seconds)?",
+                    if max_green < min_green {
+                        min_green
+                    } else {
+                        max_green
+                    },
+                    min_green,
+                )?;
+                let extension = input_seconds(
+                    &mut wizard,
+                    "While there's still traffic, extend the phase by how much (seconds)?",
+                    extension,
+                    Duration::seconds(1.0),
+                )?;
+                PhaseType::Actuated {
+                    min_green,
+                    max_green,
+                    extension,
+                }
+            };
         Some(Transition::PopWithData(Box::new(move |state, ui, ctx| {
             let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
             let mut signal = ui.primary.map.get_traffic_signal(editor.i).clone();
             editor.command_stack.push(signal.clone());
             editor.redo_stack.clear();
             editor.top_panel = make_top_panel(true, false, ctx);
-            signal.phases[idx].duration = Duration::seconds(new_duration as f64);
+            signal.phases[idx].phase_type = new_type;
             change_traffic_signal(signal, ui, ctx);
             editor.change_phase(idx, ui, ctx);
         })))
     }))
 }
 
+// Whole seconds, at least min.
+fn input_seconds(
+    wizard: &mut WrappedWizard,
+    query: &str,
+    current: Duration,
+    min: Duration,
+) -> Option<Duration> {
+    let min = min.inner_seconds() as usize;
+    let secs = wizard.input_something(
+        query,
+        Some(format!("{}", current.inner_seconds() as usize)),
+        Box::new(move |line| {
+            line.parse::<usize>()
+                .ok()
+                .and_then(|n| if n >= min { Some(n) } else { None })
+        }),
+    )?;
+    Some(Duration::seconds(secs as f64))
+}
+
 fn change_offset(current_duration: Duration) -> Box<dyn State> {
     WizardState::new(Box::new(move |wiz, ctx, _| {
         let new_duration = wiz.wrap(ctx).input_usize_prefilled(
@@ -723,7 +789,7 @@ fn make_previewer(i: IntersectionID, phase: usize, suspended_sim: Sim) -> Box<dy
                 // TODO Use the offset correctly
                 let mut step = Duration::ZERO;
                 for idx in 0..phase {
-                    step += signal.phases[idx].duration;
+                    step += signal.phases[idx].phase_type.simple_duration();
                 }
                 ui.primary.sim.step(&ui.primary.map, step);
 
@@ -133,7 +133,8 @@ impl Renderable for DrawIntersection {
                 .map(|(t, _, _, _)| *t != ctx.sim.time())
                 .unwrap_or(true);
             if recalc {
-                let (idx, phase, t) = signal.current_phase_and_remaining_time(ctx.sim.time());
+                let (idx, t) = ctx.sim.current_signal_phase(self.id, ctx.map);
+                let phase = &signal.phases[idx];
                 let mut batch = GeomBatch::new();
                 draw_signal_phase(
                     phase,
@@ -56,6 +56,7 @@ impl DrawLane {
                 LaneType::Construction => {
                     cs.get_def("construction background", Color::rgb(255, 109, 0))
                 }
+                LaneType::LightRail => cs.get_def("light rail track", Color::rgb(145, 108, 80)),
             },
             polygon.clone(),
         );
@@ -144,6 +145,17 @@ impl DrawLane {
                         polygon.clone(),
                     );
                 }
+                LaneType::LightRail => {
+                    for pl in vec![
+                        lane.lane_center_pts.shift_right(lane.width / 4.0),
+                        lane.lane_center_pts.shift_left(lane.width / 4.0),
+                    ] {
+                        draw.push(
+                            cs.get_def("rails", Color::grey(0.6)),
+                            pl.get(timer).make_polygons(Distance::meters(0.2)),
+                        );
+                    }
+                }
             };
         }
 
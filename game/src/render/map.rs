@@ -463,6 +463,10 @@ impl InnerAgentColorScheme {
                     ("car", cs.get_def("unzoomed car", Color::RED.alpha(0.5))),
                     ("bike", cs.get_def("unzoomed bike", Color::GREEN.alpha(0.5))),
                     ("bus", cs.get_def("unzoomed bus", Color::BLUE.alpha(0.5))),
+                    (
+                        "train",
+                        cs.get_def("unzoomed train", Color::PURPLE.alpha(0.5)),
+                    ),
                     (
                         "pedestrian",
                         cs.get_def("unzoomed pedestrian", Color::ORANGE.alpha(0.5)),
@@ -512,6 +516,7 @@ impl InnerAgentColorScheme {
                 Some(VehicleType::Car) => "car".to_string(),
                 Some(VehicleType::Bike) => "bike".to_string(),
                 Some(VehicleType::Bus) => "bus".to_string(),
+                Some(VehicleType::Train) => "train".to_string(),
                 None => "pedestrian".to_string(),
             },
             InnerAgentColorScheme::Delay => classify_delay(agent.metadata.time_spent_blocked),
@@ -152,7 +152,7 @@ pub fn draw_signal_phase(
 
     let radius = Distance::meters(2.0);
     let center = ctx.map.get_i(i).polygon.center();
-    let percent = time_left.unwrap() / phase.duration;
+    let percent = time_left.unwrap() / phase.phase_type.simple_duration();
     // TODO Tune colors.
     batch.push(
         ctx.cs.get_def("traffic signal box", Color::grey(0.5)),
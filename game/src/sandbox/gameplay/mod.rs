@@ -104,7 +104,10 @@ impl GameplayMode {
     pub fn allows(&self, edits: &MapEdits) -> bool {
         for cmd in &edits.commands {
             match cmd {
-                EditCmd::ChangeLaneType { .. } | EditCmd::ReverseLane { .. } => {
+                EditCmd::ChangeLaneType { .. }
+                | EditCmd::ReverseLane { .. }
+                | EditCmd::ChangeLaneParkingPolicy { .. }
+                | EditCmd::ChangeBldgParkingPolicy { .. } => {
                     if !self.can_edit_lanes() {
                         return false;
                     }
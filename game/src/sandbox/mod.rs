@@ -5,7 +5,7 @@ mod speed;
 use crate::common::{tool_panel, CommonState, Minimap, Overlays, ShowBusRoute};
 use crate::debug::DebugMode;
 use crate::edit::{apply_map_edits, save_edits, EditMode, StopSignEditor, TrafficSignalEditor};
-use crate::game::{State, Transition, WizardState};
+use crate::game::{msg, State, Transition, WizardState};
 use crate::helpers::ID;
 use crate::managed::{WrappedComposite, WrappedOutcome};
 use crate::pregame::main_menu;
@@ -18,7 +18,7 @@ use ezgui::{
 pub use gameplay::spawner::spawn_agents_around;
 pub use gameplay::GameplayMode;
 use geom::Time;
-use map_model::MapEdits;
+use map_model::{EditCmd, IntersectionType, MapEdits};
 use sim::TripMode;
 pub use speed::{SpeedControls, TimePanel};
 
@@ -84,6 +84,35 @@ impl State for SandboxMode {
                     Box::new(StopSignEditor::new(i, ctx, ui)),
                 );
             }
+            // Unlike EditMode, this keeps the simulation running, so agents have to cope.
+            let it = ui.primary.map.get_i(i).intersection_type;
+            if ui
+                .primary
+                .current_flags
+                .sim_flags
+                .opts
+                .reroute_around_closures
+                && it != IntersectionType::Construction
+                && it != IntersectionType::Border
+                && ui.per_obj.action(ctx, Key::X, "close intersection now")
+            {
+                if let Err(err) = ui
+                    .primary
+                    .sim
+                    .can_close_intersection_live(i, &ui.primary.map)
+                {
+                    return Transition::Push(msg("Can't close intersection", vec![err]));
+                }
+                let mut edits = ui.primary.map.get_edits().clone();
+                edits
+                    .commands
+                    .push(EditCmd::CloseIntersection { id: i, orig_it: it });
+                apply_map_edits(ctx, ui, edits);
+                ui.primary
+                    .map
+                    .recalculate_pathfinding_after_edits(&mut Timer::new("close intersection"));
+                ui.primary.sim.handle_live_edits(&ui.primary.map);
+            }
         }
 
         self.time_panel.event(ctx, ui);
@@ -242,6 +242,9 @@ impl State for TutorialMode {
                                 LaneType::Construction => {
                                     vec!["This lane is currently closed for construction."]
                                 }
+                                LaneType::LightRail => {
+                                    vec!["This is a track for streetcars and light rail."]
+                                }
                             },
                         ));
                     }
@@ -31,6 +31,10 @@ impl Speed {
         Speed::meters_per_second(0.44704 * value)
     }
 
+    pub fn km_per_hour(value: f64) -> Speed {
+        Speed::meters_per_second(value / 3.6)
+    }
+
     pub fn from_dist_time(d: Distance, t: Duration) -> Speed {
         Speed::meters_per_second(d.inner_meters() / t.inner_seconds())
     }
@@ -40,6 +44,10 @@ impl Speed {
         self.0
     }
 
+    pub fn to_miles_per_hour(self) -> f64 {
+        self.0 / 0.44704
+    }
+
     pub fn max(self, other: Speed) -> Speed {
         if self >= other {
             self
@@ -107,6 +115,6 @@ impl ops::Mul<Duration> for Speed {
 
 impl fmt::Display for Speed {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} mph", (self.0 * 2.23694).round())
+        write!(f, "{} mph", self.to_miles_per_hour().round())
     }
 }
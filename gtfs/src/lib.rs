@@ -1,89 +1,270 @@
-use geom::LonLat;
+use geom::{Duration, LonLat};
 use itertools::Itertools;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use transitfeed::GTFSIterator;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Route {
     pub name: String,
-    pub stops: Vec<LonLat>,
+    // Bus, LightRail, Subway, etc
+    pub route_type: String,
+    // Every distinct sequence of stops that trips follow, forwards ones first. Within each
+    // direction, the pattern with the most trips comes first.
+    pub patterns: Vec<Pattern>,
+    // Just the services used by this route's trips
+    pub calendars: BTreeMap<String, Calendar>,
+}
+
+// One sequence of stops that some trips along a route follow in one direction.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Pattern {
+    pub forwards: bool,
+    pub stops: Vec<Stop>,
+    pub trips: Vec<Trip>,
+    pub shape: Option<Vec<LonLat>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Stop {
+    pub id: String,
+    pub name: String,
+    pub pos: LonLat,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Trip {
+    pub id: String,
+    pub service_id: String,
+    // One per stop in the pattern
+    pub stop_times: Vec<StopTime>,
+}
+
+// Since midnight of the service day. GTFS allows these to go past 24 hours.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct StopTime {
+    pub arrival: Duration,
+    pub departure: Duration,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Calendar {
+    // Monday first
+    pub days: [bool; 7],
+    pub start_date: String,
+    pub end_date: String,
+}
+
+impl Route {
+    // Streetcars, light rail, subways, and other trains run on tracks. GTFS calls streetcars and
+    // light rail both "LightRail".
+    pub fn is_rail(&self) -> bool {
+        match self.route_type.as_str() {
+            "LightRail" | "Subway" | "Rail" => true,
+            _ => false,
+        }
+    }
+
+    pub fn runs_on(&self, trip: &Trip, day: usize) -> bool {
+        self.calendars
+            .get(&trip.service_id)
+            .map(|c| c.days[day])
+            .unwrap_or(false)
+    }
+}
+
+impl Pattern {
+    // Only keep the stops passing the check, and the stop times and shape points to go with them.
+    // Returns false if there's nothing useful left.
+    pub fn retain_stops<F: Fn(LonLat) -> bool>(&mut self, keep: F) -> bool {
+        let keep_idx: Vec<bool> = self.stops.iter().map(|s| keep(s.pos)).collect();
+        let mut idx = 0;
+        self.stops.retain(|_| {
+            idx += 1;
+            keep_idx[idx - 1]
+        });
+        for trip in &mut self.trips {
+            let mut idx = 0;
+            trip.stop_times.retain(|_| {
+                idx += 1;
+                keep_idx[idx - 1]
+            });
+        }
+        if let Some(pts) = self.shape.take() {
+            let pts: Vec<LonLat> = pts.into_iter().filter(|pt| keep(*pt)).collect();
+            if pts.len() >= 2 {
+                self.shape = Some(pts);
+            }
+        }
+        self.stops.len() >= 2
+    }
 }
 
 pub fn load(dir_path: &str) -> Vec<Route> {
     println!("Loading GTFS from {}", dir_path);
 
-    let mut route_id_to_name: HashMap<String, String> = HashMap::new();
+    // route ID => (name, type)
+    let mut routes: HashMap<String, (String, String)> = HashMap::new();
     for rec in GTFSIterator::<_, transitfeed::Route>::from_path(&format!("{}/routes.txt", dir_path))
         .unwrap()
     {
         let rec = rec.unwrap();
-        route_id_to_name.insert(rec.route_id.clone(), rec.route_short_name.clone());
+        routes.insert(
+            rec.route_id.clone(),
+            (
+                rec.route_short_name.clone(),
+                format!("{:?}", rec.route_type),
+            ),
+        );
     }
 
-    let mut stop_id_to_pt: HashMap<String, LonLat> = HashMap::new();
+    let mut stops: HashMap<String, Stop> = HashMap::new();
     for rec in
         GTFSIterator::<_, transitfeed::Stop>::from_path(&format!("{}/stops.txt", dir_path)).unwrap()
     {
         let rec = rec.unwrap();
-        stop_id_to_pt.insert(rec.stop_id.clone(), LonLat::new(rec.stop_lon, rec.stop_lat));
+        stops.insert(
+            rec.stop_id.clone(),
+            Stop {
+                id: rec.stop_id.clone(),
+                name: rec.stop_name.clone(),
+                pos: LonLat::new(rec.stop_lon, rec.stop_lat),
+            },
+        );
+    }
+
+    // Calendars and shapes are optional in GTFS.
+    let mut calendars: HashMap<String, Calendar> = HashMap::new();
+    if let Ok(iter) =
+        GTFSIterator::<_, transitfeed::Calendar>::from_path(&format!("{}/calendar.txt", dir_path))
+    {
+        for rec in iter {
+            let rec = rec.unwrap();
+            calendars.insert(
+                rec.service_id.clone(),
+                Calendar {
+                    days: [
+                        rec.monday,
+                        rec.tuesday,
+                        rec.wednesday,
+                        rec.thursday,
+                        rec.friday,
+                        rec.saturday,
+                        rec.sunday,
+                    ],
+                    start_date: rec.start_date.to_string(),
+                    end_date: rec.end_date.to_string(),
+                },
+            );
+        }
+    }
+    let mut shapes: HashMap<String, Vec<(u64, LonLat)>> = HashMap::new();
+    if let Ok(iter) =
+        GTFSIterator::<_, transitfeed::Shape>::from_path(&format!("{}/shapes.txt", dir_path))
+    {
+        for rec in iter {
+            let rec = rec.unwrap();
+            shapes
+                .entry(rec.shape_id.clone())
+                .or_insert_with(Vec::new)
+                .push((
+                    rec.shape_pt_sequence,
+                    LonLat::new(rec.shape_pt_lon, rec.shape_pt_lat),
+                ));
+        }
     }
 
-    let mut trip_id_to_route_id_and_direction: HashMap<String, (String, bool)> = HashMap::new();
+    // trip ID => (route ID, forwards, service ID, shape ID)
+    let mut trips: HashMap<String, (String, bool, String, Option<String>)> = HashMap::new();
     for rec in
         GTFSIterator::<_, transitfeed::Trip>::from_path(&format!("{}/trips.txt", dir_path)).unwrap()
     {
         let rec = rec.unwrap();
-        trip_id_to_route_id_and_direction.insert(
+        trips.insert(
             rec.trip_id.clone(),
             (
                 rec.route_id.clone(),
                 rec.direction_id.map(|d| d == "0").unwrap_or(true),
+                rec.service_id.clone(),
+                rec.shape_id.clone(),
             ),
         );
     }
 
-    // Each (directed) route has many trips. Ignore all but the first and assume the list of stops
-    // is the same. Also assume that records with the same trip are contiguous and that
-    // stop_sequence is monotonic.
-    let mut directed_routes: HashMap<(String, bool), Vec<LonLat>> = HashMap::new();
-    for (key, group) in
+    // Group trips by route, direction, and the exact sequence of stops. Assume that records with
+    // the same trip are contiguous.
+    let mut patterns: BTreeMap<(String, bool, Vec<String>), Pattern> = BTreeMap::new();
+    for (trip_id, group) in
         GTFSIterator::<_, transitfeed::StopTime>::from_path(&format!("{}/stop_times.txt", dir_path))
             .unwrap()
             .map(|rec| rec.unwrap())
             .group_by(|rec| rec.trip_id.clone())
             .into_iter()
     {
-        let (route_id, forwards) = trip_id_to_route_id_and_direction[&key].clone();
-        if directed_routes.contains_key(&(route_id.clone(), forwards)) {
-            continue;
-        }
-        directed_routes.insert(
-            (route_id, forwards),
-            group.map(|rec| stop_id_to_pt[&rec.stop_id]).collect(),
-        );
+        let (route_id, forwards, service_id, shape_id) = trips[&trip_id].clone();
+        let mut records: Vec<transitfeed::StopTime> = group.collect();
+        records.sort_by_key(|rec| rec.stop_sequence);
+        let stop_ids: Vec<String> = records.iter().map(|rec| rec.stop_id.clone()).collect();
+        let trip = Trip {
+            id: trip_id,
+            service_id,
+            stop_times: records
+                .iter()
+                .map(|rec| StopTime {
+                    arrival: time_offset(&rec.arrival_time),
+                    departure: time_offset(&rec.departure_time),
+                })
+                .collect(),
+        };
+        patterns
+            .entry((route_id, forwards, stop_ids.clone()))
+            .or_insert_with(|| Pattern {
+                forwards,
+                stops: stop_ids.iter().map(|id| stops[id].clone()).collect(),
+                trips: Vec::new(),
+                shape: shape_id.and_then(|id| shapes.get(&id)).map(|pts| {
+                    pts.iter()
+                        .sorted_by_key(|(seq, _)| *seq)
+                        .map(|(_, pt)| *pt)
+                        .collect()
+                }),
+            })
+            .trips
+            .push(trip);
+    }
+
+    // A route can have many patterns in each direction, because of short-turns and the like.
+    // Keep all of them; how to simulate them is up to the caller.
+    let mut per_route: BTreeMap<String, Vec<Pattern>> = BTreeMap::new();
+    for ((route_id, _, _), pattern) in patterns {
+        per_route
+            .entry(route_id)
+            .or_insert_with(Vec::new)
+            .push(pattern);
     }
 
-    // Group together the pairs of directed routes
-    let route_ids: BTreeSet<String> = directed_routes
-        .keys()
-        .map(|(id, _)| id.to_string())
-        .collect();
     let mut results = Vec::new();
-    for route_id in route_ids {
-        let mut stops = directed_routes
-            .remove(&(route_id.clone(), true))
-            .unwrap_or_else(Vec::new);
-        if let Some(more_stops) = directed_routes.remove(&(route_id.clone(), false)) {
-            stops.extend(more_stops);
-        }
-        assert!(!stops.is_empty());
+    for (route_id, mut route_patterns) in per_route {
+        route_patterns.sort_by_key(|p| (!p.forwards, std::cmp::Reverse(p.trips.len())));
+        let services: BTreeSet<&String> = route_patterns
+            .iter()
+            .flat_map(|p| p.trips.iter().map(|t| &t.service_id))
+            .collect();
+        let (name, route_type) = routes[&route_id].clone();
         results.push(Route {
-            name: route_id_to_name[&route_id].to_string(),
-            stops,
+            name,
+            route_type,
+            calendars: services
+                .into_iter()
+                .filter_map(|id| calendars.get(id).map(|c| (id.clone(), c.clone())))
+                .collect(),
+            patterns: route_patterns,
         });
     }
-    assert!(directed_routes.is_empty());
 
     results
 }
+
+fn time_offset(t: &transitfeed::TimeOffset) -> Duration {
+    Duration::seconds(f64::from(t.hours * 3600 + t.minutes * 60 + t.seconds))
+}
@@ -0,0 +1,280 @@
+use abstutil::Timer;
+use geom::Duration;
+use map_model::{Map, MapEdits};
+use serde_derive::Serialize;
+use sim::{ABTest, Analytics, Scenario, Sim, SimFlags, TripID, TripMode};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+enum TripOutcome {
+    Finished(TripMode, Duration),
+    Aborted,
+    // The trip never started or never ended in this run
+    Unfinished,
+}
+
+impl TripOutcome {
+    fn finished(self) -> Option<(TripMode, Duration)> {
+        match self {
+            TripOutcome::Finished(mode, dt) => Some((mode, dt)),
+            TripOutcome::Aborted | TripOutcome::Unfinished => None,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            TripOutcome::Finished(_, _) => "finished",
+            TripOutcome::Aborted => "aborted",
+            TripOutcome::Unfinished => "unfinished",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TripComparison {
+    trip: TripID,
+    // None if the trip didn't finish in either run
+    mode: Option<TripMode>,
+    outcome1: TripOutcome,
+    outcome2: TripOutcome,
+    // None means the trip aborted or never finished
+    duration1: Option<Duration>,
+    duration2: Option<Duration>,
+    // duration2 - duration1, so negative means the second edits helped
+    delta: Option<Duration>,
+}
+
+#[derive(Serialize)]
+struct ModeSummary {
+    mode: TripMode,
+    num_compared: usize,
+    num_faster: usize,
+    num_slower: usize,
+    total_delta: Duration,
+    finished_only_in_1: usize,
+    finished_only_in_2: usize,
+}
+
+#[derive(Serialize)]
+struct ABTestReport {
+    test: ABTest,
+    per_mode: Vec<ModeSummary>,
+    aborted_in_1: usize,
+    aborted_in_2: usize,
+    unfinished_in_1: usize,
+    unfinished_in_2: usize,
+    trips: Vec<TripComparison>,
+}
+
+// Run both sides of an A/B test to completion and write a trip-by-trip comparison to
+// report_prefix.json and report_prefix.csv.
+pub fn run(test: ABTest, flags: &SimFlags, report_prefix: String, timer: &mut Timer) {
+    let mut flags = flags.clone();
+    // Both runs need the exact same trips.
+    if flags.rng_seed.is_none() {
+        flags.rng_seed = Some(42);
+    }
+    // The second run would clobber the first run's log.
+    flags.opts.event_log = None;
+    flags.opts.record_trajectories = None;
+    flags.opts.savestate_every = None;
+
+    let scenario: Scenario = abstutil::read_binary(
+        abstutil::path_scenario(&test.map_name, &test.scenario_name),
+        timer,
+    );
+    let analytics1 = run_side(&test, &test.edits1_name, &scenario, &flags, timer);
+    let analytics2 = run_side(&test, &test.edits2_name, &scenario, &flags, timer);
+
+    let report = compare(test, &analytics1, &analytics2);
+    for summary in &report.per_mode {
+        println!(
+            "{:?}: {} trips compared ({} faster, {} slower, total change {}), {} only finished \
+             with {}, {} only finished with {}",
+            summary.mode,
+            summary.num_compared,
+            summary.num_faster,
+            summary.num_slower,
+            summary.total_delta,
+            summary.finished_only_in_1,
+            report.test.edits1_name,
+            summary.finished_only_in_2,
+            report.test.edits2_name
+        );
+    }
+    println!(
+        "{} trips aborted and {} didn't finish with {}",
+        report.aborted_in_1, report.unfinished_in_1, report.test.edits1_name
+    );
+    println!(
+        "{} trips aborted and {} didn't finish with {}",
+        report.aborted_in_2, report.unfinished_in_2, report.test.edits2_name
+    );
+
+    abstutil::write_json(format!("{}.json", report_prefix), &report);
+    write_csv(format!("{}.csv", report_prefix), &report).unwrap();
+}
+
+fn run_side(
+    test: &ABTest,
+    edits_name: &str,
+    scenario: &Scenario,
+    flags: &SimFlags,
+    timer: &mut Timer,
+) -> Analytics {
+    timer.start(format!("run {} with {}", test.test_name, edits_name));
+    let mut map = Map::new(abstutil::path_map(&test.map_name), false, timer);
+    if edits_name != "no_edits" {
+        map.apply_edits(MapEdits::load(&test.map_name, edits_name, timer), timer);
+        map.mark_edits_fresh();
+        map.recalculate_pathfinding_after_edits(timer);
+    }
+
+    let mut opts = flags.opts.clone();
+    opts.run_name = format!("{} with {}", test.test_name, edits_name);
+    let mut sim = Sim::new(&map, opts, timer);
+    scenario.instantiate(&mut sim, &map, &mut flags.make_rng(), timer);
+    sim.run_until_done(&map, |_, _| {}, None);
+    timer.stop(format!("run {} with {}", test.test_name, edits_name));
+    sim.get_analytics().clone()
+}
+
+fn compare(test: ABTest, analytics1: &Analytics, analytics2: &Analytics) -> ABTestReport {
+    // Analytics records a mode for finished trips and None for aborted ones. Trips missing
+    // entirely are unfinished.
+    let outcomes = |a: &Analytics| -> BTreeMap<TripID, TripOutcome> {
+        a.finished_trips
+            .iter()
+            .map(|(_, id, mode, dt)| {
+                let outcome = match mode {
+                    Some(m) => TripOutcome::Finished(*m, *dt),
+                    None => TripOutcome::Aborted,
+                };
+                (*id, outcome)
+            })
+            .collect()
+    };
+    let outcomes1 = outcomes(analytics1);
+    let outcomes2 = outcomes(analytics2);
+
+    let mut per_mode: BTreeMap<TripMode, ModeSummary> = TripMode::all()
+        .into_iter()
+        .map(|mode| {
+            (
+                mode,
+                ModeSummary {
+                    mode,
+                    num_compared: 0,
+                    num_faster: 0,
+                    num_slower: 0,
+                    total_delta: Duration::ZERO,
+                    finished_only_in_1: 0,
+                    finished_only_in_2: 0,
+                },
+            )
+        })
+        .collect();
+    let mut aborted_in_1 = 0;
+    let mut aborted_in_2 = 0;
+    let mut unfinished_in_1 = 0;
+    let mut unfinished_in_2 = 0;
+    let mut trips = Vec::new();
+
+    let mut ids: Vec<TripID> = outcomes1.keys().chain(outcomes2.keys()).cloned().collect();
+    ids.sort();
+    ids.dedup();
+    for id in ids {
+        let outcome1 = outcomes1
+            .get(&id)
+            .cloned()
+            .unwrap_or(TripOutcome::Unfinished);
+        let outcome2 = outcomes2
+            .get(&id)
+            .cloned()
+            .unwrap_or(TripOutcome::Unfinished);
+        match outcome1 {
+            TripOutcome::Aborted => aborted_in_1 += 1,
+            TripOutcome::Unfinished => unfinished_in_1 += 1,
+            TripOutcome::Finished(_, _) => {}
+        }
+        match outcome2 {
+            TripOutcome::Aborted => aborted_in_2 += 1,
+            TripOutcome::Unfinished => unfinished_in_2 += 1,
+            TripOutcome::Finished(_, _) => {}
+        }
+        let side1 = outcome1.finished();
+        let side2 = outcome2.finished();
+        let mode = side1.or(side2).map(|(m, _)| m);
+        let delta = match (side1, side2) {
+            (Some((m, dt1)), Some((_, dt2))) => {
+                let summary = per_mode.get_mut(&m).unwrap();
+                summary.num_compared += 1;
+                if dt2 < dt1 {
+                    summary.num_faster += 1;
+                } else if dt2 > dt1 {
+                    summary.num_slower += 1;
+                }
+                summary.total_delta += dt2 - dt1;
+                Some(dt2 - dt1)
+            }
+            (Some((m, _)), None) => {
+                per_mode.get_mut(&m).unwrap().finished_only_in_1 += 1;
+                None
+            }
+            (None, Some((m, _))) => {
+                per_mode.get_mut(&m).unwrap().finished_only_in_2 += 1;
+                None
+            }
+            (None, None) => None,
+        };
+        trips.push(TripComparison {
+            trip: id,
+            mode,
+            outcome1,
+            outcome2,
+            duration1: side1.map(|(_, dt)| dt),
+            duration2: side2.map(|(_, dt)| dt),
+            delta,
+        });
+    }
+
+    ABTestReport {
+        test,
+        per_mode: per_mode.into_iter().map(|(_, s)| s).collect(),
+        aborted_in_1,
+        aborted_in_2,
+        unfinished_in_1,
+        unfinished_in_2,
+        trips,
+    }
+}
+
+// One row per trip. The outcome is finished, aborted, or unfinished. Durations are in seconds, and
+// blank when the trip didn't finish.
+fn write_csv(path: String, report: &ABTestReport) -> Result<(), std::io::Error> {
+    std::fs::create_dir_all(std::path::Path::new(&path).parent().unwrap())?;
+    let mut f = std::fs::File::create(&path)?;
+    writeln!(f, "trip,mode,outcome1,outcome2,duration1,duration2,delta")?;
+    let secs = |dt: Option<Duration>| {
+        dt.map(|dt| dt.inner_seconds().to_string())
+            .unwrap_or_else(String::new)
+    };
+    for t in &report.trips {
+        writeln!(
+            f,
+            "{},{},{},{},{},{},{}",
+            t.trip.0,
+            t.mode
+                .map(|m| format!("{:?}", m))
+                .unwrap_or_else(String::new),
+            t.outcome1.describe(),
+            t.outcome2.describe(),
+            secs(t.duration1),
+            secs(t.duration2),
+            secs(t.delta)
+        )?;
+    }
+    println!("Wrote {}", path);
+    Ok(())
+}
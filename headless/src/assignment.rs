@@ -0,0 +1,180 @@
+use abstutil::Timer;
+use geom::Duration;
+use map_model::{Map, PathStep, TravelTimes};
+use rand::Rng;
+use serde_derive::Serialize;
+use sim::{Analytics, Scenario, Sim, SimFlags, TripID, TripMode};
+use std::collections::BTreeMap;
+
+pub struct AssignmentOptions {
+    pub max_iterations: usize,
+    // In [0, 1]. After each iteration, this fraction of car trips picks a new route using the
+    // latest travel times. Everybody else drives the same route as last time.
+    pub replan_fraction: f64,
+    // Stop once the relative gap drops below this.
+    pub gap_tolerance: f64,
+    // Or stop once total trip time changes by less than this fraction between iterations.
+    pub tolerance: f64,
+    pub bucket_size: Duration,
+    pub summary_path: String,
+}
+
+#[derive(Serialize)]
+struct IterationSummary {
+    iteration: usize,
+    finished_trips: usize,
+    aborted_trips: usize,
+    total_trip_time: Duration,
+    total_drive_time: Duration,
+    // Compared to the previous iteration
+    relative_change: Option<f64>,
+    // How much car trips could save by switching to the best route under the travel times
+    // observed this iteration, as a fraction of what their routes cost. Zero at equilibrium.
+    relative_gap: Option<f64>,
+    // Car trips that picked a new route going into this iteration, instead of keeping their old
+    // one
+    replanned_trips: usize,
+}
+
+// Run the same scenario repeatedly, re-planning some car routes each time with the lane travel
+// times observed in the previous run, until the relative gap or total trip time stabilizes.
+//
+// Most car paths are only calculated mid-trip, so trips that don't re-plan remember their route
+// by trip ID. A remembered route is only reused if it still starts and ends on the same lanes;
+// otherwise the trip just pathfinds with the latest travel times.
+pub fn run(
+    mut map: Map,
+    scenario: &Scenario,
+    flags: &SimFlags,
+    opts: AssignmentOptions,
+    timer: &mut Timer,
+) {
+    assert!(opts.replan_fraction >= 0.0 && opts.replan_fraction <= 1.0);
+    let mut flags = flags.clone();
+    // Every iteration needs the exact same trips.
+    if flags.rng_seed.is_none() {
+        flags.rng_seed = Some(42);
+    }
+    // Later iterations would clobber the first one's log.
+    flags.opts.event_log = None;
+    flags.opts.record_trajectories = None;
+    // Picking who re-plans shouldn't disturb the scenario's RNG.
+    let mut replan_rng = flags.make_rng();
+
+    // The first iteration uses free-flow costs.
+    map.set_travel_times(None, timer);
+    let mut fixed_routes: BTreeMap<TripID, Vec<PathStep>> = BTreeMap::new();
+    let mut replanned_trips = 0;
+    let mut summaries: Vec<IterationSummary> = Vec::new();
+    for iteration in 0..opts.max_iterations {
+        timer.start(format!("assignment iteration {}", iteration));
+        let mut sim = Sim::new(&map, flags.opts.clone(), timer);
+        sim.set_fixed_car_routes(std::mem::replace(&mut fixed_routes, BTreeMap::new()));
+        scenario.instantiate(&mut sim, &map, &mut flags.make_rng(), timer);
+        sim.run_until_done(&map, |_, _| {}, None);
+        timer.stop(format!("assignment iteration {}", iteration));
+
+        let analytics = sim.get_analytics();
+        let mut summary = IterationSummary {
+            iteration,
+            finished_trips: 0,
+            aborted_trips: 0,
+            total_trip_time: Duration::ZERO,
+            total_drive_time: Duration::ZERO,
+            relative_change: None,
+            relative_gap: None,
+            replanned_trips,
+        };
+        for (_, _, mode, dt) in &analytics.finished_trips {
+            match mode {
+                Some(m) => {
+                    summary.finished_trips += 1;
+                    summary.total_trip_time += *dt;
+                    if *m == TripMode::Drive {
+                        summary.total_drive_time += *dt;
+                    }
+                }
+                None => {
+                    summary.aborted_trips += 1;
+                }
+            }
+        }
+        if let Some(prev) = summaries.last() {
+            if prev.total_trip_time > Duration::ZERO {
+                summary.relative_change = Some(
+                    ((summary.total_trip_time - prev.total_trip_time) / prev.total_trip_time).abs(),
+                );
+            }
+        }
+
+        // The next iteration routes with what was just observed.
+        let observed = analytics.travel_times(opts.bucket_size);
+        map.set_travel_times(Some(observed.clone()), timer);
+        summary.relative_gap = relative_gap(analytics, &observed, &map);
+
+        replanned_trips = 0;
+        for (trip, (_, _, steps)) in &analytics.car_routes {
+            if replan_rng.gen_bool(opts.replan_fraction) {
+                replanned_trips += 1;
+            } else {
+                fixed_routes.insert(*trip, steps.clone());
+            }
+        }
+
+        println!(
+            "Iteration {}: {} trips finished ({} aborted), total trip time {}, driving {}, \
+             relative gap {:?}",
+            iteration,
+            summary.finished_trips,
+            summary.aborted_trips,
+            summary.total_trip_time,
+            summary.total_drive_time,
+            summary.relative_gap
+        );
+
+        let converged_gap = summary
+            .relative_gap
+            .map(|x| x < opts.gap_tolerance)
+            .unwrap_or(false);
+        let converged_time = summary
+            .relative_change
+            .map(|x| x < opts.tolerance)
+            .unwrap_or(false);
+        summaries.push(summary);
+        // Write after every iteration, so long runs can be watched and interrupted.
+        abstutil::write_json(opts.summary_path.clone(), &summaries);
+
+        if converged_gap {
+            println!(
+                "Relative gap is less than {}%, stopping",
+                opts.gap_tolerance * 100.0
+            );
+            break;
+        }
+        if converged_time {
+            println!(
+                "Total trip time changed by less than {}%, stopping",
+                opts.tolerance * 100.0
+            );
+            break;
+        }
+    }
+}
+
+// The map must already use the observed travel times, so pathfinding finds the best routes under
+// them. None if no car trips happened.
+fn relative_gap(analytics: &Analytics, observed: &TravelTimes, map: &Map) -> Option<f64> {
+    let mut experienced = Duration::ZERO;
+    let mut best = Duration::ZERO;
+    for (departure, req, steps) in analytics.car_routes.values() {
+        if let Some(path) = map.pathfind_at(req.clone(), *departure) {
+            experienced += observed.path_cost(steps, *departure, map);
+            best += observed.path_cost(path.get_steps(), *departure, map);
+        }
+    }
+    if experienced == Duration::ZERO {
+        return None;
+    }
+    // The best route can't cost more, but don't let rounding make the gap negative.
+    Some(((experienced - best) / experienced).max(0.0))
+}
@@ -0,0 +1,104 @@
+use abstutil::Timer;
+use geom::{Duration, DurationHistogram, Statistic};
+use map_model::Map;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use sim::{Scenario, Sim, SimFlags, TripMode};
+use std::collections::BTreeMap;
+
+// Run the same scenario with different RNG seeds in parallel, then report every statistic of
+// trip times per mode, averaged over the seeds, with a 95% confidence interval. The seed for each
+// run is drawn from --rng_seed, so the same batch can be repeated.
+pub fn run(map: &Map, scenario: &Scenario, flags: &SimFlags, num_seeds: usize, timer: &mut Timer) {
+    assert!(num_seeds >= 2, "A batch needs at least 2 seeds");
+    let mut flags = flags.clone();
+    // Every run would write to the same log.
+    flags.opts.event_log = None;
+    flags.opts.record_trajectories = None;
+
+    if flags.rng_seed.is_none() {
+        flags.rng_seed = Some(42);
+    }
+    let mut rng = flags.make_rng();
+    let seeds: Vec<u64> = (0..num_seeds).map(|_| rng.gen()).collect();
+
+    let flags = &flags;
+    let results = timer.parallelize("run each seed", seeds, |seed| {
+        let mut timer = Timer::throwaway();
+        let mut sim = Sim::new(map, flags.opts.clone(), &mut timer);
+        scenario.instantiate(
+            &mut sim,
+            map,
+            &mut XorShiftRng::seed_from_u64(seed),
+            &mut timer,
+        );
+        sim.run_until_done(map, |_, _| {}, None);
+        let (_, num_aborted, per_mode) = sim.get_analytics().all_finished_trips(sim.time());
+        (per_mode, num_aborted)
+    });
+
+    println!("Across {} seeds:", num_seeds);
+    let aborted: Vec<f64> = results.iter().map(|(_, n)| *n as f64).collect();
+    let (mean, half_width) = confidence_interval(&aborted);
+    println!("  aborted trips: {:.1} +/- {:.1}", mean, half_width);
+    for mode in TripMode::all() {
+        let histograms: Vec<&DurationHistogram> = results
+            .iter()
+            .map(|(per_mode, _)| &per_mode[&mode])
+            .filter(|h| h.count() > 0)
+            .collect();
+        if histograms.len() < 2 {
+            println!("  {:?}: not enough finished trips", mode);
+            continue;
+        }
+        let counts: Vec<f64> = histograms.iter().map(|h| h.count() as f64).collect();
+        let (mean, half_width) = confidence_interval(&counts);
+        println!(
+            "  {:?} ({} seeds with trips): {:.1} +/- {:.1} trips",
+            mode,
+            histograms.len(),
+            mean,
+            half_width
+        );
+        for stat in Statistic::all() {
+            let values: Vec<f64> = histograms
+                .iter()
+                .map(|h| h.select(stat).inner_seconds())
+                .collect();
+            let (mean, half_width) = confidence_interval(&values);
+            println!(
+                "    {}: {} +/- {}",
+                stat,
+                Duration::seconds(mean),
+                Duration::seconds(half_width)
+            );
+        }
+    }
+}
+
+// Returns the sample mean and the half-width of the 95% confidence interval around it, using
+// Student's t-distribution.
+fn confidence_interval(samples: &[f64]) -> (f64, f64) {
+    let n = samples.len();
+    assert!(n >= 2);
+    let mean = samples.iter().sum::<f64>() / (n as f64);
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / ((n - 1) as f64);
+    (
+        mean,
+        t_critical_value(n - 1) * (variance / (n as f64)).sqrt(),
+    )
+}
+
+// Two-sided, 95%
+fn t_critical_value(degrees_of_freedom: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    if degrees_of_freedom <= TABLE.len() {
+        TABLE[degrees_of_freedom - 1]
+    } else {
+        1.96
+    }
+}
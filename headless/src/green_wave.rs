@@ -0,0 +1,73 @@
+use abstutil::Timer;
+use geom::{DurationHistogram, Speed, Statistic};
+use map_model::{IntersectionID, Map, WaveDirection};
+use sim::{Scenario, Sim, SimFlags};
+
+// Run the same scenario before and after coordinating the signals along a corridor, and report
+// the delay at those signals both times.
+pub fn compare(
+    mut map: Map,
+    scenario: &Scenario,
+    flags: &SimFlags,
+    corridor: Vec<IntersectionID>,
+    speed: Speed,
+    direction: WaveDirection,
+    timer: &mut Timer,
+) {
+    let mut flags = flags.clone();
+    // Both runs need the exact same trips.
+    if flags.rng_seed.is_none() {
+        flags.rng_seed = Some(42);
+    }
+    // The second run would clobber the first run's log.
+    flags.opts.event_log = None;
+    flags.opts.record_trajectories = None;
+
+    let cmds = match map_model::green_wave(&map, &corridor, speed, direction) {
+        Ok(cmds) => cmds,
+        Err(err) => panic!("Can't make a green wave: {}", err),
+    };
+
+    let before = corridor_delays(&map, scenario, &flags, &corridor, timer);
+
+    let mut edits = map.get_edits().clone();
+    edits.commands.extend(cmds);
+    map.apply_edits(edits, timer);
+    map.recalculate_pathfinding_after_edits(timer);
+
+    let after = corridor_delays(&map, scenario, &flags, &corridor, timer);
+
+    println!("Delay along {:?}, {:?} at {}", corridor, direction, speed);
+    println!("  before: {}", before.describe());
+    println!("  after:  {}", after.describe());
+    if before.count() > 0 && after.count() > 0 {
+        println!(
+            "  {} delay went from {} to {}",
+            Statistic::Mean,
+            before.select(Statistic::Mean),
+            after.select(Statistic::Mean)
+        );
+    }
+}
+
+fn corridor_delays(
+    map: &Map,
+    scenario: &Scenario,
+    flags: &SimFlags,
+    corridor: &[IntersectionID],
+    timer: &mut Timer,
+) -> DurationHistogram {
+    let mut sim = Sim::new(map, flags.opts.clone(), timer);
+    scenario.instantiate(&mut sim, map, &mut flags.make_rng(), timer);
+    sim.run_until_done(map, |_, _| {}, None);
+
+    let mut delays = DurationHistogram::new();
+    for i in corridor {
+        if let Some(list) = sim.get_analytics().intersection_delays.get(i) {
+            for (_, dt) in list {
+                delays.add(*dt);
+            }
+        }
+    }
+    delays
+}
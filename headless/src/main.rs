@@ -1,22 +1,102 @@
+mod abtest;
+mod assignment;
+mod batch;
+mod green_wave;
+
 use abstutil::{CmdArgs, Timer};
-use geom::Time;
-use sim::{GetDrawAgents, Scenario, SimFlags};
+use geom::{Duration, Speed, Time};
+use map_model::{IntersectionID, WaveDirection};
+use sim::{ABTest, GetDrawAgents, Scenario, SimFlags, ThruputQuery, TripMode};
 
 fn main() {
     let mut args = CmdArgs::new();
-    let sim_flags = SimFlags::from_args(&mut args);
+    let mut sim_flags = SimFlags::from_args(&mut args);
+    // Stream every event to a .jsonl or .bin file, to rebuild Analytics later.
+    sim_flags.opts.event_log = args.optional("--event_log");
+    // Sample every moving agent to a .bin, .csv, or .geojsonl file.
+    if let Some(path) = args.optional("--trajectories") {
+        let every = args
+            .optional_parse("--trajectory_interval", Duration::parse)
+            .unwrap_or(Duration::seconds(1.0));
+        sim_flags.opts.record_trajectories = Some((path, every));
+    }
     let save_at = args.optional_parse("--save_at", Time::parse);
     let num_agents = args.optional_parse("--num_agents", |s| s.parse::<usize>());
     let enable_profiler = args.enabled("--enable_profiler");
     // Every 0.1s, pretend to draw everything to make sure there are no bugs.
     let paranoia = args.enabled("--paranoia");
+    // A comma-separated list of traffic signals along a corridor. Instead of one normal run,
+    // compare delay along the corridor before and after coordinating their offsets.
+    let green_wave = args.optional_parse("--green_wave", |s| {
+        s.split(',')
+            .map(|i| i.parse::<usize>().map(IntersectionID))
+            .collect::<Result<Vec<_>, _>>()
+    });
+    let green_wave_speed = Speed::miles_per_hour(
+        args.optional_parse("--green_wave_mph", |s| s.parse::<f64>())
+            .unwrap_or(25.0),
+    );
+    let green_wave_direction = args
+        .optional_parse("--green_wave_direction", |s| match s {
+            "forwards" => Ok(WaveDirection::Forwards),
+            "backwards" => Ok(WaveDirection::Backwards),
+            "both" => Ok(WaveDirection::Both),
+            _ => Err(()),
+        })
+        .unwrap_or(WaveDirection::Both);
+    // Instead of one normal run, iterate towards a user equilibrium for at most this many runs.
+    let assignment_iterations =
+        args.optional_parse("--assignment_iterations", |s| s.parse::<usize>());
+    let replan_fraction = args
+        .optional_parse("--replan_fraction", |s| s.parse::<f64>())
+        .unwrap_or(0.5);
+    let assignment_gap = args
+        .optional_parse("--assignment_gap", |s| s.parse::<f64>())
+        .unwrap_or(0.01);
+    let assignment_tolerance = args
+        .optional_parse("--assignment_tolerance", |s| s.parse::<f64>())
+        .unwrap_or(0.01);
+    let assignment_bucket = args
+        .optional_parse("--assignment_bucket", Duration::parse)
+        .unwrap_or(Duration::minutes(15));
+    let assignment_summary = args
+        .optional("--assignment_summary")
+        .unwrap_or_else(|| "assignment.json".to_string());
+    // Instead of one normal run, run with this many different RNG seeds in parallel and report
+    // trip time statistics with confidence intervals.
+    let batch_seeds = args.optional_parse("--batch_seeds", |s| s.parse::<usize>());
+    // Path to an A/B test. Instead of one normal run, run both sides and compare every trip.
+    let ab_test = args.optional("--ab_test");
+    // Written to this path with .json and .csv added
+    let ab_test_report = args.optional("--ab_test_report");
+    // After a normal run, write the number of agents entering every road per bucket to a CSV file.
+    let thruput_csv = args.optional("--thruput_csv");
+    let thruput_bucket = args
+        .optional_parse("--thruput_bucket", Duration::parse)
+        .unwrap_or(Duration::hours(1));
+    // After a normal run, write roads, intersections, and buildings with results to GeoJSON files
+    // in this directory.
+    let export_geojson = args.optional("--export_geojson");
     args.done();
 
+    if let Some(path) = ab_test {
+        let mut timer = Timer::new("run A/B test");
+        let test: ABTest = abstutil::read_json(path, &mut timer);
+        let report_prefix = ab_test_report.unwrap_or_else(|| {
+            format!(
+                "../data/player/ab_test_reports/{}/{}",
+                test.map_name, test.test_name
+            )
+        });
+        abtest::run(test, &sim_flags, report_prefix, &mut timer);
+        return;
+    }
+
     let mut timer = Timer::new("setup headless");
     let (map, mut sim, mut rng) = sim_flags.load(&mut timer);
 
     // TODO not the ideal way to distinguish what thing we loaded
-    if sim_flags.load.starts_with(&abstutil::path_all_raw_maps())
+    let scenario = if sim_flags.load.starts_with(&abstutil::path_all_raw_maps())
         || sim_flags.load.starts_with(&abstutil::path_all_maps())
     {
         let s = if let Some(n) = num_agents {
@@ -25,9 +105,60 @@ fn main() {
             Scenario::small_run(&map)
         };
         s.instantiate(&mut sim, &map, &mut rng, &mut timer);
-    }
+        Some(s)
+    } else if sim_flags.load.starts_with("../data/system/scenarios/") {
+        Some(abstutil::read_binary(sim_flags.load.clone(), &mut timer))
+    } else {
+        None
+    };
     timer.done();
 
+    if let Some(corridor) = green_wave {
+        let scenario = scenario.expect("--green_wave needs a map or scenario, not a savestate");
+        green_wave::compare(
+            map,
+            &scenario,
+            &sim_flags,
+            corridor,
+            green_wave_speed,
+            green_wave_direction,
+            &mut Timer::new("compare green wave"),
+        );
+        return;
+    }
+
+    if let Some(max_iterations) = assignment_iterations {
+        let scenario =
+            scenario.expect("--assignment_iterations needs a map or scenario, not a savestate");
+        assignment::run(
+            map,
+            &scenario,
+            &sim_flags,
+            assignment::AssignmentOptions {
+                max_iterations,
+                replan_fraction,
+                gap_tolerance: assignment_gap,
+                tolerance: assignment_tolerance,
+                bucket_size: assignment_bucket,
+                summary_path: assignment_summary,
+            },
+            &mut Timer::new("iterative traffic assignment"),
+        );
+        return;
+    }
+
+    if let Some(num_seeds) = batch_seeds {
+        let scenario = scenario.expect("--batch_seeds needs a map or scenario, not a savestate");
+        batch::run(
+            &map,
+            &scenario,
+            &sim_flags,
+            num_seeds,
+            &mut Timer::new("run batch of seeds"),
+        );
+        return;
+    }
+
     if enable_profiler {
         #[cfg(feature = "profiler")]
         {
@@ -61,6 +192,34 @@ fn main() {
     );
     timer.done();
     println!("Done at {}", sim.time());
+    let otp = sim.get_analytics().on_time_performance(sim.time(), None);
+    if let Some(pct) = otp.percent_on_time() {
+        println!(
+            "Scheduled buses: {:.1}% on time ({} early, {} on time, {} late). Lateness: {}",
+            pct,
+            otp.early,
+            otp.on_time,
+            otp.late,
+            otp.lateness.describe()
+        );
+    }
+    if let Some(path) = thruput_csv {
+        let table = sim.get_analytics().road_thruput_table(&ThruputQuery {
+            objects: map.all_roads().iter().map(|r| r.id).collect(),
+            start: Time::START_OF_DAY,
+            end: sim.time(),
+            bucket_size: thruput_bucket,
+            modes: TripMode::all().into_iter().collect(),
+        });
+        let csv = table.to_csv("road,osm_way_id", |r| {
+            format!("{},{}", r.0, map.get_r(r).orig_id.osm_way_id)
+        });
+        std::fs::write(&path, csv + "\n").unwrap();
+        println!("Wrote {}", path);
+    }
+    if let Some(dir) = export_geojson {
+        sim.export_geojson(&map, &dir);
+    }
     if enable_profiler && save_at.is_none() {
         #[cfg(feature = "profiler")]
         {
@@ -64,6 +64,11 @@ for poly in `ls data/input/polygons/`; do
 	fi
 done
 
+# Just for testing the PBF reader against the XML one
+if [ ! -f data/input/osm/montlake.osm.pbf ]; then
+	osmconvert data/input/osm/montlake.osm -o=data/input/osm/montlake.osm.pbf
+fi
+
 if [ ! -f data/input/blockface.bin ]; then
 	# From http://data-seattlecitygis.opendata.arcgis.com/datasets/blockface
 	get_if_needed https://opendata.arcgis.com/datasets/a1458ad1abca41869b81f7c0db0cd777_0.kml data/input/blockface.kml;
@@ -388,6 +388,7 @@ impl Model {
                 ],
                 osm_tags,
                 turn_restrictions: Vec::new(),
+                conditional_turn_restrictions: Vec::new(),
             },
         );
         self.road_added(id, prerender);
@@ -663,6 +664,7 @@ impl Model {
             LaneType::Biking => Color::rgb(15, 125, 75),
             LaneType::SharedLeftTurn => Color::YELLOW,
             LaneType::Construction => Color::rgb(255, 109, 0),
+            LaneType::LightRail => Color::rgb(145, 108, 80),
         };
         if unset {
             match color {
@@ -1,5 +1,5 @@
-use crate::{osm, LaneID, Map, Position};
-use geom::{Line, Polygon, Pt2D};
+use crate::{osm, parse_conditional, LaneID, Map, Position, TimeWindows};
+use geom::{Duration, Line, Polygon, Pt2D, Time};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
@@ -29,6 +29,99 @@ pub struct OffstreetParking {
     pub driveway_line: Line,
     // Guaranteed to be at least 7m before the end of the lane
     pub driving_pos: Position,
+    pub policy: ParkingPolicy,
+}
+
+// Restrictions on a parking lane or an offstreet lot. The default is free and unlimited.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct ParkingPolicy {
+    // 0 is free
+    pub hourly_price_cents: usize,
+    pub max_stay: Option<Duration>,
+    // Only cars owned by a building on the same road (or the same building, for offstreet parking)
+    pub residents_only: bool,
+    // Nobody can park here during these times, like peak-hour clearways
+    pub no_parking: Option<TimeWindows>,
+}
+
+impl ParkingPolicy {
+    // Understands the parking:condition scheme. The prefix is something like
+    // "parking:condition:right" for one side of a road, or "parking:condition" for a lot.
+    pub fn from_osm_tags(tags: &BTreeMap<String, String>, prefix: &str) -> ParkingPolicy {
+        let mut policy = ParkingPolicy::default();
+        if let Some(condition) = tags.get(prefix) {
+            policy.residents_only = condition == "residents" || condition == "private";
+        }
+        // Like "2 USD/hour". Only hourly charges are understood, and the currency is ignored.
+        if let Some(charge) = tags.get(&format!("{}:charge", prefix)) {
+            let parts: Vec<&str> = charge.split(' ').collect();
+            if parts.len() == 2 && (parts[1].ends_with("/hour") || parts[1].ends_with("/h")) {
+                if let Ok(amount) = parts[0].parse::<f64>() {
+                    policy.hourly_price_cents = (amount * 100.0).round() as usize;
+                }
+            }
+        }
+        // Like "2 h" or "90 min"
+        if let Some(maxstay) = tags.get(&format!("{}:maxstay", prefix)) {
+            let parts: Vec<&str> = maxstay.split(' ').collect();
+            if parts.len() == 2 {
+                if let Ok(amount) = parts[0].parse::<f64>() {
+                    policy.max_stay = match parts[1] {
+                        "h" | "hour" | "hours" => Some(Duration::seconds(amount * 3600.0)),
+                        "min" | "minutes" => Some(Duration::seconds(amount * 60.0)),
+                        _ => None,
+                    };
+                }
+            }
+        }
+        // Like "no_stopping @ (Mo-Fr 07:00-09:00)". Both the parking:condition and parking:lane
+        // schemes are used for this.
+        for key in vec![
+            format!("{}:conditional", prefix),
+            format!(
+                "{}:conditional",
+                prefix.replace("parking:condition", "parking:lane")
+            ),
+        ] {
+            if let Some(conditional) = tags.get(&key) {
+                for (value, windows) in parse_conditional(conditional) {
+                    if value == "no_parking" || value == "no_stopping" {
+                        policy.no_parking = Some(windows);
+                    }
+                }
+            }
+        }
+        policy
+    }
+
+    pub fn can_park_at(&self, time: Time) -> bool {
+        self.no_parking
+            .as_ref()
+            .map(|windows| !windows.contains(time))
+            .unwrap_or(true)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.hourly_price_cents == 0 {
+            parts.push("free".to_string());
+        } else {
+            parts.push(format!(
+                "${:.2}/hour",
+                (self.hourly_price_cents as f64) / 100.0
+            ));
+        }
+        if let Some(dt) = self.max_stay {
+            parts.push(format!("{} max", dt));
+        }
+        if self.residents_only {
+            parts.push("residents only".to_string());
+        }
+        if let Some(ref windows) = self.no_parking {
+            parts.push(format!("no parking {}", windows));
+        }
+        parts.join(", ")
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -1,4 +1,5 @@
-use crate::{LaneID, Position};
+use crate::{LaneID, PathConstraints, Position};
+use geom::{Distance, Duration};
 use serde_derive::{Deserialize, Serialize};
 use std::fmt;
 
@@ -39,4 +40,37 @@ pub struct BusRoute {
     pub id: BusRouteID,
     pub name: String,
     pub stops: Vec<BusStopID>,
+    // From GTFS, when there is one
+    pub schedule: Option<BusSchedule>,
+    // Bus or Train
+    pub route_type: PathConstraints,
+    // Every vehicle serving the route is this long, and holds this many riders
+    pub vehicle_length: Distance,
+    pub capacity: usize,
+}
+
+// Weekday service
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BusSchedule {
+    // For every scheduled trip, when it reaches each of the route's stops, since midnight. This can
+    // go past 24 hours. Trips that only serve part of the route, like one direction or a
+    // short-turn, have None for the other stops. The stops a trip serves are always contiguous.
+    pub trips: Vec<Vec<Option<Duration>>>,
+}
+
+impl BusSchedule {
+    // Keep the schedule lined up with the route's stops after removing some. Trips left with fewer
+    // than two stops are dropped.
+    pub fn retain_stops(&mut self, keep: &Vec<bool>) {
+        for trip in &mut self.trips {
+            *trip = trip
+                .iter()
+                .zip(keep.iter())
+                .filter(|(_, ok)| **ok)
+                .map(|(dt, _)| *dt)
+                .collect();
+        }
+        self.trips
+            .retain(|trip| trip.iter().filter(|dt| dt.is_some()).count() >= 2);
+    }
 }
@@ -1,6 +1,6 @@
 use crate::{
-    ControlStopSign, ControlTrafficSignal, IntersectionID, IntersectionType, LaneID, LaneType, Map,
-    RoadID, TurnID,
+    BuildingID, ControlStopSign, ControlTrafficSignal, IntersectionID, IntersectionType, LaneID,
+    LaneType, Map, ParkingPolicy, RoadID, TurnID,
 };
 use abstutil::{retain_btreemap, retain_btreeset, Timer};
 use serde_derive::{Deserialize, Serialize};
@@ -16,6 +16,8 @@ pub struct MapEdits {
     pub original_lts: BTreeMap<LaneID, LaneType>,
     pub reversed_lanes: BTreeSet<LaneID>,
     pub changed_intersections: BTreeSet<IntersectionID>,
+    pub original_lane_parking: BTreeMap<LaneID, ParkingPolicy>,
+    pub original_bldg_parking: BTreeMap<BuildingID, ParkingPolicy>,
 
     #[serde(skip_serializing, skip_deserializing)]
     pub dirty: bool,
@@ -40,6 +42,16 @@ pub enum EditCmd {
         orig_it: IntersectionType,
     },
     UncloseIntersection(IntersectionID, IntersectionType),
+    ChangeLaneParkingPolicy {
+        id: LaneID,
+        policy: ParkingPolicy,
+        orig_policy: ParkingPolicy,
+    },
+    ChangeBldgParkingPolicy {
+        id: BuildingID,
+        policy: ParkingPolicy,
+        orig_policy: ParkingPolicy,
+    },
 }
 
 pub struct EditEffects {
@@ -61,6 +73,8 @@ impl MapEdits {
             original_lts: BTreeMap::new(),
             reversed_lanes: BTreeSet::new(),
             changed_intersections: BTreeSet::new(),
+            original_lane_parking: BTreeMap::new(),
+            original_bldg_parking: BTreeMap::new(),
             dirty: false,
         }
     }
@@ -97,9 +111,12 @@ impl MapEdits {
         panic!("{} isn't closed", i);
     }
 
-    // Original lane types, reversed lanes, and all changed intersections
+    // Original lane types, reversed lanes, all changed intersections, and original parking
+    // policies
     pub(crate) fn update_derived(&mut self, map: &Map, timer: &mut Timer) {
         let mut orig_lts = BTreeMap::new();
+        let mut orig_lane_parking = BTreeMap::new();
+        let mut orig_bldg_parking = BTreeMap::new();
         let mut reversed_lanes = BTreeSet::new();
         let mut changed_stop_signs = BTreeSet::new();
         let mut changed_traffic_signals = BTreeSet::new();
@@ -131,10 +148,30 @@ impl MapEdits {
                 EditCmd::UncloseIntersection(id, _) => {
                     closed_intersections.remove(id);
                 }
+                EditCmd::ChangeLaneParkingPolicy {
+                    id, orig_policy, ..
+                } => {
+                    if !orig_lane_parking.contains_key(id) {
+                        orig_lane_parking.insert(*id, orig_policy.clone());
+                    }
+                }
+                EditCmd::ChangeBldgParkingPolicy {
+                    id, orig_policy, ..
+                } => {
+                    if !orig_bldg_parking.contains_key(id) {
+                        orig_bldg_parking.insert(*id, orig_policy.clone());
+                    }
+                }
             }
         }
 
         retain_btreemap(&mut orig_lts, |l, lt| map.get_l(*l).lane_type != *lt);
+        retain_btreemap(&mut orig_lane_parking, |l, p| {
+            &map.get_l(*l).parking_policy != p
+        });
+        retain_btreemap(&mut orig_bldg_parking, |b, p| {
+            &map.get_b(*b).parking.as_ref().unwrap().policy != p
+        });
         for i in &closed_intersections {
             changed_stop_signs.remove(i);
             changed_traffic_signals.remove(i);
@@ -147,6 +184,8 @@ impl MapEdits {
         });
 
         self.original_lts = orig_lts;
+        self.original_lane_parking = orig_lane_parking;
+        self.original_bldg_parking = orig_bldg_parking;
         self.reversed_lanes = reversed_lanes;
         self.changed_intersections = closed_intersections;
         self.changed_intersections.extend(changed_stop_signs);
@@ -170,6 +209,20 @@ impl MapEdits {
                 dst_i: map.get_l(*l).dst_i,
             });
         }
+        for (l, orig_policy) in &self.original_lane_parking {
+            self.commands.push(EditCmd::ChangeLaneParkingPolicy {
+                id: *l,
+                policy: map.get_l(*l).parking_policy.clone(),
+                orig_policy: orig_policy.clone(),
+            });
+        }
+        for (b, orig_policy) in &self.original_bldg_parking {
+            self.commands.push(EditCmd::ChangeBldgParkingPolicy {
+                id: *b,
+                policy: map.get_b(*b).parking.as_ref().unwrap().policy.clone(),
+                orig_policy: orig_policy.clone(),
+            });
+        }
         for i in &self.changed_intersections {
             match map.get_i(*i).intersection_type {
                 IntersectionType::StopSign => {
@@ -222,6 +275,12 @@ impl EditCmd {
             EditCmd::ChangeTrafficSignal(ts) => format!("Edit traffic signal {}", ts.id),
             EditCmd::CloseIntersection { id, .. } => format!("Close {}", id),
             EditCmd::UncloseIntersection(id, _) => format!("Restore {}", id),
+            EditCmd::ChangeLaneParkingPolicy { id, policy, .. } => {
+                format!("Change parking on {} to {}", id, policy.describe())
+            }
+            EditCmd::ChangeBldgParkingPolicy { id, policy, .. } => {
+                format!("Change parking at {} to {}", id, policy.describe())
+            }
         }
     }
 }
@@ -0,0 +1,205 @@
+use crate::{Map, RoadSpec};
+use geojson::{Feature, FeatureCollection, GeoJson, Geometry, Value};
+use geom::{GPSBounds, Pt2D};
+use serde_json::{Map as JsonObject, Value as JsonValue};
+
+pub type Properties = JsonObject<String, JsonValue>;
+
+// Everything in the map as GeoJSON in WGS84, to inspect in GIS tools. IDs are the same as in the
+// map, so they're stable as long as the map isn't regenerated. Turn IDs are built from the
+// intersection, source lane, and destination lane, like "12_345_678". Each layer only has one
+// type of geometry, so they're written separately.
+impl Map {
+    pub fn export_geojson(&self, dir: &str) {
+        for (name, layer) in vec![
+            ("lanes", self.lanes_geojson()),
+            ("roads", self.roads_geojson()),
+            ("intersections", self.intersections_geojson()),
+            ("turns", self.turns_geojson()),
+            ("buildings", self.buildings_geojson()),
+            ("bus_stops", self.bus_stops_geojson()),
+            ("areas", self.areas_geojson()),
+        ] {
+            abstutil::write_json(format!("{}/{}.geojson", dir, name), &layer);
+        }
+    }
+
+    pub fn lanes_geojson(&self) -> GeoJson {
+        let mut features = Vec::new();
+        for l in self.all_lanes() {
+            let r = self.get_r(l.parent);
+            let mut props = Properties::new();
+            props.insert("id".to_string(), l.id.0.into());
+            props.insert("road".to_string(), r.id.0.into());
+            props.insert("osm_way_id".to_string(), r.orig_id.osm_way_id.into());
+            props.insert("lane_type".to_string(), format!("{:?}", l.lane_type).into());
+            props.insert("forwards".to_string(), r.is_forwards(l.id).into());
+            props.insert("width_m".to_string(), l.width.inner_meters().into());
+            props.insert("src_i".to_string(), l.src_i.0.into());
+            props.insert("dst_i".to_string(), l.dst_i.0.into());
+            features.push(feature(
+                Value::LineString(pts_to_gps(
+                    l.lane_center_pts.points(),
+                    self.get_gps_bounds(),
+                )),
+                props,
+            ));
+        }
+        collection(features)
+    }
+
+    pub fn roads_geojson(&self) -> GeoJson {
+        let mut features = Vec::new();
+        for r in self.all_roads() {
+            let (fwd, back) = r.get_lane_types();
+            let mut props = Properties::new();
+            props.insert("id".to_string(), r.id.0.into());
+            props.insert("osm_way_id".to_string(), r.orig_id.osm_way_id.into());
+            props.insert("name".to_string(), r.get_name().into());
+            props.insert(
+                "speed_limit_mph".to_string(),
+                r.get_speed_limit().to_miles_per_hour().into(),
+            );
+            // Like "spd/dps": one character per lane type, forwards then backwards
+            props.insert(
+                "lanes".to_string(),
+                RoadSpec { fwd, back }.to_string().into(),
+            );
+            props.insert("src_i".to_string(), r.src_i.0.into());
+            props.insert("dst_i".to_string(), r.dst_i.0.into());
+            features.push(feature(
+                Value::LineString(pts_to_gps(r.center_pts.points(), self.get_gps_bounds())),
+                props,
+            ));
+        }
+        collection(features)
+    }
+
+    pub fn intersections_geojson(&self) -> GeoJson {
+        let mut features = Vec::new();
+        for i in self.all_intersections() {
+            let mut props = Properties::new();
+            props.insert("id".to_string(), i.id.0.into());
+            props.insert("osm_node_id".to_string(), i.orig_id.osm_node_id.into());
+            props.insert(
+                "intersection_type".to_string(),
+                format!("{:?}", i.intersection_type).into(),
+            );
+            features.push(feature(
+                Value::Polygon(vec![ring_to_gps(i.polygon.points(), self.get_gps_bounds())]),
+                props,
+            ));
+        }
+        collection(features)
+    }
+
+    pub fn turns_geojson(&self) -> GeoJson {
+        let mut features = Vec::new();
+        for t in self.all_turns().values() {
+            let mut props = Properties::new();
+            // Turns don't have a number of their own, so use what they connect
+            props.insert(
+                "id".to_string(),
+                format!("{}_{}_{}", t.id.parent.0, t.id.src.0, t.id.dst.0).into(),
+            );
+            props.insert("intersection".to_string(), t.id.parent.0.into());
+            props.insert("src".to_string(), t.id.src.0.into());
+            props.insert("dst".to_string(), t.id.dst.0.into());
+            props.insert("turn_type".to_string(), format!("{:?}", t.turn_type).into());
+            features.push(feature(
+                Value::LineString(pts_to_gps(t.geom.points(), self.get_gps_bounds())),
+                props,
+            ));
+        }
+        collection(features)
+    }
+
+    pub fn buildings_geojson(&self) -> GeoJson {
+        let mut features = Vec::new();
+        for b in self.all_buildings() {
+            let mut props = Properties::new();
+            props.insert("id".to_string(), b.id.0.into());
+            props.insert("osm_way_id".to_string(), b.osm_way_id.into());
+            props.insert("sidewalk".to_string(), b.sidewalk().0.into());
+            props.insert(
+                "parking_stalls".to_string(),
+                b.parking.as_ref().map(|p| p.num_stalls).unwrap_or(0).into(),
+            );
+            features.push(feature(
+                Value::Polygon(vec![ring_to_gps(b.polygon.points(), self.get_gps_bounds())]),
+                props,
+            ));
+        }
+        collection(features)
+    }
+
+    pub fn bus_stops_geojson(&self) -> GeoJson {
+        let mut features = Vec::new();
+        for bs in self.all_bus_stops().values() {
+            let mut props = Properties::new();
+            props.insert("sidewalk".to_string(), bs.id.sidewalk.0.into());
+            props.insert("idx".to_string(), bs.id.idx.into());
+            props.insert("driving_lane".to_string(), bs.driving_pos.lane().0.into());
+            let pt = bs
+                .sidewalk_pos
+                .pt(self)
+                .forcibly_to_gps(self.get_gps_bounds());
+            features.push(feature(
+                Value::Point(vec![pt.longitude, pt.latitude]),
+                props,
+            ));
+        }
+        collection(features)
+    }
+
+    pub fn areas_geojson(&self) -> GeoJson {
+        let mut features = Vec::new();
+        for a in self.all_areas() {
+            let mut props = Properties::new();
+            props.insert("id".to_string(), a.id.0.into());
+            props.insert("osm_id".to_string(), a.osm_id.into());
+            props.insert("area_type".to_string(), format!("{:?}", a.area_type).into());
+            features.push(feature(
+                Value::Polygon(vec![ring_to_gps(a.polygon.points(), self.get_gps_bounds())]),
+                props,
+            ));
+        }
+        collection(features)
+    }
+}
+
+pub fn feature(value: Value, properties: Properties) -> Feature {
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(value)),
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+pub fn collection(features: Vec<Feature>) -> GeoJson {
+    GeoJson::FeatureCollection(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    })
+}
+
+pub fn pts_to_gps(pts: &Vec<Pt2D>, gps_bounds: &GPSBounds) -> Vec<Vec<f64>> {
+    pts.iter()
+        .map(|pt| {
+            let gps = pt.forcibly_to_gps(gps_bounds);
+            vec![gps.longitude, gps.latitude]
+        })
+        .collect()
+}
+
+// GeoJSON rings have to be closed
+pub fn ring_to_gps(pts: &Vec<Pt2D>, gps_bounds: &GPSBounds) -> Vec<Vec<f64>> {
+    let mut ring = pts_to_gps(pts, gps_bounds);
+    if ring.first() != ring.last() {
+        ring.push(ring[0].clone());
+    }
+    ring
+}
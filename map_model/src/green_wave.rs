@@ -0,0 +1,230 @@
+use crate::{ControlTrafficSignal, EditCmd, IntersectionID, Map, PhaseType, RoadID, TurnType};
+use geom::{Distance, Duration, Speed};
+use petgraph::graphmap::UnGraphMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaveDirection {
+    // In the order the corridor is given
+    Forwards,
+    Backwards,
+    Both,
+}
+
+// Given an ordered list of traffic signals along a corridor, pick offsets so that traffic moving
+// at speed hits green lights all the way through. Each signal's "corridor phase" is the first one
+// protecting the through movement along the corridor. The first signal in the direction of travel
+// keeps its offset; everything downstream starts its corridor phase just as the platoon arrives.
+//
+// Only fixed-time signals can be coordinated. The wave is only perfect when the signals share a
+// cycle length; otherwise it drifts. When both
+// directions are requested, each signal splits the difference between the two one-way offsets,
+// which is the usual compromise.
+pub fn green_wave(
+    map: &Map,
+    corridor: &[IntersectionID],
+    speed: Speed,
+    direction: WaveDirection,
+) -> Result<Vec<EditCmd>, String> {
+    if corridor.len() < 2 {
+        return Err(format!(
+            "A corridor needs at least 2 signals, not {:?}",
+            corridor
+        ));
+    }
+    for i in corridor {
+        if let Some(signal) = map.maybe_get_traffic_signal(*i) {
+            // Actuated phases end early or get skipped, so the cycle drifts away from any offset.
+            if signal.phases.iter().any(|p| match p.phase_type {
+                PhaseType::Fixed(_) => false,
+                PhaseType::Actuated { .. } => true,
+            }) {
+                return Err(format!(
+                    "{} has actuated phases, so it doesn't keep a fixed cycle to coordinate",
+                    i
+                ));
+            }
+        } else {
+            return Err(format!("{} isn't a traffic signal", i));
+        }
+    }
+
+    // For every signal, the road leading to the previous and next signal along the corridor, and
+    // the distance to the next signal.
+    let graph = road_graph(map);
+    let mut prev_road: Vec<Option<RoadID>> = vec![None; corridor.len()];
+    let mut next_road: Vec<Option<RoadID>> = vec![None; corridor.len()];
+    let mut dist_to_next: Vec<Distance> = Vec::new();
+    for (idx, pair) in corridor.windows(2).enumerate() {
+        let (dist, path) = petgraph::algo::astar(
+            &graph,
+            pair[0],
+            |i| i == pair[1],
+            |(_, _, dist)| *dist,
+            |_| 0.0,
+        )
+        .ok_or_else(|| format!("No path along the corridor from {} to {}", pair[0], pair[1]))?;
+        next_road[idx] = Some(road_between(map, path[0], path[1]));
+        prev_road[idx + 1] = Some(road_between(
+            map,
+            path[path.len() - 2],
+            path[path.len() - 1],
+        ));
+        dist_to_next.push(Distance::meters(dist));
+    }
+
+    let forwards = if direction != WaveDirection::Backwards {
+        Some(one_way_offsets(
+            map,
+            corridor,
+            &prev_road,
+            &next_road,
+            &dist_to_next,
+            speed,
+        )?)
+    } else {
+        None
+    };
+    let backwards = if direction != WaveDirection::Forwards {
+        // Just flip everything around.
+        let mut rev_corridor = corridor.to_vec();
+        rev_corridor.reverse();
+        let mut rev_prev = next_road.to_vec();
+        rev_prev.reverse();
+        let mut rev_next = prev_road.to_vec();
+        rev_next.reverse();
+        let mut rev_dist = dist_to_next.to_vec();
+        rev_dist.reverse();
+        let mut offsets =
+            one_way_offsets(map, &rev_corridor, &rev_prev, &rev_next, &rev_dist, speed)?;
+        offsets.reverse();
+        Some(offsets)
+    } else {
+        None
+    };
+
+    let mut cmds = Vec::new();
+    for (idx, i) in corridor.iter().enumerate() {
+        let mut signal = map.get_traffic_signal(*i).clone();
+        let cycle = signal.cycle_length();
+        signal.offset = match (&forwards, &backwards) {
+            (Some(f), None) => f[idx],
+            (None, Some(b)) => b[idx],
+            (Some(f), Some(b)) => {
+                // The midpoint of the two offsets, going the short way around the cycle.
+                let mut diff = wrap(b[idx] - f[idx], cycle);
+                if diff > cycle / 2.0 {
+                    diff = diff - cycle;
+                }
+                wrap(f[idx] + diff / 2.0, cycle)
+            }
+            (None, None) => unreachable!(),
+        };
+        // Keep edits readable
+        signal.offset = Duration::seconds(signal.offset.inner_seconds().round());
+        cmds.push(EditCmd::ChangeTrafficSignal(signal));
+    }
+    Ok(cmds)
+}
+
+fn one_way_offsets(
+    map: &Map,
+    corridor: &[IntersectionID],
+    prev_road: &[Option<RoadID>],
+    next_road: &[Option<RoadID>],
+    dist_to_next: &[Distance],
+    speed: Speed,
+) -> Result<Vec<Duration>, String> {
+    // When the corridor phase starts (measured from the beginning of the cycle) at each signal
+    let mut phase_starts = Vec::new();
+    for (idx, i) in corridor.iter().enumerate() {
+        let signal = map.get_traffic_signal(*i);
+        let phase = corridor_phase(signal, prev_road[idx], next_road[idx])
+            .ok_or_else(|| format!("No phase at {} protects travel along the corridor", i))?;
+        phase_starts.push(
+            signal.phases[0..phase]
+                .iter()
+                .fold(Duration::ZERO, |sum, p| {
+                    sum + p.phase_type.simple_duration()
+                }),
+        );
+    }
+
+    // The first signal defines when the platoon leaves. The signal's time is now + offset, so the
+    // corridor phase at the first signal starts at phase_starts[0] - offsets[0] (modulo the cycle).
+    let first = map.get_traffic_signal(corridor[0]);
+    let depart = phase_starts[0] - first.offset;
+    let mut offsets = vec![first.offset];
+    let mut travel_time = Duration::ZERO;
+    for idx in 1..corridor.len() {
+        travel_time += dist_to_next[idx - 1] / speed;
+        let cycle = map.get_traffic_signal(corridor[idx]).cycle_length();
+        offsets.push(wrap(phase_starts[idx] - depart - travel_time, cycle));
+    }
+    Ok(offsets)
+}
+
+// The first phase protecting movement from prev to next. At the ends of the corridor, only one
+// side is known. Straight movements win over turns.
+fn corridor_phase(
+    signal: &ControlTrafficSignal,
+    prev: Option<RoadID>,
+    next: Option<RoadID>,
+) -> Option<usize> {
+    let candidates: Vec<_> = signal
+        .turn_groups
+        .values()
+        .filter(|g| {
+            g.id.crosswalk.is_none()
+                && prev.map(|r| g.id.from == r).unwrap_or(true)
+                && next.map(|r| g.id.to == r).unwrap_or(true)
+        })
+        .collect();
+    let straight: Vec<_> = candidates
+        .iter()
+        .filter(|g| g.turn_type == TurnType::Straight)
+        .collect();
+    let groups = if straight.is_empty() {
+        candidates.iter().collect()
+    } else {
+        straight
+    };
+    signal
+        .phases
+        .iter()
+        .position(|p| groups.iter().any(|g| p.protected_groups.contains(&g.id)))
+}
+
+fn road_graph(map: &Map) -> UnGraphMap<IntersectionID, f64> {
+    let mut graph = UnGraphMap::new();
+    for r in map.all_roads() {
+        let len = r.center_pts.length().inner_meters();
+        if let Some(existing) = graph.edge_weight(r.src_i, r.dst_i) {
+            if *existing <= len {
+                continue;
+            }
+        }
+        graph.add_edge(r.src_i, r.dst_i, len);
+    }
+    graph
+}
+
+// The shortest road directly connecting two intersections
+fn road_between(map: &Map, i1: IntersectionID, i2: IntersectionID) -> RoadID {
+    map.get_i(i1)
+        .roads
+        .iter()
+        .map(|r| map.get_r(*r))
+        .filter(|r| (r.src_i == i1 && r.dst_i == i2) || (r.src_i == i2 && r.dst_i == i1))
+        .min_by_key(|r| r.center_pts.length())
+        .unwrap()
+        .id
+}
+
+fn wrap(d: Duration, cycle: Duration) -> Duration {
+    let d = d % cycle;
+    if d < Duration::ZERO {
+        d + cycle
+    } else {
+        d
+    }
+}
@@ -1,7 +1,7 @@
 use crate::pathfind;
 use crate::{
-    osm, BuildingID, BusStopID, DirectedRoadID, IntersectionID, Map, PathConstraints, Road, RoadID,
-    TurnType,
+    osm, BuildingID, BusStopID, DirectedRoadID, IntersectionID, Map, ParkingPolicy,
+    PathConstraints, Road, RoadID, TimeWindows, TurnType,
 };
 use geom::{Angle, Distance, Line, PolyLine, Pt2D};
 use serde_derive::{Deserialize, Serialize};
@@ -30,6 +30,7 @@ pub enum LaneType {
     Bus,
     SharedLeftTurn,
     Construction,
+    LightRail,
 }
 
 impl LaneType {
@@ -42,6 +43,7 @@ impl LaneType {
             LaneType::Sidewalk => false,
             LaneType::SharedLeftTurn => false,
             LaneType::Construction => false,
+            LaneType::LightRail => true,
         }
     }
 
@@ -54,6 +56,7 @@ impl LaneType {
             LaneType::Sidewalk => true,
             LaneType::SharedLeftTurn => false,
             LaneType::Construction => false,
+            LaneType::LightRail => true,
         }
     }
 
@@ -66,6 +69,7 @@ impl LaneType {
             LaneType::Sidewalk => "a sidewalk",
             LaneType::SharedLeftTurn => "a shared left-turn lane",
             LaneType::Construction => "a lane that's closed for construction",
+            LaneType::LightRail => "a light rail track",
         }
     }
 }
@@ -88,6 +92,12 @@ pub struct Lane {
     // If set, cars trying to park near here should actually start their search at this other lane.
     // Only populated for driving lanes inevitably leading to borders.
     pub parking_blackhole: Option<LaneID>,
+
+    // Only meaningful for parking lanes
+    pub parking_policy: ParkingPolicy,
+    // Only meaningful for bus lanes. If set, the lane is only reserved for buses at these times,
+    // and cars can use it otherwise; see Map::is_turn_allowed_at.
+    pub bus_hours: Option<TimeWindows>,
 }
 
 impl Lane {
@@ -182,6 +192,10 @@ impl Lane {
         self.lane_type == LaneType::Parking
     }
 
+    pub fn is_light_rail(&self) -> bool {
+        self.lane_type == LaneType::LightRail
+    }
+
     // TODO Store this natively if this winds up being useful.
     pub fn get_directed_parent(&self, map: &Map) -> DirectedRoadID {
         let r = map.get_r(self.parent);
@@ -192,12 +206,14 @@ impl Lane {
         }
     }
 
+    // From turn:lanes tags, which movements this lane is marked for. None if the lane isn't
+    // marked, or the tags don't match the lanes of the road.
     pub fn get_turn_restrictions(&self, road: &Road) -> Option<BTreeSet<TurnType>> {
-        if !self.is_driving() {
+        if !self.is_driving() && !self.is_bus() {
             return None;
         }
 
-        let (dir, offset) = road.dir_and_offset(self.id);
+        let (dir, _) = road.dir_and_offset(self.id);
         let all = if dir && road.osm_tags.contains_key(osm::ENDPT_FWD) {
             road.osm_tags
                 .get("turn:lanes:forward")
@@ -207,36 +223,24 @@ impl Lane {
         } else {
             return None;
         };
-        let parts: Vec<&str> = all.split('|').collect();
-        // TODO Verify the number of lanes matches up
-        let part = parts.get(offset)?;
-        if part == &"none" {
+        let per_lane = parse_turn_lanes(all)?;
+
+        // turn:lanes only counts the lanes in the lanes tag, ordered from the left.
+        let children = if dir {
+            &road.children_forwards
+        } else {
+            &road.children_backwards
+        };
+        let marked: Vec<LaneID> = children
+            .iter()
+            .filter(|(_, lt)| *lt == LaneType::Driving || *lt == LaneType::Bus)
+            .map(|(id, _)| *id)
+            .collect();
+        if marked.len() != per_lane.len() {
             return None;
         }
-        Some(
-            part.split(';')
-                .flat_map(|s| match s {
-                    "left" => vec![TurnType::Left],
-                    "right" => vec![TurnType::Right],
-                    // TODO What is blank supposed to mean? From few observed cases, same as through
-                    "through" | "" => vec![
-                        TurnType::Straight,
-                        TurnType::LaneChangeLeft,
-                        TurnType::LaneChangeRight,
-                    ],
-                    // TODO Check this more carefully
-                    "slight_right" | "slight right" | "merge_to_right" => vec![
-                        TurnType::Straight,
-                        TurnType::LaneChangeRight,
-                        TurnType::Right,
-                    ],
-                    "slight_left" | "slight left" | "merge_to_left" => {
-                        vec![TurnType::Straight, TurnType::LaneChangeLeft, TurnType::Left]
-                    }
-                    _ => panic!("What's turn restriction {}?", s),
-                })
-                .collect(),
-        )
+        let idx = marked.iter().position(|id| *id == self.id)?;
+        per_lane[idx].clone()
     }
 
     pub fn get_max_cost(&self, constraints: PathConstraints, map: &Map) -> usize {
@@ -251,3 +255,51 @@ impl Lane {
             })
     }
 }
+
+// Parses a turn:lanes value like "left|left;through|through;right" into the movements allowed for
+// each lane, from left to right. "none" lanes aren't restricted. None if some value isn't
+// understood.
+pub fn parse_turn_lanes(value: &str) -> Option<Vec<Option<BTreeSet<TurnType>>>> {
+    let mut result = Vec::new();
+    for part in value.split('|') {
+        if part == "none" {
+            result.push(None);
+            continue;
+        }
+        let mut types = BTreeSet::new();
+        for movement in part.split(';') {
+            match movement.trim() {
+                "left" | "sharp_left" => {
+                    types.insert(TurnType::Left);
+                }
+                "right" | "sharp_right" => {
+                    types.insert(TurnType::Right);
+                }
+                // TODO What is blank supposed to mean? From few observed cases, same as through
+                "through" | "" => {
+                    types.insert(TurnType::Straight);
+                    types.insert(TurnType::LaneChangeLeft);
+                    types.insert(TurnType::LaneChangeRight);
+                }
+                // TODO Check this more carefully
+                "slight_right" | "slight right" | "merge_to_right" => {
+                    types.insert(TurnType::Straight);
+                    types.insert(TurnType::LaneChangeRight);
+                    types.insert(TurnType::Right);
+                }
+                "slight_left" | "slight left" | "merge_to_left" => {
+                    types.insert(TurnType::Straight);
+                    types.insert(TurnType::LaneChangeLeft);
+                    types.insert(TurnType::Left);
+                }
+                // U-turns aren't modeled
+                "reverse" => {}
+                _ => {
+                    return None;
+                }
+            }
+        }
+        result.push(Some(types));
+    }
+    Some(result)
+}
@@ -3,6 +3,8 @@ mod building;
 mod bus_stop;
 pub mod connectivity;
 mod edits;
+pub mod export;
+mod green_wave;
 mod intersection;
 mod lane;
 mod make;
@@ -11,25 +13,32 @@ mod neighborhood;
 pub mod osm;
 mod pathfind;
 pub mod raw;
+mod reversible;
 mod road;
+mod speed_limits;
 mod stop_signs;
+mod time_windows;
 mod traffic_signals;
 mod traversable;
 mod turn;
 
 pub use crate::area::{Area, AreaID, AreaType};
-pub use crate::building::{Building, BuildingID, FrontPath, OffstreetParking};
-pub use crate::bus_stop::{BusRoute, BusRouteID, BusStop, BusStopID};
+pub use crate::building::{Building, BuildingID, FrontPath, OffstreetParking, ParkingPolicy};
+pub use crate::bus_stop::{BusRoute, BusRouteID, BusSchedule, BusStop, BusStopID};
 pub use crate::edits::{EditCmd, EditEffects, MapEdits};
+pub use crate::green_wave::{green_wave, WaveDirection};
 pub use crate::intersection::{Intersection, IntersectionID, IntersectionType};
-pub use crate::lane::{Lane, LaneID, LaneType, PARKING_SPOT_LENGTH};
-pub use crate::make::RoadSpec;
+pub use crate::lane::{parse_turn_lanes, Lane, LaneID, LaneType, PARKING_SPOT_LENGTH};
+pub use crate::make::{is_light_rail, RoadSpec};
 pub use crate::map::Map;
 pub use crate::neighborhood::{FullNeighborhoodInfo, Neighborhood, NeighborhoodBuilder};
-pub use crate::pathfind::{Path, PathConstraints, PathRequest, PathStep};
+pub use crate::pathfind::{Path, PathConstraints, PathRequest, PathStep, TravelTimes};
+pub use crate::reversible::ReversibleSchedule;
 pub use crate::road::{DirectedRoadID, Road, RoadID};
+pub use crate::speed_limits::SpeedLimitDefaults;
 pub use crate::stop_signs::{ControlStopSign, RoadWithStopSign};
-pub use crate::traffic_signals::{ControlTrafficSignal, Phase};
+pub use crate::time_windows::{parse_conditional, TimeWindows};
+pub use crate::traffic_signals::{ControlTrafficSignal, Phase, PhaseType};
 pub use crate::traversable::{Position, Traversable};
 pub use crate::turn::{Turn, TurnGroup, TurnGroupID, TurnID, TurnPriority, TurnType};
 use abstutil::Cloneable;
@@ -1,13 +1,23 @@
 use crate::make::sidewalk_finder::find_sidewalk_points;
+use crate::time_windows::SIMULATED_WEEKDAY;
 use crate::{
-    BusRoute, BusRouteID, BusStop, BusStopID, LaneID, LaneType, Map, PathConstraints, PathRequest,
-    Position,
+    BusRoute, BusRouteID, BusSchedule, BusStop, BusStopID, LaneID, LaneType, Map, PathConstraints,
+    PathRequest, Position,
 };
 use abstutil::{MultiMap, Timer};
-use geom::{Bounds, Distance, GPSBounds, HashablePt2D, Pt2D};
+use geom::{Bounds, Distance, Duration, FindClosest, GPSBounds, HashablePt2D, Pt2D};
 use gtfs;
 use std::collections::{BTreeMap, HashMap, HashSet};
 
+// A standard 40-foot bus. Note this is more than the longest car.
+const BUS_LENGTH: Distance = Distance::const_meters(12.5);
+// Seated and standing
+const BUS_CAPACITY: usize = 60;
+// A single streetcar or light rail vehicle. These must be shorter than any track lane they'll
+// appear on.
+const TRAIN_LENGTH: Distance = Distance::const_meters(20.0);
+const TRAIN_CAPACITY: usize = 140;
+
 pub fn make_bus_stops(
     map: &Map,
     bus_routes: &Vec<gtfs::Route>,
@@ -17,18 +27,27 @@ pub fn make_bus_stops(
 ) -> (BTreeMap<BusStopID, BusStop>, Vec<BusRoute>) {
     timer.start("make bus stops");
     let mut bus_stop_pts: HashSet<HashablePt2D> = HashSet::new();
-    let mut route_lookups: HashMap<String, Vec<HashablePt2D>> = HashMap::new();
+    // Stops served by trains need to be next to a track, not a driving lane.
+    let mut rail_stop_pts: HashSet<HashablePt2D> = HashSet::new();
+    // Per route, each stop along it, with the index into all of the route's stops
+    let mut route_lookups: Vec<Vec<(usize, HashablePt2D)>> = Vec::new();
     for route in bus_routes {
-        for gps in &route.stops {
-            if let Some(pt) = Pt2D::from_gps(*gps, gps_bounds) {
+        let mut lookup = Vec::new();
+        for (idx, stop) in main_patterns(route)
+            .into_iter()
+            .flat_map(|p| p.stops.iter())
+            .enumerate()
+        {
+            if let Some(pt) = Pt2D::from_gps(stop.pos, gps_bounds) {
                 let hash_pt = pt.to_hashable();
                 bus_stop_pts.insert(hash_pt);
-                route_lookups
-                    .entry(route.name.clone())
-                    .or_insert_with(Vec::new)
-                    .push(hash_pt);
+                if route_constraints(route) == PathConstraints::Train {
+                    rail_stop_pts.insert(hash_pt);
+                }
+                lookup.push((idx, hash_pt));
             }
         }
+        route_lookups.push(lookup);
     }
 
     let mut stops_per_sidewalk: MultiMap<LaneID, (Distance, HashablePt2D)> = MultiMap::new();
@@ -43,56 +62,108 @@ pub fn make_bus_stops(
     {
         stops_per_sidewalk.insert(pos.lane(), (pos.dist_along(), pt));
     }
+    let mut tracks: FindClosest<LaneID> = FindClosest::new(bounds);
+    for l in map.all_lanes() {
+        if l.is_light_rail() {
+            tracks.add(l.id, l.lane_center_pts.points());
+        }
+    }
     let mut point_to_stop_id: HashMap<HashablePt2D, BusStopID> = HashMap::new();
     let mut bus_stops: BTreeMap<BusStopID, BusStop> = BTreeMap::new();
 
     for (sidewalk_id, dists_set) in stops_per_sidewalk.consume().into_iter() {
         let road = map.get_parent(sidewalk_id);
-        if let Ok(driving_lane) =
-            road.find_closest_lane(sidewalk_id, vec![LaneType::Driving, LaneType::Bus])
-        {
-            let mut dists: Vec<(Distance, HashablePt2D)> = dists_set.into_iter().collect();
-            dists.sort_by_key(|(dist, _)| *dist);
-            for (idx, (dist_along, orig_pt)) in dists.into_iter().enumerate() {
-                let stop_id = BusStopID {
-                    sidewalk: sidewalk_id,
-                    idx,
-                };
-                point_to_stop_id.insert(orig_pt, stop_id);
-                let sidewalk_pos = Position::new(sidewalk_id, dist_along);
-                let driving_pos = sidewalk_pos.equiv_pos(driving_lane, Distance::ZERO, map);
-                bus_stops.insert(
-                    stop_id,
-                    BusStop {
-                        id: stop_id,
-                        sidewalk_pos,
-                        driving_pos,
-                    },
-                );
-            }
-        } else {
-            timer.warn(format!(
-                "Can't find driving lane next to {}: {:?} and {:?}",
-                sidewalk_id, road.children_forwards, road.children_backwards
-            ));
+        let driving_lane = road
+            .find_closest_lane(sidewalk_id, vec![LaneType::Driving, LaneType::Bus])
+            .ok();
+        let mut dists: Vec<(Distance, HashablePt2D)> = dists_set.into_iter().collect();
+        dists.sort_by_key(|(dist, _)| *dist);
+        for (idx, (dist_along, orig_pt)) in dists.into_iter().enumerate() {
+            let stop_id = BusStopID {
+                sidewalk: sidewalk_id,
+                idx,
+            };
+            let sidewalk_pos = Position::new(sidewalk_id, dist_along);
+            let driving_pos = if rail_stop_pts.contains(&orig_pt) {
+                // Tracks are usually their own road, so just use the nearest one. With a track in
+                // each direction, the platform is normally closest to the one going the right way.
+                if let Some(pos) = tracks
+                    .closest_pt(orig_pt.to_pt2d(), Distance::meters(30.0))
+                    .and_then(|(l, pt)| {
+                        map.get_l(l)
+                            .dist_along_of_point(pt)
+                            .map(|dist| Position::new(l, dist))
+                    })
+                {
+                    pos
+                } else {
+                    timer.warn(format!(
+                        "Can't find a track near the stop on {}",
+                        sidewalk_id
+                    ));
+                    continue;
+                }
+            } else if let Some(l) = driving_lane {
+                sidewalk_pos.equiv_pos(l, Distance::ZERO, map)
+            } else {
+                timer.warn(format!(
+                    "Can't find driving lane next to {}: {:?} and {:?}",
+                    sidewalk_id, road.children_forwards, road.children_backwards
+                ));
+                continue;
+            };
+            point_to_stop_id.insert(orig_pt, stop_id);
+            bus_stops.insert(
+                stop_id,
+                BusStop {
+                    id: stop_id,
+                    sidewalk_pos,
+                    driving_pos,
+                },
+            );
         }
     }
 
     let mut routes: Vec<BusRoute> = Vec::new();
-    for route in bus_routes {
-        let route_name = route.name.to_string();
-        let stops: Vec<BusStopID> = route_lookups
-            .remove(&route_name)
-            .unwrap_or_else(Vec::new)
-            .into_iter()
-            .filter_map(|pt| point_to_stop_id.get(&pt))
-            .cloned()
-            .collect();
+    for (route, lookup) in bus_routes.iter().zip(route_lookups.into_iter()) {
+        let main = main_patterns(route);
+        let mut keep = vec![false; main.iter().map(|p| p.stops.len()).sum()];
+        let mut stops = Vec::new();
+        for (idx, pt) in lookup {
+            if let Some(id) = point_to_stop_id.get(&pt) {
+                keep[idx] = true;
+                stops.push(*id);
+            }
+        }
+        let schedule = weekday_schedule(route, &main).and_then(|mut schedule| {
+            schedule.retain_stops(&keep);
+            if schedule.trips.is_empty() {
+                None
+            } else {
+                Some(schedule)
+            }
+        });
         let id = BusRouteID(routes.len());
         routes.push(BusRoute {
             id,
-            name: route_name.to_string(),
+            name: route.name.to_string(),
             stops,
+            schedule,
+            route_type: if route.is_rail() {
+                PathConstraints::Train
+            } else {
+                PathConstraints::Bus
+            },
+            vehicle_length: if route.is_rail() {
+                TRAIN_LENGTH
+            } else {
+                BUS_LENGTH
+            },
+            capacity: if route.is_rail() {
+                TRAIN_CAPACITY
+            } else {
+                BUS_CAPACITY
+            },
         });
     }
     timer.stop("make bus stops");
@@ -100,30 +171,47 @@ pub fn make_bus_stops(
 }
 
 pub fn fix_bus_route(map: &Map, r: &mut BusRoute) -> bool {
-    // Trim out stops if needed; map borders sometimes mean some paths don't work.
-    let mut stops = Vec::new();
-    for stop in r.stops.drain(..) {
-        if stops.is_empty() {
-            stops.push(stop);
-        } else {
-            if check_stops(*stops.last().unwrap(), stop, map) {
-                stops.push(stop);
-            }
+    // Trim out stops if needed; map borders sometimes mean some paths don't work. Keep the
+    // schedule lined up with the stops.
+    let mut keep = Vec::new();
+    let mut last_kept: Option<BusStopID> = None;
+    for stop in &r.stops {
+        let ok = last_kept
+            .map(|prev| check_stops(r, prev, *stop, map))
+            .unwrap_or(true);
+        keep.push(ok);
+        if ok {
+            last_kept = Some(*stop);
         }
     }
     // Don't forget the last and first
-    while stops.len() >= 2 {
-        if check_stops(*stops.last().unwrap(), stops[0], map) {
+    while keep.iter().filter(|x| **x).count() >= 2 {
+        let first = r.stops[keep.iter().position(|x| *x).unwrap()];
+        let last_idx = keep.iter().rposition(|x| *x).unwrap();
+        if check_stops(r, r.stops[last_idx], first, map) {
             break;
         }
         // TODO Or the front one
-        stops.pop();
+        keep[last_idx] = false;
+    }
+
+    r.stops = r
+        .stops
+        .iter()
+        .zip(keep.iter())
+        .filter(|(_, ok)| **ok)
+        .map(|(stop, _)| *stop)
+        .collect();
+    if let Some(mut schedule) = r.schedule.take() {
+        schedule.retain_stops(&keep);
+        if !schedule.trips.is_empty() {
+            r.schedule = Some(schedule);
+        }
     }
-    r.stops = stops;
     r.stops.len() >= 2
 }
 
-fn check_stops(stop1: BusStopID, stop2: BusStopID, map: &Map) -> bool {
+fn check_stops(r: &BusRoute, stop1: BusStopID, stop2: BusStopID, map: &Map) -> bool {
     let bs1 = map.get_bs(stop1);
     let bs2 = map.get_bs(stop2);
     // This is coming up because the dist_along's are in a bad order. But why should
@@ -133,8 +221,88 @@ fn check_stops(stop1: BusStopID, stop2: BusStopID, map: &Map) -> bool {
         .pathfind(PathRequest {
             start: bs1.driving_pos,
             end: bs2.driving_pos,
-            constraints: PathConstraints::Bus,
+            constraints: r.route_type,
         })
         .is_some();
     ok1 && ok2
 }
+
+// The simulation runs each route as one loop through its stops, so only the pattern with the most
+// trips in each direction decides what the stops are, forwards first. Trips following other
+// patterns, like short-turns, are matched onto these stops.
+fn main_patterns(route: &gtfs::Route) -> Vec<&gtfs::Pattern> {
+    let mut patterns = Vec::new();
+    for forwards in vec![true, false] {
+        if let Some(p) = route
+            .patterns
+            .iter()
+            .filter(|p| p.forwards == forwards)
+            .max_by_key(|p| p.trips.len())
+        {
+            patterns.push(p);
+        }
+    }
+    patterns
+}
+
+// Every trip in either direction running on the simulated weekday, lined up with the stops of the
+// main patterns. Without any calendars, every trip runs. None if nothing runs that day.
+fn weekday_schedule(route: &gtfs::Route, main: &Vec<&gtfs::Pattern>) -> Option<BusSchedule> {
+    let num_stops: usize = main.iter().map(|p| p.stops.len()).sum();
+    let mut trips = Vec::new();
+    let mut offset = 0;
+    for main_pattern in main {
+        for pattern in route
+            .patterns
+            .iter()
+            .filter(|p| p.forwards == main_pattern.forwards)
+        {
+            for trip in &pattern.trips {
+                if !route.calendars.is_empty() && !route.runs_on(trip, SIMULATED_WEEKDAY) {
+                    continue;
+                }
+                let mut times = vec![None; num_stops];
+                // Walk along the main pattern, skipping stops this trip doesn't serve. The bus
+                // leaves the first stop it serves at the departure time, and just arrives at the
+                // rest.
+                let mut next = 0;
+                for (stop, st) in pattern.stops.iter().zip(trip.stop_times.iter()) {
+                    if let Some(idx) = main_pattern.stops[next..]
+                        .iter()
+                        .position(|s| s.id == stop.id)
+                    {
+                        times[offset + next + idx] =
+                            Some(if next == 0 { st.departure } else { st.arrival });
+                        next += idx + 1;
+                    }
+                }
+                if interpolate(&mut times[offset..offset + main_pattern.stops.len()]) {
+                    trips.push(times);
+                }
+            }
+        }
+        offset += main_pattern.stops.len();
+    }
+    if trips.is_empty() {
+        return None;
+    }
+    trips.sort_by_key(|times| times.iter().find_map(|dt| *dt));
+    Some(BusSchedule { trips })
+}
+
+// Fill in the times of stops skipped between the first and last stop served, evenly spaced.
+// Returns false if fewer than two stops are served.
+fn interpolate(times: &mut [Option<Duration>]) -> bool {
+    let known: Vec<usize> = (0..times.len()).filter(|i| times[*i].is_some()).collect();
+    if known.len() < 2 {
+        return false;
+    }
+    for pair in known.windows(2) {
+        let (i1, i2) = (pair[0], pair[1]);
+        let (t1, t2) = (times[i1].unwrap(), times[i2].unwrap());
+        for i in (i1 + 1)..i2 {
+            times[i] = Some(t1 + (t2 - t1) * (((i - i1) as f64) / ((i2 - i1) as f64)));
+        }
+    }
+    true
+}
@@ -1,4 +1,4 @@
-use crate::{osm, LaneType};
+use crate::{osm, parse_conditional, LaneType, ReversibleSchedule, TimeWindows};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::{fmt, iter};
@@ -21,9 +21,25 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
         return (vec![LaneType::Sidewalk], Vec::new());
     }
 
-    // TODO Reversible roads should be handled differently?
+    // Reversible roads with a schedule get the same lanes in both directions, and only one side is
+    // open at a time. Without a schedule, just use the direction the way points. Contraflow roads
+    // get a center lane on both sides instead of a shared turn lane.
+    let schedule = ReversibleSchedule::parse(osm_tags);
+    let reversible = schedule
+        .as_ref()
+        .map(|s| !s.is_contraflow())
+        .unwrap_or(false);
+    let contraflow = schedule.map(|s| s.is_contraflow()).unwrap_or(false);
     let oneway = osm_tags.get("oneway") == Some(&"yes".to_string())
-        || osm_tags.get("oneway") == Some(&"reversible".to_string());
+        || (osm_tags.get("oneway") == Some(&"reversible".to_string()) && !reversible);
+
+    // One track in each direction, unless it's tagged oneway.
+    if is_light_rail(osm_tags) {
+        if oneway {
+            return (vec![LaneType::LightRail], Vec::new());
+        }
+        return (vec![LaneType::LightRail], vec![LaneType::LightRail]);
+    }
 
     // How many driving lanes in each direction?
     let num_driving_fwd = if let Some(n) = osm_tags
@@ -35,7 +51,7 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
         .get("lanes")
         .and_then(|num| num.parse::<usize>().ok())
     {
-        if oneway {
+        if oneway || reversible {
             n
         } else if n % 2 == 0 {
             n / 2
@@ -47,7 +63,9 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
         // TODO Grrr.
         1
     };
-    let num_driving_back = if let Some(n) = osm_tags
+    let num_driving_back = if reversible {
+        num_driving_fwd
+    } else if let Some(n) = osm_tags
         .get("lanes:backward")
         .and_then(|num| num.parse::<usize>().ok())
     {
@@ -80,14 +98,18 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
         .take(num_driving_back)
         .collect();
     // TODO Fix upstream. https://wiki.openstreetmap.org/wiki/Key:centre_turn_lane
-    if osm_tags.get("lanes:both_ways") == Some(&"1".to_string())
+    if contraflow {
+        fwd_side.insert(0, LaneType::Driving);
+        back_side.insert(0, LaneType::Driving);
+    } else if osm_tags.get("lanes:both_ways") == Some(&"1".to_string())
         || osm_tags.get("centre_turn_lane") == Some(&"yes".to_string())
     {
         fwd_side.insert(0, LaneType::SharedLeftTurn);
     }
 
     // TODO Handle bus lanes properly.
-    let has_bus_lane = osm_tags.contains_key("bus:lanes");
+    let has_bus_lane =
+        osm_tags.contains_key("bus:lanes") || part_time_bus_lane_hours(osm_tags).is_some();
     if has_bus_lane {
         fwd_side.pop();
         fwd_side.push(LaneType::Bus);
@@ -142,6 +164,27 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
     (fwd_side, back_side)
 }
 
+// Streetcar and light rail tracks, with no highway tag, become their own roads with only track
+// lanes.
+pub fn is_light_rail(osm_tags: &BTreeMap<String, String>) -> bool {
+    !osm_tags.contains_key(osm::HIGHWAY)
+        && (osm_tags.get("railway") == Some(&"tram".to_string())
+            || osm_tags.get("railway") == Some(&"light_rail".to_string()))
+}
+
+// Bus lanes only reserved at some times, like "bus:lanes:conditional=|designated @ (Mo-Fr
+// 07:00-09:00)". They're still bus lanes, but cars can use them outside these times. None for
+// bus lanes that apply all day.
+pub fn part_time_bus_lane_hours(osm_tags: &BTreeMap<String, String>) -> Option<TimeWindows> {
+    if osm_tags.contains_key("bus:lanes") {
+        return None;
+    }
+    parse_conditional(osm_tags.get("bus:lanes:conditional")?)
+        .into_iter()
+        .find(|(value, _)| value.split('|').any(|lane| lane == "designated"))
+        .map(|(_, windows)| windows)
+}
+
 // This is a convenient way for map_editor to plumb instructions here.
 #[derive(Serialize, Deserialize)]
 pub struct RoadSpec {
@@ -196,6 +239,7 @@ impl RoadSpec {
             LaneType::Bus => 'u',
             LaneType::SharedLeftTurn => 'l',
             LaneType::Construction => 'c',
+            LaneType::LightRail => 't',
         }
     }
 
@@ -208,6 +252,7 @@ impl RoadSpec {
             'u' => Some(LaneType::Bus),
             'l' => Some(LaneType::SharedLeftTurn),
             'c' => Some(LaneType::Construction),
+            't' => Some(LaneType::LightRail),
             _ => None,
         }
     }
@@ -3,7 +3,9 @@ pub mod lane_specs;
 
 pub use self::geometry::intersection_polygon;
 use crate::raw::{OriginalIntersection, OriginalRoad, RawMap, RawRoad};
-use crate::{IntersectionType, LaneType, NORMAL_LANE_THICKNESS, SIDEWALK_THICKNESS};
+use crate::{
+    IntersectionType, LaneType, ReversibleSchedule, NORMAL_LANE_THICKNESS, SIDEWALK_THICKNESS,
+};
 use abstutil::Timer;
 use geom::{Bounds, Distance, PolyLine, Pt2D};
 use std::collections::{BTreeMap, BTreeSet};
@@ -26,6 +28,9 @@ pub struct Road {
     pub fwd_width: Distance,
     pub back_width: Distance,
     pub lane_specs: Vec<LaneSpec>,
+    // Reversible lanes on both sides are really the same pavement, so each side of the road is
+    // this much narrower than its lanes add up to. See Road::reversible_overlap.
+    pub reversible_overlap: Distance,
 }
 
 impl Road {
@@ -33,18 +38,36 @@ impl Road {
         let lane_specs = get_lane_specs(&r.osm_tags);
         let mut fwd_width = Distance::ZERO;
         let mut back_width = Distance::ZERO;
-        for l in &lane_specs {
+        let mut fwd_reversible = Distance::ZERO;
+        let mut back_reversible = Distance::ZERO;
+        let schedule = ReversibleSchedule::parse(&r.osm_tags);
+        for (idx, l) in lane_specs.iter().enumerate() {
             let w = if l.lane_type == LaneType::Sidewalk {
                 SIDEWALK_THICKNESS
             } else {
                 NORMAL_LANE_THICKNESS
             };
+            // Same as Road::is_reversible. Contraflow lanes are the first on each side.
+            let reversible = match schedule {
+                Some(ref s) if s.is_contraflow() => {
+                    idx == 0 || (l.reverse_pts && !lane_specs[idx - 1].reverse_pts)
+                }
+                Some(_) => l.lane_type.is_for_moving_vehicles(),
+                None => false,
+            };
             if l.reverse_pts {
                 back_width += w;
+                if reversible {
+                    back_reversible += w;
+                }
             } else {
                 fwd_width += w;
+                if reversible {
+                    fwd_reversible += w;
+                }
             }
         }
+        let reversible_overlap = fwd_reversible.min(back_reversible) / 2.0;
 
         let center_pts = PolyLine::new(r.center_points.clone());
         Road {
@@ -53,9 +76,10 @@ impl Road {
             dst_i: id.i2,
             original_center_pts: center_pts.clone(),
             trimmed_center_pts: center_pts,
-            fwd_width,
-            back_width,
+            fwd_width: fwd_width - reversible_overlap,
+            back_width: back_width - reversible_overlap,
             lane_specs,
+            reversible_overlap,
         }
     }
 }
@@ -7,6 +7,8 @@ mod turns;
 
 pub use self::buildings::make_all_buildings;
 pub use self::bus_stops::{fix_bus_route, make_bus_stops};
-pub use self::initial::lane_specs::{get_lane_types, RoadSpec};
+pub use self::initial::lane_specs::{
+    get_lane_types, is_light_rail, part_time_bus_lane_hours, RoadSpec,
+};
 pub use self::remove_disconnected::remove_disconnected_roads;
 pub use self::turns::make_all_turns;
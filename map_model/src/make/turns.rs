@@ -220,23 +220,23 @@ fn make_vehicle_turns(
                         }
                         all_incoming_lanes_covered = true;
                     }
-                    TurnType::Right => {
+                    TurnType::Right | TurnType::Left => {
+                        // Without turn:lanes tags, only the rightmost lane can turn right and the
+                        // leftmost can turn left, unless no lanes go straight.
+                        let heuristic_idx = if tt == TurnType::Right {
+                            incoming.len() - 1
+                        } else {
+                            0
+                        };
                         for (idx, l1) in incoming.iter().enumerate() {
+                            let marked = lanes[l1.0].get_turn_restrictions(r1);
                             for l2 in &outgoing {
                                 let turn = make_vehicle_turn(lanes, i.id, *l1, *l2, tt);
-                                if idx == incoming.len() - 1 {
-                                    result.push(turn);
-                                } else {
-                                    maybe_add_turns.push(turn);
-                                }
-                            }
-                        }
-                    }
-                    TurnType::Left => {
-                        for (idx, l1) in incoming.iter().enumerate() {
-                            for l2 in &outgoing {
-                                let turn = make_vehicle_turn(lanes, i.id, *l1, *l2, tt);
-                                if idx == 0 {
+                                if let Some(ref types) = marked {
+                                    if types.contains(&tt) {
+                                        result.push(turn);
+                                    }
+                                } else if idx == heuristic_idx {
                                     result.push(turn);
                                 } else {
                                     maybe_add_turns.push(turn);
@@ -620,9 +620,10 @@ fn get_sidewalk<'a>(lanes: &'a Vec<Lane>, children: &Vec<(LaneID, LaneType)>) ->
     None
 }
 
-fn filter_vehicle_lanes(lanes: &Vec<(LaneID, LaneType)>, preferred: LaneType) -> Vec<LaneID> {
-    let preferred = filter_lanes(lanes, preferred);
-    if !preferred.is_empty() {
+fn filter_vehicle_lanes(lanes: &Vec<(LaneID, LaneType)>, preferred_lt: LaneType) -> Vec<LaneID> {
+    let preferred = filter_lanes(lanes, preferred_lt);
+    // Trains can't leave the tracks.
+    if !preferred.is_empty() || preferred_lt == LaneType::LightRail {
         return preferred;
     }
     filter_lanes(lanes, LaneType::Driving)
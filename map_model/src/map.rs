@@ -1,14 +1,14 @@
 use crate::pathfind::Pathfinder;
-use crate::raw::{OriginalIntersection, OriginalRoad, RawMap};
+use crate::raw::{OriginalIntersection, OriginalRoad, RawMap, RestrictionType};
 use crate::{
     connectivity, make, Area, AreaID, Building, BuildingID, BusRoute, BusRouteID, BusStop,
     BusStopID, ControlStopSign, ControlTrafficSignal, EditCmd, EditEffects, Intersection,
-    IntersectionID, IntersectionType, Lane, LaneID, LaneType, MapEdits, Path, PathConstraints,
-    PathRequest, Position, Road, RoadID, Turn, TurnGroupID, TurnID, TurnType,
-    NORMAL_LANE_THICKNESS, SIDEWALK_THICKNESS,
+    IntersectionID, IntersectionType, Lane, LaneID, LaneType, MapEdits, ParkingPolicy, Path,
+    PathConstraints, PathRequest, Position, ReversibleSchedule, Road, RoadID, TravelTimes, Turn,
+    TurnGroupID, TurnID, TurnType, NORMAL_LANE_THICKNESS, SIDEWALK_THICKNESS,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Error, Timer};
-use geom::{Bounds, Distance, GPSBounds, PolyLine, Polygon, Pt2D};
+use geom::{Bounds, Distance, GPSBounds, PolyLine, Polygon, Pt2D, Time};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
@@ -396,6 +396,103 @@ impl Map {
         turns
     }
 
+    // Turns banned by conditional turn restrictions still exist in the map, since the ban only
+    // applies at some times. Likewise for turns into a reversible road's closed side, or into a
+    // part-time bus lane while it's reserved.
+    pub fn is_turn_allowed_at(&self, t: TurnID, constraints: PathConstraints, time: Time) -> bool {
+        let src = self.get_l(t.src);
+        if src.is_sidewalk() {
+            return true;
+        }
+        if constraints == PathConstraints::Car {
+            if let Some(ref hours) = self.get_l(t.dst).bus_hours {
+                if hours.contains(time) {
+                    return false;
+                }
+            }
+        }
+        let dst = self.get_l(t.dst).parent;
+        let dst_road = self.get_r(dst);
+        if dst_road.is_reversible(t.dst) {
+            // Once inside a reversible corridor, cars can always finish going through it.
+            let continuing = src.parent != dst && self.get_r(src.parent).is_reversible(t.src);
+            if !continuing
+                && !dst_road
+                    .reversible
+                    .as_ref()
+                    .unwrap()
+                    .is_open(dst_road.is_forwards(t.dst), time)
+            {
+                return false;
+            }
+        }
+        let roads = &self.get_i(t.parent).roads;
+        for (restriction, to, windows) in &self.get_r(src.parent).conditional_turn_restrictions {
+            // The restriction only applies to one direction of the road.
+            if !roads.contains(to) || !windows.contains(time) {
+                continue;
+            }
+            match restriction {
+                RestrictionType::BanTurns => {
+                    if dst == *to {
+                        return false;
+                    }
+                }
+                RestrictionType::OnlyAllowTurns => {
+                    if dst != *to {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // The next time is_turn_allowed_at might change its answer for this turn. None if it never
+    // will.
+    pub fn next_turn_change(
+        &self,
+        t: TurnID,
+        constraints: PathConstraints,
+        time: Time,
+    ) -> Option<Time> {
+        let mut changes = Vec::new();
+        if constraints == PathConstraints::Car {
+            if let Some(ref hours) = self.get_l(t.dst).bus_hours {
+                changes.push(hours.next_change(time));
+            }
+        }
+        let dst_road = self.get_parent(t.dst);
+        if dst_road.is_reversible(t.dst) {
+            changes.push(dst_road.reversible.as_ref().unwrap().next_change(time));
+        }
+        let roads = &self.get_i(t.parent).roads;
+        for (_, to, windows) in &self.get_parent(t.src).conditional_turn_restrictions {
+            if roads.contains(to) {
+                changes.push(windows.next_change(time));
+            }
+        }
+        changes.into_iter().min()
+    }
+
+    // All of the reversible roads connected to this one, including itself. Empty if the road
+    // isn't reversible.
+    pub fn get_reversible_corridor(&self, r: RoadID) -> BTreeSet<RoadID> {
+        let mut corridor = BTreeSet::new();
+        let mut queue = vec![r];
+        while let Some(current) = queue.pop() {
+            let road = self.get_r(current);
+            if road.reversible.is_none() || corridor.contains(&current) {
+                continue;
+            }
+            corridor.insert(current);
+            for i in vec![road.src_i, road.dst_i] {
+                queue.extend(self.get_i(i).roads.iter().cloned());
+            }
+        }
+        corridor
+    }
+
     // These come back sorted
     pub fn get_next_roads(&self, from: RoadID) -> Vec<RoadID> {
         let mut roads: BTreeSet<RoadID> = BTreeSet::new();
@@ -597,6 +694,22 @@ impl Map {
         self.pathfinder.as_ref().unwrap().pathfind(req, self)
     }
 
+    // Cars departing at some time use observed travel times, if they've been set. Vehicles also
+    // respect conditional turn restrictions in effect at departure.
+    pub fn pathfind_at(&self, req: PathRequest, departure: Time) -> Option<Path> {
+        assert!(!self.pathfinder_dirty);
+        self.pathfinder
+            .as_ref()
+            .unwrap()
+            .pathfind_at(req, departure, self)
+    }
+
+    pub fn set_travel_times(&mut self, travel_times: Option<TravelTimes>, timer: &mut Timer) {
+        let mut pathfinder = self.pathfinder.take().unwrap();
+        pathfinder.set_travel_times(travel_times, self, timer);
+        self.pathfinder = Some(pathfinder);
+    }
+
     pub fn should_use_transit(
         &self,
         start: Position,
@@ -686,6 +799,13 @@ impl Map {
         for id in &effects.changed_roads {
             let stops = self.get_r(*id).all_bus_stops(self);
             for s in stops {
+                // Stops served by trains are next to a track on some other road.
+                if self
+                    .get_l(self.get_bs(s).driving_pos.lane())
+                    .is_light_rail()
+                {
+                    continue;
+                }
                 let sidewalk_pos = self.get_bs(s).sidewalk_pos;
                 // Must exist, because we aren't allowed to orphan a bus stop.
                 let driving_lane = self
@@ -818,7 +938,24 @@ fn make_half_map(
                     }
                 })
                 .collect(),
+            conditional_turn_restrictions: raw.roads[&r.id]
+                .conditional_turn_restrictions
+                .iter()
+                .filter_map(|(rt, to, windows)| {
+                    if let Some(t) = road_id_mapping.get(to) {
+                        Some((*rt, *t, windows.clone()))
+                    } else {
+                        timer.warn(format!(
+                            "Conditional turn restriction from {} points to invalid dst {}",
+                            r.id, to
+                        ));
+                        None
+                    }
+                })
+                .collect(),
             orig_id: r.id,
+            speed_limit: raw.speed_limits.get(&raw.roads[&r.id].osm_tags),
+            reversible: ReversibleSchedule::parse(&raw.roads[&r.id].osm_tags),
             children_forwards: Vec::new(),
             children_backwards: Vec::new(),
             center_pts: r.trimmed_center_pts.clone(),
@@ -826,6 +963,8 @@ fn make_half_map(
             dst_i: i2,
         };
 
+        let mut width_fwd = Distance::ZERO;
+        let mut width_back = Distance::ZERO;
         for lane in &r.lane_specs {
             let id = LaneID(map.lanes.len());
 
@@ -833,15 +972,6 @@ fn make_half_map(
             map.intersections[src_i.0].outgoing_lanes.push(id);
             map.intersections[dst_i.0].incoming_lanes.push(id);
 
-            let (unshifted_pts, other_lanes_width): (PolyLine, Distance) = if lane.reverse_pts {
-                let w = road.width_left(&map);
-                road.children_backwards.push((id, lane.lane_type));
-                (road.center_pts.reversed(), w)
-            } else {
-                let w = road.width_right(&map);
-                road.children_forwards.push((id, lane.lane_type));
-                (road.center_pts.clone(), w)
-            };
             // TODO probably different behavior for oneways
             // TODO need to factor in yellow center lines (but what's the right thing to even do?
             // Reverse points for British-style driving on the left
@@ -850,8 +980,21 @@ fn make_half_map(
             } else {
                 NORMAL_LANE_THICKNESS
             };
+            // Road::width_left and width_right only make sense once every lane is there
+            let (unshifted_pts, other_lanes_width): (PolyLine, Distance) = if lane.reverse_pts {
+                let w = width_back;
+                width_back += width;
+                road.children_backwards.push((id, lane.lane_type));
+                (road.center_pts.reversed(), w)
+            } else {
+                let w = width_fwd;
+                width_fwd += width;
+                road.children_forwards.push((id, lane.lane_type));
+                (road.center_pts.clone(), w)
+            };
+            // Reversible lanes on both sides overlap
             let lane_center_pts = unshifted_pts
-                .shift_right(other_lanes_width + width / 2.0)
+                .shift_right(other_lanes_width + width / 2.0 - r.reversible_overlap)
                 .with_context(timer, format!("shift for {}", id));
 
             map.lanes.push(Lane {
@@ -865,6 +1008,30 @@ fn make_half_map(
                 building_paths: Vec::new(),
                 bus_stops: Vec::new(),
                 parking_blackhole: None,
+                parking_policy: if lane.lane_type == LaneType::Parking {
+                    // Forwards is the right side of the road
+                    let (side, lane_side) = if lane.reverse_pts {
+                        ("parking:condition:left", "parking:lane:left:conditional")
+                    } else {
+                        ("parking:condition:right", "parking:lane:right:conditional")
+                    };
+                    if road
+                        .osm_tags
+                        .keys()
+                        .any(|k| k.starts_with(side) || k == lane_side)
+                    {
+                        ParkingPolicy::from_osm_tags(&road.osm_tags, side)
+                    } else {
+                        ParkingPolicy::from_osm_tags(&road.osm_tags, "parking:condition:both")
+                    }
+                } else {
+                    ParkingPolicy::default()
+                },
+                bus_hours: if lane.lane_type == LaneType::Bus {
+                    make::part_time_bus_lane_hours(&road.osm_tags)
+                } else {
+                    None
+                },
             });
         }
         if road.get_name() == "???" {
@@ -1103,6 +1270,26 @@ impl EditCmd {
                 effects.changed_intersections.insert(id);
                 true
             }
+            EditCmd::ChangeLaneParkingPolicy { id, policy, .. } => {
+                let lane = &mut map.lanes[id.0];
+                if &lane.parking_policy == policy {
+                    return false;
+                }
+                lane.parking_policy = policy.clone();
+                effects.changed_lanes.insert(*id);
+                true
+            }
+            EditCmd::ChangeBldgParkingPolicy { id, policy, .. } => {
+                let p = map.buildings[id.0]
+                    .parking
+                    .as_mut()
+                    .expect("Can't change parking policy of a building without parking");
+                if &p.policy == policy {
+                    return false;
+                }
+                p.policy = policy.clone();
+                true
+            }
         }
     }
 
@@ -1143,6 +1330,26 @@ impl EditCmd {
                 orig_it: *orig_it,
             }
             .apply(effects, map, timer),
+            EditCmd::ChangeLaneParkingPolicy {
+                id,
+                policy,
+                orig_policy,
+            } => EditCmd::ChangeLaneParkingPolicy {
+                id: *id,
+                policy: orig_policy.clone(),
+                orig_policy: policy.clone(),
+            }
+            .apply(effects, map, timer),
+            EditCmd::ChangeBldgParkingPolicy {
+                id,
+                policy,
+                orig_policy,
+            } => EditCmd::ChangeBldgParkingPolicy {
+                id: *id,
+                policy: orig_policy.clone(),
+                orig_policy: policy.clone(),
+            }
+            .apply(effects, map, timer),
         }
     }
 }
@@ -1,8 +1,13 @@
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
 use crate::{Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Turn, TurnID};
 use fast_paths::{FastGraph, InputGraph, PathCalculator};
+use geom::{Duration, Time};
+use petgraph::graphmap::DiGraphMap;
+use petgraph::visit::EdgeFiltered;
 use serde_derive::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 use thread_local::ThreadLocal;
 
 #[derive(Serialize, Deserialize)]
@@ -14,13 +19,92 @@ pub struct VehiclePathfinder {
 
     #[serde(skip_serializing, skip_deserializing)]
     path_calc: ThreadLocal<RefCell<PathCalculator>>,
+    // Every turn, for the rare searches that have to skip some of them. Built the first time it's
+    // needed, and thrown away when the map is edited.
+    #[serde(skip_serializing, skip_deserializing)]
+    all_turns: Mutex<Option<Arc<DiGraphMap<LaneID, usize>>>>,
+}
+
+// Observed travel times for cars, bucketed by time of day, usually from a previous simulation.
+// For every lane, this is the time from entering the lane to entering the next one, so it includes
+// the turn and any waiting at the intersection. Lanes without an observation in some bucket fall
+// back to free-flow costs.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TravelTimes {
+    bucket_size: Duration,
+    // The first bucket starts at midnight.
+    buckets: Vec<BTreeMap<LaneID, Duration>>,
+}
+
+impl TravelTimes {
+    pub fn new(bucket_size: Duration) -> TravelTimes {
+        assert!(bucket_size > Duration::ZERO);
+        TravelTimes {
+            bucket_size,
+            buckets: Vec::new(),
+        }
+    }
+
+    pub fn set(&mut self, time: Time, lane: LaneID, dt: Duration) {
+        let idx = self.bucket(time);
+        while self.buckets.len() <= idx {
+            self.buckets.push(BTreeMap::new());
+        }
+        self.buckets[idx].insert(lane, dt);
+    }
+
+    pub fn get(&self, time: Time, lane: LaneID) -> Option<Duration> {
+        self.buckets.get(self.bucket(time))?.get(&lane).cloned()
+    }
+
+    // What a car leaving at departure would pay to follow these steps, measured the same way as
+    // the time-dependent graphs: the whole trip uses the departure's bucket, and unobserved lanes
+    // cost their free-flow time.
+    pub fn path_cost<'a, I: IntoIterator<Item = &'a PathStep>>(
+        &self,
+        steps: I,
+        departure: Time,
+        map: &Map,
+    ) -> Duration {
+        let mut total = 0;
+        for step in steps {
+            if let PathStep::Turn(t) = step {
+                total += if let Some(dt) = self.get(departure, t.src) {
+                    observed_weight(dt)
+                } else {
+                    cost(map.get_l(t.src), map.get_t(*t), PathConstraints::Car, map)
+                };
+            }
+        }
+        Duration::seconds(total as f64)
+    }
+
+    pub fn bucket_size(&self) -> Duration {
+        self.bucket_size
+    }
+
+    pub fn num_buckets(&self) -> usize {
+        self.buckets.len()
+    }
+
+    // The observed time for every lane, one map per bucket
+    pub(crate) fn buckets(&self) -> &Vec<BTreeMap<LaneID, Duration>> {
+        &self.buckets
+    }
+
+    // Times past the last bucket use the last bucket.
+    pub fn bucket(&self, time: Time) -> usize {
+        ((time - Time::START_OF_DAY) / self.bucket_size).floor() as usize
+    }
 }
 
 impl VehiclePathfinder {
+    // If overrides are given, they replace the normal cost of those lanes (and the turn after).
     pub fn new(
         map: &Map,
         constraints: PathConstraints,
         seed: Option<&VehiclePathfinder>,
+        overrides: Option<&BTreeMap<LaneID, Duration>>,
     ) -> VehiclePathfinder {
         // Insert every lane as a node. Even if the lane type is wrong now, it might change later,
         // and we want the node in the graph. Do this first, so the IDs of all the nodes doesn't
@@ -29,7 +113,7 @@ impl VehiclePathfinder {
         for l in map.all_lanes() {
             nodes.get_or_insert(l.id);
         }
-        let input_graph = make_input_graph(map, &nodes, constraints);
+        let input_graph = make_input_graph(map, &nodes, constraints, overrides);
 
         // All VehiclePathfinders have the same nodes (lanes), so if we're not the first being
         // built, seed from the node ordering.
@@ -45,6 +129,7 @@ impl VehiclePathfinder {
             nodes,
             constraints,
             path_calc: ThreadLocal::new(),
+            all_turns: Mutex::new(None),
         }
     }
 
@@ -76,21 +161,92 @@ impl VehiclePathfinder {
         ))
     }
 
-    pub fn apply_edits(&mut self, map: &Map) {
+    pub fn apply_edits(&mut self, map: &Map, overrides: Option<&BTreeMap<LaneID, Duration>>) {
         // The NodeMap is just all lanes -- it won't change. So we can also reuse the node
         // ordering.
         // TODO Make sure the result of this is deterministic and equivalent to computing from
         // scratch.
-        let input_graph = make_input_graph(map, &self.nodes, self.constraints);
+        let input_graph = make_input_graph(map, &self.nodes, self.constraints, overrides);
         let node_ordering = self.graph.get_node_ordering();
         self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
+        self.all_turns = Mutex::new(None);
     }
+
+    // Plain Dijkstra, skipping turns that aren't allowed. Much slower than pathfind, but works
+    // when the allowed turns change often, like with the time of day. The overrides must be the
+    // same ones this pathfinder was built with.
+    pub fn pathfind_avoiding_turns<F: Fn(TurnID) -> bool>(
+        &self,
+        req: &PathRequest,
+        map: &Map,
+        overrides: Option<&BTreeMap<LaneID, Duration>>,
+        allowed: F,
+    ) -> Option<Path> {
+        let graph = self
+            .all_turns
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| Arc::new(make_turn_graph(map, self.constraints, overrides)))
+            .clone();
+        if !graph.contains_node(req.start.lane()) {
+            return None;
+        }
+        let filtered = EdgeFiltered::from_fn(&*graph, |(src, dst, _): (LaneID, LaneID, &usize)| {
+            allowed(TurnID {
+                parent: map.get_l(src).dst_i,
+                src,
+                dst,
+            })
+        });
+        let (_, lanes) = petgraph::algo::astar(
+            &filtered,
+            req.start.lane(),
+            |l| l == req.end.lane(),
+            |(_, _, cost)| *cost,
+            |_| 0,
+        )?;
+
+        let mut steps = Vec::new();
+        for pair in lanes.windows(2) {
+            steps.push(PathStep::Lane(pair[0]));
+            steps.push(PathStep::Turn(TurnID {
+                parent: map.get_l(pair[0]).dst_i,
+                src: pair[0],
+                dst: pair[1],
+            }));
+        }
+        steps.push(PathStep::Lane(req.end.lane()));
+        Some(Path::new(map, steps, req.end.dist_along()))
+    }
+}
+
+fn make_turn_graph(
+    map: &Map,
+    constraints: PathConstraints,
+    overrides: Option<&BTreeMap<LaneID, Duration>>,
+) -> DiGraphMap<LaneID, usize> {
+    let mut graph = DiGraphMap::new();
+    for l in map.all_lanes() {
+        if !constraints.can_use(l, map) {
+            continue;
+        }
+        let observed = overrides.and_then(|o| o.get(&l.id));
+        for turn in map.get_turns_for(l.id, constraints) {
+            graph.add_edge(
+                l.id,
+                turn.id.dst,
+                weight(l, turn, constraints, observed, map),
+            );
+        }
+    }
+    graph
 }
 
 fn make_input_graph(
     map: &Map,
     nodes: &NodeMap<LaneID>,
     constraints: PathConstraints,
+    overrides: Option<&BTreeMap<LaneID, Duration>>,
 ) -> InputGraph {
     let mut input_graph = InputGraph::new();
     let num_lanes = map.all_lanes().len();
@@ -98,12 +254,13 @@ fn make_input_graph(
         let from = nodes.get(l.id);
         let mut any = false;
         if constraints.can_use(l, map) {
+            let observed = overrides.and_then(|o| o.get(&l.id));
             for turn in map.get_turns_for(l.id, constraints) {
                 any = true;
                 input_graph.add_edge(
                     from,
                     nodes.get(turn.id.dst),
-                    cost(l, turn, constraints, map),
+                    weight(l, turn, constraints, observed, map),
                 );
             }
         }
@@ -120,6 +277,33 @@ fn make_input_graph(
     input_graph
 }
 
+fn weight(
+    lane: &Lane,
+    turn: &Turn,
+    constraints: PathConstraints,
+    observed: Option<&Duration>,
+    map: &Map,
+) -> usize {
+    if let Some(dt) = observed {
+        observed_weight(*dt)
+    } else {
+        cost(lane, turn, constraints, map)
+    }
+}
+
+fn observed_weight(dt: Duration) -> usize {
+    // Same units as cost() for cars. Zero-weight edges confuse fast_paths.
+    (dt.inner_seconds().round() as usize).max(1)
+}
+
+// True if both sets of observed times would build exactly the same graph
+pub(crate) fn same_costs(a: &BTreeMap<LaneID, Duration>, b: &BTreeMap<LaneID, Duration>) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b.iter()).all(|((l1, dt1), (l2, dt2))| {
+            l1 == l2 && observed_weight(*dt1) == observed_weight(*dt2)
+        })
+}
+
 pub fn cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map: &Map) -> usize {
     // TODO Could cost turns differently.
 
@@ -163,6 +347,11 @@ pub fn cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map: &Map) -
             };
             (lt_penalty * (t1 + t2)).inner_seconds().round() as usize
         }
+        PathConstraints::Train => {
+            let t1 = lane.length() / map.get_r(lane.parent).get_speed_limit();
+            let t2 = turn.geom.length() / map.get_parent(turn.id.dst).get_speed_limit();
+            (t1 + t2).inner_seconds().round() as usize
+        }
         PathConstraints::Pedestrian => unreachable!(),
     }
 }
@@ -2,14 +2,14 @@ mod driving;
 mod node_map;
 mod walking;
 
-pub use self::driving::cost;
-use self::driving::VehiclePathfinder;
+pub use self::driving::{cost, TravelTimes};
+use self::driving::{same_costs, VehiclePathfinder};
 use self::walking::SidewalkPathfinder;
 use crate::{
     osm, BusRouteID, BusStopID, Lane, LaneID, LaneType, Map, Position, Traversable, TurnID,
 };
 use abstutil::Timer;
-use geom::{Distance, PolyLine};
+use geom::{Distance, PolyLine, Time};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::fmt;
@@ -116,6 +116,22 @@ impl Path {
         }
     }
 
+    // Follow steps from an earlier path again, as long as they still match the request and every
+    // turn still exists.
+    pub fn reuse(req: &PathRequest, steps: Vec<PathStep>, map: &Map) -> Option<Path> {
+        if steps.first() != Some(&PathStep::Lane(req.start.lane()))
+            || steps.last() != Some(&PathStep::Lane(req.end.lane()))
+        {
+            return None;
+        }
+        for step in &steps {
+            if let PathStep::Turn(t) = step {
+                map.maybe_get_t(*t)?;
+            }
+        }
+        Some(Path::new(map, steps, req.end.dist_along()))
+    }
+
     // Only used for weird serialization magic.
     pub fn dummy() -> Path {
         Path {
@@ -187,6 +203,23 @@ impl Path {
         }
     }
 
+    // Keep the current step, then follow new_steps (which must connect to it) instead of the rest
+    // of the original path. Progress along the original path is kept.
+    pub fn reroute(&mut self, new_steps: Vec<PathStep>, end_dist: Distance, map: &Map) {
+        let current = self.steps.pop_front().unwrap();
+        self.steps.clear();
+        self.total_length = self.crossed_so_far;
+        self.add(current, map);
+        for step in new_steps {
+            self.add(step, map);
+        }
+        self.end_dist = end_dist;
+    }
+
+    pub fn end_dist(&self) -> Distance {
+        self.end_dist
+    }
+
     pub fn current_step(&self) -> PathStep {
         self.steps[0]
     }
@@ -306,6 +339,7 @@ pub enum PathConstraints {
     Car,
     Bike,
     Bus,
+    Train,
 }
 
 impl PathConstraints {
@@ -316,6 +350,7 @@ impl PathConstraints {
             LaneType::Driving => PathConstraints::Car,
             LaneType::Biking => PathConstraints::Bike,
             LaneType::Bus => PathConstraints::Bus,
+            LaneType::LightRail => PathConstraints::Train,
             _ => panic!("PathConstraints::from_lt({:?}) doesn't make sense", lt),
         }
     }
@@ -323,7 +358,8 @@ impl PathConstraints {
     pub fn can_use(self, l: &Lane, map: &Map) -> bool {
         match self {
             PathConstraints::Pedestrian => l.is_sidewalk(),
-            PathConstraints::Car => l.is_driving(),
+            // Part-time bus lanes are only open at some times; see Map::is_turn_allowed_at.
+            PathConstraints::Car => l.is_driving() || (l.is_bus() && l.bus_hours.is_some()),
             PathConstraints::Bike => {
                 if l.is_biking() {
                     true
@@ -338,6 +374,7 @@ impl PathConstraints {
                 }
             }
             PathConstraints::Bus => l.is_driving() || l.is_bus(),
+            PathConstraints::Train => l.is_light_rail(),
         }
     }
 }
@@ -411,42 +448,63 @@ pub struct Pathfinder {
     car_graph: VehiclePathfinder,
     bike_graph: VehiclePathfinder,
     bus_graph: VehiclePathfinder,
+    train_graph: VehiclePathfinder,
     walking_graph: SidewalkPathfinder,
     // TODO Option just during initialization! Ewww.
     walking_with_transit_graph: Option<SidewalkPathfinder>,
+
+    // When set, cars use one graph per time-of-day bucket instead of car_graph. These aren't saved
+    // with the map.
+    #[serde(skip_serializing, skip_deserializing)]
+    travel_times: Option<TravelTimes>,
+    #[serde(skip_serializing, skip_deserializing)]
+    car_graphs_by_time: Vec<VehiclePathfinder>,
 }
 
 impl Pathfinder {
     pub fn new_without_transit(map: &Map, timer: &mut Timer) -> Pathfinder {
         timer.start("prepare pathfinding for cars");
-        let car_graph = VehiclePathfinder::new(map, PathConstraints::Car, None);
+        let car_graph = VehiclePathfinder::new(map, PathConstraints::Car, None, None);
         timer.stop("prepare pathfinding for cars");
 
         // The edge weights for bikes are so different from the driving graph that reusing the node
         // ordering actually hurts!
         timer.start("prepare pathfinding for bikes");
-        let bike_graph = VehiclePathfinder::new(map, PathConstraints::Bike, None);
+        let bike_graph = VehiclePathfinder::new(map, PathConstraints::Bike, None, None);
         timer.stop("prepare pathfinding for bikes");
 
         timer.start("prepare pathfinding for buses");
-        let bus_graph = VehiclePathfinder::new(map, PathConstraints::Bus, Some(&car_graph));
+        let bus_graph = VehiclePathfinder::new(map, PathConstraints::Bus, Some(&car_graph), None);
         timer.stop("prepare pathfinding for buses");
 
+        // Most maps have no tracks at all, so this graph is usually trivial.
+        timer.start("prepare pathfinding for trains");
+        let train_graph = VehiclePathfinder::new(map, PathConstraints::Train, None, None);
+        timer.stop("prepare pathfinding for trains");
+
         timer.start("prepare pathfinding for pedestrians");
-        let walking_graph = SidewalkPathfinder::new(map, false, &bus_graph);
+        let walking_graph = SidewalkPathfinder::new(map, false, &bus_graph, &train_graph);
         timer.stop("prepare pathfinding for pedestrians");
 
         Pathfinder {
             car_graph,
             bike_graph,
             bus_graph,
+            train_graph,
             walking_graph,
             walking_with_transit_graph: None,
+            travel_times: None,
+            car_graphs_by_time: Vec::new(),
         }
     }
 
     pub fn setup_walking_with_transit(&mut self, map: &Map) {
-        self.walking_with_transit_graph = Some(SidewalkPathfinder::new(map, true, &self.bus_graph));
+        self.walking_with_transit_graph = Some(SidewalkPathfinder::new(
+            map,
+            true,
+            &self.bus_graph,
+            &self.train_graph,
+        ));
     }
 
     pub fn pathfind(&self, req: PathRequest, map: &Map) -> Option<Path> {
@@ -455,7 +513,90 @@ impl Pathfinder {
             PathConstraints::Car => self.car_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
+            PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
+        }
+    }
+
+    // Like pathfind, but cars use the observed travel times for the bucket containing departure,
+    // and vehicles avoid turns banned at departure by conditional turn restrictions or leading into
+    // the closed side of a reversible road.
+    pub fn pathfind_at(&self, req: PathRequest, departure: Time, map: &Map) -> Option<Path> {
+        let (graph, overrides) = match req.constraints {
+            PathConstraints::Pedestrian => {
+                return self.pathfind(req, map);
+            }
+            PathConstraints::Car => match self.travel_times {
+                Some(ref tt) if !self.car_graphs_by_time.is_empty() => {
+                    let idx = tt.bucket(departure).min(self.car_graphs_by_time.len() - 1);
+                    (&self.car_graphs_by_time[idx], Some(&tt.buckets()[idx]))
+                }
+                _ => (&self.car_graph, None),
+            },
+            PathConstraints::Bike => (&self.bike_graph, None),
+            PathConstraints::Bus => (&self.bus_graph, None),
+            PathConstraints::Train => (&self.train_graph, None),
+        };
+        let path = graph.pathfind(&req, map).map(|(p, _)| p)?;
+
+        // The graphs don't know about conditional turn restrictions, reversible roads, or
+        // part-time bus lanes. They're rare, so only fall back to the much slower search when the
+        // usual path hits one.
+        let allowed = |t: TurnID| map.is_turn_allowed_at(t, req.constraints, departure);
+        if path.get_steps().iter().any(|step| match step {
+            PathStep::Turn(t) => !allowed(*t),
+            _ => false,
+        }) {
+            return graph.pathfind_avoiding_turns(&req, map, overrides, allowed);
+        }
+        Some(path)
+    }
+
+    // Pass None to go back to free-flow costs. Buckets with the same costs as before keep their
+    // graph, since preparing each one is slow.
+    pub fn set_travel_times(
+        &mut self,
+        travel_times: Option<TravelTimes>,
+        map: &Map,
+        timer: &mut Timer,
+    ) {
+        let old_times = self.travel_times.take();
+        let mut old_graphs: Vec<Option<VehiclePathfinder>> =
+            self.car_graphs_by_time.drain(..).map(Some).collect();
+        if let Some(ref tt) = travel_times {
+            let mut rebuilt = 0;
+            timer.start_iter("prepare time-dependent car pathfinding", tt.num_buckets());
+            for (idx, bucket) in tt.buckets().iter().enumerate() {
+                timer.next();
+                let reuse = match (&old_times, old_graphs.get_mut(idx)) {
+                    (Some(old), Some(graph))
+                        if old
+                            .buckets()
+                            .get(idx)
+                            .map(|b| same_costs(b, bucket))
+                            .unwrap_or(false) =>
+                    {
+                        graph.take()
+                    }
+                    _ => None,
+                };
+                let graph = reuse.unwrap_or_else(|| {
+                    rebuilt += 1;
+                    VehiclePathfinder::new(
+                        map,
+                        PathConstraints::Car,
+                        Some(&self.car_graph),
+                        Some(bucket),
+                    )
+                });
+                self.car_graphs_by_time.push(graph);
+            }
+            timer.note(format!(
+                "Rebuilt {} of {} time-dependent car graphs",
+                rebuilt,
+                tt.num_buckets()
+            ));
         }
+        self.travel_times = travel_times;
     }
 
     pub fn should_use_transit(
@@ -472,26 +613,36 @@ impl Pathfinder {
 
     pub fn apply_edits(&mut self, map: &Map, timer: &mut Timer) {
         timer.start("apply edits to car pathfinding");
-        self.car_graph.apply_edits(map);
+        self.car_graph.apply_edits(map, None);
+        if let Some(ref tt) = self.travel_times {
+            for (graph, bucket) in self.car_graphs_by_time.iter_mut().zip(tt.buckets()) {
+                graph.apply_edits(map, Some(bucket));
+            }
+        }
         timer.stop("apply edits to car pathfinding");
 
         timer.start("apply edits to bike pathfinding");
-        self.bike_graph.apply_edits(map);
+        self.bike_graph.apply_edits(map, None);
         timer.stop("apply edits to bike pathfinding");
 
         timer.start("apply edits to bus pathfinding");
-        self.bus_graph.apply_edits(map);
+        self.bus_graph.apply_edits(map, None);
         timer.stop("apply edits to bus pathfinding");
 
+        timer.start("apply edits to train pathfinding");
+        self.train_graph.apply_edits(map, None);
+        timer.stop("apply edits to train pathfinding");
+
         timer.start("apply edits to pedestrian pathfinding");
-        self.walking_graph.apply_edits(map, &self.bus_graph);
+        self.walking_graph
+            .apply_edits(map, &self.bus_graph, &self.train_graph);
         timer.stop("apply edits to pedestrian pathfinding");
 
         timer.start("apply edits to pedestrian using transit pathfinding");
         self.walking_with_transit_graph
             .as_mut()
             .unwrap()
-            .apply_edits(map, &self.bus_graph);
+            .apply_edits(map, &self.bus_graph, &self.train_graph);
         timer.stop("apply edits to pedestrian using transit pathfinding");
     }
 }
@@ -28,7 +28,12 @@ enum Node {
 }
 
 impl SidewalkPathfinder {
-    pub fn new(map: &Map, use_transit: bool, bus_graph: &VehiclePathfinder) -> SidewalkPathfinder {
+    pub fn new(
+        map: &Map,
+        use_transit: bool,
+        bus_graph: &VehiclePathfinder,
+        train_graph: &VehiclePathfinder,
+    ) -> SidewalkPathfinder {
         let mut nodes = NodeMap::new();
         // We're assuming that to start with, no sidewalks are closed for construction!
         for l in map.all_lanes() {
@@ -44,7 +49,13 @@ impl SidewalkPathfinder {
             }
         }
 
-        let graph = fast_paths::prepare(&make_input_graph(map, &nodes, use_transit, bus_graph));
+        let graph = fast_paths::prepare(&make_input_graph(
+            map,
+            &nodes,
+            use_transit,
+            bus_graph,
+            train_graph,
+        ));
         SidewalkPathfinder {
             graph,
             nodes,
@@ -53,10 +64,16 @@ impl SidewalkPathfinder {
         }
     }
 
-    pub fn apply_edits(&mut self, map: &Map, bus_graph: &VehiclePathfinder) {
+    pub fn apply_edits(
+        &mut self,
+        map: &Map,
+        bus_graph: &VehiclePathfinder,
+        train_graph: &VehiclePathfinder,
+    ) {
         // The NodeMap is all sidewalks and bus stops -- it won't change. So we can also reuse the
         // node ordering.
-        let input_graph = make_input_graph(map, &self.nodes, self.use_transit, bus_graph);
+        let input_graph =
+            make_input_graph(map, &self.nodes, self.use_transit, bus_graph, train_graph);
         let node_ordering = self.graph.get_node_ordering();
         self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
     }
@@ -200,6 +217,7 @@ fn make_input_graph(
     nodes: &NodeMap<Node>,
     use_transit: bool,
     bus_graph: &VehiclePathfinder,
+    train_graph: &VehiclePathfinder,
 ) -> InputGraph {
     let mut input_graph = InputGraph::new();
 
@@ -244,6 +262,11 @@ fn make_input_graph(
         // Connect each adjacent stop along a route, with the cost based on how long it'll take a
         // bus to drive between the stops. Optimistically assume no waiting time at a stop.
         for route in map.get_all_bus_routes() {
+            let graph = if route.route_type == PathConstraints::Train {
+                train_graph
+            } else {
+                bus_graph
+            };
             for (stop1, stop2) in
                 route
                     .stops
@@ -254,12 +277,12 @@ fn make_input_graph(
                         &route.stops[0],
                     )))
             {
-                let driving_cost = bus_graph
+                let driving_cost = graph
                     .pathfind(
                         &PathRequest {
                             start: map.get_bs(*stop1).driving_pos,
                             end: map.get_bs(*stop2).driving_pos,
-                            constraints: PathConstraints::Bus,
+                            constraints: route.route_type,
                         },
                         map,
                     )
@@ -1,5 +1,7 @@
 use crate::make::get_lane_types;
-use crate::{osm, AreaType, IntersectionType, OffstreetParking, RoadSpec};
+use crate::{
+    osm, AreaType, IntersectionType, OffstreetParking, RoadSpec, SpeedLimitDefaults, TimeWindows,
+};
 use abstutil::{deserialize_btreemap, retain_btreemap, serialize_btreemap, Error, Timer};
 use geom::{GPSBounds, Polygon, Pt2D};
 use gtfs::Route;
@@ -30,6 +32,9 @@ pub struct RawMap {
 
     pub boundary_polygon: Polygon,
     pub gps_bounds: GPSBounds,
+    // For roads without a usable maxspeed tag
+    #[serde(default)]
+    pub speed_limits: SpeedLimitDefaults,
 }
 
 // A way to refer to roads across many maps.
@@ -95,6 +100,7 @@ impl RawMap {
             // Some nonsense thing
             boundary_polygon: Polygon::rectangle(1.0, 1.0),
             gps_bounds: GPSBounds::new(),
+            speed_limits: SpeedLimitDefaults::for_region("us").unwrap(),
         }
     }
 
@@ -357,6 +363,7 @@ impl RawMap {
             );
             self.delete_turn_restriction(*tr);
         }
+        self.delete_conditional_turn_restrictions_involving(r);
         self.roads.remove(&r).unwrap();
         restrictions
     }
@@ -408,6 +415,7 @@ impl RawMap {
             .chain(self.roads_per_intersection(short.i2))
         {
             orig_restrictions.extend(self.turn_restrictions_involving(r));
+            self.delete_conditional_turn_restrictions_involving(r);
         }
         // Clear out these restrictions first
         for tr in &orig_restrictions {
@@ -490,6 +498,23 @@ impl RawMap {
             .retain(|(rt, to)| tr.1 != *rt || tr.2 != *to);
     }
 
+    // These can't be edited in map_editor, so they're just dropped, not returned for undo.
+    fn delete_conditional_turn_restrictions_involving(&mut self, r: OriginalRoad) {
+        for (src, road) in self.roads.iter_mut() {
+            road.conditional_turn_restrictions.retain(|(tr, to, _)| {
+                if *src == r || *to == r {
+                    println!(
+                        "Deleting {}, but first deleting conditional turn restriction {:?} {}->{}",
+                        r, tr, src, to
+                    );
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+    }
+
     pub fn move_intersection(
         &mut self,
         id: OriginalIntersection,
@@ -521,6 +546,8 @@ pub struct RawRoad {
     pub center_points: Vec<Pt2D>,
     pub osm_tags: BTreeMap<String, String>,
     pub turn_restrictions: Vec<(RestrictionType, OriginalRoad)>,
+    // Only apply during some times of day, so they don't remove any turns from the map.
+    pub conditional_turn_restrictions: Vec<(RestrictionType, OriginalRoad, TimeWindows)>,
 }
 
 impl RawRoad {
@@ -569,19 +596,19 @@ pub enum RestrictionType {
 pub struct TurnRestriction(pub OriginalRoad, pub RestrictionType, pub OriginalRoad);
 
 impl RestrictionType {
-    pub fn new(restriction: &str) -> RestrictionType {
+    // Time windows (like " @ (Mo-Fr 06:00-09:00)") have to be split off first; see
+    // parse_conditional. None for values that aren't understood.
+    pub fn new(restriction: &str) -> Option<RestrictionType> {
         // Ignore the TurnType. Between two roads, there's only one category of TurnType (treating
         // Straight/LaneChangeLeft/LaneChangeRight as the same).
-        //
-        // Strip off time restrictions (like " @ (Mo-Fr 06:00-09:00, 15:00-18:30)")
-        match restriction.split(" @ ").next().unwrap() {
+        match restriction {
             "no_left_turn" | "no_right_turn" | "no_straight_on" | "no_u_turn" | "no_anything" => {
-                RestrictionType::BanTurns
+                Some(RestrictionType::BanTurns)
             }
             "only_left_turn" | "only_right_turn" | "only_straight_on" => {
-                RestrictionType::OnlyAllowTurns
+                Some(RestrictionType::OnlyAllowTurns)
             }
-            _ => panic!("Unknown turn restriction {}", restriction),
+            _ => None,
         }
     }
 }
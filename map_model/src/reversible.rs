@@ -0,0 +1,109 @@
+use crate::{parse_conditional, TimeWindows};
+use geom::Time;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Roads like express lanes that carry traffic one way during some times of day and the other way
+// later, from oneway=reversible and oneway:conditional like
+// "yes @ (Mo-Fr 05:00-11:00); -1 @ (Mo-Fr 11:30-23:00)". The road has lanes in both directions,
+// but they're the same physical lanes, so only one side is open at a time. Outside of every
+// window, the whole road is closed.
+//
+// Contraflow roads are normal two-way roads with one center lane that switches directions, from
+// lanes:both_ways=1 and lanes:both_ways:conditional with the same values. Only the centermost lane
+// on each side follows the schedule.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReversibleSchedule {
+    // When the lanes pointing the same way as the road are open
+    forwards: Vec<TimeWindows>,
+    backwards: Vec<TimeWindows>,
+    contraflow: bool,
+}
+
+impl ReversibleSchedule {
+    // None if the road isn't reversible, or there's no usable schedule.
+    pub fn parse(osm_tags: &BTreeMap<String, String>) -> Option<ReversibleSchedule> {
+        let (raw, contraflow) = if osm_tags.get("oneway") == Some(&"reversible".to_string()) {
+            (osm_tags.get("oneway:conditional")?, false)
+        } else if osm_tags.get("lanes:both_ways") == Some(&"1".to_string()) {
+            (osm_tags.get("lanes:both_ways:conditional")?, true)
+        } else {
+            return None;
+        };
+        let mut schedule = ReversibleSchedule {
+            forwards: Vec::new(),
+            backwards: Vec::new(),
+            contraflow,
+        };
+        for (value, windows) in parse_conditional(raw) {
+            match value.as_str() {
+                "yes" => {
+                    schedule.forwards.push(windows);
+                }
+                "-1" => {
+                    schedule.backwards.push(windows);
+                }
+                "no" => {
+                    schedule.forwards.push(windows.clone());
+                    schedule.backwards.push(windows);
+                }
+                _ => {
+                    println!("Ignoring reversible schedule value {}", value);
+                }
+            }
+        }
+        if schedule.forwards.is_empty() && schedule.backwards.is_empty() {
+            return None;
+        }
+        Some(schedule)
+    }
+
+    pub fn windows(&self, forwards: bool) -> &Vec<TimeWindows> {
+        if forwards {
+            &self.forwards
+        } else {
+            &self.backwards
+        }
+    }
+
+    pub fn is_open(&self, forwards: bool, time: Time) -> bool {
+        self.windows(forwards).iter().any(|w| w.contains(time))
+    }
+
+    // The next time either direction might open or close
+    pub fn next_change(&self, time: Time) -> Time {
+        self.forwards
+            .iter()
+            .chain(self.backwards.iter())
+            .map(|w| w.next_change(time))
+            .min()
+            .unwrap()
+    }
+
+    pub fn is_contraflow(&self) -> bool {
+        self.contraflow
+    }
+}
+
+impl fmt::Display for ReversibleSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |windows: &Vec<TimeWindows>| {
+            if windows.is_empty() {
+                "never".to_string()
+            } else {
+                windows
+                    .iter()
+                    .map(|w| w.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            }
+        };
+        write!(
+            f,
+            "forwards {}, backwards {}",
+            describe(&self.forwards),
+            describe(&self.backwards)
+        )
+    }
+}
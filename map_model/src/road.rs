@@ -1,5 +1,8 @@
 use crate::raw::{OriginalRoad, RestrictionType};
-use crate::{osm, BusStopID, IntersectionID, LaneID, LaneType, Map, PathConstraints};
+use crate::{
+    osm, BusStopID, IntersectionID, LaneID, LaneType, Map, PathConstraints, ReversibleSchedule,
+    TimeWindows,
+};
 use abstutil::{Error, Warn};
 use geom::{Distance, PolyLine, Polygon, Speed};
 use serde_derive::{Deserialize, Serialize};
@@ -101,7 +104,13 @@ pub struct Road {
     pub osm_tags: BTreeMap<String, String>,
     // self is 'from'
     pub turn_restrictions: Vec<(RestrictionType, RoadID)>,
+    // Only in effect during some times of day; see Map::is_turn_allowed_at
+    pub conditional_turn_restrictions: Vec<(RestrictionType, RoadID, TimeWindows)>,
     pub orig_id: OriginalRoad,
+    // Calculated once from osm_tags when the map is built; see SpeedLimitDefaults
+    pub speed_limit: Speed,
+    // If set, only one direction is open at a time; see Map::is_turn_allowed_at
+    pub reversible: Option<ReversibleSchedule>,
 
     // Invariant: A road must contain at least one child
     // These are ordered from left-most lane (closest to center lane) to rightmost (sidewalk)
@@ -210,22 +219,7 @@ impl Road {
     }
 
     pub fn get_speed_limit(&self) -> Speed {
-        // TODO Should probably cache this
-        if let Some(limit) = self.osm_tags.get(osm::MAXSPEED) {
-            // TODO handle other units
-            if limit.ends_with(" mph") {
-                if let Ok(mph) = limit[0..limit.len() - 4].parse::<f64>() {
-                    return Speed::miles_per_hour(mph);
-                }
-            }
-        }
-
-        if self.osm_tags.get(osm::HIGHWAY) == Some(&"primary".to_string())
-            || self.osm_tags.get(osm::HIGHWAY) == Some(&"secondary".to_string())
-        {
-            return Speed::miles_per_hour(40.0);
-        }
-        Speed::miles_per_hour(20.0)
+        self.speed_limit
     }
 
     pub fn get_zorder(&self) -> isize {
@@ -323,7 +317,9 @@ impl Road {
         } else {
             self.children_backwards[0].0
         });
-        lane.lane_center_pts.shift_left(lane.width / 2.0).unwrap()
+        lane.lane_center_pts
+            .shift_left(lane.width / 2.0 - self.reversible_overlap(map))
+            .unwrap()
     }
 
     pub fn any_on_other_side(&self, l: LaneID, lt: LaneType) -> Option<LaneID> {
@@ -335,17 +331,49 @@ impl Road {
         search.iter().find(|(_, t)| lt == *t).map(|(id, _)| *id)
     }
 
+    // True if the lane only carries traffic in its direction part of the time. That's every lane
+    // for vehicles on a reversible road, or just the centermost lane on each side for contraflow.
+    pub fn is_reversible(&self, l: LaneID) -> bool {
+        match self.reversible {
+            Some(ref schedule) if schedule.is_contraflow() => {
+                self.children_forwards.first().map(|(id, _)| *id) == Some(l)
+                    || self.children_backwards.first().map(|(id, _)| *id) == Some(l)
+            }
+            Some(_) => self
+                .children_forwards
+                .iter()
+                .chain(self.children_backwards.iter())
+                .any(|(id, lt)| *id == l && lt.is_for_moving_vehicles()),
+            None => false,
+        }
+    }
+
+    // Reversible lanes on both sides are really the same pavement, so each side of the road is
+    // this much narrower than its lanes add up to.
+    pub fn reversible_overlap(&self, map: &Map) -> Distance {
+        let width = |children: &Vec<(LaneID, LaneType)>| -> Distance {
+            children
+                .iter()
+                .filter(|(l, _)| self.is_reversible(*l))
+                .map(|(l, _)| map.get_l(*l).width)
+                .sum()
+        };
+        width(&self.children_forwards).min(width(&self.children_backwards)) / 2.0
+    }
+
     pub fn width_right(&self, map: &Map) -> Distance {
         self.children_forwards
             .iter()
             .map(|(l, _)| map.get_l(*l).width)
-            .sum()
+            .sum::<Distance>()
+            - self.reversible_overlap(map)
     }
     pub fn width_left(&self, map: &Map) -> Distance {
         self.children_backwards
             .iter()
             .map(|(l, _)| map.get_l(*l).width)
-            .sum()
+            .sum::<Distance>()
+            - self.reversible_overlap(map)
     }
 
     pub fn get_thick_polyline(&self, map: &Map) -> Warn<(PolyLine, Distance)> {
@@ -0,0 +1,216 @@
+use crate::osm;
+use abstutil::Error;
+use geom::Speed;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Pedestrian pace, for maxspeed=walk and living streets
+const WALK: Speed = Speed::const_meters_per_second(7.0 / 3.6);
+// maxspeed=none means no limit at all, but nobody drives arbitrarily fast. This is the advisory
+// speed on German autobahns.
+const NO_LIMIT: Speed = Speed::const_meters_per_second(130.0 / 3.6);
+
+// How to figure out the speed limit of a road. OSM maxspeed tags are used when they're present;
+// otherwise the limit depends on the highway class, and the defaults for that vary by country.
+// Speeds are stored in meters per second, so that's what a custom JSON table uses too.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SpeedLimitDefaults {
+    // Keyed by the highway tag, like "residential". Links like "primary_link" use their class.
+    pub per_highway: BTreeMap<String, Speed>,
+    // For every other highway class
+    pub other: Speed,
+    // Implicit limits, like "DE:urban" or "GB:nsl_single"
+    pub zones: BTreeMap<String, Speed>,
+}
+
+impl SpeedLimitDefaults {
+    pub fn for_region(region: &str) -> Result<SpeedLimitDefaults, Error> {
+        let (per_highway, other) = match region {
+            // The defaults match what was hardcoded before. Seattle roads with maxspeed tags not in
+            // mph used to ignore them, so those change.
+            "us" => (
+                vec![
+                    ("primary", Speed::miles_per_hour(40.0)),
+                    ("secondary", Speed::miles_per_hour(40.0)),
+                ],
+                Speed::miles_per_hour(20.0),
+            ),
+            "gb" => (
+                vec![
+                    ("motorway", Speed::miles_per_hour(70.0)),
+                    ("trunk", Speed::miles_per_hour(60.0)),
+                    ("primary", Speed::miles_per_hour(30.0)),
+                    ("secondary", Speed::miles_per_hour(30.0)),
+                    ("tertiary", Speed::miles_per_hour(30.0)),
+                    ("residential", Speed::miles_per_hour(30.0)),
+                    ("living_street", WALK),
+                ],
+                Speed::miles_per_hour(20.0),
+            ),
+            "de" => (
+                vec![
+                    ("motorway", NO_LIMIT),
+                    ("trunk", Speed::km_per_hour(100.0)),
+                    ("primary", Speed::km_per_hour(50.0)),
+                    ("secondary", Speed::km_per_hour(50.0)),
+                    ("tertiary", Speed::km_per_hour(50.0)),
+                    ("residential", Speed::km_per_hour(50.0)),
+                    ("living_street", WALK),
+                ],
+                Speed::km_per_hour(30.0),
+            ),
+            "fr" => (
+                vec![
+                    ("motorway", Speed::km_per_hour(130.0)),
+                    ("trunk", Speed::km_per_hour(110.0)),
+                    ("primary", Speed::km_per_hour(50.0)),
+                    ("secondary", Speed::km_per_hour(50.0)),
+                    ("tertiary", Speed::km_per_hour(50.0)),
+                    ("residential", Speed::km_per_hour(50.0)),
+                    ("living_street", Speed::km_per_hour(20.0)),
+                ],
+                Speed::km_per_hour(30.0),
+            ),
+            _ => {
+                return Err(Error::new(format!(
+                    "Unknown speed limit region {}; try us, gb, de, or fr",
+                    region
+                )));
+            }
+        };
+        Ok(SpeedLimitDefaults {
+            per_highway: per_highway
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+            other,
+            zones: common_zones(),
+        })
+    }
+
+    // The limit of a road with these tags
+    pub fn get(&self, tags: &BTreeMap<String, String>) -> Speed {
+        if let Some(speed) = tags
+            .get(osm::MAXSPEED)
+            .and_then(|raw| self.parse_maxspeed(raw))
+        {
+            return speed;
+        }
+
+        // A whole road only has one limit, so use the lower one.
+        let mut directional = vec!["maxspeed:forward", "maxspeed:backward"]
+            .into_iter()
+            .filter_map(|key| tags.get(key))
+            .filter_map(|raw| self.parse_maxspeed(raw));
+        if let Some(first) = directional.next() {
+            return directional.fold(first, |a, b| a.min(b));
+        }
+
+        // Sometimes the zone is only tagged separately
+        for key in vec!["maxspeed:type", "source:maxspeed", "zone:maxspeed"] {
+            if let Some(speed) = tags.get(key).and_then(|raw| self.parse_zone(raw)) {
+                return speed;
+            }
+        }
+
+        tags.get(osm::HIGHWAY)
+            .and_then(|hwy| self.per_highway.get(hwy.trim_end_matches("_link")))
+            .cloned()
+            .unwrap_or(self.other)
+    }
+
+    // Understands plain numbers in km/h, explicit units ("30 mph", "50 km/h", "5 knots"), "walk",
+    // "none", and zones like "DE:urban" or "DE:zone30". Lists like "50;30" use the first value that
+    // parses. None if nothing makes sense, like "signals".
+    pub fn parse_maxspeed(&self, raw: &str) -> Option<Speed> {
+        raw.split(';')
+            .filter_map(|x| self.parse_one(x.trim()))
+            .next()
+    }
+
+    fn parse_one(&self, raw: &str) -> Option<Speed> {
+        match raw {
+            "walk" => {
+                return Some(WALK);
+            }
+            "none" => {
+                return Some(NO_LIMIT);
+            }
+            _ => {}
+        }
+        if raw.contains(':') {
+            return self.parse_zone(raw);
+        }
+
+        let idx = raw
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(raw.len());
+        let value = raw[..idx].parse::<f64>().ok()?;
+        if value <= 0.0 {
+            return None;
+        }
+        match raw[idx..].trim() {
+            "" | "km/h" | "kmh" | "kph" => Some(Speed::km_per_hour(value)),
+            "mph" => Some(Speed::miles_per_hour(value)),
+            "knots" => Some(Speed::km_per_hour(1.852 * value)),
+            _ => None,
+        }
+    }
+
+    fn parse_zone(&self, raw: &str) -> Option<Speed> {
+        if let Some(speed) = self.zones.get(raw) {
+            return Some(*speed);
+        }
+        // Like "DE:zone30" or "DE:zone:30", in the country's usual unit
+        let parts: Vec<&str> = raw.splitn(2, ':').collect();
+        if parts.len() != 2 || !parts[1].starts_with("zone") {
+            return None;
+        }
+        let value = parts[1]
+            .trim_start_matches("zone")
+            .trim_start_matches(':')
+            .parse::<f64>()
+            .ok()?;
+        if value <= 0.0 {
+            return None;
+        }
+        if parts[0] == "GB" || parts[0] == "US" {
+            Some(Speed::miles_per_hour(value))
+        } else {
+            Some(Speed::km_per_hour(value))
+        }
+    }
+}
+
+// Older raw maps don't have any defaults saved, and they're all in the US.
+impl Default for SpeedLimitDefaults {
+    fn default() -> SpeedLimitDefaults {
+        SpeedLimitDefaults::for_region("us").unwrap()
+    }
+}
+
+// Zone values are prefixed by country, so every region can understand all of them.
+fn common_zones() -> BTreeMap<String, Speed> {
+    let mut zones = BTreeMap::new();
+    for (country, urban, rural, motorway) in vec![
+        ("AT", 50.0, 100.0, 130.0),
+        ("CH", 50.0, 80.0, 120.0),
+        ("DE", 50.0, 100.0, 130.0),
+        ("FR", 50.0, 80.0, 130.0),
+        ("IT", 50.0, 90.0, 130.0),
+        ("NL", 50.0, 80.0, 130.0),
+    ] {
+        zones.insert(format!("{}:urban", country), Speed::km_per_hour(urban));
+        zones.insert(format!("{}:rural", country), Speed::km_per_hour(rural));
+        zones.insert(
+            format!("{}:motorway", country),
+            Speed::km_per_hour(motorway),
+        );
+    }
+    zones.insert("DE:living_street".to_string(), WALK);
+    zones.insert("DE:bicycle_road".to_string(), Speed::km_per_hour(30.0));
+    zones.insert("GB:nsl_single".to_string(), Speed::miles_per_hour(60.0));
+    zones.insert("GB:nsl_dual".to_string(), Speed::miles_per_hour(70.0));
+    zones.insert("GB:motorway".to_string(), Speed::miles_per_hour(70.0));
+    zones
+}
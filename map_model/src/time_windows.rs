@@ -0,0 +1,218 @@
+use abstutil::Error;
+use geom::{Duration, Time};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+
+// The simulation doesn't model the day of the week. Rules for weekdays are the ones that matter
+// for peak-hour restrictions, so pretend every day is a Tuesday. Transit schedules use the same
+// day.
+pub(crate) const SIMULATED_WEEKDAY: usize = 1;
+const DAYS: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+
+// When something conditional applies, parsed from a subset of the OSM opening_hours syntax, like
+// "Mo-Fr 06:00-09:00,15:00-18:30; Sa 10:00-14:00". Public holidays and other special cases aren't
+// understood.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TimeWindows {
+    // Each rule applies on some days (Monday first) during some (start, end) times of day. A
+    // window crossing midnight has end < start.
+    rules: Vec<([bool; 7], Vec<(Duration, Duration)>)>,
+}
+
+impl TimeWindows {
+    pub fn parse(raw: &str) -> Result<TimeWindows, Error> {
+        let mut rules = Vec::new();
+        for rule in raw.split(';') {
+            let rule = rule.trim();
+            if rule.is_empty() {
+                continue;
+            }
+            // The days are optional and default to every day.
+            let (days, times) = match rule.find(|c: char| c.is_ascii_digit()) {
+                Some(idx) => (rule[..idx].trim(), rule[idx..].trim()),
+                None => {
+                    return Err(Error::new(format!("No times in {}", raw)));
+                }
+            };
+            let days = if days.is_empty() {
+                [true; 7]
+            } else {
+                parse_days(days)?
+            };
+            let mut windows = Vec::new();
+            for window in times.split(',') {
+                let parts: Vec<&str> = window.trim().split('-').collect();
+                if parts.len() != 2 {
+                    return Err(Error::new(format!("Bad time window {} in {}", window, raw)));
+                }
+                windows.push((parse_time_of_day(parts[0])?, parse_time_of_day(parts[1])?));
+            }
+            rules.push((days, windows));
+        }
+        if rules.is_empty() {
+            return Err(Error::new(format!("No rules in {}", raw)));
+        }
+        Ok(TimeWindows { rules })
+    }
+
+    // Times past midnight wrap around to the next day.
+    pub fn contains(&self, time: Time) -> bool {
+        let secs = time.inner_seconds();
+        let day = (SIMULATED_WEEKDAY + (secs / 86400.0).floor() as usize) % 7;
+        let time_of_day = Duration::seconds(secs % 86400.0);
+        let yesterday = (day + 6) % 7;
+        for (days, windows) in &self.rules {
+            for (start, end) in windows {
+                if start < end {
+                    if days[day] && time_of_day >= *start && time_of_day < *end {
+                        return true;
+                    }
+                } else {
+                    // Overnight, so the early part belongs to the previous day's rule
+                    if (days[day] && time_of_day >= *start)
+                        || (days[yesterday] && time_of_day < *end)
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    // The first window boundary strictly after time. Days of the week aren't considered, so
+    // sometimes nothing actually changes then.
+    pub fn next_change(&self, time: Time) -> Time {
+        let midnight =
+            Time::START_OF_DAY + Duration::hours(24) * (time.inner_seconds() / 86400.0).floor();
+        let mut next: Option<Time> = None;
+        for day in 0..2 {
+            for (_, windows) in &self.rules {
+                for (start, end) in windows {
+                    for boundary in vec![*start, *end] {
+                        let t = midnight + Duration::hours(24 * day) + boundary;
+                        if t > time && next.map(|n| t < n).unwrap_or(true) {
+                            next = Some(t);
+                        }
+                    }
+                }
+            }
+        }
+        // Every rule has at least one window, and the windows are within a day
+        next.unwrap()
+    }
+}
+
+impl fmt::Display for TimeWindows {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut rules = Vec::new();
+        for (days, windows) in &self.rules {
+            let days: Vec<&str> = (0..7).filter(|d| days[*d]).map(|d| DAYS[d]).collect();
+            let windows: Vec<String> = windows
+                .iter()
+                .map(|(start, end)| {
+                    format!(
+                        "{}-{}",
+                        describe_time_of_day(*start),
+                        describe_time_of_day(*end)
+                    )
+                })
+                .collect();
+            if days.len() == 7 {
+                rules.push(windows.join(","));
+            } else {
+                rules.push(format!("{} {}", days.join(","), windows.join(",")));
+            }
+        }
+        write!(f, "{}", rules.join("; "))
+    }
+}
+
+// Splits a *:conditional tag like "no_left_turn @ (Mo-Fr 07:00-09:00); no_u_turn @ 16:00-18:00"
+// into each value and when it applies. Parts that can't be understood are skipped with a warning.
+pub fn parse_conditional(raw: &str) -> Vec<(String, TimeWindows)> {
+    let mut results = Vec::new();
+    // The condition itself may contain semicolons when it's in parentheses, so track nesting.
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in raw.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ';' if depth == 0 => {
+                parts.push(std::mem::replace(&mut current, String::new()));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    parts.push(current);
+
+    for part in parts {
+        let pair: Vec<&str> = part.splitn(2, '@').collect();
+        if pair.len() != 2 {
+            println!("Ignoring conditional {} without a condition", part);
+            continue;
+        }
+        let condition = pair[1].trim().trim_start_matches('(').trim_end_matches(')');
+        match TimeWindows::parse(condition) {
+            Ok(windows) => {
+                results.push((pair[0].trim().to_string(), windows));
+            }
+            Err(err) => {
+                println!("Ignoring conditional {}: {}", part, err);
+            }
+        }
+    }
+    results
+}
+
+fn parse_days(raw: &str) -> Result<[bool; 7], Error> {
+    let mut days = [false; 7];
+    for part in raw.split(',') {
+        let range: Vec<&str> = part.trim().split('-').collect();
+        let (start, end) = match range.len() {
+            1 => (parse_day(range[0])?, parse_day(range[0])?),
+            2 => (parse_day(range[0])?, parse_day(range[1])?),
+            _ => {
+                return Err(Error::new(format!("Bad days {}", raw)));
+            }
+        };
+        // Ranges like Sa-Mo wrap around the week
+        let mut d = start;
+        loop {
+            days[d] = true;
+            if d == end {
+                break;
+            }
+            d = (d + 1) % 7;
+        }
+    }
+    Ok(days)
+}
+
+fn parse_day(raw: &str) -> Result<usize, Error> {
+    DAYS.iter()
+        .position(|d| *d == raw.trim())
+        .ok_or_else(|| Error::new(format!("Unknown day {}", raw)))
+}
+
+// Like "06:00". "24:00" is allowed for the end of the day.
+fn parse_time_of_day(raw: &str) -> Result<Duration, Error> {
+    let parts: Vec<&str> = raw.trim().split(':').collect();
+    if parts.len() == 2 {
+        if let (Ok(hours), Ok(mins)) = (parts[0].parse::<usize>(), parts[1].parse::<usize>()) {
+            if hours <= 24 && mins < 60 && (hours < 24 || mins == 0) {
+                return Ok(Duration::hours(hours) + Duration::minutes(mins));
+            }
+        }
+    }
+    Err(Error::new(format!("Bad time of day {}", raw)))
+}
+
+fn describe_time_of_day(dt: Duration) -> String {
+    let mins = (dt.inner_seconds() / 60.0).round() as usize;
+    format!("{:02}:{:02}", mins / 60, mins % 60)
+}
@@ -1,8 +1,10 @@
 use crate::{IntersectionID, Map, RoadID, TurnGroup, TurnGroupID, TurnID, TurnPriority, TurnType};
 use abstutil::{deserialize_btreemap, retain_btreeset, serialize_btreemap, Timer};
 use geom::{Duration, Time};
+use serde::{Deserialize, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ControlTrafficSignal {
@@ -21,7 +23,57 @@ pub struct ControlTrafficSignal {
 pub struct Phase {
     pub protected_groups: BTreeSet<TurnGroupID>,
     pub yield_groups: BTreeSet<TurnGroupID>,
-    pub duration: Duration,
+    // Signal edits saved before actuated phases existed just have a duration.
+    #[serde(alias = "duration", deserialize_with = "deserialize_phase_type")]
+    pub phase_type: PhaseType,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum PhaseType {
+    Fixed(Duration),
+    // Starts with min_green. While somebody is still waiting for or doing one of this phase's
+    // turns, it's extended by extension at a time, up to max_green total. If nobody's waiting for
+    // an actuated phase when its turn comes, it's skipped entirely.
+    Actuated {
+        min_green: Duration,
+        max_green: Duration,
+        extension: Duration,
+    },
+}
+
+impl PhaseType {
+    // The longest this phase can last. Anything that needs a fixed schedule uses this.
+    pub fn simple_duration(&self) -> Duration {
+        match self {
+            PhaseType::Fixed(d) => *d,
+            PhaseType::Actuated { max_green, .. } => *max_green,
+        }
+    }
+
+    // How long the phase lasts when it first starts, before any extensions.
+    pub fn initial_duration(&self) -> Duration {
+        match self {
+            PhaseType::Fixed(d) => *d,
+            PhaseType::Actuated { min_green, .. } => *min_green,
+        }
+    }
+}
+
+impl fmt::Display for PhaseType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PhaseType::Fixed(d) => write!(f, "{}", d),
+            PhaseType::Actuated {
+                min_green,
+                max_green,
+                extension,
+            } => write!(
+                f,
+                "actuated, {} to {} (extend by {})",
+                min_green, max_green, extension
+            ),
+        }
+    }
 }
 
 impl ControlTrafficSignal {
@@ -67,21 +119,26 @@ impl ControlTrafficSignal {
         results
     }
 
+    // Actuated phases make the real cycle vary; this is the worst case, with every actuated phase
+    // running to max_green.
     pub fn cycle_length(&self) -> Duration {
         let mut cycle_length = Duration::ZERO;
         for p in &self.phases {
-            cycle_length += p.duration;
+            cycle_length += p.phase_type.simple_duration();
         }
         cycle_length
     }
 
+    // Follows the fixed-time schedule (see cycle_length). The simulation tracks the real phase of
+    // actuated signals separately.
     pub fn current_phase_and_remaining_time(&self, now: Time) -> (usize, &Phase, Duration) {
         let mut now_offset = ((now + self.offset) - Time::START_OF_DAY) % self.cycle_length();
         for (idx, p) in self.phases.iter().enumerate() {
-            if now_offset < p.duration {
-                return (idx, p, p.duration - now_offset);
+            let duration = p.phase_type.simple_duration();
+            if now_offset < duration {
+                return (idx, p, duration - now_offset);
             } else {
-                now_offset -= p.duration;
+                now_offset -= duration;
             }
         }
         unreachable!()
@@ -128,6 +185,33 @@ impl ControlTrafficSignal {
             for g in phase.yield_groups.iter().map(|g| &self.turn_groups[g]) {
                 assert!(g.turn_type != TurnType::Crosswalk);
             }
+
+            match phase.phase_type {
+                PhaseType::Fixed(d) => {
+                    if d <= Duration::ZERO {
+                        return Err(format!("Traffic signal {} has an empty phase", self.id));
+                    }
+                }
+                PhaseType::Actuated {
+                    min_green,
+                    max_green,
+                    extension,
+                } => {
+                    if min_green <= Duration::ZERO || min_green > max_green {
+                        return Err(format!(
+                            "Traffic signal {} has an actuated phase with min_green {} and \
+                             max_green {}",
+                            self.id, min_green, max_green
+                        ));
+                    }
+                    if extension <= Duration::ZERO {
+                        return Err(format!(
+                            "Traffic signal {} has an actuated phase that can't be extended",
+                            self.id
+                        ));
+                    }
+                }
+            }
         }
 
         Ok(self)
@@ -500,7 +584,7 @@ impl Phase {
         Phase {
             protected_groups: BTreeSet::new(),
             yield_groups: BTreeSet::new(),
-            duration: Duration::seconds(30.0),
+            phase_type: PhaseType::Fixed(Duration::seconds(30.0)),
         }
     }
 
@@ -570,6 +654,25 @@ impl Phase {
 }
 
 // Add all possible protected groups to existing phases.
+// Accepts an old fixed duration in place of a PhaseType. Binary formats don't have field names, so
+// only the current format works there.
+fn deserialize_phase_type<'de, D: Deserializer<'de>>(d: D) -> Result<PhaseType, D::Error> {
+    if !d.is_human_readable() {
+        return PhaseType::deserialize(d);
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Compat {
+        Current(PhaseType),
+        Old(Duration),
+    }
+    Ok(match Compat::deserialize(d)? {
+        Compat::Current(phase_type) => phase_type,
+        Compat::Old(duration) => PhaseType::Fixed(duration),
+    })
+}
+
 fn expand_all_phases(phases: &mut Vec<Phase>, turn_groups: &BTreeMap<TurnGroupID, TurnGroup>) {
     for phase in phases.iter_mut() {
         for g in turn_groups.keys() {
@@ -3,7 +3,7 @@ mod trips;
 
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-pub use trips::{clip_trips, trips_to_scenario, Trip, TripEndpt};
+pub use trips::{clip_trips, group_by_person, trips_to_scenario, Trip, TripEndpt};
 
 #[derive(Serialize, Deserialize)]
 pub struct PopDat {
@@ -12,6 +12,8 @@ pub struct Trip {
     pub to: Endpoint,
     pub depart_at: Time,
     pub mode: Mode,
+    // (household, person number within the household)
+    pub person: (usize, usize),
 
     pub purpose: (Purpose, Purpose),
     pub trip_time: Duration,
@@ -63,7 +65,18 @@ pub fn import_trips(
 
     let mut trips = Vec::new();
     let (reader, done) = FileWithProgress::new(trips_path)?;
-    for rec in csv::Reader::from_reader(reader).records() {
+    let mut csv = csv::Reader::from_reader(reader);
+    // The columns identifying the person aren't always in the same place
+    let headers = csv.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| failure::err_msg(format!("{} is missing a {} column", trips_path, name)))
+    };
+    let hhno = column("hhno")?;
+    let pno = column("pno")?;
+    for rec in csv.records() {
         let rec = rec?;
 
         // opcl
@@ -97,12 +110,18 @@ pub fn import_trips(
         // travdist
         let trip_dist = Distance::miles(rec[24].parse::<f64>()?);
 
+        let person = (
+            rec[hhno].trim_end_matches(".0").parse::<usize>()?,
+            rec[pno].trim_end_matches(".0").parse::<usize>()?,
+        );
+
         trips.push(Trip {
             from,
             to,
             depart_at,
             purpose,
             mode,
+            person,
             trip_time,
             trip_dist,
         });
@@ -3,7 +3,9 @@ use crate::PopDat;
 use abstutil::Timer;
 use geom::{Distance, Duration, LonLat, Polygon, Pt2D, Time};
 use map_model::{BuildingID, IntersectionID, Map, PathConstraints, Position};
-use sim::{DrivingGoal, Scenario, SidewalkSpot, SpawnTrip, TripSpec};
+use sim::{
+    Activity, DrivingGoal, PersonSpec, Scenario, SidewalkSpot, SpawnTrip, TripMode, TripSpec,
+};
 use std::collections::{BTreeMap, HashMap};
 
 #[derive(Clone, Debug)]
@@ -13,6 +15,8 @@ pub struct Trip {
     pub depart_at: Time,
     pub purpose: (Purpose, Purpose),
     pub mode: Mode,
+    // (household, person number within the household)
+    pub person: (usize, usize),
     // These are an upper bound when TripEndpt::Border is involved.
     pub trip_time: Duration,
     pub trip_dist: Distance,
@@ -248,6 +252,7 @@ pub fn clip_trips(map: &Map, timer: &mut Timer) -> (Vec<Trip>, HashMap<BuildingI
             depart_at: trip.depart_at,
             purpose: trip.purpose,
             mode: trip.mode,
+            person: trip.person,
             trip_time: trip.trip_time,
             trip_dist: trip.trip_dist,
         };
@@ -278,11 +283,87 @@ pub fn clip_trips(map: &Map, timer: &mut Timer) -> (Vec<Trip>, HashMap<BuildingI
     (trips, bldgs)
 }
 
+// If all of somebody's trips stay in the map and each starts where the previous one ended, they
+// become a PersonSpec, going between activities. Everything else is an independent trip.
+pub fn group_by_person(trips: &Vec<Trip>) -> (Vec<PersonSpec>, Vec<Trip>) {
+    let mut per_person: BTreeMap<(usize, usize), Vec<&Trip>> = BTreeMap::new();
+    for trip in trips {
+        per_person
+            .entry(trip.person)
+            .or_insert_with(Vec::new)
+            .push(trip);
+    }
+
+    let mut people = Vec::new();
+    let mut individ_trips = Vec::new();
+    for (_, mut list) in per_person {
+        list.sort_by_key(|t| t.depart_at);
+        // Every activity happens at a building, so the last trip can't leave the map either.
+        let chained = list.len() > 1
+            && list.iter().all(|t| match t.to {
+                TripEndpt::Building(_) => true,
+                TripEndpt::Border(_, _) => false,
+            })
+            && list
+                .windows(2)
+                .all(|pair| match (&pair[0].to, &pair[1].from) {
+                    (TripEndpt::Building(b1), TripEndpt::Building(b2)) => b1 == b2,
+                    _ => false,
+                });
+        let home = match list[0].from {
+            TripEndpt::Building(b) => Some(b),
+            TripEndpt::Border(_, _) => None,
+        };
+        match home {
+            Some(home) if chained => {
+                let mut activities = Vec::new();
+                for (idx, trip) in list.iter().enumerate() {
+                    let bldg = match trip.to {
+                        TripEndpt::Building(b) => b,
+                        TripEndpt::Border(_, _) => unreachable!(),
+                    };
+                    // Stay until the next trip leaves. Stay at the last place forever.
+                    let duration = match list.get(idx + 1) {
+                        Some(next) if next.depart_at > trip.end_time() => {
+                            next.depart_at - trip.end_time()
+                        }
+                        _ => Duration::ZERO,
+                    };
+                    activities.push(Activity {
+                        bldg,
+                        mode: match trip.mode {
+                            Mode::Walk => TripMode::Walk,
+                            Mode::Bike => TripMode::Bike,
+                            Mode::Drive => TripMode::Drive,
+                            Mode::Transit => TripMode::Transit,
+                        },
+                        duration,
+                    });
+                }
+                people.push(PersonSpec {
+                    home,
+                    depart: list[0].depart_at,
+                    activities,
+                });
+            }
+            _ => {
+                individ_trips.extend(list.into_iter().cloned());
+            }
+        }
+    }
+    (people, individ_trips)
+}
+
 pub fn trips_to_scenario(map: &Map, timer: &mut Timer) -> Scenario {
     let (trips, _) = clip_trips(map, timer);
-    // TODO Don't clone trips for parallelize
+    let (people, loose_trips) = group_by_person(&trips);
+    timer.note(format!(
+        "{} people with a chain of trips, {} independent trips",
+        people.len(),
+        loose_trips.len()
+    ));
     let individ_trips = timer
-        .parallelize("turn PSRC trips into SpawnTrips", trips.clone(), |trip| {
+        .parallelize("turn PSRC trips into SpawnTrips", loose_trips, |trip| {
             trip.to_spawn_trip(map)
         })
         .into_iter()
@@ -314,14 +395,19 @@ pub fn trips_to_scenario(map: &Map, timer: &mut Timer) -> Scenario {
         }
     }
 
-    Scenario {
+    let mut s = Scenario {
         scenario_name: "weekday".to_string(),
         map_name: map.get_name().to_string(),
         only_seed_buses: None,
+        bus_timetables: Vec::new(),
         seed_parked_cars: Vec::new(),
         spawn_over_time: Vec::new(),
         border_spawn_over_time: Vec::new(),
         individ_trips,
+        people,
         individ_parked_cars,
-    }
+    };
+    // This is a real weekday, so run the real bus schedules too.
+    s.use_gtfs_timetables(map);
+    s
 }
@@ -1,12 +1,13 @@
-use crate::{CarID, Event, TripID, TripMode};
-use abstutil::Counter;
+use crate::{AgentID, CarID, Event, LevelOfService, PedestrianID, TripID, TripMode, VehicleType};
+use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use derivative::Derivative;
 use geom::{Distance, Duration, DurationHistogram, PercentageHistogram, Time};
 use map_model::{
-    BusRouteID, BusStopID, IntersectionID, Map, Path, PathRequest, RoadID, Traversable, TurnGroupID,
+    BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest,
+    PathStep, RoadID, TravelTimes, Traversable, TurnGroupID,
 };
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
 #[derive(Clone, Serialize, Deserialize, Derivative)]
 pub struct Analytics {
@@ -14,13 +15,33 @@ pub struct Analytics {
     #[serde(skip_serializing, skip_deserializing)]
     pub(crate) test_expectations: VecDeque<Event>,
     pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID)>,
+    // Only for buses following a timetable. Actual arrival, bus, route, stop, scheduled arrival
+    pub scheduled_bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID, Time)>,
     pub bus_passengers_waiting: Vec<(Time, BusStopID, BusRouteID)>,
+    // Passengers riding divided by capacity, each time a bus leaves a stop
+    pub bus_load_factors: BTreeMap<CarID, Vec<(Time, BusStopID, f64)>>,
+    // Pedestrians left waiting because the bus was full
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub denied_boardings: BTreeMap<BusStopID, Vec<(Time, PedestrianID, CarID)>>,
     // TODO Hack: No TripMode means aborted
     // Finish time, ID, mode (or None as aborted), trip duration
     pub finished_trips: Vec<(Time, TripID, Option<TripMode>, Duration)>,
     // TODO This subsumes finished_trips
     pub trip_log: Vec<(Time, TripID, Option<PathRequest>, String)>,
     pub intersection_delays: BTreeMap<IntersectionID, Vec<(Time, Duration)>>,
+    // For cars only, the time from entering a lane to entering the next lane, recorded when they
+    // entered the first lane.
+    pub lane_travel_times: BTreeMap<LaneID, Vec<(Time, Duration)>>,
+    // The lane each car most recently entered, and when
+    car_entered_lane: BTreeMap<CarID, (LaneID, Time)>,
+    // For trips driving a car, when the car started, what it asked for, and the path it first
+    // chose
+    pub car_routes: BTreeMap<TripID, (Time, PathRequest, Vec<PathStep>)>,
+    // Only recorded when worse than LevelOfService::A
+    pub ped_crowding: BTreeMap<Traversable, Vec<(Time, LevelOfService)>>,
 
     // After we restore from a savestate, don't record anything. This is only going to make sense
     // if savestates are only used for quickly previewing against prebaked results, where we have
@@ -28,6 +49,30 @@ pub struct Analytics {
     record_anything: bool,
 }
 
+// The usual definition: no more than 1 minute early or 5 minutes late
+const ON_TIME_IF_EARLY_BY: Duration = Duration::const_seconds(60.0);
+const ON_TIME_IF_LATE_BY: Duration = Duration::const_seconds(5.0 * 60.0);
+
+pub struct OnTimePerformance {
+    pub early: usize,
+    pub on_time: usize,
+    pub late: usize,
+    // Early arrivals count as zero
+    pub lateness: DurationHistogram,
+}
+
+impl OnTimePerformance {
+    // None if no scheduled buses have arrived anywhere yet
+    pub fn percent_on_time(&self) -> Option<f64> {
+        let total = self.early + self.on_time + self.late;
+        if total == 0 {
+            None
+        } else {
+            Some(100.0 * (self.on_time as f64) / (total as f64))
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Derivative)]
 pub struct ThruputStats {
     #[serde(skip_serializing, skip_deserializing)]
@@ -54,10 +99,17 @@ impl Analytics {
             },
             test_expectations: VecDeque::new(),
             bus_arrivals: Vec::new(),
+            scheduled_bus_arrivals: Vec::new(),
             bus_passengers_waiting: Vec::new(),
+            bus_load_factors: BTreeMap::new(),
+            denied_boardings: BTreeMap::new(),
             finished_trips: Vec::new(),
             trip_log: Vec::new(),
             intersection_delays: BTreeMap::new(),
+            lane_travel_times: BTreeMap::new(),
+            car_entered_lane: BTreeMap::new(),
+            car_routes: BTreeMap::new(),
+            ped_crowding: BTreeMap::new(),
             record_anything: true,
         }
     }
@@ -107,11 +159,30 @@ impl Analytics {
             self.bus_arrivals.push((time, bus, route, stop));
         }
 
+        if let Event::ScheduledBusArrival(bus, route, stop, scheduled) = ev {
+            self.scheduled_bus_arrivals
+                .push((time, bus, route, stop, scheduled));
+        }
+
         // Bus passengers
         if let Event::PedReachedBusStop(_, stop, route) = ev {
             self.bus_passengers_waiting.push((time, stop, route));
         }
 
+        // Bus crowding
+        if let Event::BusLoadMeasured(bus, stop, riding, capacity) = ev {
+            self.bus_load_factors
+                .entry(bus)
+                .or_insert_with(Vec::new)
+                .push((time, stop, (riding as f64) / (capacity as f64)));
+        }
+        if let Event::PedDeniedBoarding(ped, bus, stop) = ev {
+            self.denied_boardings
+                .entry(stop)
+                .or_insert_with(Vec::new)
+                .push((time, ped, bus));
+        }
+
         // Finished trips
         if let Event::TripFinished(id, mode, dt) = ev {
             self.finished_trips.push((time, id, Some(mode), dt));
@@ -127,6 +198,29 @@ impl Analytics {
                 .push((time, delay));
         }
 
+        // Pedestrian crowding
+        if let Event::PedestrianCrowding(on, los) = ev {
+            self.ped_crowding
+                .entry(on)
+                .or_insert_with(Vec::new)
+                .push((time, los));
+        }
+
+        // Lane travel times
+        if let Event::AgentEntersTraversable(AgentID::Car(car), Traversable::Lane(l)) = ev {
+            if car.1 == VehicleType::Car {
+                if let Some((prev_l, entered)) = self.car_entered_lane.insert(car, (l, time)) {
+                    self.lane_travel_times
+                        .entry(prev_l)
+                        .or_insert_with(Vec::new)
+                        .push((entered, time - entered));
+                }
+            }
+        }
+        if let Event::CarReachedParkingSpot(car, _) = ev {
+            self.car_entered_lane.remove(&car);
+        }
+
         // TODO Kinda hacky, but these all consume the event, so kinda bundle em.
         match ev {
             Event::TripPhaseStarting(id, maybe_req, metadata) => {
@@ -157,6 +251,39 @@ impl Analytics {
         }
     }
 
+    pub fn record_car_route(&mut self, trip: TripID, time: Time, req: &PathRequest, path: &Path) {
+        if !self.record_anything || req.constraints != PathConstraints::Car {
+            return;
+        }
+        self.car_routes.entry(trip).or_insert_with(|| {
+            (
+                time,
+                req.clone(),
+                path.get_steps().iter().cloned().collect(),
+            )
+        });
+    }
+
+    // Average the observed lane travel times into buckets, for time-dependent pathfinding.
+    pub fn travel_times(&self, bucket_size: Duration) -> TravelTimes {
+        let mut travel_times = TravelTimes::new(bucket_size);
+        for (l, list) in &self.lane_travel_times {
+            let mut per_bucket: BTreeMap<usize, (Time, Duration, usize)> = BTreeMap::new();
+            for (t, dt) in list {
+                let entry =
+                    per_bucket
+                        .entry(travel_times.bucket(*t))
+                        .or_insert((*t, Duration::ZERO, 0));
+                entry.1 += *dt;
+                entry.2 += 1;
+            }
+            for (t, sum, cnt) in per_bucket.values() {
+                travel_times.set(*t, *l, *sum / (*cnt as f64));
+            }
+        }
+        travel_times
+    }
+
     // TODO If these ever need to be speeded up, just cache the histogram and index in the events
     // list.
 
@@ -258,6 +385,45 @@ impl Analytics {
         delay_to_stop
     }
 
+    // Compare scheduled and actual bus arrivals up to now, for all routes or just one.
+    pub fn on_time_performance(&self, now: Time, route: Option<BusRouteID>) -> OnTimePerformance {
+        let mut otp = OnTimePerformance {
+            early: 0,
+            on_time: 0,
+            late: 0,
+            lateness: DurationHistogram::new(),
+        };
+        for (actual, _, r, _, scheduled) in &self.scheduled_bus_arrivals {
+            if *actual > now {
+                break;
+            }
+            if route.map(|x| x != *r).unwrap_or(false) {
+                continue;
+            }
+            if *actual < *scheduled - ON_TIME_IF_EARLY_BY {
+                otp.early += 1;
+            } else if *actual > *scheduled + ON_TIME_IF_LATE_BY {
+                otp.late += 1;
+            } else {
+                otp.on_time += 1;
+            }
+            if *actual > *scheduled {
+                otp.lateness.add(*actual - *scheduled);
+            } else {
+                otp.lateness.add(Duration::ZERO);
+            }
+        }
+        otp
+    }
+
+    // How many times a full bus left somebody waiting at each stop, up to now
+    pub fn denied_boardings_per_stop(&self, now: Time) -> BTreeMap<BusStopID, usize> {
+        self.denied_boardings
+            .iter()
+            .map(|(stop, list)| (*stop, list.iter().filter(|(t, _, _)| *t <= now).count()))
+            .collect()
+    }
+
     // TODO Refactor!
     // For each stop, a list of (time, delay)
     pub fn bus_arrivals_over_time(
@@ -359,6 +525,17 @@ impl Analytics {
         )
     }
 
+    pub fn road_thruput_table(&self, query: &ThruputQuery<RoadID>) -> ThruputTable<RoadID> {
+        ThruputTable::new(query, &self.thruput_stats.raw_per_road)
+    }
+
+    pub fn intersection_thruput_table(
+        &self,
+        query: &ThruputQuery<IntersectionID>,
+    ) -> ThruputTable<IntersectionID> {
+        ThruputTable::new(query, &self.thruput_stats.raw_per_intersection)
+    }
+
     fn throughput<X: PartialEq>(
         &self,
         now: Time,
@@ -469,7 +646,7 @@ impl Analytics {
                 // TODO New enum instead of strings, if there'll be more analyses like this
                 if p.description.starts_with("CarID(") {
                     driving_time += dt;
-                } else if p.description == "parking somewhere else"
+                } else if p.description == "cruising for parking"
                     || p.description == "parking on the current lane"
                 {
                     overhead += dt;
@@ -499,6 +676,46 @@ impl Analytics {
         ]
     }
 
+    // The worst pedestrian level of service seen on every sidewalk or crosswalk between t1 and t2.
+    // Anything missing stayed at A.
+    pub fn ped_level_of_service(
+        &self,
+        t1: Time,
+        t2: Time,
+    ) -> BTreeMap<Traversable, LevelOfService> {
+        let mut results = BTreeMap::new();
+        for (on, list) in &self.ped_crowding {
+            if let Some(worst) = list
+                .iter()
+                .filter(|(t, _)| *t >= t1 && *t <= t2)
+                .map(|(_, los)| *los)
+                .max()
+            {
+                results.insert(*on, worst);
+            }
+        }
+        results
+    }
+
+    // Per finished trip that had to cruise, the total time spent looking for parking
+    pub fn cruising_times(&self) -> DurationHistogram {
+        let mut distrib = DurationHistogram::new();
+        for (_, phases) in self.get_all_trip_phases() {
+            let mut total = Duration::ZERO;
+            for p in phases {
+                if p.description == "cruising for parking" {
+                    if let Some(end) = p.end_time {
+                        total += end - p.start_time;
+                    }
+                }
+            }
+            if total > Duration::ZERO {
+                distrib.add(total);
+            }
+        }
+        distrib
+    }
+
     pub fn intersection_delays(&self, i: IntersectionID, t1: Time, t2: Time) -> DurationHistogram {
         let mut delays = DurationHistogram::new();
         // TODO Binary search
@@ -551,6 +768,77 @@ impl Default for Analytics {
     }
 }
 
+// What throughput to count in a ThruputTable. Slightly misleading -- TripMode::Transit means buses,
+// not pedestrians taking transit.
+pub struct ThruputQuery<X: Ord> {
+    pub objects: BTreeSet<X>,
+    pub start: Time,
+    pub end: Time,
+    pub bucket_size: Duration,
+    pub modes: BTreeSet<TripMode>,
+}
+
+// How many agents entered each road or intersection, per time bucket
+#[derive(Serialize)]
+pub struct ThruputTable<X: Ord> {
+    pub start: Time,
+    pub bucket_size: Duration,
+    pub num_buckets: usize,
+    // Every object in the query is present, with one count per bucket
+    pub counts: BTreeMap<X, Vec<usize>>,
+}
+
+impl<X: Ord + Copy> ThruputTable<X> {
+    fn new(query: &ThruputQuery<X>, data: &[(Time, TripMode, X)]) -> ThruputTable<X> {
+        assert!(query.start <= query.end);
+        assert!(query.bucket_size > Duration::ZERO);
+        let num_buckets = ((query.end - query.start) / query.bucket_size).ceil() as usize;
+        let mut counts: BTreeMap<X, Vec<usize>> = query
+            .objects
+            .iter()
+            .map(|x| (*x, vec![0; num_buckets]))
+            .collect();
+        for (t, m, x) in data {
+            if *t >= query.end {
+                break;
+            }
+            if *t < query.start || !query.modes.contains(m) {
+                continue;
+            }
+            if let Some(buckets) = counts.get_mut(x) {
+                buckets[((*t - query.start) / query.bucket_size) as usize] += 1;
+            }
+        }
+        ThruputTable {
+            start: query.start,
+            bucket_size: query.bucket_size,
+            num_buckets,
+            counts,
+        }
+    }
+
+    pub fn bucket_start(&self, idx: usize) -> Time {
+        self.start + self.bucket_size * (idx as f64)
+    }
+
+    // One row per object, one column per bucket. The caller describes each object with as many
+    // columns as the header has.
+    pub fn to_csv<F: Fn(X) -> String>(&self, header: &str, describe: F) -> String {
+        let mut lines = vec![header.to_string()];
+        for idx in 0..self.num_buckets {
+            lines[0].push_str(&format!(",{}", self.bucket_start(idx)));
+        }
+        for (x, buckets) in &self.counts {
+            let mut line = describe(*x);
+            for cnt in buckets {
+                line.push_str(&format!(",{}", cnt));
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
+
 pub struct TripPhase {
     pub start_time: Time,
     pub end_time: Option<Time>,
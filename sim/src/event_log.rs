@@ -0,0 +1,127 @@
+use crate::{Analytics, Event};
+use abstutil::Timer;
+use geom::Time;
+use map_model::Map;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+
+// Streams every Event (and the time it happened) to disk as the simulation runs, so any metric
+// can be rebuilt later without re-running the sim. The format is picked by the file extension:
+// .jsonl is newline-delimited JSON, .bin is a sequence of bincoded (Time, Event) records.
+pub struct EventLog {
+    path: String,
+    // None if this is a clone that shouldn't write anything
+    out: Option<BufWriter<File>>,
+    binary: bool,
+}
+
+impl EventLog {
+    pub fn new(path: String) -> EventLog {
+        let binary = is_binary(&path);
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            std::fs::create_dir_all(parent).expect("Creating parent dir failed");
+        }
+        let file = match File::create(&path) {
+            Ok(f) => f,
+            Err(err) => panic!("Can't create event log {}: {}", path, err),
+        };
+        EventLog {
+            path,
+            out: Some(BufWriter::new(file)),
+            binary,
+        }
+    }
+
+    pub fn record(&mut self, time: Time, ev: &Event) {
+        let out = if let Some(ref mut out) = self.out {
+            out
+        } else {
+            return;
+        };
+        let result = if self.binary {
+            bincode::serialize_into(out, &(time, ev))
+                .map_err(|err| Error::new(ErrorKind::Other, err))
+        } else {
+            serde_json::to_writer(&mut *out, &(time, ev))
+                .map_err(Error::from)
+                .and_then(|_| out.write_all(b"\n"))
+        };
+        if let Err(err) = result {
+            panic!("Can't write to event log {}: {}", self.path, err);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(ref mut out) = self.out {
+            if let Err(err) = out.flush() {
+                panic!("Can't flush event log {}: {}", self.path, err);
+            }
+        }
+    }
+
+    pub fn read(path: &str) -> Result<Vec<(Time, Event)>, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut events = Vec::new();
+        if is_binary(path) {
+            loop {
+                match bincode::deserialize_from(&mut reader) {
+                    Ok(pair) => {
+                        events.push(pair);
+                    }
+                    Err(err) => match *err {
+                        bincode::ErrorKind::Io(ref io) if io.kind() == ErrorKind::UnexpectedEof => {
+                            break;
+                        }
+                        _ => {
+                            return Err(Error::new(ErrorKind::Other, err));
+                        }
+                    },
+                }
+            }
+        } else {
+            for line in reader.lines() {
+                let line = line?;
+                if line.is_empty() {
+                    continue;
+                }
+                events.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(events)
+    }
+
+    // Feed a recorded log through a fresh Analytics. Demand is recorded directly by the sim, not
+    // through events, so it won't be reconstructed -- it's only meaningful at one moment anyway.
+    pub fn replay(path: &str, map: &Map, timer: &mut Timer) -> Result<Analytics, Error> {
+        timer.note(format!("Replaying event log {}", path));
+        let events = EventLog::read(path)?;
+        let mut analytics = Analytics::new();
+        timer.start_iter("replay events", events.len());
+        for (time, ev) in events {
+            timer.next();
+            analytics.event(ev, time, map);
+        }
+        Ok(analytics)
+    }
+}
+
+// Cloned sims (like the one suspended while editing the map) don't keep writing to the same log.
+impl Clone for EventLog {
+    fn clone(&self) -> EventLog {
+        EventLog {
+            path: self.path.clone(),
+            out: None,
+            binary: self.binary,
+        }
+    }
+}
+
+fn is_binary(path: &str) -> bool {
+    if path.ends_with(".bin") {
+        true
+    } else if path.ends_with(".jsonl") {
+        false
+    } else {
+        panic!("Event log {} must end with .bin or .jsonl", path);
+    }
+}
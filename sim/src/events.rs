@@ -1,5 +1,5 @@
-use crate::{AgentID, CarID, ParkingSpot, PedestrianID, TripID, TripMode};
-use geom::Duration;
+use crate::{AgentID, CarID, LevelOfService, ParkingSpot, PedestrianID, TripID, TripMode};
+use geom::{Duration, Time};
 use map_model::{
     BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Path, PathRequest, Traversable,
 };
@@ -12,6 +12,10 @@ pub enum Event {
 
     BusArrivedAtStop(CarID, BusRouteID, BusStopID),
     BusDepartedFromStop(CarID, BusRouteID, BusStopID),
+    // Only for buses following a timetable; the last field is when the bus should've arrived.
+    ScheduledBusArrival(CarID, BusRouteID, BusStopID, Time),
+    // Passengers riding and capacity, as the bus leaves the stop
+    BusLoadMeasured(CarID, BusStopID, usize, usize),
 
     PedReachedParkingSpot(PedestrianID, ParkingSpot),
     PedReachedBuilding(PedestrianID, BuildingID),
@@ -19,11 +23,15 @@ pub enum Event {
     PedReachedBusStop(PedestrianID, BusStopID, BusRouteID),
     PedEntersBus(PedestrianID, CarID, BusRouteID),
     PedLeavesBus(PedestrianID, CarID, BusRouteID),
+    // The bus was full, so the pedestrian keeps waiting at the stop.
+    PedDeniedBoarding(PedestrianID, CarID, BusStopID),
 
     BikeStoppedAtSidewalk(CarID, LaneID),
 
     AgentEntersTraversable(AgentID, Traversable),
     IntersectionDelayMeasured(IntersectionID, Duration),
+    // Only when a pedestrian starts crossing something at worse than LevelOfService::A
+    PedestrianCrowding(Traversable, LevelOfService),
 
     TripFinished(TripID, TripMode, Duration),
     TripAborted(TripID),
@@ -0,0 +1,122 @@
+use crate::{Sim, ThruputQuery, TripMode};
+use geojson::{GeoJson, Value};
+use geom::Time;
+use map_model::export::{collection, feature, pts_to_gps, ring_to_gps, Properties};
+use map_model::Map;
+use serde_json::Value as JsonValue;
+
+// Map geometry joined with simulation results, in WGS84, for GIS tools like QGIS. Each layer only
+// has one type of geometry, so they're written separately.
+impl Sim {
+    pub fn export_geojson(&self, map: &Map, dir: &str) {
+        abstutil::write_json(format!("{}/roads.geojson", dir), &self.roads_geojson(map));
+        abstutil::write_json(
+            format!("{}/intersections.geojson", dir),
+            &self.intersections_geojson(map),
+        );
+        abstutil::write_json(
+            format!("{}/buildings.geojson", dir),
+            &self.buildings_geojson(map),
+        );
+    }
+
+    // Every road, with how many agents of each mode entered it so far
+    pub fn roads_geojson(&self, map: &Map) -> GeoJson {
+        let gps_bounds = map.get_gps_bounds();
+        let mut per_mode = Vec::new();
+        for mode in TripMode::all() {
+            let table = self.get_analytics().road_thruput_table(&ThruputQuery {
+                objects: map.all_roads().iter().map(|r| r.id).collect(),
+                start: Time::START_OF_DAY,
+                end: self.time(),
+                // Usually just one bucket, unless the simulation went past midnight
+                bucket_size: Time::END_OF_DAY - Time::START_OF_DAY,
+                modes: vec![mode].into_iter().collect(),
+            });
+            per_mode.push((mode, table));
+        }
+
+        let mut features = Vec::new();
+        for r in map.all_roads() {
+            let mut props = Properties::new();
+            props.insert("id".to_string(), r.id.0.into());
+            props.insert("osm_way_id".to_string(), r.orig_id.osm_way_id.into());
+            props.insert("name".to_string(), r.get_name().into());
+            let mut total = 0;
+            for (mode, table) in &per_mode {
+                let cnt: usize = table.counts[&r.id].iter().sum();
+                props.insert(format!("{:?}", mode).to_lowercase(), cnt.into());
+                total += cnt;
+            }
+            props.insert("total".to_string(), total.into());
+            features.push(feature(
+                Value::LineString(pts_to_gps(r.center_pts.points(), gps_bounds)),
+                props,
+            ));
+        }
+        collection(features)
+    }
+
+    // Every intersection, with percentiles of how long agents waited there so far. Intersections
+    // nobody waited at have null delays.
+    pub fn intersections_geojson(&self, map: &Map) -> GeoJson {
+        let gps_bounds = map.get_gps_bounds();
+        let mut features = Vec::new();
+        for i in map.all_intersections() {
+            let delays =
+                self.get_analytics()
+                    .intersection_delays(i.id, Time::START_OF_DAY, self.time());
+            let mut props = Properties::new();
+            props.insert("id".to_string(), i.id.0.into());
+            props.insert("osm_node_id".to_string(), i.orig_id.osm_node_id.into());
+            for (key, p) in vec![
+                ("delay_p50", 50.0),
+                ("delay_p90", 90.0),
+                ("delay_p99", 99.0),
+            ] {
+                props.insert(
+                    key.to_string(),
+                    delays
+                        .percentile(p)
+                        .map(|d| d.inner_seconds().into())
+                        .unwrap_or(JsonValue::Null),
+                );
+            }
+            features.push(feature(
+                Value::Polygon(vec![ring_to_gps(i.polygon.points(), gps_bounds)]),
+                props,
+            ));
+        }
+        collection(features)
+    }
+
+    // Every building, with how many trips start and end there
+    pub fn buildings_geojson(&self, map: &Map) -> GeoJson {
+        let gps_bounds = map.get_gps_bounds();
+        let mut features = Vec::new();
+        for b in map.all_buildings() {
+            let cnt = self.count_trips_involving_bldg(b.id);
+            let mut props = Properties::new();
+            props.insert("id".to_string(), b.id.0.into());
+            props.insert("osm_way_id".to_string(), b.osm_way_id.into());
+            props.insert(
+                "trips_from".to_string(),
+                (cnt.from_aborted + cnt.from_in_progress + cnt.from_completed + cnt.from_unstarted)
+                    .into(),
+            );
+            props.insert(
+                "trips_to".to_string(),
+                (cnt.to_aborted + cnt.to_in_progress + cnt.to_completed + cnt.to_unstarted).into(),
+            );
+            props.insert(
+                "trips_completed".to_string(),
+                (cnt.from_completed + cnt.to_completed).into(),
+            );
+            features.push(feature(
+                Value::Polygon(vec![ring_to_gps(b.polygon.points(), gps_bounds)]),
+                props,
+            ));
+        }
+        collection(features)
+    }
+}
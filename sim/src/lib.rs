@@ -1,26 +1,36 @@
 mod analytics;
+mod event_log;
 mod events;
+mod export;
 mod make;
 mod mechanics;
 mod render;
 mod router;
 mod scheduler;
 mod sim;
+mod trajectories;
 mod transit;
 mod trips;
 
-pub use self::analytics::{Analytics, TripPhase};
+pub use self::analytics::{Analytics, OnTimePerformance, ThruputQuery, ThruputTable, TripPhase};
+pub use self::event_log::EventLog;
 pub use self::events::Event;
 pub use self::make::{
-    ABTest, BorderSpawnOverTime, OriginDestination, Scenario, SeedParkedCars, SimFlags,
-    SpawnOverTime, SpawnTrip, TripSpawner, TripSpec,
+    ABTest, Activity, BorderSpawnOverTime, BusTimetable, Headway, OriginDestination, PersonSpec,
+    Scenario, ScheduledRun, SeedParkedCars, SimFlags, SpawnOverTime, SpawnTrip, TripSpawner,
+    TripSpec,
+};
+pub use self::mechanics::{
+    Admission, FreeformPolicy, IntersectionController, IntersectionState, LevelOfService, Request,
+    StopSignPolicy, TrafficSignalPolicy,
 };
 pub(crate) use self::mechanics::{
-    DrivingSimState, IntersectionSimState, ParkingSimState, WalkingSimState,
+    DrivingSimState, IntersectionSimState, ParkingSimState, WalkingSimState, DEFAULT_PARKING_STAY,
 };
 pub(crate) use self::router::{ActionAtEnd, Router};
 pub(crate) use self::scheduler::{Command, Scheduler};
 pub use self::sim::{Sim, SimOptions};
+pub use self::trajectories::{TrajectoryFrame, TrajectoryRecorder};
 pub(crate) use self::transit::TransitSimState;
 pub use self::trips::{TripCount, TripResult};
 pub use self::trips::{TripEnd, TripMode, TripStart};
@@ -30,7 +40,7 @@ pub use crate::render::{
     GetDrawAgents, PedCrowdLocation, UnzoomedAgent,
 };
 use abstutil::Cloneable;
-use geom::{Distance, Pt2D, Speed, Time};
+use geom::{Distance, Duration, Pt2D, Speed, Time};
 use map_model::{
     BuildingID, BusStopID, DirectedRoadID, IntersectionID, LaneID, Map, Path, PathConstraints,
     PathRequest, Position,
@@ -44,8 +54,6 @@ pub const BIKE_LENGTH: Distance = Distance::const_meters(1.8);
 // These two must be < PARKING_SPOT_LENGTH
 pub const MIN_CAR_LENGTH: Distance = Distance::const_meters(4.5);
 pub const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
-// Note this is more than MAX_CAR_LENGTH
-pub const BUS_LENGTH: Distance = Distance::const_meters(12.5);
 
 // At all speeds (including at rest), cars must be at least this far apart, measured from front of
 // one car to the back of the other.
@@ -72,6 +80,17 @@ impl fmt::Display for PedestrianID {
     }
 }
 
+// Somebody going through a schedule of activities. They're represented by the same pedestrian on
+// every trip.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PersonID(pub usize);
+
+impl fmt::Display for PersonID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PersonID({0})", self.0)
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
 pub enum AgentID {
     Car(CarID),
@@ -110,6 +129,7 @@ pub enum VehicleType {
     Car,
     Bus,
     Bike,
+    Train,
 }
 
 impl fmt::Display for VehicleType {
@@ -118,6 +138,7 @@ impl fmt::Display for VehicleType {
             VehicleType::Car => write!(f, "car"),
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::Train => write!(f, "train"),
         }
     }
 }
@@ -128,8 +149,14 @@ impl VehicleType {
             VehicleType::Car => PathConstraints::Car,
             VehicleType::Bus => PathConstraints::Bus,
             VehicleType::Bike => PathConstraints::Bike,
+            VehicleType::Train => PathConstraints::Train,
         }
     }
+
+    // Serves a transit route, carrying passengers between stops
+    pub fn is_transit(self) -> bool {
+        self == VehicleType::Bus || self == VehicleType::Train
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -217,13 +244,22 @@ impl DrivingGoal {
                     let l = map.find_biking_lane_near_building(*b);
                     Position::new(l, map.get_l(l).length() / 2.0)
                 }
-                PathConstraints::Bus | PathConstraints::Pedestrian => unreachable!(),
+                PathConstraints::Bus | PathConstraints::Train | PathConstraints::Pedestrian => {
+                    unreachable!()
+                }
             },
             DrivingGoal::Border(_, l) => Position::new(*l, map.get_l(*l).length()),
         }
     }
 
-    pub(crate) fn make_router(&self, path: Path, map: &Map, vt: VehicleType) -> Router {
+    // stay only matters for cars parking near a building.
+    pub(crate) fn make_router(
+        &self,
+        path: Path,
+        map: &Map,
+        vt: VehicleType,
+        stay: Duration,
+    ) -> Router {
         match self {
             DrivingGoal::ParkNear(b) => {
                 if vt == VehicleType::Bike {
@@ -231,7 +267,7 @@ impl DrivingGoal {
                     let end = path.last_step().as_lane();
                     Router::bike_then_stop(path, map.get_l(end).length() / 2.0)
                 } else {
-                    Router::park_near(path, *b)
+                    Router::park_near(path, *b, stay)
                 }
             }
             DrivingGoal::Border(i, last_lane) => {
@@ -29,6 +29,11 @@ impl SimFlags {
                 use_freeform_policy_everywhere: args.enabled("--freeform_policy"),
                 disable_block_the_box: args.enabled("--disable_block_the_box"),
                 recalc_lanechanging: !args.enabled("--dont_recalc_lc"),
+                event_log: None,
+                record_trajectories: None,
+                reroute_blocked_after: args
+                    .optional_parse("--reroute_blocked_after", Duration::parse),
+                reroute_around_closures: args.enabled("--reroute_around_closures"),
             },
         }
     }
@@ -2,10 +2,13 @@ mod a_b_test;
 mod load;
 mod scenario;
 mod spawner;
+mod timetable;
 
 pub use self::a_b_test::ABTest;
 pub use self::load::SimFlags;
 pub use self::scenario::{
-    BorderSpawnOverTime, OriginDestination, Scenario, SeedParkedCars, SpawnOverTime, SpawnTrip,
+    Activity, BorderSpawnOverTime, OriginDestination, PersonSpec, Scenario, SeedParkedCars,
+    SpawnOverTime, SpawnTrip,
 };
 pub use self::spawner::{TripSpawner, TripSpec};
+pub use self::timetable::{BusTimetable, Headway, ScheduledRun};
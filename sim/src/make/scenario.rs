@@ -1,6 +1,6 @@
 use crate::{
-    CarID, DrivingGoal, ParkingSpot, SidewalkSpot, Sim, TripSpec, VehicleSpec, VehicleType,
-    BIKE_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
+    BusTimetable, CarID, DrivingGoal, ParkingSpot, SidewalkSpot, Sim, TripMode, TripSpec,
+    VehicleSpec, VehicleType, BIKE_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
 };
 use abstutil::{fork_rng, prettyprint_usize, Timer, WeightedUsizeChoice};
 use geom::{Distance, Duration, Speed, Time};
@@ -22,15 +22,39 @@ pub struct Scenario {
     // Higher-level ways of specifying stuff
     // None means seed all buses. Otherwise the route name must be present here.
     pub only_seed_buses: Option<BTreeSet<String>>,
+    // Routes listed here are dispatched by timetable instead of seeded, whether or not they're in
+    // only_seed_buses.
+    pub bus_timetables: Vec<BusTimetable>,
     pub seed_parked_cars: Vec<SeedParkedCars>,
     pub spawn_over_time: Vec<SpawnOverTime>,
     pub border_spawn_over_time: Vec<BorderSpawnOverTime>,
 
     // Much more detailed
     pub individ_trips: Vec<SpawnTrip>,
+    pub people: Vec<PersonSpec>,
     pub individ_parked_cars: BTreeMap<BuildingID, usize>,
 }
 
+// Somebody going from building to building over the day. Each trip starts when the previous
+// activity ends, and the same car or bike gets used throughout.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PersonSpec {
+    // Where the day starts
+    pub home: BuildingID,
+    // When the person leaves home for the first activity
+    pub depart: Time,
+    pub activities: Vec<Activity>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Activity {
+    pub bldg: BuildingID,
+    // How to get here from the previous building
+    pub mode: TripMode,
+    // How long to stay here before leaving for the next activity
+    pub duration: Duration,
+}
+
 // SpawnOverTime and BorderSpawnOverTime should be kept separate. Agents in SpawnOverTime pick
 // their mode (use a car, walk, bus) based on the situation. When spawning directly a border,
 // agents have to start as a car or pedestrian already.
@@ -82,6 +106,11 @@ impl Scenario {
                 prettyprint_usize(self.border_spawn_over_time.len())
             ),
             format!("{} SpawnTrip", prettyprint_usize(self.individ_trips.len())),
+            format!("{} PersonSpec", prettyprint_usize(self.people.len())),
+            format!(
+                "{} BusTimetable",
+                prettyprint_usize(self.bus_timetables.len())
+            ),
         ]
     }
 
@@ -91,16 +120,29 @@ impl Scenario {
 
         timer.start(format!("Instantiating {}", self.scenario_name));
 
-        if let Some(ref routes) = self.only_seed_buses {
-            for route in map.get_all_bus_routes() {
-                if routes.contains(&route.name) {
-                    sim.seed_bus_route(route, map, timer);
+        let scheduled: BTreeSet<&String> =
+            self.bus_timetables.iter().map(|t| &t.route_name).collect();
+        for route in map.get_all_bus_routes() {
+            if scheduled.contains(&route.name) {
+                continue;
+            }
+            if let Some(ref routes) = self.only_seed_buses {
+                if !routes.contains(&route.name) {
+                    continue;
                 }
             }
-        } else {
-            // All of them
-            for route in map.get_all_bus_routes() {
-                sim.seed_bus_route(route, map, timer);
+            sim.seed_bus_route(route, map, timer);
+        }
+        for timetable in &self.bus_timetables {
+            if let Some(route) = map.get_bus_route(&timetable.route_name) {
+                if let Err(err) = sim.dispatch_bus_route(route, timetable, map, timer) {
+                    timer.warn(err);
+                }
+            } else {
+                timer.warn(format!(
+                    "Timetable for {} doesn't match any bus route",
+                    timetable.route_name
+                ));
             }
         }
 
@@ -163,10 +205,30 @@ impl Scenario {
             sim.schedule_trip(depart, spec, map);
         }
 
+        timer.start_iter("PersonSpec", self.people.len());
+        for p in &self.people {
+            timer.next();
+            let bike = if p.activities.iter().any(|a| a.mode == TripMode::Bike) {
+                Some(Scenario::rand_bike(rng))
+            } else {
+                None
+            };
+            sim.schedule_person(p, Scenario::rand_ped_speed(rng), bike);
+        }
+
         sim.spawn_all_trips(map, timer, true);
         timer.stop(format!("Instantiating {}", self.scenario_name));
     }
 
+    // Dispatch buses using the real schedules from GTFS, wherever the map has them.
+    pub fn use_gtfs_timetables(&mut self, map: &Map) {
+        self.bus_timetables = map
+            .get_all_bus_routes()
+            .iter()
+            .filter_map(BusTimetable::from_gtfs)
+            .collect();
+    }
+
     pub fn save(&self) {
         abstutil::write_binary(
             abstutil::path_scenario(&self.map_name, &self.scenario_name),
@@ -178,6 +240,7 @@ impl Scenario {
         let mut s = Scenario {
             scenario_name: "small_run".to_string(),
             only_seed_buses: None,
+            bus_timetables: Vec::new(),
             map_name: map.get_name().to_string(),
             seed_parked_cars: vec![SeedParkedCars {
                 neighborhood: "_everywhere_".to_string(),
@@ -211,6 +274,7 @@ impl Scenario {
                 })
                 .collect(),
             individ_trips: Vec::new(),
+            people: Vec::new(),
             individ_parked_cars: BTreeMap::new(),
         };
         for i in map.all_outgoing_borders() {
@@ -232,10 +296,12 @@ impl Scenario {
             scenario_name: name.to_string(),
             map_name: map.get_name().to_string(),
             only_seed_buses: Some(BTreeSet::new()),
+            bus_timetables: Vec::new(),
             seed_parked_cars: Vec::new(),
             spawn_over_time: Vec::new(),
             border_spawn_over_time: Vec::new(),
             individ_trips: Vec::new(),
+            people: Vec::new(),
             individ_parked_cars: BTreeMap::new(),
         }
     }
@@ -246,6 +312,7 @@ impl Scenario {
             scenario_name: "scaled_run".to_string(),
             map_name: map.get_name().to_string(),
             only_seed_buses: Some(BTreeSet::new()),
+            bus_timetables: Vec::new(),
             seed_parked_cars: vec![SeedParkedCars {
                 neighborhood: "_everywhere_".to_string(),
                 cars_per_building: WeightedUsizeChoice {
@@ -263,6 +330,7 @@ impl Scenario {
             }],
             border_spawn_over_time: Vec::new(),
             individ_trips: Vec::new(),
+            people: Vec::new(),
             individ_parked_cars: BTreeMap::new(),
         }
     }
@@ -193,7 +193,7 @@ impl TripSpawner {
             std::mem::replace(&mut self.trips, Vec::new()),
             |tuple| {
                 let req = tuple.3.get_pathfinding_request(map, parking);
-                (tuple, req.clone(), map.pathfind(req))
+                (tuple, req.clone(), map.pathfind_at(req, tuple.0))
             },
         );
 
@@ -226,8 +226,14 @@ impl TripSpawner {
                     }
                     let trip_start = TripStart::Border(map.get_l(start_pos.lane()).src_i);
                     let trip = trips.new_trip(start_time, trip_start, legs);
+                    let maybe_path = trips.fixed_car_route(trip, &req, map).or(maybe_path);
                     if let Some(path) = maybe_path {
-                        let router = goal.make_router(path, map, vehicle.vehicle_type);
+                        let router = goal.make_router(
+                            path,
+                            map,
+                            vehicle.vehicle_type,
+                            trips.expected_stay(trip),
+                        );
                         scheduler.quick_push(
                             start_time,
                             Command::SpawnCar(
@@ -0,0 +1,169 @@
+use geom::{Duration, Time};
+use map_model::BusRoute;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// Instead of seeding a few buses that loop forever, dispatch one bus per run. Each bus serves the
+// route's stops once, in order, and then disappears.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BusTimetable {
+    pub route_name: String,
+    // When buses leave the first stop
+    pub departures: Vec<Time>,
+    // More departures, for when buses just come every few minutes
+    pub headways: Vec<Headway>,
+    // How long after leaving the first stop a bus is scheduled to reach each stop along the route,
+    // so the first entry is zero. Only used by departures and headways.
+    pub time_to_stop: Vec<Duration>,
+    // Runs with their own time at each stop, like the ones imported from GTFS
+    pub runs: Vec<ScheduledRun>,
+    // Indices into the route's stops. Buses that're early wait here until the scheduled time. The
+    // first stop always acts like a timepoint.
+    pub timepoints: BTreeSet<usize>,
+}
+
+// Departures every headway, from start until before end
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Headway {
+    pub start: Time,
+    pub end: Time,
+    pub every: Duration,
+}
+
+// One bus serving a contiguous part of the route, like just one direction
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ScheduledRun {
+    // Index into the route's stops where the bus starts
+    pub first_stop: usize,
+    // When the bus is scheduled to reach each stop, starting with first_stop. The bus leaves the
+    // first stop at the first time.
+    pub stop_times: Vec<Time>,
+}
+
+impl BusTimetable {
+    // Use the schedule imported from GTFS, if the route has one. Runs leaving after the end of the
+    // day are dropped.
+    pub fn from_gtfs(route: &BusRoute) -> Option<BusTimetable> {
+        let schedule = route.schedule.as_ref()?;
+        let day = Time::END_OF_DAY - Time::START_OF_DAY;
+        let runs: Vec<ScheduledRun> = schedule
+            .trips
+            .iter()
+            .filter_map(|times| {
+                let first_stop = times.iter().position(|dt| dt.is_some())?;
+                let stop_times: Vec<Time> = times[first_stop..]
+                    .iter()
+                    .take_while(|dt| dt.is_some())
+                    .map(|dt| Time::START_OF_DAY + dt.unwrap())
+                    .collect();
+                if stop_times[0] - Time::START_OF_DAY >= day {
+                    return None;
+                }
+                Some(ScheduledRun {
+                    first_stop,
+                    stop_times,
+                })
+            })
+            .collect();
+        if runs.is_empty() {
+            return None;
+        }
+        Some(BusTimetable {
+            route_name: route.name.clone(),
+            departures: Vec::new(),
+            headways: Vec::new(),
+            time_to_stop: Vec::new(),
+            runs,
+            timepoints: BTreeSet::new(),
+        })
+    }
+
+    // Every run from the departures, headways, and explicit runs, sorted by when they leave.
+    // Departures are deduplicated.
+    pub fn all_runs(&self) -> Vec<ScheduledRun> {
+        let mut times = self.departures.clone();
+        for h in &self.headways {
+            let mut t = h.start;
+            while t < h.end {
+                times.push(t);
+                t = t + h.every;
+            }
+        }
+        times.sort();
+        times.dedup();
+
+        let mut runs: Vec<ScheduledRun> = times
+            .into_iter()
+            .map(|departure| ScheduledRun {
+                first_stop: 0,
+                stop_times: self.time_to_stop.iter().map(|dt| departure + *dt).collect(),
+            })
+            .chain(self.runs.iter().cloned())
+            .collect();
+        runs.sort_by_key(|r| r.stop_times[0]);
+        runs
+    }
+
+    pub fn validate(&self, route: &BusRoute) -> Result<(), String> {
+        if !self.departures.is_empty() || !self.headways.is_empty() {
+            if self.time_to_stop.len() != route.stops.len() {
+                return Err(format!(
+                    "Timetable for {} has {} stop times, but the route has {} stops",
+                    route.name,
+                    self.time_to_stop.len(),
+                    route.stops.len()
+                ));
+            }
+            if self.time_to_stop[0] != Duration::ZERO {
+                return Err(format!(
+                    "Timetable for {} doesn't start at the first stop",
+                    route.name
+                ));
+            }
+            if self.time_to_stop.windows(2).any(|pair| pair[0] > pair[1]) {
+                return Err(format!(
+                    "Timetable for {} goes back in time between stops",
+                    route.name
+                ));
+            }
+        }
+        for run in &self.runs {
+            if run.stop_times.len() < 2 || run.first_stop + run.stop_times.len() > route.stops.len()
+            {
+                return Err(format!(
+                    "Timetable for {} has a run from stop {} with {} stop times, but the route \
+                     has {} stops",
+                    route.name,
+                    run.first_stop,
+                    run.stop_times.len(),
+                    route.stops.len()
+                ));
+            }
+            if run.stop_times.windows(2).any(|pair| pair[0] > pair[1]) {
+                return Err(format!(
+                    "Timetable for {} has a run going back in time between stops",
+                    route.name
+                ));
+            }
+        }
+        if let Some(idx) = self
+            .timepoints
+            .iter()
+            .find(|idx| **idx >= route.stops.len())
+        {
+            return Err(format!(
+                "Timetable for {} has a timepoint at stop {}, but the route only has {} stops",
+                route.name,
+                idx,
+                route.stops.len()
+            ));
+        }
+        if let Some(h) = self.headways.iter().find(|h| h.every <= Duration::ZERO) {
+            return Err(format!(
+                "Timetable for {} has a headway of {}",
+                route.name, h.every
+            ));
+        }
+        Ok(())
+    }
+}
@@ -14,6 +14,8 @@ pub struct Car {
     pub router: Router,
     pub trip: TripID,
     pub blocked_since: Option<Time>,
+    // While blocked, when the car last looked for a better route
+    pub last_reroute_attempt: Option<Time>,
     pub started_at: Time,
 
     // In reverse order -- most recently left is first. The sum length of these must be >=
@@ -159,7 +161,7 @@ impl Car {
                 CarState::Idling(_, _) => CarStatus::Parked,
             },
             on: self.router.head(),
-            label: if self.vehicle.vehicle_type == VehicleType::Bus {
+            label: if self.vehicle.vehicle_type.is_transit() {
                 Some(
                     map.get_br(transit.bus_route(self.vehicle.id))
                         .name
@@ -0,0 +1,322 @@
+use crate::mechanics::intersection::{IntersectionState, Request, SignalState};
+use geom::{Duration, Speed, Time};
+use map_model::{ControlTrafficSignal, Map, Phase, PhaseType, TurnPriority, TurnType};
+
+const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
+const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
+
+// Decides who gets to go at one intersection. IntersectionSimState handles everything common to
+// all policies -- tracking waiting and accepted requests, not blocking the box, measuring delay --
+// and defers to one of these for the actual admission decisions.
+//
+// Controllers themselves are stateless, so they can be shared freely. The built-in traffic signal
+// keeps its current phase in IntersectionState.
+pub trait IntersectionController: Send + Sync {
+    // Savestates can't hold controllers, so they remember this name instead. See
+    // Sim::load_savestate_with_controllers.
+    fn name(&self) -> String;
+
+    // Called when the agent is ready to start the turn. The request is already in
+    // state.waiting().
+    fn maybe_start_turn(
+        &self,
+        state: &IntersectionState,
+        req: &Request,
+        speed: Speed,
+        now: Time,
+        map: &Map,
+    ) -> Admission;
+
+    // When something changes at the intersection, waiting agents are woken up to retry. Protected
+    // turns go first, Yield turns a moment later, and Banned turns aren't woken up at all.
+    fn wakeup_priority(
+        &self,
+        state: &IntersectionState,
+        req: &Request,
+        now: Time,
+        map: &Map,
+    ) -> TurnPriority;
+
+    // Called at the start of the simulation and whenever a previously requested update happens.
+    // Returns the time of the next update, if one is needed. Waiting agents are woken up after
+    // every update.
+    fn update(&self, _state: &mut IntersectionState, _now: Time, _map: &Map) -> Option<Time> {
+        None
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Admission {
+    Allow,
+    // The agent will be woken up when something changes at the intersection.
+    Deny,
+    // The agent will be woken up at this time, even if nothing else happens.
+    RetryAt(Time),
+}
+
+pub struct FreeformPolicy;
+
+impl IntersectionController for FreeformPolicy {
+    fn name(&self) -> String {
+        "freeform".to_string()
+    }
+
+    fn maybe_start_turn(
+        &self,
+        state: &IntersectionState,
+        req: &Request,
+        _: Speed,
+        _: Time,
+        map: &Map,
+    ) -> Admission {
+        // Allow concurrent turns that don't conflict
+        if state.any_accepted_conflict_with(req.turn, map) {
+            return Admission::Deny;
+        }
+
+        Admission::Allow
+    }
+
+    fn wakeup_priority(
+        &self,
+        _: &IntersectionState,
+        _: &Request,
+        _: Time,
+        _: &Map,
+    ) -> TurnPriority {
+        TurnPriority::Protected
+    }
+}
+
+pub struct StopSignPolicy;
+
+impl IntersectionController for StopSignPolicy {
+    fn name(&self) -> String {
+        "stop sign".to_string()
+    }
+
+    fn maybe_start_turn(
+        &self,
+        state: &IntersectionState,
+        req: &Request,
+        _: Speed,
+        now: Time,
+        map: &Map,
+    ) -> Admission {
+        if state.any_accepted_conflict_with(req.turn, map) {
+            return Admission::Deny;
+        }
+
+        let sign = map.get_stop_sign(state.id());
+        let our_priority = sign.get_priority(req.turn, map);
+        assert!(our_priority != TurnPriority::Banned);
+        let our_time = state.waiting_since(req);
+
+        if our_priority == TurnPriority::Yield && now < our_time + WAIT_AT_STOP_SIGN {
+            return Admission::RetryAt(our_time + WAIT_AT_STOP_SIGN);
+        }
+
+        // Once upon a time, we'd make sure that this request doesn't conflict with another in
+        // self.waiting:
+        // 1) Higher-ranking turns get to go first.
+        // 2) Equal-ranking turns that started waiting before us get to go first.
+        // But the exceptions started stacking -- if the other agent is blocked or the turns don't
+        // even conflict, then allow it. Except determining if the other agent is blocked or not is
+        // tough and kind of recursive.
+        //
+        // So instead, don't do any of that! The WAIT_AT_STOP_SIGN scheduling above and the fact
+        // that events are processed in time order mean that case #2 is magically handled anyway.
+        // If a case #1 could've started by now, then they would have. Since they didn't, they must
+        // be blocked.
+
+        // TODO Make sure we can optimistically finish this turn before an approaching
+        // higher-priority vehicle wants to begin.
+
+        Admission::Allow
+    }
+
+    fn wakeup_priority(
+        &self,
+        state: &IntersectionState,
+        req: &Request,
+        _: Time,
+        map: &Map,
+    ) -> TurnPriority {
+        // Banned is impossible
+        if map.get_stop_sign(state.id()).get_priority(req.turn, map) == TurnPriority::Protected {
+            TurnPriority::Protected
+        } else {
+            TurnPriority::Yield
+        }
+    }
+}
+
+pub struct TrafficSignalPolicy;
+
+impl IntersectionController for TrafficSignalPolicy {
+    fn name(&self) -> String {
+        "traffic signal".to_string()
+    }
+
+    fn maybe_start_turn(
+        &self,
+        state: &IntersectionState,
+        req: &Request,
+        speed: Speed,
+        now: Time,
+        map: &Map,
+    ) -> Admission {
+        let turn = map.get_t(req.turn);
+
+        // SharedSidewalkCorner doesn't conflict with anything -- fastpath!
+        if turn.turn_type == TurnType::SharedSidewalkCorner {
+            return Admission::Allow;
+        }
+
+        let signal = map.get_traffic_signal(state.id());
+        let (idx, remaining_phase_time) = state.current_signal_phase(signal, now);
+        let phase = &signal.phases[idx];
+
+        // Can't go at all this phase.
+        let our_priority = phase.get_priority_of_turn(req.turn, signal);
+        if our_priority == TurnPriority::Banned {
+            return Admission::Deny;
+        }
+
+        // Somebody might already be doing a Yield turn that conflicts with this one.
+        if state.any_accepted_conflict_with(req.turn, map) {
+            return Admission::Deny;
+        }
+
+        let our_time = state.waiting_since(req);
+        if our_priority == TurnPriority::Yield
+            && now < our_time + WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL
+        {
+            return Admission::RetryAt(our_time + WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL);
+        }
+
+        // Previously: A yield loses to a conflicting Priority turn.
+        // But similar to the description in StopSignPolicy, this caused unnecessary gridlock.
+        // Priority vehicles getting scheduled first just requires a little tweak in
+        // wakeup_priority.
+
+        // TODO Make sure we can optimistically finish this turn before an approaching
+        // higher-priority vehicle wants to begin.
+
+        // Optimistically if nobody else is in the way, this is how long it'll take to finish the
+        // turn. Don't start the turn if we won't finish by the time the light changes. If we get
+        // it wrong, that's fine -- block the box a bit.
+        let time_to_cross = turn.geom.length() / speed;
+        if time_to_cross > remaining_phase_time {
+            // Actually, we might have bigger problems...
+            if time_to_cross > phase.phase_type.simple_duration() {
+                println!(
+                    "OYYY! {:?} is impossible to fit into phase duration of {}. Allowing, but fix \
+                     the policy!",
+                    req,
+                    phase.phase_type.simple_duration()
+                );
+            } else {
+                return Admission::Deny;
+            }
+        }
+
+        Admission::Allow
+    }
+
+    fn wakeup_priority(
+        &self,
+        state: &IntersectionState,
+        req: &Request,
+        now: Time,
+        map: &Map,
+    ) -> TurnPriority {
+        let signal = map.get_traffic_signal(state.id());
+        let (idx, _) = state.current_signal_phase(signal, now);
+        signal.phases[idx].get_priority_of_turn(req.turn, signal)
+    }
+
+    fn update(&self, state: &mut IntersectionState, now: Time, map: &Map) -> Option<Time> {
+        let signal = map.get_traffic_signal(state.id());
+        let current = match state.signal {
+            Some(ref s) if s.current_phase < signal.phases.len() => s.clone(),
+            _ => {
+                // Start the day (or resume after the signal is edited) wherever the fixed-time
+                // schedule and offset say.
+                let (idx, _, remaining) = signal.current_phase_and_remaining_time(now);
+                state.signal = Some(SignalState {
+                    current_phase: idx,
+                    phase_started: now,
+                    phase_ends: now + remaining,
+                });
+                return Some(now + remaining);
+            }
+        };
+        // Not time to change yet; this happens when the controller is registered again.
+        if now < current.phase_ends {
+            return Some(current.phase_ends);
+        }
+
+        // Keep an actuated phase going while there's still traffic using it.
+        let phase = &signal.phases[current.current_phase];
+        if let PhaseType::Actuated {
+            max_green,
+            extension,
+            ..
+        } = phase.phase_type
+        {
+            let max_end = current.phase_started + max_green;
+            if now < max_end
+                && has_demand(
+                    phase,
+                    signal,
+                    state.waiting().keys().chain(state.accepted().iter()),
+                    map,
+                )
+            {
+                let phase_ends = (now + extension).min(max_end);
+                state.signal = Some(SignalState {
+                    phase_ends,
+                    ..current
+                });
+                return Some(phase_ends);
+            }
+        }
+
+        // Skip actuated phases that nobody is waiting for. If nobody's waiting for anything, this
+        // wraps around and just moves to the next phase.
+        let num_phases = signal.phases.len();
+        let mut next = (current.current_phase + 1) % num_phases;
+        for _ in 0..num_phases {
+            let p = &signal.phases[next];
+            if let PhaseType::Fixed(_) = p.phase_type {
+                break;
+            }
+            if has_demand(p, signal, state.waiting().keys(), map) {
+                break;
+            }
+            next = (next + 1) % num_phases;
+        }
+
+        let phase_ends = now + signal.phases[next].phase_type.initial_duration();
+        state.signal = Some(SignalState {
+            current_phase: next,
+            phase_started: now,
+            phase_ends,
+        });
+        Some(phase_ends)
+    }
+}
+
+// Does anybody in reqs want a turn that this phase allows?
+fn has_demand<'a, I: Iterator<Item = &'a Request>>(
+    phase: &Phase,
+    signal: &ControlTrafficSignal,
+    mut reqs: I,
+    map: &Map,
+) -> bool {
+    reqs.any(|req| {
+        map.get_t(req.turn).turn_type != TurnType::SharedSidewalkCorner
+            && phase.get_priority_of_turn(req.turn, signal) != TurnPriority::Banned
+    })
+}
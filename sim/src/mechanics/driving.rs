@@ -8,18 +8,24 @@ use crate::{
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
 use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{BuildingID, LaneID, Map, Path, PathStep, Traversable};
+use map_model::{
+    BuildingID, LaneID, Map, Path, PathRequest, PathStep, Position, Traversable, TurnID,
+};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
 const TIME_TO_UNPARK: Duration = Duration::const_seconds(10.0);
 const TIME_TO_PARK: Duration = Duration::const_seconds(15.0);
-const TIME_TO_WAIT_AT_STOP: Duration = Duration::const_seconds(10.0);
 
 // TODO Do something else.
 pub(crate) const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
 pub(crate) const BLIND_RETRY_TO_REACH_END_DIST: Duration = Duration::const_seconds(5.0);
 
+// A blocked car only switches to a new route if it looks at least this much faster.
+const REROUTE_IF_BETTER_BY: f64 = 0.8;
+// Never consider a lane more than this full when estimating congested travel time.
+const MAX_OCCUPANCY: f64 = 0.9;
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct DrivingSimState {
     #[serde(
@@ -33,17 +39,30 @@ pub struct DrivingSimState {
     )]
     queues: BTreeMap<Traversable, Queue>,
     events: Vec<Event>,
+    // Leaders waiting for everybody going the other way to leave a reversible corridor. They're
+    // woken up whenever somebody leaves a reversible lane.
+    waiting_for_corridor: BTreeSet<CarID>,
 
     recalc_lanechanging: bool,
+    reroute_blocked_after: Option<Duration>,
+    reroute_around_closures: bool,
 }
 
 impl DrivingSimState {
-    pub fn new(map: &Map, recalc_lanechanging: bool) -> DrivingSimState {
+    pub fn new(
+        map: &Map,
+        recalc_lanechanging: bool,
+        reroute_blocked_after: Option<Duration>,
+        reroute_around_closures: bool,
+    ) -> DrivingSimState {
         let mut sim = DrivingSimState {
             cars: BTreeMap::new(),
             queues: BTreeMap::new(),
             events: Vec::new(),
+            waiting_for_corridor: BTreeSet::new(),
             recalc_lanechanging,
+            reroute_blocked_after,
+            reroute_around_closures,
         };
 
         for l in map.all_lanes() {
@@ -91,6 +110,7 @@ impl DrivingSimState {
                 state: CarState::Queued,
                 last_steps: VecDeque::new(),
                 blocked_since: None,
+                last_reroute_attempt: None,
                 started_at: now,
                 trip: params.trip,
             };
@@ -107,6 +127,7 @@ impl DrivingSimState {
                         params.start_dist,
                         &car.vehicle,
                         parking,
+                        now,
                         map,
                         car.trip,
                         &mut self.events,
@@ -275,6 +296,7 @@ impl DrivingSimState {
                         front,
                         &car.vehicle,
                         parking,
+                        now,
                         map,
                         car.trip,
                         &mut self.events,
@@ -284,7 +306,7 @@ impl DrivingSimState {
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             }
             CarState::Idling(dist, _) => {
-                car.router = transit.bus_departed_from_stop(car.vehicle.id);
+                car.router = transit.bus_departed_from_stop(car.vehicle.id, now, map);
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
                 car.state = car.crossing_state(dist, now, map);
@@ -331,11 +353,63 @@ impl DrivingSimState {
             CarState::Queued => unreachable!(),
             CarState::WaitingToAdvance => {
                 // 'car' is the leader.
+                self.waiting_for_corridor.remove(&car.vehicle.id);
+                // Cars spawned with a path calculated before a live edit closed their next turn
+                // didn't get rerouted yet.
+                if let Traversable::Turn(t) = car.router.next() {
+                    if self.reroute_around_closures && map.maybe_get_t(t).is_none() {
+                        self.reroute_around_edits(car, now, map, intersections, scheduler);
+                        return false;
+                    }
+                }
+
+                if let Some(threshold) = self.reroute_blocked_after {
+                    if car.router.can_reroute()
+                        && car
+                            .blocked_since
+                            .map(|t| now - t >= threshold)
+                            .unwrap_or(false)
+                        && car
+                            .last_reroute_attempt
+                            .map(|t| now - t >= threshold)
+                            .unwrap_or(true)
+                    {
+                        self.reroute_blocked_leader(car, now, map, intersections);
+                    }
+                }
+
                 let from = car.router.head();
                 let goto = car.router.next();
                 assert!(from != goto);
 
                 if let Traversable::Turn(t) = goto {
+                    let constraints = car.vehicle.vehicle_type.to_constraints();
+                    if !map.is_turn_allowed_at(t, constraints, now) {
+                        // A conditional turn ban or part-time bus lane started, or a reversible
+                        // lane switched directions, since the path was calculated.
+                        if car.router.can_detour() {
+                            if let Some((_, steps, end_dist)) =
+                                self.best_route_from_lane(car, Some(t), now, map)
+                            {
+                                intersections.cancel_request(AgentID::Car(car.vehicle.id), t);
+                                car.router.reroute(steps, end_dist, map, &mut self.events);
+                                scheduler.update(now, Command::UpdateCar(car.vehicle.id));
+                                return false;
+                            }
+                        }
+                        // No way around, so wait for the turn to open up again.
+                        if let Some(time) = map.next_turn_change(t, constraints, now) {
+                            scheduler.update(time, Command::UpdateCar(car.vehicle.id));
+                        }
+                        return false;
+                    }
+                    if map.get_parent(t.dst).is_reversible(t.dst)
+                        && self.reversible_corridor_occupied(t, map)
+                    {
+                        self.waiting_for_corridor.insert(car.vehicle.id);
+                        return false;
+                    }
+
                     let mut speed = goto.speed_limit(map);
                     if let Some(s) = car.vehicle.max_speed {
                         speed = speed.min(s);
@@ -352,7 +426,23 @@ impl DrivingSimState {
                             &car,
                         )),
                     ) {
-                        // Don't schedule a retry here.
+                        // Don't schedule a retry here, except to check if it's time to reroute.
+                        // If something happens at the intersection sooner, we'll be woken up.
+                        if let (Some(threshold), Some(blocked_since)) =
+                            (self.reroute_blocked_after, car.blocked_since)
+                        {
+                            let since = car
+                                .last_reroute_attempt
+                                .map(|t| t.max(blocked_since))
+                                .unwrap_or(blocked_since);
+                            let check_at = if since + threshold > now {
+                                since + threshold
+                            } else {
+                                now + threshold
+                            };
+                            scheduler
+                                .update_if_sooner(check_at, Command::UpdateCar(car.vehicle.id));
+                        }
                         return false;
                     }
                 }
@@ -368,9 +458,10 @@ impl DrivingSimState {
 
                 let last_step =
                     car.router
-                        .advance(&car.vehicle, parking, map, car.trip, &mut self.events);
+                        .advance(&car.vehicle, parking, now, map, car.trip, &mut self.events);
                 car.state = car.crossing_state(Distance::ZERO, now, map);
                 car.blocked_since = None;
+                car.last_reroute_attempt = None;
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                 self.events.push(Event::AgentEntersTraversable(
                     AgentID::Car(car.vehicle.id),
@@ -444,6 +535,7 @@ impl DrivingSimState {
                     our_dist,
                     &car.vehicle,
                     parking,
+                    now,
                     map,
                     car.trip,
                     &mut self.events,
@@ -452,7 +544,7 @@ impl DrivingSimState {
                         trips.car_or_bike_reached_border(now, car.vehicle.id, i);
                     }
                     Some(ActionAtEnd::AbortTrip) => {
-                        trips.abort_trip_impossible_parking(car.vehicle.id);
+                        trips.abort_car_trip(car.vehicle.id);
                     }
                     Some(ActionAtEnd::StartParking(spot)) => {
                         car.state = CarState::Parking(
@@ -478,21 +570,23 @@ impl DrivingSimState {
                         trips.bike_reached_end(now, car.vehicle.id, bike_rack, map, scheduler);
                     }
                     Some(ActionAtEnd::BusAtStop) => {
-                        transit.bus_arrived_at_stop(
+                        if let Some(dwell_time) = transit.bus_arrived_at_stop(
                             now,
                             car.vehicle.id,
                             trips,
                             walking,
                             scheduler,
                             map,
-                        );
-                        car.state = CarState::Idling(
-                            our_dist,
-                            TimeInterval::new(now, now + TIME_TO_WAIT_AT_STOP),
-                        );
-                        scheduler
-                            .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
-                        return true;
+                        ) {
+                            car.state = CarState::Idling(
+                                our_dist,
+                                TimeInterval::new(now, now + dwell_time),
+                            );
+                            scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            return true;
+                        }
+                        // Otherwise the bus finished its run and vanishes.
                     }
                     None => {
                         scheduler.push(
@@ -537,6 +631,226 @@ impl DrivingSimState {
         false
     }
 
+    // The car is the leader of its queue, waiting to start a turn, and has been stuck for a while.
+    // If some other way to the same destination looks much better, take it.
+    fn reroute_blocked_leader(
+        &mut self,
+        car: &mut Car,
+        now: Time,
+        map: &Map,
+        intersections: &mut IntersectionSimState,
+    ) {
+        // Searching is expensive, so wait another full threshold before trying again, even if
+        // nothing better turns up.
+        car.last_reroute_attempt = Some(now);
+        // Cars waiting at the end of a turn have nowhere else to go.
+        let old_turn = match car.router.next() {
+            Traversable::Turn(t) => t,
+            Traversable::Lane(_) => {
+                return;
+            }
+        };
+        let current_cost = congested_time(
+            car.router.get_path().get_steps().iter().skip(1),
+            &self.queues,
+            map,
+        );
+        if let Some((cost, steps, end_dist)) =
+            self.best_route_from_lane(car, Some(old_turn), now, map)
+        {
+            if cost < current_cost * REROUTE_IF_BETTER_BY {
+                intersections.cancel_request(AgentID::Car(car.vehicle.id), old_turn);
+                car.router.reroute(steps, end_dist, map, &mut self.events);
+            }
+        }
+    }
+
+    // Cars only enter a reversible corridor once everybody going the other way has left it, so
+    // switching directions never puts cars head-on. Cars already inside can always keep going.
+    fn reversible_corridor_occupied(&self, t: TurnID, map: &Map) -> bool {
+        let dst_road = map.get_parent(t.dst);
+        let corridor = map.get_reversible_corridor(dst_road.id);
+        let src_road = map.get_parent(t.src);
+        if src_road.is_reversible(t.src) && corridor.contains(&src_road.id) {
+            return false;
+        }
+        let our_windows = dst_road
+            .reversible
+            .as_ref()
+            .unwrap()
+            .windows(dst_road.is_forwards(t.dst));
+        for r in corridor {
+            let road = map.get_r(r);
+            let schedule = road.reversible.as_ref().unwrap();
+            for (l, lt) in road
+                .children_forwards
+                .iter()
+                .chain(road.children_backwards.iter())
+            {
+                if !road.is_reversible(*l) || schedule.windows(road.is_forwards(*l)) == our_windows
+                {
+                    continue;
+                }
+                let queue = &self.queues[&Traversable::Lane(*l)];
+                if !queue.cars.is_empty() || queue.laggy_head.is_some() {
+                    return true;
+                }
+                for turn in map.get_turns_to_lane(*l) {
+                    if let Some(q) = self.queues.get(&Traversable::Turn(turn.id)) {
+                        if !q.cars.is_empty() {
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    // Starting from the end of the car's current lane, find the best route to where it's headed,
+    // accounting for current congestion. Returns the estimated time, the steps after the current
+    // lane, and the end distance.
+    fn best_route_from_lane(
+        &self,
+        car: &Car,
+        skip_turn: Option<TurnID>,
+        now: Time,
+        map: &Map,
+    ) -> Option<(Duration, Vec<PathStep>, Distance)> {
+        let current_lane = car.router.head().as_lane();
+        let end = car.router.end_pos();
+        let constraints = car.vehicle.vehicle_type.to_constraints();
+        let mut best: Option<(Duration, Vec<PathStep>, Distance)> = None;
+        // The pathfinder only knows free-flow times, so try every way out of this lane and let
+        // the current congestion pick between them.
+        for turn in map.get_turns_for(current_lane, constraints) {
+            if Some(turn.id) == skip_turn || !map.is_turn_allowed_at(turn.id, constraints, now) {
+                continue;
+            }
+            let rest = if let Some(p) = map.pathfind_at(
+                PathRequest {
+                    start: Position::new(turn.id.dst, Distance::ZERO),
+                    end,
+                    constraints,
+                },
+                now,
+            ) {
+                p
+            } else {
+                continue;
+            };
+            let mut steps = vec![PathStep::Turn(turn.id)];
+            steps.extend(rest.get_steps().iter().cloned());
+            let cost = congested_time(steps.iter(), &self.queues, map);
+            if best.as_ref().map(|(c, _, _)| cost < *c).unwrap_or(true) {
+                best = Some((cost, steps, rest.end_dist()));
+            }
+        }
+        best
+    }
+
+    // After live map edits, some turns might not exist anymore. Reroute cars that were going to
+    // use them. Returns the cars that can't find any way now; the caller has to delete them.
+    // Sim::can_close_intersection_live makes sure nobody is on a removed turn or stuck to a path
+    // through it.
+    pub fn handle_live_edits(
+        &mut self,
+        now: Time,
+        map: &Map,
+        intersections: &mut IntersectionSimState,
+        scheduler: &mut Scheduler,
+    ) -> Vec<CarID> {
+        let mut stuck = Vec::new();
+        if !self.reroute_around_closures {
+            return stuck;
+        }
+        let ids: Vec<CarID> = self.cars.keys().cloned().collect();
+        for id in ids {
+            let mut car = self.cars.remove(&id).unwrap();
+            let broken = car
+                .router
+                .get_path()
+                .get_steps()
+                .iter()
+                .skip(1)
+                .any(|step| match step {
+                    PathStep::Turn(t) => map.maybe_get_t(*t).is_none(),
+                    _ => false,
+                });
+            if broken && !self.reroute_around_edits(&mut car, now, map, intersections, scheduler) {
+                stuck.push(id);
+            }
+            self.cars.insert(id, car);
+        }
+        stuck
+    }
+
+    // Cars that can't reroute at all are the caller's problem. Returns false if there's no way
+    // around.
+    fn reroute_around_edits(
+        &mut self,
+        car: &mut Car,
+        now: Time,
+        map: &Map,
+        intersections: &mut IntersectionSimState,
+        scheduler: &mut Scheduler,
+    ) -> bool {
+        assert!(
+            car.router.can_reroute(),
+            "{}'s path goes through something that was closed, but it can't reroute",
+            car.vehicle.id
+        );
+        let new_route = match car.router.head() {
+            Traversable::Lane(_) => self.best_route_from_lane(car, None, now, map),
+            Traversable::Turn(t) => {
+                assert!(
+                    map.maybe_get_t(t).is_some(),
+                    "{} is in the middle of {}, which was just closed",
+                    car.vehicle.id,
+                    t
+                );
+                map.pathfind_at(
+                    PathRequest {
+                        start: Position::new(t.dst, Distance::ZERO),
+                        end: car.router.end_pos(),
+                        constraints: car.vehicle.vehicle_type.to_constraints(),
+                    },
+                    now,
+                )
+                .map(|p| {
+                    (
+                        Duration::ZERO,
+                        p.get_steps().iter().cloned().collect(),
+                        p.end_dist(),
+                    )
+                })
+            }
+        };
+        if let Some((_, steps, end_dist)) = new_route {
+            if car.state == CarState::WaitingToAdvance {
+                // Stop waiting for the old turn and try the new one.
+                if let Traversable::Turn(t) = car.router.next() {
+                    intersections.cancel_request(AgentID::Car(car.vehicle.id), t);
+                }
+                scheduler.update(now, Command::UpdateCar(car.vehicle.id));
+            }
+            car.router.reroute(steps, end_dist, map, &mut self.events);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Whether this car will find a new path when live edits close something on its way
+    pub fn can_reroute_around_closures(&self, id: CarID) -> bool {
+        self.reroute_around_closures
+            && self
+                .cars
+                .get(&id)
+                .map(|car| car.router.can_reroute())
+                .unwrap_or(false)
+    }
+
     pub fn kill_stuck_car(
         &mut self,
         c: CarID,
@@ -565,6 +879,7 @@ impl DrivingSimState {
         self.delete_car(&mut car, dists, idx, now, map, scheduler, intersections);
         // delete_car cancels UpdateLaggyHead
         scheduler.cancel(Command::UpdateCar(c));
+        self.waiting_for_corridor.remove(&c);
     }
 
     fn delete_car(
@@ -588,6 +903,7 @@ impl DrivingSimState {
             };
             intersections.space_freed(now, i, scheduler, map);
         }
+        self.left_traversable(car.router.head(), now, map, scheduler);
 
         // We might be vanishing while partly clipping into other stuff.
         self.clear_last_steps(now, car, intersections, scheduler, map);
@@ -752,6 +1068,35 @@ impl DrivingSimState {
                 assert!(self.queues[&on].cars.is_empty());
             }
         }
+        for on in last_steps {
+            self.left_traversable(on, now, map, scheduler);
+        }
+    }
+
+    // Somebody finished leaving a queue. If it was a reversible lane, the corridor might be clear
+    // now.
+    fn left_traversable(
+        &mut self,
+        on: Traversable,
+        now: Time,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        if let Traversable::Lane(l) = on {
+            if !map.get_parent(l).is_reversible(l) {
+                return;
+            }
+            for id in std::mem::replace(&mut self.waiting_for_corridor, BTreeSet::new()) {
+                if self
+                    .cars
+                    .get(&id)
+                    .map(|car| car.state == CarState::WaitingToAdvance)
+                    == Some(true)
+                {
+                    scheduler.update(now, Command::UpdateCar(id));
+                }
+            }
+        }
     }
 
     pub fn get_unzoomed_agents(&self, now: Time, map: &Map) -> Vec<UnzoomedAgent> {
@@ -983,3 +1328,23 @@ impl DrivingSimState {
         std::mem::replace(&mut self.events, Vec::new())
     }
 }
+
+// Free-flow time to cross everything, inflated for how full each queue currently is.
+fn congested_time<'a, I: Iterator<Item = &'a PathStep>>(
+    steps: I,
+    queues: &BTreeMap<Traversable, Queue>,
+    map: &Map,
+) -> Duration {
+    let mut total = Duration::ZERO;
+    for step in steps {
+        let on = step.as_traversable();
+        let free_flow = on.length(map) / on.speed_limit(map);
+        let occupancy = queues
+            .get(&on)
+            .map(|q| q.reserved_length / q.geom_len)
+            .unwrap_or(0.0)
+            .min(MAX_OCCUPANCY);
+        total += free_flow / (1.0 - occupancy);
+    }
+    total
+}
@@ -1,30 +1,37 @@
 use crate::mechanics::car::Car;
+use crate::mechanics::controllers::{
+    Admission, FreeformPolicy, IntersectionController, StopSignPolicy, TrafficSignalPolicy,
+};
 use crate::mechanics::Queue;
 use crate::{AgentID, Command, Event, Scheduler, Speed};
 use abstutil::{deserialize_btreemap, serialize_btreemap};
 use derivative::Derivative;
 use geom::{Duration, Time};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, Map, TurnID, TurnPriority,
-    TurnType,
+    ControlTrafficSignal, IntersectionID, LaneID, Map, TurnID, TurnPriority, TurnType,
 };
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::Arc;
 
-const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
-const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
-
-#[derive(Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Clone, Derivative)]
+#[derivative(PartialEq)]
 pub struct IntersectionSimState {
-    state: BTreeMap<IntersectionID, State>,
+    state: BTreeMap<IntersectionID, IntersectionState>,
     use_freeform_policy_everywhere: bool,
     force_queue_entry: bool,
     events: Vec<Event>,
+    // Overrides the policy normally picked from the map. These can't be savestated, so only their
+    // names are, and restore_controllers rebuilds them after loading.
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    controllers: BTreeMap<IntersectionID, Arc<dyn IntersectionController>>,
+    controller_names: BTreeMap<IntersectionID, String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Derivative)]
 #[derivative(PartialEq)]
-struct State {
+pub struct IntersectionState {
     id: IntersectionID,
     accepted: BTreeSet<Request>,
     // Track when a request is first made.
@@ -33,6 +40,17 @@ struct State {
         deserialize_with = "deserialize_btreemap"
     )]
     waiting: BTreeMap<Request, Time>,
+    // Only used by traffic signals. Actuated phases don't follow a fixed schedule, so the current
+    // phase has to be tracked.
+    pub(crate) signal: Option<SignalState>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct SignalState {
+    pub current_phase: usize,
+    pub phase_started: Time,
+    // Actuated phases might be extended past this.
+    pub phase_ends: Time,
 }
 
 impl IntersectionSimState {
@@ -47,23 +65,80 @@ impl IntersectionSimState {
             use_freeform_policy_everywhere,
             force_queue_entry: disable_block_the_box,
             events: Vec::new(),
+            controllers: BTreeMap::new(),
+            controller_names: BTreeMap::new(),
         };
         for i in map.all_intersections() {
-            sim.state.insert(
-                i.id,
-                State {
-                    id: i.id,
-                    accepted: BTreeSet::new(),
-                    waiting: BTreeMap::new(),
-                },
-            );
-            if i.is_traffic_signal() && !use_freeform_policy_everywhere {
-                sim.update_intersection(Time::START_OF_DAY, i.id, map, scheduler);
+            let mut state = IntersectionState {
+                id: i.id,
+                accepted: BTreeSet::new(),
+                waiting: BTreeMap::new(),
+                signal: None,
+            };
+            if let Some(c) = controller(&sim.controllers, use_freeform_policy_everywhere, i.id, map)
+            {
+                if let Some(t) = c.update(&mut state, Time::START_OF_DAY, map) {
+                    scheduler.push(t, Command::UpdateIntersection(i.id));
+                }
             }
+            sim.state.insert(i.id, state);
         }
         sim
     }
 
+    // Replace the policy at one intersection. Anybody waiting there retries under the new rules.
+    pub fn set_controller(
+        &mut self,
+        now: Time,
+        i: IntersectionID,
+        c: Arc<dyn IntersectionController>,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        // The old controller might have an update pending.
+        scheduler.cancel(Command::UpdateIntersection(i));
+        if let Some(t) = c.update(self.state.get_mut(&i).unwrap(), now, map) {
+            scheduler.push(t, Command::UpdateIntersection(i));
+        }
+        self.controller_names.insert(i, c.name());
+        self.controllers.insert(i, c);
+        self.wakeup_waiting(now, i, scheduler, map);
+    }
+
+    // After loading a savestate, turn the names of the registered controllers back into
+    // controllers. Their pending updates are already in the scheduler.
+    pub fn restore_controllers(
+        &mut self,
+        make: &dyn Fn(&str) -> Option<Arc<dyn IntersectionController>>,
+    ) -> Result<(), String> {
+        for (i, name) in &self.controller_names {
+            if let Some(c) = make(name) {
+                self.controllers.insert(*i, c);
+            } else {
+                return Err(format!("Can't rebuild the {} controller at {}", name, i));
+            }
+        }
+        Ok(())
+    }
+
+    // After live map edits, some intersections might not have a controller anymore, like a traffic
+    // signal closed for construction. Forget their pending updates and signal state.
+    pub fn handle_live_edits(&mut self, map: &Map, scheduler: &mut Scheduler) {
+        for (id, state) in self.state.iter_mut() {
+            if controller(
+                &self.controllers,
+                self.use_freeform_policy_everywhere,
+                *id,
+                map,
+            )
+            .is_none()
+            {
+                scheduler.cancel(Command::UpdateIntersection(*id));
+                state.signal = None;
+            }
+        }
+    }
+
     pub fn nobody_headed_towards(&self, lane: LaneID, i: IntersectionID) -> bool {
         !self.state[&i]
             .accepted
@@ -120,14 +195,15 @@ impl IntersectionSimState {
         let mut protected = Vec::new();
         let mut yielding = Vec::new();
 
-        if self.use_freeform_policy_everywhere {
-            for (req, _) in all {
-                protected.push(req);
-            }
-        } else if let Some(ref signal) = map.maybe_get_traffic_signal(i) {
-            let (_, phase, _) = signal.current_phase_and_remaining_time(now);
+        if let Some(c) = controller(
+            &self.controllers,
+            self.use_freeform_policy_everywhere,
+            i,
+            map,
+        ) {
+            let state = &self.state[&i];
             for (req, _) in all {
-                match phase.get_priority_of_turn(req.turn, signal) {
+                match c.wakeup_priority(state, &req, now, map) {
                     TurnPriority::Protected => {
                         protected.push(req);
                     }
@@ -138,15 +214,6 @@ impl IntersectionSimState {
                     TurnPriority::Banned => {}
                 }
             }
-        } else if let Some(ref sign) = map.maybe_get_stop_sign(i) {
-            for (req, _) in all {
-                // Banned is impossible
-                if sign.get_priority(req.turn, map) == TurnPriority::Protected {
-                    protected.push(req);
-                } else {
-                    yielding.push(req);
-                }
-            }
         } else {
             assert!(map.get_i(i).is_border());
         };
@@ -166,19 +233,41 @@ impl IntersectionSimState {
         }
     }
 
-    // This is only triggered for traffic signals.
+    // This is only triggered for controllers that ask for updates, like traffic signals.
     pub fn update_intersection(
-        &self,
+        &mut self,
         now: Time,
         id: IntersectionID,
         map: &Map,
         scheduler: &mut Scheduler,
     ) {
+        // Closed by a live edit
+        let c = match controller(
+            &self.controllers,
+            self.use_freeform_policy_everywhere,
+            id,
+            map,
+        ) {
+            Some(c) => c,
+            None => {
+                return;
+            }
+        };
+        if let Some(t) = c.update(self.state.get_mut(&id).unwrap(), now, map) {
+            scheduler.push(t, Command::UpdateIntersection(id));
+        }
+        // Wake people up after the update, so they see the new phase.
         self.wakeup_waiting(now, id, scheduler, map);
-        let (_, _, remaining) = map
-            .get_traffic_signal(id)
-            .current_phase_and_remaining_time(now);
-        scheduler.push(now + remaining, Command::UpdateIntersection(id));
+    }
+
+    // Which phase is the traffic signal in, and how long until it (might) change?
+    pub fn current_signal_phase(
+        &self,
+        i: IntersectionID,
+        now: Time,
+        map: &Map,
+    ) -> (usize, Duration) {
+        self.state[&i].current_signal_phase(map.get_traffic_signal(i), now)
     }
 
     // For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
@@ -203,20 +292,32 @@ impl IntersectionSimState {
         let state = self.state.get_mut(&turn.parent).unwrap();
         state.waiting.entry(req.clone()).or_insert(now);
 
-        let allowed = if self.use_freeform_policy_everywhere {
-            state.freeform_policy(&req, map)
-        } else if let Some(ref signal) = map.maybe_get_traffic_signal(state.id) {
-            state.traffic_signal_policy(signal, &req, speed, now, map, scheduler)
-        } else if let Some(ref sign) = map.maybe_get_stop_sign(state.id) {
-            state.stop_sign_policy(sign, &req, now, map, scheduler)
-        } else {
-            unreachable!()
+        // Nobody gets through an intersection closed by a live edit.
+        let c = match controller(
+            &self.controllers,
+            self.use_freeform_policy_everywhere,
+            turn.parent,
+            map,
+        ) {
+            Some(c) => c,
+            None => {
+                return false;
+            }
         };
-        if !allowed {
-            /*if debug {
-                println!("{}: {} can't go yet", now, agent)
-            };*/
-            return false;
+        match c.maybe_start_turn(state, &req, speed, now, map) {
+            Admission::Allow => {}
+            Admission::Deny => {
+                /*if debug {
+                    println!("{}: {} can't go yet", now, agent)
+                };*/
+                return false;
+            }
+            Admission::RetryAt(t) => {
+                // Since we have "ownership" of scheduling for req.agent, don't need to use
+                // scheduler.update.
+                scheduler.push(t, Command::update_agent(req.agent));
+                return false;
+            }
         }
 
         // Don't block the box
@@ -264,146 +365,80 @@ impl IntersectionSimState {
             .collect()
     }
 
+    pub fn any_waiting(&self, id: IntersectionID) -> bool {
+        !self.state[&id].waiting.is_empty()
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::replace(&mut self.events, Vec::new())
     }
 }
 
-impl State {
-    fn any_accepted_conflict_with(&self, t: TurnID, map: &Map) -> bool {
-        let turn = map.get_t(t);
-        self.accepted
-            .iter()
-            .any(|req| map.get_t(req.turn).conflicts_with(turn))
+impl IntersectionState {
+    pub fn id(&self) -> IntersectionID {
+        self.id
     }
 
-    fn freeform_policy(&self, req: &Request, map: &Map) -> bool {
-        // Allow concurrent turns that don't conflict
-        if self.any_accepted_conflict_with(req.turn, map) {
-            return false;
-        }
-
-        true
+    pub fn accepted(&self) -> &BTreeSet<Request> {
+        &self.accepted
     }
 
-    fn stop_sign_policy(
-        &self,
-        sign: &ControlStopSign,
-        req: &Request,
-        now: Time,
-        map: &Map,
-        scheduler: &mut Scheduler,
-    ) -> bool {
-        if self.any_accepted_conflict_with(req.turn, map) {
-            return false;
-        }
-
-        let our_priority = sign.get_priority(req.turn, map);
-        assert!(our_priority != TurnPriority::Banned);
-        let our_time = self.waiting[req];
-
-        if our_priority == TurnPriority::Yield && now < our_time + WAIT_AT_STOP_SIGN {
-            // Since we have "ownership" of scheduling for req.agent, don't need to use
-            // scheduler.update.
-            scheduler.push(
-                our_time + WAIT_AT_STOP_SIGN,
-                Command::update_agent(req.agent),
-            );
-            return false;
-        }
-
-        // Once upon a time, we'd make sure that this request doesn't conflict with another in
-        // self.waiting:
-        // 1) Higher-ranking turns get to go first.
-        // 2) Equal-ranking turns that started waiting before us get to go first.
-        // But the exceptions started stacking -- if the other agent is blocked or the turns don't
-        // even conflict, then allow it. Except determining if the other agent is blocked or not is
-        // tough and kind of recursive.
-        //
-        // So instead, don't do any of that! The WAIT_AT_STOP_SIGN scheduling above and the fact
-        // that events are processed in time order mean that case #2 is magically handled anyway.
-        // If a case #1 could've started by now, then they would have. Since they didn't, they must
-        // be blocked.
-
-        // TODO Make sure we can optimistically finish this turn before an approaching
-        // higher-priority vehicle wants to begin.
+    pub fn waiting(&self) -> &BTreeMap<Request, Time> {
+        &self.waiting
+    }
 
-        true
+    pub fn waiting_since(&self, req: &Request) -> Time {
+        self.waiting[req]
     }
 
-    fn traffic_signal_policy(
+    // Falls back to the map's fixed-time schedule before the signal has been updated, or if the
+    // signal was edited out from under us.
+    pub fn current_signal_phase(
         &self,
         signal: &ControlTrafficSignal,
-        req: &Request,
-        speed: Speed,
         now: Time,
-        map: &Map,
-        scheduler: &mut Scheduler,
-    ) -> bool {
-        let turn = map.get_t(req.turn);
-
-        // SharedSidewalkCorner doesn't conflict with anything -- fastpath!
-        if turn.turn_type == TurnType::SharedSidewalkCorner {
-            return true;
-        }
-
-        let (_, phase, remaining_phase_time) = signal.current_phase_and_remaining_time(now);
-
-        // Can't go at all this phase.
-        let our_priority = phase.get_priority_of_turn(req.turn, signal);
-        if our_priority == TurnPriority::Banned {
-            return false;
-        }
-
-        // Somebody might already be doing a Yield turn that conflicts with this one.
-        if self.any_accepted_conflict_with(req.turn, map) {
-            return false;
-        }
-
-        let our_time = self.waiting[req];
-        if our_priority == TurnPriority::Yield
-            && now < our_time + WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL
-        {
-            // Since we have "ownership" of scheduling for req.agent, don't need to use
-            // scheduler.update.
-            scheduler.push(
-                our_time + WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL,
-                Command::update_agent(req.agent),
-            );
-            return false;
-        }
-
-        // Previously: A yield loses to a conflicting Priority turn.
-        // But similar to the description in stop_sign_policy, this caused unnecessary gridlock.
-        // Priority vehicles getting scheduled first just requires a little tweak in
-        // update_intersection.
-
-        // TODO Make sure we can optimistically finish this turn before an approaching
-        // higher-priority vehicle wants to begin.
-
-        // Optimistically if nobody else is in the way, this is how long it'll take to finish the
-        // turn. Don't start the turn if we won't finish by the time the light changes. If we get
-        // it wrong, that's fine -- block the box a bit.
-        let time_to_cross = turn.geom.length() / speed;
-        if time_to_cross > remaining_phase_time {
-            // Actually, we might have bigger problems...
-            if time_to_cross > phase.duration {
-                println!(
-                    "OYYY! {:?} is impossible to fit into phase duration of {}. Allowing, but fix \
-                     the policy!",
-                    req, phase.duration
-                );
-            } else {
-                return false;
+    ) -> (usize, Duration) {
+        match self.signal {
+            Some(ref s) if s.current_phase < signal.phases.len() => {
+                (s.current_phase, s.phase_ends - now)
+            }
+            _ => {
+                let (idx, _, remaining) = signal.current_phase_and_remaining_time(now);
+                (idx, remaining)
             }
         }
+    }
 
-        true
+    pub fn any_accepted_conflict_with(&self, t: TurnID, map: &Map) -> bool {
+        let turn = map.get_t(t);
+        self.accepted
+            .iter()
+            .any(|req| map.get_t(req.turn).conflicts_with(turn))
     }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Debug)]
-struct Request {
-    agent: AgentID,
-    turn: TurnID,
+pub struct Request {
+    pub agent: AgentID,
+    pub turn: TurnID,
+}
+
+// Registered controllers win, then the debug override, then whatever the map says.
+fn controller<'a>(
+    controllers: &'a BTreeMap<IntersectionID, Arc<dyn IntersectionController>>,
+    use_freeform_policy_everywhere: bool,
+    i: IntersectionID,
+    map: &Map,
+) -> Option<&'a dyn IntersectionController> {
+    if let Some(c) = controllers.get(&i) {
+        Some(c.as_ref())
+    } else if use_freeform_policy_everywhere {
+        Some(&FreeformPolicy)
+    } else if map.maybe_get_traffic_signal(i).is_some() {
+        Some(&TrafficSignalPolicy)
+    } else if map.maybe_get_stop_sign(i).is_some() {
+        Some(&StopSignPolicy)
+    } else {
+        None
+    }
 }
@@ -1,12 +1,16 @@
 mod car;
+mod controllers;
 mod driving;
 mod intersection;
 mod parking;
 mod queue;
 mod walking;
 
+pub use self::controllers::{
+    Admission, FreeformPolicy, IntersectionController, StopSignPolicy, TrafficSignalPolicy,
+};
 pub use self::driving::DrivingSimState;
-pub use self::intersection::IntersectionSimState;
-pub use self::parking::ParkingSimState;
+pub use self::intersection::{IntersectionSimState, IntersectionState, Request};
+pub use self::parking::{ParkingSimState, DEFAULT_PARKING_STAY};
 pub use self::queue::Queue;
-pub use self::walking::WalkingSimState;
+pub use self::walking::{LevelOfService, WalkingSimState};
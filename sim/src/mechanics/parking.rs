@@ -3,12 +3,20 @@ use abstutil::{
     deserialize_btreemap, deserialize_multimap, serialize_btreemap, serialize_multimap, MultiMap,
     Timer,
 };
-use geom::{Distance, Duration, Pt2D};
+use geom::{Distance, Duration, Pt2D, Time};
 use map_model;
-use map_model::{BuildingID, Lane, LaneID, LaneType, Map, Position, Traversable};
+use map_model::{BuildingID, Lane, LaneID, LaneType, Map, ParkingPolicy, Position, Traversable};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
+// Drivers judge prices and time limits by how long they'll stay. People know from their next
+// activity; everybody else is assumed to run a quick errand.
+pub const DEFAULT_PARKING_STAY: Duration = Duration::const_seconds(30.0 * 60.0);
+// Drivers won't pay more than this per hour.
+const MAX_HOURLY_PRICE_CENTS: f64 = 1000.0;
+// Trading off walking against paying. About $20/hour of walking at 1.34m/s.
+const CENTS_PER_METER_WALKED: f64 = 0.41;
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct ParkingSimState {
     #[serde(
@@ -145,6 +153,16 @@ impl ParkingSimState {
         None
     }
 
+    // None if the car isn't parked right now or is already reserved
+    pub fn dynamically_reserve_specific_car(&mut self, c: CarID) -> Option<ParkedCar> {
+        if self.dynamically_reserved_cars.contains(&c) {
+            return None;
+        }
+        let p = self.parked_cars.get(&c)?.clone();
+        self.dynamically_reserved_cars.insert(c);
+        Some(p)
+    }
+
     pub fn dynamically_return_car(&mut self, p: ParkedCar) {
         self.dynamically_reserved_cars.remove(&p.vehicle.id);
     }
@@ -217,16 +235,18 @@ impl ParkingSimState {
         Some(&self.parked_cars[&car])
     }
 
-    // And the driving position
-    // TODO This one is trickier!
-    pub fn get_first_free_spot(
+    // The cheapest acceptable free spot ahead of driving_pos on this lane, with its cost and
+    // driving position. Without a target, the cost is just the price.
+    pub fn get_best_free_spot(
         &self,
         driving_pos: Position,
         vehicle: &Vehicle,
+        target: Option<BuildingID>,
+        stay: Duration,
+        now: Time,
         map: &Map,
-    ) -> Option<(ParkingSpot, Position)> {
-        let mut maybe_spot = None;
-        // TODO Ideally don't fill in one side first before considering the other.
+    ) -> Option<(ParkingSpot, Position, f64)> {
+        let mut candidates: Vec<ParkingSpot> = Vec::new();
         for l in self.driving_to_parking_lanes.get(driving_pos.lane()) {
             let parking_dist = driving_pos
                 .equiv_pos(*l, driving_pos.dist_along(), map)
@@ -235,8 +255,7 @@ impl ParkingSimState {
             // Bit hacky to enumerate here to conveniently get idx.
             for (idx, spot) in lane.spots().into_iter().enumerate() {
                 if self.is_free(spot) && parking_dist <= lane.dist_along_for_car(idx, vehicle) {
-                    maybe_spot = Some(spot);
-                    break;
+                    candidates.push(spot);
                 }
             }
         }
@@ -252,25 +271,82 @@ impl ParkingSimState {
             if driving_pos.dist_along() > bldg_dist {
                 continue;
             }
-            // Is this potential spot closer than the current best spot?
-            if maybe_spot
-                .map(|spot| bldg_dist > self.spot_to_driving_pos(spot, vehicle, map).dist_along())
-                .unwrap_or(false)
-            {
-                continue;
+            // All stalls in one building are equivalent
+            if let Some(spot) = self.get_free_offstreet_spots(*b).into_iter().next() {
+                candidates.push(spot);
             }
+        }
 
-            for idx in 0..self.num_spots_per_offstreet[&b] {
-                let spot = ParkingSpot::offstreet(*b, idx);
-                if self.is_free(spot) {
-                    maybe_spot = Some(spot);
-                    break;
+        candidates
+            .into_iter()
+            .filter(|spot| self.is_acceptable(*spot, vehicle, stay, now, map))
+            .map(|spot| {
+                let pos = self.spot_to_driving_pos(spot, vehicle, map);
+                // Break ties by the closest spot
+                let cost = self.spot_cost(spot, target, stay, map)
+                    + 0.001 * (pos.dist_along() - driving_pos.dist_along()).inner_meters();
+                (spot, pos, cost)
+            })
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
+    }
+
+    pub fn get_policy<'a>(&self, spot: ParkingSpot, map: &'a Map) -> &'a ParkingPolicy {
+        match spot {
+            ParkingSpot::Onstreet(l, _) => &map.get_l(l).parking_policy,
+            ParkingSpot::Offstreet(b, _) => &map.get_b(b).parking.as_ref().unwrap().policy,
+        }
+    }
+
+    // Would a driver staying this long ever consider this spot right now?
+    pub fn is_acceptable(
+        &self,
+        spot: ParkingSpot,
+        vehicle: &Vehicle,
+        stay: Duration,
+        now: Time,
+        map: &Map,
+    ) -> bool {
+        let policy = self.get_policy(spot, map);
+        if !policy.can_park_at(now) {
+            return false;
+        }
+        if policy.residents_only {
+            let resident = match (spot, vehicle.owner) {
+                (ParkingSpot::Onstreet(l, _), Some(owner)) => {
+                    map.get_l(map.get_b(owner).sidewalk()).parent == map.get_l(l).parent
                 }
+                (ParkingSpot::Offstreet(b, _), Some(owner)) => b == owner,
+                (_, None) => false,
+            };
+            if !resident {
+                return false;
+            }
+        }
+        if let Some(max) = policy.max_stay {
+            if max < stay {
+                return false;
             }
         }
+        (policy.hourly_price_cents as f64) <= MAX_HOURLY_PRICE_CENTS
+    }
 
-        let spot = maybe_spot?;
-        Some((spot, self.spot_to_driving_pos(spot, vehicle, map)))
+    // In cents. Combines the price with walking to the target.
+    fn spot_cost(
+        &self,
+        spot: ParkingSpot,
+        target: Option<BuildingID>,
+        stay: Duration,
+        map: &Map,
+    ) -> f64 {
+        let mut cost = price_for_stay(self.get_policy(spot, map), stay);
+        if let Some(b) = target {
+            let walk = self
+                .spot_to_sidewalk_pos(spot, map)
+                .pt(map)
+                .dist_to(map.get_b(b).front_path.sidewalk.pt(map));
+            cost += CENTS_PER_METER_WALKED * walk.inner_meters();
+        }
+        cost
     }
 
     pub fn spot_to_driving_pos(&self, spot: ParkingSpot, vehicle: &Vehicle, map: &Map) -> Position {
@@ -405,3 +481,7 @@ impl ParkingLane {
         spots
     }
 }
+
+fn price_for_stay(policy: &ParkingPolicy, stay: Duration) -> f64 {
+    (policy.hourly_price_cents as f64) * stay.inner_seconds() / 3600.0
+}
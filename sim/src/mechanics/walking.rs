@@ -6,12 +6,18 @@ use crate::{
 };
 use abstutil::{deserialize_multimap, serialize_multimap, MultiMap};
 use geom::{Distance, Duration, Line, PolyLine, Speed, Time};
-use map_model::{BuildingID, BusRouteID, Map, Path, PathStep, Traversable, SIDEWALK_THICKNESS};
+use map_model::{
+    BuildingID, BusRouteID, Map, Path, PathStep, Traversable, TurnType, SIDEWALK_THICKNESS,
+};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 const TIME_TO_START_BIKING: Duration = Duration::const_seconds(30.0);
 const TIME_TO_FINISH_BIKING: Duration = Duration::const_seconds(45.0);
+// Pedestrians per square meter where walking stops entirely, from Weidmann's fundamental diagram
+const JAM_DENSITY: f64 = 5.4;
+// Don't let crowds freeze people completely; somebody always shuffles forward.
+const MIN_CROWDED_SPEED_FACTOR: f64 = 0.1;
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct WalkingSimState {
@@ -77,7 +83,13 @@ impl WalkingSimState {
                 Line::new(driving_pos.pt(map), params.start.sidewalk_pos.pt(map)),
                 TimeInterval::new(now, now + TIME_TO_FINISH_BIKING),
             ),
-            _ => ped.crossing_state(params.start.sidewalk_pos.dist_along(), now, map),
+            _ => ped.crossing_state(
+                params.start.sidewalk_pos.dist_along(),
+                now,
+                map,
+                &self.peds_per_traversable,
+                &mut self.events,
+            ),
         };
 
         scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
@@ -139,8 +151,8 @@ impl WalkingSimState {
                             scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
                         }
                         SidewalkPOI::BusStop(stop) => {
-                            if let Some(route) =
-                                trips.ped_reached_bus_stop(now, ped.id, stop, map, transit)
+                            if let Some(route) = trips
+                                .ped_reached_bus_stop(now, ped.id, stop, map, transit, scheduler)
                             {
                                 ped.state = PedState::WaitingForBus(route);
                                 ped.blocked_since = Some(now);
@@ -211,14 +223,19 @@ impl WalkingSimState {
                 }
             }
             PedState::LeavingBuilding(b, _) => {
-                ped.state =
-                    ped.crossing_state(map.get_b(b).front_path.sidewalk.dist_along(), now, map);
+                ped.state = ped.crossing_state(
+                    map.get_b(b).front_path.sidewalk.dist_along(),
+                    now,
+                    map,
+                    &self.peds_per_traversable,
+                    &mut self.events,
+                );
                 scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
             PedState::EnteringBuilding(bldg, _) => {
                 self.peds_per_traversable
                     .remove(ped.path.current_step().as_traversable(), ped.id);
-                trips.ped_reached_building(now, ped.id, bldg, map);
+                trips.ped_reached_building(now, ped.id, bldg, map, scheduler);
                 self.peds.remove(&id);
             }
             PedState::StartingToBike(ref spot, _, _) => {
@@ -228,7 +245,13 @@ impl WalkingSimState {
                 self.peds.remove(&id);
             }
             PedState::FinishingBiking(ref spot, _, _) => {
-                ped.state = ped.crossing_state(spot.sidewalk_pos.dist_along(), now, map);
+                ped.state = ped.crossing_state(
+                    spot.sidewalk_pos.dist_along(),
+                    now,
+                    map,
+                    &self.peds_per_traversable,
+                    &mut self.events,
+                );
                 scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
             PedState::WaitingForBus(_) => unreachable!(),
@@ -236,6 +259,11 @@ impl WalkingSimState {
     }
 
     pub fn ped_boarded_bus(&mut self, id: PedestrianID) {
+        self.ped_stopped_waiting_for_bus(id);
+    }
+
+    // The bus isn't coming, so the pedestrian will respawn at the stop and walk.
+    pub fn ped_stopped_waiting_for_bus(&mut self, id: PedestrianID) {
         let ped = self.peds.remove(&id).unwrap();
         match ped.state {
             PedState::WaitingForBus(_) => {
@@ -439,7 +467,15 @@ struct Pedestrian {
 }
 
 impl Pedestrian {
-    fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> PedState {
+    // The speed is fixed for the whole crossing, based on how crowded things are at the start.
+    fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        map: &Map,
+        peds_per_traversable: &MultiMap<Traversable, PedestrianID>,
+        events: &mut Vec<Event>,
+    ) -> PedState {
         let end_dist = if self.path.is_last_step() {
             self.goal.sidewalk_pos.dist_along()
         } else {
@@ -451,7 +487,20 @@ impl Pedestrian {
             }
         };
         let dist_int = DistanceInterval::new_walking(start_dist, end_dist);
-        let time_int = TimeInterval::new(start_time, start_time + dist_int.length() / self.speed);
+
+        let on = self.path.current_step().as_traversable();
+        let mut num_peds = peds_per_traversable.get(on).len();
+        if !peds_per_traversable.get(on).contains(&self.id) {
+            num_peds += 1;
+        }
+        let density = pedestrian_density(on, num_peds, map);
+        let los = LevelOfService::from_density(density);
+        if los != LevelOfService::A {
+            events.push(Event::PedestrianCrowding(on, los));
+        }
+        let speed = self.speed * crowded_speed_factor(density);
+
+        let time_int = TimeInterval::new(start_time, start_time + dist_int.length() / speed);
         PedState::Crossing(dist_int, time_int)
     }
 
@@ -597,7 +646,7 @@ impl Pedestrian {
             PathStep::ContraflowLane(l) => map.get_l(l).length(),
             PathStep::Turn(_) => Distance::ZERO,
         };
-        self.state = self.crossing_state(start_dist, now, map);
+        self.state = self.crossing_state(start_dist, now, map, peds_per_traversable, events);
         peds_per_traversable.insert(self.path.current_step().as_traversable(), self.id);
         events.push(Event::AgentEntersTraversable(
             AgentID::Pedestrian(self.id),
@@ -633,6 +682,69 @@ impl PedState {
     }
 }
 
+// Level of service for walkways, from the Highway Capacity Manual. A is free-flowing, F is a
+// crush.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LevelOfService {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+}
+
+impl LevelOfService {
+    // In pedestrians per square meter
+    pub fn from_density(density: f64) -> LevelOfService {
+        if density == 0.0 {
+            return LevelOfService::A;
+        }
+        let area_per_ped = 1.0 / density;
+        if area_per_ped > 5.6 {
+            LevelOfService::A
+        } else if area_per_ped > 3.7 {
+            LevelOfService::B
+        } else if area_per_ped > 2.2 {
+            LevelOfService::C
+        } else if area_per_ped > 1.4 {
+            LevelOfService::D
+        } else if area_per_ped > 0.75 {
+            LevelOfService::E
+        } else {
+            LevelOfService::F
+        }
+    }
+}
+
+// In pedestrians per square meter. Crosswalks are as wide as the sidewalk they start from. Corners
+// are so small and quick to get around that anybody there would look crowded, so they never are.
+fn pedestrian_density(on: Traversable, num_peds: usize, map: &Map) -> f64 {
+    let width = match on {
+        Traversable::Lane(l) => map.get_l(l).width,
+        Traversable::Turn(t) => {
+            if map.get_t(t).turn_type == TurnType::SharedSidewalkCorner {
+                return 0.0;
+            }
+            map.get_l(t.src).width
+        }
+    };
+    let area = on.length(map).inner_meters() * width.inner_meters();
+    if area <= 0.0 {
+        return 0.0;
+    }
+    (num_peds as f64) / area
+}
+
+// Weidmann's speed-density relationship. Barely changes anything until a sidewalk is busy.
+fn crowded_speed_factor(density: f64) -> f64 {
+    if density == 0.0 {
+        return 1.0;
+    }
+    let factor = 1.0 - (-1.913 * (1.0 / density - 1.0 / JAM_DENSITY)).exp();
+    factor.max(MIN_CROWDED_SPEED_FACTOR).min(1.0)
+}
+
 // The crowds returned here may have low/high values extending up to radius past the real geometry.
 fn find_crowds(
     input: Vec<(PedestrianID, Distance)>,
@@ -1,6 +1,6 @@
 use crate::{CarID, PedestrianID, VehicleType};
 use geom::{Angle, Distance, Duration, PolyLine, Pt2D, Time};
-use map_model::{BuildingID, Map, Traversable, TurnID};
+use map_model::{BuildingID, IntersectionID, Map, Traversable, TurnID};
 
 // Intermediate structures so that sim and game crates don't have a cyclic dependency.
 #[derive(Clone)]
@@ -80,6 +80,8 @@ pub trait GetDrawAgents {
     fn get_all_draw_cars(&self, map: &Map) -> Vec<DrawCarInput>;
     fn get_all_draw_peds(&self, map: &Map) -> Vec<DrawPedestrianInput>;
     fn get_unzoomed_agents(&self, map: &Map) -> Vec<UnzoomedAgent>;
+    // Which phase a traffic signal is in, and how long until it might change.
+    fn current_signal_phase(&self, i: IntersectionID, map: &Map) -> (usize, Duration);
 }
 
 pub struct DontDrawAgents;
@@ -116,4 +118,10 @@ impl GetDrawAgents for DontDrawAgents {
     fn get_unzoomed_agents(&self, _: &Map) -> Vec<UnzoomedAgent> {
         Vec::new()
     }
+    fn current_signal_phase(&self, i: IntersectionID, map: &Map) -> (usize, Duration) {
+        let (idx, _, remaining) = map
+            .get_traffic_signal(i)
+            .current_phase_and_remaining_time(Time::START_OF_DAY);
+        (idx, remaining)
+    }
 }
@@ -1,6 +1,6 @@
 use crate::mechanics::Queue;
 use crate::{Event, ParkingSimState, ParkingSpot, SidewalkSpot, TripID, Vehicle};
-use geom::Distance;
+use geom::{Distance, Duration, Time};
 use map_model::{
     BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
     Position, Traversable, TurnID,
@@ -31,6 +31,8 @@ enum Goal {
     // TODO Right now, the building is ignored when choosing the best spot.
     ParkNearBuilding {
         target: BuildingID,
+        // How long the driver expects to stay, for judging prices and time limits
+        stay: Duration,
         spot: Option<(ParkingSpot, Distance)>,
         // No parking available at all!
         stuck_end_dist: Option<Distance>,
@@ -55,11 +57,12 @@ impl Router {
         }
     }
 
-    pub fn park_near(path: Path, bldg: BuildingID) -> Router {
+    pub fn park_near(path: Path, bldg: BuildingID, stay: Duration) -> Router {
         Router {
             path,
             goal: Goal::ParkNearBuilding {
                 target: bldg,
+                stay,
                 spot: None,
                 stuck_end_dist: None,
             },
@@ -119,11 +122,43 @@ impl Router {
         &self.path
     }
 
+    // Buses stick to their route, and there's no point once we're on the last step.
+    pub fn can_reroute(&self) -> bool {
+        match self.goal {
+            Goal::FollowBusRoute { .. } => false,
+            _ => self.path.isnt_last_step(),
+        }
+    }
+
+    // Buses normally stick to their route, but not when the way is closed outright, like a
+    // reversible lane switching directions. They still reach the same stop.
+    pub fn can_detour(&self) -> bool {
+        self.path.isnt_last_step()
+    }
+
+    // Where the path currently ends. Rerouting has to end up here too.
+    pub fn end_pos(&self) -> Position {
+        Position::new(self.path.last_step().as_lane(), self.path.end_dist())
+    }
+
+    // Keep the current step, then follow new_steps instead.
+    pub fn reroute(
+        &mut self,
+        new_steps: Vec<PathStep>,
+        end_dist: Distance,
+        map: &Map,
+        events: &mut Vec<Event>,
+    ) {
+        self.path.reroute(new_steps, end_dist, map);
+        events.push(Event::PathAmended(self.path.clone()));
+    }
+
     // Returns the step just finished
     pub fn advance(
         &mut self,
         vehicle: &Vehicle,
         parking: &ParkingSimState,
+        now: Time,
         map: &Map,
         trip: TripID,
         events: &mut Vec<Event>,
@@ -131,7 +166,7 @@ impl Router {
         let prev = self.path.shift(map).as_traversable();
         if self.last_step() {
             // Do this to trigger the side-effect of looking for parking.
-            self.maybe_handle_end(Distance::ZERO, vehicle, parking, map, trip, events);
+            self.maybe_handle_end(Distance::ZERO, vehicle, parking, now, map, trip, events);
         }
 
         // Sanity check laws haven't been broken
@@ -155,6 +190,7 @@ impl Router {
         front: Distance,
         vehicle: &Vehicle,
         parking: &ParkingSimState,
+        now: Time,
         map: &Map,
         // TODO Not so nice to plumb all of this here
         trip: TripID,
@@ -169,9 +205,10 @@ impl Router {
                 }
             }
             Goal::ParkNearBuilding {
+                target,
+                stay,
                 ref mut spot,
                 ref mut stuck_end_dist,
-                ..
             } => {
                 if let Some(d) = stuck_end_dist {
                     if *d == front {
@@ -187,9 +224,12 @@ impl Router {
                 };
                 if need_new_spot {
                     let current_lane = self.path.current_step().as_lane();
-                    if let Some((new_spot, new_pos)) = parking.get_first_free_spot(
+                    if let Some((new_spot, new_pos, _)) = parking.get_best_free_spot(
                         Position::new(current_lane, front),
                         vehicle,
+                        Some(target),
+                        stay,
+                        now,
                         map,
                     ) {
                         events.push(Event::TripPhaseStarting(
@@ -203,9 +243,15 @@ impl Router {
                         ));
                         *spot = Some((new_spot, new_pos.dist_along()));
                     } else {
-                        if let Some((new_path_steps, new_spot, new_pos)) =
-                            path_to_free_parking_spot(current_lane, vehicle, map, parking)
-                        {
+                        if let Some((new_path_steps, new_spot, new_pos)) = path_to_free_parking_spot(
+                            current_lane,
+                            vehicle,
+                            target,
+                            stay,
+                            now,
+                            map,
+                            parking,
+                        ) {
                             *spot = Some((new_spot, new_pos.dist_along()));
                             for step in new_path_steps {
                                 self.path.add(step, map);
@@ -219,12 +265,13 @@ impl Router {
                                     end: new_pos,
                                     constraints: PathConstraints::Car,
                                 }),
-                                format!("parking somewhere else"),
+                                format!("cruising for parking"),
                             ));
                         } else {
                             println!(
-                                "WARNING: {} can't find parking on {} or anywhere reachable from \
-                                 it. Possibly we're just totally out of parking space!",
+                                "WARNING: {} can't find acceptable parking on {} or anywhere \
+                                 reachable from it. Possibly we're just totally out of parking \
+                                 space!",
                                 vehicle.id, current_lane
                             );
                             *stuck_end_dist = Some(map.get_l(current_lane).length());
@@ -346,45 +393,62 @@ impl Router {
 fn path_to_free_parking_spot(
     start: LaneID,
     vehicle: &Vehicle,
+    target: BuildingID,
+    stay: Duration,
+    now: Time,
     map: &Map,
     parking: &ParkingSimState,
 ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
+    // Cruise past a few lanes with acceptable spots, then pick the cheapest one seen.
+    const CANDIDATE_LANES: usize = 3;
+
     let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
     // BFS, so we wind up vaguely closer to the start
     let mut queue: VecDeque<LaneID> = VecDeque::new();
     queue.push_back(start);
+    let mut candidates: Vec<(LaneID, ParkingSpot, Position, f64)> = Vec::new();
 
-    while !queue.is_empty() {
+    while !queue.is_empty() && candidates.len() < CANDIDATE_LANES {
         let current = queue.pop_front().unwrap();
         // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
         // opens up on the 'start' lane, but behind the car.
         if current != start {
-            if let Some((spot, pos)) =
-                parking.get_first_free_spot(Position::new(current, Distance::ZERO), vehicle, map)
-            {
-                let mut steps = vec![PathStep::Lane(current)];
-                let mut current = current;
-                loop {
-                    if current == start {
-                        // Don't include PathStep::Lane(start)
-                        steps.pop();
-                        steps.reverse();
-                        return Some((steps, spot, pos));
-                    }
-                    let turn = backrefs[&current];
-                    steps.push(PathStep::Turn(turn));
-                    steps.push(PathStep::Lane(turn.src));
-                    current = turn.src;
-                }
+            if let Some((spot, pos, cost)) = parking.get_best_free_spot(
+                Position::new(current, Distance::ZERO),
+                vehicle,
+                Some(target),
+                stay,
+                now,
+                map,
+            ) {
+                candidates.push((current, spot, pos, cost));
             }
         }
         for turn in map.get_turns_for(current, PathConstraints::Car) {
-            if !backrefs.contains_key(&turn.id.dst) {
+            if !backrefs.contains_key(&turn.id.dst)
+                && map.is_turn_allowed_at(turn.id, PathConstraints::Car, now)
+            {
                 backrefs.insert(turn.id.dst, turn.id);
                 queue.push_back(turn.id.dst);
             }
         }
     }
 
-    None
+    let (lane, spot, pos, _) = candidates
+        .into_iter()
+        .min_by(|a, b| a.3.partial_cmp(&b.3).unwrap())?;
+    let mut steps = vec![PathStep::Lane(lane)];
+    let mut current = lane;
+    loop {
+        if current == start {
+            // Don't include PathStep::Lane(start)
+            steps.pop();
+            steps.reverse();
+            return Some((steps, spot, pos));
+        }
+        let turn = backrefs[&current];
+        steps.push(PathStep::Turn(turn));
+        steps.push(PathStep::Lane(turn.src));
+        current = turn.src;
+    }
 }
@@ -1,7 +1,7 @@
-use crate::{AgentID, CarID, CreateCar, CreatePedestrian, PedestrianID};
+use crate::{AgentID, CarID, CreateCar, CreatePedestrian, PedestrianID, PersonID, TripID};
 use derivative::Derivative;
 use geom::{Duration, DurationHistogram, Time};
-use map_model::{IntersectionID, Path, PathRequest};
+use map_model::{IntersectionID, Map, Path, PathRequest, PathStep};
 use serde_derive::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BinaryHeap};
@@ -16,7 +16,10 @@ pub enum Command {
     UpdateLaggyHead(CarID),
     UpdatePed(PedestrianID),
     UpdateIntersection(IntersectionID),
+    // The person's current activity is over, so they leave for the next one
+    StartPersonTrip(PersonID),
     Savestate(Duration),
+    RecordTrajectories(Duration),
 }
 
 impl Command {
@@ -35,7 +38,9 @@ impl Command {
             Command::UpdateLaggyHead(id) => CommandType::CarLaggyHead(*id),
             Command::UpdatePed(id) => CommandType::Ped(*id),
             Command::UpdateIntersection(id) => CommandType::Intersection(*id),
+            Command::StartPersonTrip(id) => CommandType::Person(*id),
             Command::Savestate(_) => CommandType::Savestate,
+            Command::RecordTrajectories(_) => CommandType::RecordTrajectories,
         }
     }
 }
@@ -48,7 +53,9 @@ pub enum CommandType {
     CarLaggyHead(CarID),
     Ped(PedestrianID),
     Intersection(IntersectionID),
+    Person(PersonID),
     Savestate,
+    RecordTrajectories,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -152,6 +159,16 @@ impl Scheduler {
         });
     }
 
+    // Like update, but never delays a command that's already scheduled sooner.
+    pub fn update_if_sooner(&mut self, new_time: Time, cmd: Command) {
+        if let Some((_, existing_time)) = self.queued_commands.get(&cmd.to_type()) {
+            if *existing_time <= new_time {
+                return;
+            }
+        }
+        self.update(new_time, cmd);
+    }
+
     pub fn cancel(&mut self, cmd: Command) {
         // It's fine if a previous command hasn't actually been scheduled.
         self.queued_commands.remove(&cmd.to_type());
@@ -181,6 +198,49 @@ impl Scheduler {
         }
     }
 
+    // Spawns carry paths calculated ahead of time. After live map edits, recalculate the ones
+    // using turns that don't exist anymore. If there's no way now, the spawn is canceled, and its
+    // trip is returned. Transit vehicles have to stick to their route, so they're left alone.
+    pub fn handle_live_edits(&mut self, map: &Map) -> Vec<TripID> {
+        let mut impossible = Vec::new();
+        for (cmd, time) in self.queued_commands.values_mut() {
+            match cmd {
+                Command::SpawnCar(ref mut create_car, _) => {
+                    if create_car.vehicle.vehicle_type.is_transit()
+                        || !uses_missing_turn(create_car.router.get_path(), map)
+                    {
+                        continue;
+                    }
+                    if let Some(path) = map.pathfind_at(create_car.req.clone(), *time) {
+                        create_car.router.replace_path_for_serialization(path);
+                    } else {
+                        let trip = create_car.trip;
+                        impossible.push((cmd.to_type(), trip));
+                    }
+                }
+                Command::SpawnPed(ref mut create_ped) => {
+                    if !uses_missing_turn(&create_ped.path, map) {
+                        continue;
+                    }
+                    if let Some(path) = map.pathfind(create_ped.req.clone()) {
+                        create_ped.path = path;
+                    } else {
+                        let trip = create_ped.trip;
+                        impossible.push((cmd.to_type(), trip));
+                    }
+                }
+                _ => {}
+            }
+        }
+        impossible
+            .into_iter()
+            .map(|(cmd_type, trip)| {
+                self.queued_commands.remove(&cmd_type);
+                trip
+            })
+            .collect()
+    }
+
     pub fn describe_stats(&self) -> String {
         format!("delta times for events: {}", self.delta_times.describe())
     }
@@ -244,3 +304,10 @@ impl Scheduler {
         assert!(restore.is_empty());
     }
 }
+
+fn uses_missing_turn(path: &Path, map: &Map) -> bool {
+    path.get_steps().iter().any(|step| match step {
+        PathStep::Turn(t) => map.maybe_get_t(*t).is_none(),
+        _ => false,
+    })
+}
@@ -1,25 +1,29 @@
 use crate::{
-    AgentID, AgentMetadata, Analytics, CarID, Command, CreateCar, DrawCarInput, DrawPedCrowdInput,
-    DrawPedestrianInput, DrivingGoal, DrivingSimState, Event, GetDrawAgents, IntersectionSimState,
-    ParkedCar, ParkingSimState, ParkingSpot, PedestrianID, Router, Scheduler, SidewalkPOI,
-    SidewalkSpot, TransitSimState, TripCount, TripEnd, TripID, TripLeg, TripManager, TripMode,
-    TripPositions, TripResult, TripSpawner, TripSpec, TripStart, UnzoomedAgent, VehicleSpec,
-    VehicleType, WalkingSimState, BUS_LENGTH,
+    AgentID, AgentMetadata, Analytics, BusTimetable, CarID, Command, CreateCar, DrawCarInput,
+    DrawPedCrowdInput, DrawPedestrianInput, DrivingGoal, DrivingSimState, Event, EventLog,
+    GetDrawAgents, IntersectionController, IntersectionSimState, ParkedCar, ParkingSimState,
+    ParkingSpot, PedestrianID, PersonID, PersonSpec, Router, Scheduler, SidewalkPOI, SidewalkSpot,
+    TrajectoryRecorder, TransitSimState, TripCount, TripEnd, TripID, TripLeg, TripManager,
+    TripMode, TripPositions, TripResult, TripSpawner, TripSpec, TripStart, UnzoomedAgent,
+    VehicleSpec, VehicleType, WalkingSimState,
 };
 use abstutil::Timer;
 use derivative::Derivative;
-use geom::{Distance, Duration, PolyLine, Pt2D, Time};
+use geom::{Distance, Duration, PolyLine, Pt2D, Speed, Time};
 use map_model::{
     BuildingID, BusRoute, BusRouteID, IntersectionID, LaneID, Map, Path, PathConstraints,
-    PathRequest, PathStep, Traversable,
+    PathRequest, PathStep, Position, Traversable,
 };
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashSet};
 use std::panic;
+use std::sync::Arc;
 use std::time::Instant;
 
 // TODO Do something else.
 const BLIND_RETRY_TO_SPAWN: Duration = Duration::const_seconds(5.0);
+// Buses from a timetable appear this long before their departure from the first stop.
+const TIME_TO_DISPATCH_BUS: Duration = Duration::const_seconds(60.0);
 
 #[derive(Serialize, Deserialize, Clone, Derivative)]
 #[derivative(PartialEq)]
@@ -55,6 +59,12 @@ pub struct Sim {
     #[derivative(PartialEq = "ignore")]
     #[serde(skip_serializing, skip_deserializing)]
     analytics: Analytics,
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    event_log: Option<EventLog>,
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    trajectories: Option<TrajectoryRecorder>,
 }
 
 #[derive(Clone)]
@@ -64,6 +74,15 @@ pub struct SimOptions {
     pub use_freeform_policy_everywhere: bool,
     pub disable_block_the_box: bool,
     pub recalc_lanechanging: bool,
+    // Stream every event to this file. See EventLog for the formats.
+    pub event_log: Option<String>,
+    // Sample every moving agent to this file this often. See TrajectoryRecorder for the formats.
+    pub record_trajectories: Option<(String, Duration)>,
+    // Cars stuck at the front of a queue for this long look for a less congested route.
+    pub reroute_blocked_after: Option<Duration>,
+    // When live edits close something, cars with paths through it find another way. See
+    // Sim::handle_live_edits.
+    pub reroute_around_closures: bool,
 }
 
 impl SimOptions {
@@ -74,6 +93,10 @@ impl SimOptions {
             use_freeform_policy_everywhere: false,
             disable_block_the_box: false,
             recalc_lanechanging: true,
+            event_log: None,
+            record_trajectories: None,
+            reroute_blocked_after: None,
+            reroute_around_closures: false,
         }
     }
 }
@@ -85,8 +108,21 @@ impl Sim {
         if let Some(d) = opts.savestate_every {
             scheduler.push(Time::START_OF_DAY + d, Command::Savestate(d));
         }
+        let trajectories = opts.record_trajectories.map(|(path, every)| {
+            assert!(every > Duration::ZERO);
+            scheduler.push(
+                Time::START_OF_DAY + every,
+                Command::RecordTrajectories(every),
+            );
+            TrajectoryRecorder::new(path)
+        });
         Sim {
-            driving: DrivingSimState::new(map, opts.recalc_lanechanging),
+            driving: DrivingSimState::new(
+                map,
+                opts.recalc_lanechanging,
+                opts.reroute_blocked_after,
+                opts.reroute_around_closures,
+            ),
             parking: ParkingSimState::new(map, timer),
             walking: WalkingSimState::new(),
             intersections: IntersectionSimState::new(
@@ -111,6 +147,8 @@ impl Sim {
             trip_positions: None,
 
             analytics: Analytics::new(),
+            event_log: opts.event_log.map(EventLog::new),
+            trajectories,
         }
     }
 
@@ -160,6 +198,31 @@ impl Sim {
         (ped_id, car_id)
     }
 
+    // Unlike schedule_trip, the person's trips are created as they go, so they can reuse the same
+    // car or bike.
+    pub fn schedule_person(
+        &mut self,
+        spec: &PersonSpec,
+        ped_speed: Speed,
+        bike: Option<VehicleSpec>,
+    ) -> PersonID {
+        let ped = PedestrianID(self.ped_id_counter);
+        self.ped_id_counter += 1;
+        let bike = bike.map(|spec| {
+            let id = CarID(self.car_id_counter, VehicleType::Bike);
+            self.car_id_counter += 1;
+            spec.make(id, None)
+        });
+        self.trips
+            .new_person(spec, ped, ped_speed, bike, &mut self.scheduler)
+    }
+
+    // Call before spawning trips. These car trips follow the given route instead of pathfinding,
+    // as long as it still fits.
+    pub fn set_fixed_car_routes(&mut self, routes: BTreeMap<TripID, Vec<PathStep>>) {
+        self.trips.set_fixed_car_routes(routes);
+    }
+
     pub fn spawn_all_trips(&mut self, map: &Map, timer: &mut Timer, retry_if_no_room: bool) {
         self.spawner.spawn_all(
             map,
@@ -217,14 +280,15 @@ impl Sim {
         for (next_stop_idx, req, mut path, end_dist) in
             self.transit.create_empty_route(route, map).into_iter()
         {
-            let id = CarID(self.car_id_counter, VehicleType::Bus);
+            let (vehicle_type, length, capacity) = transit_vehicle(route);
+            let id = CarID(self.car_id_counter, vehicle_type);
             self.car_id_counter += 1;
 
             // For now, no desire for randomness. Caller can pass in list of specs if that ever
             // changes.
             let vehicle = VehicleSpec {
-                vehicle_type: VehicleType::Bus,
-                length: BUS_LENGTH,
+                vehicle_type,
+                length,
                 max_speed: None,
             }
             .make(id, None);
@@ -277,7 +341,8 @@ impl Sim {
                     &mut self.scheduler,
                 ) {
                     self.trips.agent_starting_trip_leg(AgentID::Car(id), trip);
-                    self.transit.bus_created(id, route.id, next_stop_idx);
+                    self.transit
+                        .bus_created(id, route.id, next_stop_idx, capacity);
                     self.analytics.record_demand(&path, map);
                     results.push(id);
                     return results;
@@ -293,9 +358,170 @@ impl Sim {
         results
     }
 
+    // Schedule one bus per run in the timetable. Each bus appears on its first stop's lane a little
+    // before it's due to leave, and waits there. Runs starting from a stop that buses can't reach
+    // are skipped.
+    pub fn dispatch_bus_route(
+        &mut self,
+        route: &BusRoute,
+        timetable: &BusTimetable,
+        map: &Map,
+        timer: &mut Timer,
+    ) -> Result<Vec<CarID>, String> {
+        timetable.validate(route)?;
+        let (vehicle_type, length, capacity) = transit_vehicle(route);
+        self.transit.create_empty_route(route, map);
+
+        // Most runs start at one of a few stops, like the first in each direction.
+        let mut paths_to_first_stop: BTreeMap<usize, Result<(PathRequest, Path), String>> =
+            BTreeMap::new();
+        let mut results = Vec::new();
+        let mut skipped = Vec::new();
+        for run in timetable.all_runs() {
+            let (req, path) = match paths_to_first_stop
+                .entry(run.first_stop)
+                .or_insert_with(|| path_to_stop(route, run.first_stop, length, map))
+            {
+                Ok((req, path)) => (req.clone(), path.clone()),
+                Err(err) => {
+                    skipped.push(err.clone());
+                    continue;
+                }
+            };
+            let departure = run.stop_times[0];
+
+            let id = CarID(self.car_id_counter, vehicle_type);
+            self.car_id_counter += 1;
+            results.push(id);
+            let vehicle = VehicleSpec {
+                vehicle_type,
+                length,
+                max_speed: None,
+            }
+            .make(id, None);
+            let trip = self.trips.new_trip(
+                departure,
+                TripStart::Border(map.get_l(req.start.lane()).src_i),
+                vec![TripLeg::ServeBusRoute(id, route.id)],
+            );
+            self.transit.bus_scheduled(
+                id,
+                route.id,
+                run.first_stop,
+                run.stop_times,
+                timetable.timepoints.clone(),
+                capacity,
+            );
+            // Time can't go negative
+            let appear_at = if departure - self.time > TIME_TO_DISPATCH_BUS {
+                departure - TIME_TO_DISPATCH_BUS
+            } else {
+                self.time
+            };
+            self.scheduler.push(
+                appear_at,
+                Command::SpawnCar(
+                    CreateCar {
+                        start_dist: length,
+                        vehicle,
+                        router: Router::follow_bus_route(path, req.end.dist_along()),
+                        req,
+                        maybe_parked_car: None,
+                        trip,
+                    },
+                    true,
+                ),
+            );
+        }
+        if results.is_empty() && !skipped.is_empty() {
+            return Err(skipped.remove(0));
+        }
+        if !skipped.is_empty() {
+            timer.warn(format!(
+                "Skipped {} runs of {}: {}",
+                skipped.len(),
+                route.name,
+                skipped[0]
+            ));
+        }
+        Ok(results)
+    }
+
     pub fn set_name(&mut self, name: String) {
         self.run_name = name;
     }
+
+    // Call after applying edits to the map without resetting the simulation. Cars whose paths go
+    // through newly closed intersections reroute, if the reroute_around_closures option is on;
+    // the ones with no way around are deleted and their trips aborted. Trips that haven't started
+    // yet find new paths. Check can_close_intersection_live first.
+    pub fn handle_live_edits(&mut self, map: &Map) {
+        self.intersections
+            .handle_live_edits(map, &mut self.scheduler);
+        for id in self.driving.handle_live_edits(
+            self.time,
+            map,
+            &mut self.intersections,
+            &mut self.scheduler,
+        ) {
+            self.trips.abort_car_trip(id);
+            self.driving.kill_stuck_car(
+                id,
+                self.time,
+                map,
+                &mut self.scheduler,
+                &mut self.intersections,
+            );
+        }
+        for trip in self.scheduler.handle_live_edits(map) {
+            self.trips.abort_trip_failed_start(trip);
+        }
+    }
+
+    // Closing an intersection while the sim runs only works if everybody affected can cope.
+    // Agents already in or waiting at the intersection can't leave, and pedestrians, buses, and
+    // cars that don't reroute would get stuck there later. Call this with the map before the edit.
+    pub fn can_close_intersection_live(&self, i: IntersectionID, map: &Map) -> Result<(), String> {
+        if map.get_i(i).is_border() {
+            return Err(format!("{} is a border", i));
+        }
+        if !self.intersections.get_accepted_agents(i).is_empty()
+            || self.intersections.any_waiting(i)
+        {
+            return Err(format!("Agents are in or waiting at {}", i));
+        }
+        for agent in self.trips.active_agents() {
+            let can_reroute = match agent {
+                AgentID::Car(car) => self.driving.can_reroute_around_closures(car),
+                AgentID::Pedestrian(_) => false,
+            };
+            if can_reroute {
+                continue;
+            }
+            if let Some(path) = self.get_path(agent) {
+                if path.get_steps().iter().any(|step| match step {
+                    PathStep::Turn(t) => t.parent == i,
+                    _ => false,
+                }) {
+                    return Err(format!("{} can't find another way around {}", agent, i));
+                }
+            }
+        }
+        if self.transit.any_route_crosses(i) {
+            return Err(format!("A bus route goes through {}", i));
+        }
+        Ok(())
+    }
+
+    pub fn set_intersection_controller(
+        &mut self,
+        i: IntersectionID,
+        controller: Arc<dyn IntersectionController>,
+        map: &Map,
+    ) {
+        self.intersections
+            .set_controller(self.time, i, controller, map, &mut self.scheduler);
+    }
 }
 
 // Drawing
@@ -354,6 +580,10 @@ impl GetDrawAgents for Sim {
         result.extend(self.walking.get_unzoomed_agents(self.time, map));
         result
     }
+
+    fn current_signal_phase(&self, i: IntersectionID, map: &Map) -> (usize, Duration) {
+        self.intersections.current_signal_phase(i, self.time, map)
+    }
 }
 
 // Running
@@ -396,6 +626,9 @@ impl Sim {
                         if let Some(parked_car) = create_car.maybe_parked_car {
                             self.parking.remove_parked_car(parked_car);
                         }
+                        if create_car.vehicle.vehicle_type.is_transit() {
+                            self.transit.bus_dispatched(create_car.vehicle.id);
+                        }
                         events.push(Event::TripPhaseStarting(
                             create_car.trip,
                             Some(create_car.req.clone()),
@@ -403,6 +636,12 @@ impl Sim {
                         ));
                         self.analytics
                             .record_demand(create_car.router.get_path(), map);
+                        self.analytics.record_car_route(
+                            create_car.trip,
+                            self.time,
+                            &create_car.req,
+                            create_car.router.get_path(),
+                        );
                     } else if retry_if_no_room {
                         // TODO Record this in the trip log
                         self.scheduler.push(
@@ -557,12 +796,38 @@ impl Sim {
                     self.intersections
                         .update_intersection(self.time, i, map, &mut self.scheduler);
                 }
+                Command::StartPersonTrip(p) => {
+                    self.trips.start_person_trip(
+                        self.time,
+                        p,
+                        map,
+                        &mut self.parking,
+                        &mut self.scheduler,
+                    );
+                }
                 Command::Savestate(frequency) => {
                     self.scheduler
                         .push(self.time + frequency, Command::Savestate(frequency));
                     assert_eq!(savestate_at, None);
                     savestate_at = Some(self.time);
                 }
+                Command::RecordTrajectories(frequency) => {
+                    // Savestates don't keep recording after they're loaded.
+                    if self.trajectories.is_some() {
+                        let cars = self
+                            .driving
+                            .get_all_draw_cars(self.time, map, &self.transit);
+                        let peds = self.walking.get_all_draw_peds(self.time, map);
+                        self.trajectories
+                            .as_mut()
+                            .unwrap()
+                            .record(self.time, cars, peds, map);
+                        self.scheduler.push(
+                            self.time + frequency,
+                            Command::RecordTrajectories(frequency),
+                        );
+                    }
+                }
             }
 
             // Record events at precisely the time they occur.
@@ -572,6 +837,9 @@ impl Sim {
             events.extend(self.walking.collect_events());
             events.extend(self.intersections.collect_events());
             for ev in events {
+                if let Some(ref mut log) = self.event_log {
+                    log.record(self.time, &ev);
+                }
                 self.analytics.event(ev, self.time, map);
             }
         }
@@ -679,6 +947,12 @@ impl Sim {
             }
             callback(self, map);
             if self.is_done() {
+                if let Some(ref mut log) = self.event_log {
+                    log.flush();
+                }
+                if let Some(ref mut recorder) = self.trajectories {
+                    recorder.flush();
+                }
                 println!(
                     "{}: speed = {:.2}x, {}",
                     self.time(),
@@ -791,8 +1065,22 @@ impl Sim {
         path: String,
         map: &Map,
         timer: &mut Timer,
+    ) -> Result<Sim, std::io::Error> {
+        Sim::load_savestate_with_controllers(path, map, &|_| None, timer)
+    }
+
+    // Savestates only have the names of intersection controllers registered with
+    // set_intersection_controller, so make has to build them again. Fails if it doesn't know one.
+    pub fn load_savestate_with_controllers(
+        path: String,
+        map: &Map,
+        make: &dyn Fn(&str) -> Option<Arc<dyn IntersectionController>>,
+        timer: &mut Timer,
     ) -> Result<Sim, std::io::Error> {
         let mut sim: Sim = abstutil::maybe_read_binary(path, timer)?;
+        sim.intersections
+            .restore_controllers(make)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
         sim.restore_paths(map, timer);
         Ok(sim)
     }
@@ -813,6 +1101,12 @@ impl Sim {
         self.time
     }
 
+    // The phase a traffic signal is in and how long until it might change. Actuated signals don't
+    // follow the map's fixed schedule.
+    pub fn current_signal_phase(&self, i: IntersectionID, map: &Map) -> (usize, Duration) {
+        self.intersections.current_signal_phase(i, self.time, map)
+    }
+
     pub fn is_done(&self) -> bool {
         self.spawner.is_done() && self.trips.is_done()
     }
@@ -866,9 +1160,13 @@ impl Sim {
 
     pub fn car_tooltip(&self, car: CarID) -> Vec<String> {
         if let Some(mut lines) = self.driving.tooltip_lines(car, self.time) {
-            if car.1 == VehicleType::Bus {
+            if car.1.is_transit() {
                 let passengers = self.transit.get_passengers(car);
-                lines.push(format!("{} passengers riding", passengers.len()));
+                lines.push(format!(
+                    "{} / {} passengers riding",
+                    passengers.len(),
+                    self.transit.get_capacity(car)
+                ));
                 for (id, stop) in passengers {
                     lines.push(format!("- {} till {:?}", id, stop));
                 }
@@ -889,7 +1187,7 @@ impl Sim {
     }
 
     pub fn bus_route_id(&self, maybe_bus: CarID) -> Option<BusRouteID> {
-        if maybe_bus.1 == VehicleType::Bus {
+        if maybe_bus.1.is_transit() {
             Some(self.transit.bus_route(maybe_bus))
         } else {
             None
@@ -913,7 +1211,12 @@ impl Sim {
     }
 
     pub fn lookup_car_id(&self, idx: usize) -> Option<CarID> {
-        for vt in &[VehicleType::Car, VehicleType::Bike, VehicleType::Bus] {
+        for vt in &[
+            VehicleType::Car,
+            VehicleType::Bike,
+            VehicleType::Bus,
+            VehicleType::Train,
+        ] {
             let id = CarID(idx, *vt);
             if self.driving.tooltip_lines(id, self.time).is_some() {
                 return Some(id);
@@ -1062,3 +1365,38 @@ impl Sim {
         }
     }
 }
+
+// Buses appear at the start of a stop's lane and drive up to it.
+fn path_to_stop(
+    route: &BusRoute,
+    stop_idx: usize,
+    length: Distance,
+    map: &Map,
+) -> Result<(PathRequest, Path), String> {
+    let stop = map.get_bs(route.stops[stop_idx]).driving_pos;
+    if stop.dist_along() <= length {
+        return Err(format!(
+            "Stop {} of {} is too close to the start of its lane for buses to appear",
+            stop_idx, route.name
+        ));
+    }
+    let req = PathRequest {
+        start: Position::new(stop.lane(), length),
+        end: stop,
+        constraints: route.route_type,
+    };
+    let path = map
+        .pathfind(req.clone())
+        .ok_or_else(|| format!("Buses can't reach stop {} of {}", stop_idx, route.name))?;
+    Ok((req, path))
+}
+
+// The vehicle type, length, and capacity for everything serving this route
+fn transit_vehicle(route: &BusRoute) -> (VehicleType, Distance, usize) {
+    let vehicle_type = if route.route_type == PathConstraints::Train {
+        VehicleType::Train
+    } else {
+        VehicleType::Bus
+    };
+    (vehicle_type, route.vehicle_length, route.capacity)
+}
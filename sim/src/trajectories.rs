@@ -0,0 +1,245 @@
+use crate::{AgentID, DrawCarInput, DrawPedestrianInput};
+use geom::{Pt2D, Time};
+use map_model::Map;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
+
+// Samples the position, heading, and speed of every moving agent at a fixed interval, for
+// comparing against probe data or animating in other tools. Like EventLog, the format is picked by
+// the file extension:
+// - .bin is a sequence of bincoded TrajectoryFrames, one per sample time
+// - .csv has one row per agent per sample time
+// - .geojsonl has one GeoJSON point feature per agent per sample time
+pub struct TrajectoryRecorder {
+    path: String,
+    // None if this is a clone that shouldn't write anything
+    out: Option<BufWriter<File>>,
+    format: Format,
+    // Where every agent was at the last sample, to calculate speed
+    last_pos: BTreeMap<AgentID, Pt2D>,
+    last_time: Option<Time>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Binary,
+    CSV,
+    GeoJSONLines,
+}
+
+// Everything sampled at one time, stored by column. Positions are in WGS84.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TrajectoryFrame {
+    pub time: Time,
+    pub agents: Vec<AgentID>,
+    pub lon: Vec<f64>,
+    pub lat: Vec<f64>,
+    // Degrees, normalized to [0, 360)
+    pub heading: Vec<f32>,
+    // Meters per second, averaged since the previous sample. Zero the first time an agent is seen.
+    pub speed: Vec<f32>,
+}
+
+impl TrajectoryRecorder {
+    pub fn new(path: String) -> TrajectoryRecorder {
+        let format = get_format(&path);
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            std::fs::create_dir_all(parent).expect("Creating parent dir failed");
+        }
+        let file = match File::create(&path) {
+            Ok(f) => f,
+            Err(err) => panic!("Can't create trajectory file {}: {}", path, err),
+        };
+        let mut out = BufWriter::new(file);
+        if format == Format::CSV {
+            if let Err(err) = writeln!(out, "time,agent_type,agent_id,lon,lat,heading,speed") {
+                panic!("Can't write to trajectory file {}: {}", path, err);
+            }
+        }
+        TrajectoryRecorder {
+            path,
+            out: Some(out),
+            format,
+            last_pos: BTreeMap::new(),
+            last_time: None,
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        time: Time,
+        cars: Vec<DrawCarInput>,
+        peds: Vec<DrawPedestrianInput>,
+        map: &Map,
+    ) {
+        if self.out.is_none() {
+            return;
+        }
+
+        // Use the front of vehicles
+        let mut agents: Vec<(AgentID, Pt2D, f64)> = cars
+            .into_iter()
+            .map(|c| {
+                (
+                    AgentID::Car(c.id),
+                    c.body.last_pt(),
+                    c.body.last_line().angle().normalized_degrees(),
+                )
+            })
+            .collect();
+        agents.extend(peds.into_iter().map(|p| {
+            (
+                AgentID::Pedestrian(p.id),
+                p.pos,
+                p.facing.normalized_degrees(),
+            )
+        }));
+
+        let mut frame = TrajectoryFrame {
+            time,
+            agents: Vec::new(),
+            lon: Vec::new(),
+            lat: Vec::new(),
+            heading: Vec::new(),
+            speed: Vec::new(),
+        };
+        let mut last_pos = BTreeMap::new();
+        for (id, pt, heading) in agents {
+            let speed = match (self.last_pos.get(&id), self.last_time) {
+                (Some(last_pt), Some(last_time)) if time > last_time => {
+                    last_pt.dist_to(pt).inner_meters() / (time - last_time).inner_seconds()
+                }
+                _ => 0.0,
+            };
+            let gps = pt.forcibly_to_gps(map.get_gps_bounds());
+            frame.agents.push(id);
+            frame.lon.push(gps.longitude);
+            frame.lat.push(gps.latitude);
+            frame.heading.push(heading as f32);
+            frame.speed.push(speed as f32);
+            last_pos.insert(id, pt);
+        }
+        self.last_pos = last_pos;
+        self.last_time = Some(time);
+
+        if let Err(err) = self.write_frame(&frame) {
+            panic!("Can't write to trajectory file {}: {}", self.path, err);
+        }
+    }
+
+    fn write_frame(&mut self, frame: &TrajectoryFrame) -> Result<(), Error> {
+        let out = self.out.as_mut().unwrap();
+        if self.format == Format::Binary {
+            return bincode::serialize_into(out, frame)
+                .map_err(|err| Error::new(ErrorKind::Other, err));
+        }
+        for idx in 0..frame.agents.len() {
+            let (agent_type, agent_id) = describe_agent(frame.agents[idx]);
+            if self.format == Format::CSV {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{:.1},{:.2}",
+                    frame.time.inner_seconds(),
+                    agent_type,
+                    agent_id,
+                    frame.lon[idx],
+                    frame.lat[idx],
+                    frame.heading[idx],
+                    frame.speed[idx]
+                )?;
+            } else {
+                let feature = serde_json::json!({
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Point",
+                        "coordinates": [frame.lon[idx], frame.lat[idx]],
+                    },
+                    "properties": {
+                        "time": frame.time.inner_seconds(),
+                        "agent_type": agent_type,
+                        "agent_id": agent_id,
+                        "heading": frame.heading[idx],
+                        "speed": frame.speed[idx],
+                    },
+                });
+                serde_json::to_writer(&mut *out, &feature)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(ref mut out) = self.out {
+            if let Err(err) = out.flush() {
+                panic!("Can't flush trajectory file {}: {}", self.path, err);
+            }
+        }
+    }
+
+    // Only the .bin format can be read back.
+    pub fn read(path: &str) -> Result<Vec<TrajectoryFrame>, Error> {
+        if get_format(path) != Format::Binary {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Can only read trajectories from .bin, not {}", path),
+            ));
+        }
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut frames = Vec::new();
+        loop {
+            match bincode::deserialize_from(&mut reader) {
+                Ok(frame) => {
+                    frames.push(frame);
+                }
+                Err(err) => match *err {
+                    bincode::ErrorKind::Io(ref io) if io.kind() == ErrorKind::UnexpectedEof => {
+                        break;
+                    }
+                    _ => {
+                        return Err(Error::new(ErrorKind::Other, err));
+                    }
+                },
+            }
+        }
+        Ok(frames)
+    }
+}
+
+// Cloned sims don't keep writing to the same file.
+impl Clone for TrajectoryRecorder {
+    fn clone(&self) -> TrajectoryRecorder {
+        TrajectoryRecorder {
+            path: self.path.clone(),
+            out: None,
+            format: self.format,
+            last_pos: BTreeMap::new(),
+            last_time: None,
+        }
+    }
+}
+
+// Car IDs are unique across vehicle types, so (type, ID) is unique.
+fn describe_agent(id: AgentID) -> (String, usize) {
+    match id {
+        AgentID::Car(c) => (format!("{:?}", c.1).to_lowercase(), c.0),
+        AgentID::Pedestrian(p) => ("pedestrian".to_string(), p.0),
+    }
+}
+
+fn get_format(path: &str) -> Format {
+    if path.ends_with(".bin") {
+        Format::Binary
+    } else if path.ends_with(".csv") {
+        Format::CSV
+    } else if path.ends_with(".geojsonl") {
+        Format::GeoJSONLines
+    } else {
+        panic!(
+            "Trajectory file {} must end with .bin, .csv, or .geojsonl",
+            path
+        );
+    }
+}
@@ -1,21 +1,29 @@
 use crate::{CarID, Event, PedestrianID, Router, Scheduler, TripManager, WalkingSimState};
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Time};
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BusRoute, BusRouteID, BusStopID, Map, Path, PathConstraints, PathRequest, Position,
+    BusRoute, BusRouteID, BusStopID, IntersectionID, Map, Path, PathRequest, PathStep, Position,
 };
 use serde_derive::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 // These index stops along a route, not stops along a single sidewalk.
 type StopIdx = usize;
 
+// How long a bus stays at a stop: a fixed time to open and close doors and pull in and out, plus
+// time for each passenger getting off and on. Boarding is slower, since people pay.
+const DWELL_TIME_FIXED: Duration = Duration::const_seconds(5.0);
+const TIME_PER_ALIGHTING: Duration = Duration::const_seconds(2.0);
+const TIME_PER_BOARDING: Duration = Duration::const_seconds(3.0);
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 struct StopForRoute {
     id: BusStopID,
     driving_pos: Position,
     req: PathRequest,
     path_to_next_stop: Path,
+    // If so, the path has to be recalculated every time, since the lanes switch directions
+    uses_reversible: bool,
     next_stop_idx: StopIdx,
 }
 
@@ -31,7 +39,29 @@ struct Bus {
     route: BusRouteID,
     // Where does each passenger want to deboard?
     passengers: Vec<(PedestrianID, BusStopID)>,
+    capacity: usize,
     state: BusState,
+    // Only for buses dispatched from a timetable
+    schedule: Option<Schedule>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+struct Schedule {
+    // The bus serves the stops from here, until it runs out of stop_times
+    first_stop: StopIdx,
+    // When the bus should reach each stop, starting with first_stop
+    stop_times: Vec<Time>,
+    timepoints: BTreeSet<StopIdx>,
+}
+
+impl Schedule {
+    fn time_at(&self, idx: StopIdx) -> Time {
+        self.stop_times[idx - self.first_stop]
+    }
+
+    fn last_stop(&self) -> StopIdx {
+        self.first_stop + self.stop_times.len() - 1
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -53,6 +83,12 @@ pub struct TransitSimState {
         deserialize_with = "deserialize_btreemap"
     )]
     routes: BTreeMap<BusRouteID, Route>,
+    // Buses from a timetable that haven't appeared yet
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    scheduled_buses: BTreeMap<CarID, Bus>,
     // waiting at => (ped, route, bound for, started waiting)
     #[serde(
         serialize_with = "serialize_btreemap",
@@ -68,6 +104,7 @@ impl TransitSimState {
         TransitSimState {
             buses: BTreeMap::new(),
             routes: BTreeMap::new(),
+            scheduled_buses: BTreeMap::new(),
             peds_waiting: BTreeMap::new(),
             events: Vec::new(),
         }
@@ -98,17 +135,22 @@ impl TransitSimState {
                     let req = PathRequest {
                         start: stop1.driving_pos,
                         end: map.get_bs(bus_route.stops[stop2_idx]).driving_pos,
-                        constraints: PathConstraints::Bus,
+                        constraints: bus_route.route_type,
                     };
                     let path = map.pathfind(req.clone()).expect(&format!(
                         "No route between bus stops {:?} and {:?}",
                         stop1_id, bus_route.stops[stop2_idx]
                     ));
+                    let uses_reversible = path.get_steps().iter().any(|step| match step {
+                        PathStep::Turn(t) => map.get_parent(t.dst).is_reversible(t.dst),
+                        _ => false,
+                    });
                     StopForRoute {
                         id: *stop1_id,
                         driving_pos: stop1.driving_pos,
                         req,
                         path_to_next_stop: path,
+                        uses_reversible,
                         next_stop_idx: stop2_idx,
                     }
                 })
@@ -131,7 +173,13 @@ impl TransitSimState {
         stops
     }
 
-    pub fn bus_created(&mut self, bus: CarID, route: BusRouteID, next_stop_idx: StopIdx) {
+    pub fn bus_created(
+        &mut self,
+        bus: CarID,
+        route: BusRouteID,
+        next_stop_idx: StopIdx,
+        capacity: usize,
+    ) {
         self.routes.get_mut(&route).unwrap().buses.push(bus);
         self.buses.insert(
             bus,
@@ -139,11 +187,49 @@ impl TransitSimState {
                 car: bus,
                 route,
                 passengers: Vec::new(),
+                capacity,
                 state: BusState::DrivingToStop(next_stop_idx),
+                schedule: None,
             },
         );
     }
 
+    // The route must already exist. The bus heads to first_stop once it's dispatched.
+    pub fn bus_scheduled(
+        &mut self,
+        bus: CarID,
+        route: BusRouteID,
+        first_stop: StopIdx,
+        stop_times: Vec<Time>,
+        timepoints: BTreeSet<StopIdx>,
+        capacity: usize,
+    ) {
+        assert!(first_stop + stop_times.len() <= self.routes[&route].stops.len());
+        self.scheduled_buses.insert(
+            bus,
+            Bus {
+                car: bus,
+                route,
+                passengers: Vec::new(),
+                capacity,
+                state: BusState::DrivingToStop(first_stop),
+                schedule: Some(Schedule {
+                    first_stop,
+                    stop_times,
+                    timepoints,
+                }),
+            },
+        );
+    }
+
+    pub fn bus_dispatched(&mut self, id: CarID) {
+        let bus = self.scheduled_buses.remove(&id).unwrap();
+        self.routes.get_mut(&bus.route).unwrap().buses.push(id);
+        self.buses.insert(id, bus);
+    }
+
+    // Returns how long the bus should wait at the stop, or None if the bus just finished its run
+    // and should disappear.
     pub fn bus_arrived_at_stop(
         &mut self,
         now: Time,
@@ -152,7 +238,7 @@ impl TransitSimState {
         walking: &mut WalkingSimState,
         scheduler: &mut Scheduler,
         map: &Map,
-    ) {
+    ) -> Option<Duration> {
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
@@ -160,25 +246,76 @@ impl TransitSimState {
                 let stop1 = self.routes[&bus.route].stops[stop_idx].id;
                 self.events
                     .push(Event::BusArrivedAtStop(id, bus.route, stop1));
+                // Buses wait at the first stop to be dispatched, so arriving there doesn't count.
+                if let Some(ref schedule) = bus.schedule {
+                    if stop_idx != schedule.first_stop {
+                        self.events.push(Event::ScheduledBusArrival(
+                            id,
+                            bus.route,
+                            stop1,
+                            schedule.time_at(stop_idx),
+                        ));
+                    }
+                }
+                let end_of_run = bus
+                    .schedule
+                    .as_ref()
+                    .map(|s| stop_idx == s.last_stop())
+                    .unwrap_or(false);
 
                 // Deboard existing passengers.
+                let mut num_alighting = 0;
                 let mut still_riding = Vec::new();
                 for (ped, stop2) in bus.passengers.drain(..) {
-                    if stop1 == stop2 {
+                    if stop1 == stop2 || end_of_run {
+                        num_alighting += 1;
                         self.events.push(Event::PedLeavesBus(ped, id, bus.route));
-                        trips.ped_left_bus(now, ped, map, scheduler);
+                        trips.ped_left_bus(now, ped, stop1, map, scheduler);
                     } else {
                         still_riding.push((ped, stop2));
                     }
                 }
                 bus.passengers = still_riding;
 
-                // Board new passengers.
+                if end_of_run {
+                    let route = bus.route;
+                    self.buses.remove(&id);
+                    self.routes
+                        .get_mut(&route)
+                        .unwrap()
+                        .buses
+                        .retain(|b| *b != id);
+                    trips.bus_finished_run(now, id);
+
+                    // Nobody else is coming, so don't leave anybody waiting forever.
+                    if self.route_finished(route) {
+                        for (stop, waiting) in self.peds_waiting.iter_mut() {
+                            for (ped, _, _, _) in waiting.iter().filter(|(_, r, _, _)| *r == route)
+                            {
+                                trips.ped_stranded_at_bus_stop(
+                                    now, *ped, *stop, map, walking, scheduler,
+                                );
+                            }
+                            waiting.retain(|(_, r, _, _)| *r != route);
+                        }
+                    }
+                    return None;
+                }
+
+                // Board new passengers, in the order they started waiting, until the bus is
+                // full. Everybody else waits for the next bus.
+                let mut num_boarding = 0;
+                let mut denied = false;
                 let mut still_waiting = Vec::new();
                 for (ped, route, stop2, started_waiting) in
                     self.peds_waiting.remove(&stop1).unwrap_or_else(Vec::new)
                 {
-                    if bus.route == route {
+                    if bus.route == route && bus.passengers.len() >= bus.capacity {
+                        self.events.push(Event::PedDeniedBoarding(ped, id, stop1));
+                        denied = true;
+                        still_waiting.push((ped, route, stop2, started_waiting));
+                    } else if bus.route == route {
+                        num_boarding += 1;
                         bus.passengers.push((ped, stop2));
                         self.events.push(Event::PedEntersBus(ped, id, route));
                         let trip = trips.ped_boarded_bus(ped, walking);
@@ -187,7 +324,7 @@ impl TransitSimState {
                             Some(PathRequest {
                                 start: map.get_bs(stop1).driving_pos,
                                 end: map.get_bs(stop2).driving_pos,
-                                constraints: PathConstraints::Bus,
+                                constraints: id.1.to_constraints(),
                             }),
                             format!("{} riding {}", ped, route),
                         ));
@@ -196,12 +333,33 @@ impl TransitSimState {
                     }
                 }
                 self.peds_waiting.insert(stop1, still_waiting);
+
+                // Don't bother stopping if nobody's getting on or off.
+                let mut dwell_time = if num_alighting == 0 && num_boarding == 0 && !denied {
+                    Duration::ZERO
+                } else {
+                    DWELL_TIME_FIXED
+                        + (num_alighting as f64) * TIME_PER_ALIGHTING
+                        + (num_boarding as f64) * TIME_PER_BOARDING
+                };
+                // Early buses hold at timepoints.
+                if let Some(ref schedule) = bus.schedule {
+                    if stop_idx == schedule.first_stop || schedule.timepoints.contains(&stop_idx) {
+                        let depart = schedule.time_at(stop_idx);
+                        if now + dwell_time < depart {
+                            dwell_time = depart - now;
+                        }
+                    }
+                }
+                Some(dwell_time)
             }
             BusState::AtStop(_) => unreachable!(),
-        };
+        }
     }
 
-    pub fn bus_departed_from_stop(&mut self, id: CarID) -> Router {
+    // When the path to the next stop uses reversible lanes, it's recalculated now, since they
+    // change through the day. If that fails, the bus sticks to the usual path and waits.
+    pub fn bus_departed_from_stop(&mut self, id: CarID, now: Time, map: &Map) -> Router {
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(_) => unreachable!(),
@@ -212,15 +370,29 @@ impl TransitSimState {
                 bus.state = BusState::DrivingToStop(stop.next_stop_idx);
                 self.events
                     .push(Event::BusDepartedFromStop(id, bus.route, stop.id));
+                self.events.push(Event::BusLoadMeasured(
+                    id,
+                    stop.id,
+                    bus.passengers.len(),
+                    bus.capacity,
+                ));
+                let path = if stop.uses_reversible {
+                    map.pathfind_at(stop.req.clone(), now)
+                        .unwrap_or_else(|| stop.path_to_next_stop.clone())
+                } else {
+                    stop.path_to_next_stop.clone()
+                };
                 Router::follow_bus_route(
-                    stop.path_to_next_stop.clone(),
+                    path,
                     route.stops[stop.next_stop_idx].driving_pos.dist_along(),
                 )
             }
         }
     }
 
-    // If true, the pedestrian boarded a bus immediately.
+    // If true, the pedestrian boarded a bus immediately. A full bus already at the stop doesn't
+    // count as a denied boarding; it's about to leave anyway.
+    // TODO Boarding a bus that's already waiting doesn't lengthen its dwell time.
     pub fn ped_waiting_for_bus(
         &mut self,
         now: Time,
@@ -233,7 +405,9 @@ impl TransitSimState {
         if let Some(route) = self.routes.get(&route_id) {
             for bus in &route.buses {
                 if let BusState::AtStop(idx) = self.buses[bus].state {
-                    if route.stops[idx].id == stop1 {
+                    if route.stops[idx].id == stop1
+                        && self.buses[bus].passengers.len() < self.buses[bus].capacity
+                    {
                         self.buses
                             .get_mut(bus)
                             .unwrap()
@@ -259,6 +433,31 @@ impl TransitSimState {
         false
     }
 
+    // Does any bus drive through this intersection between two of its stops?
+    pub fn any_route_crosses(&self, i: IntersectionID) -> bool {
+        self.routes.values().any(|route| {
+            route.stops.iter().any(|stop| {
+                stop.path_to_next_stop
+                    .get_steps()
+                    .iter()
+                    .any(|step| match step {
+                        PathStep::Turn(t) => t.parent == i,
+                        _ => false,
+                    })
+            })
+        })
+    }
+
+    // True once every bus scheduled for a timetabled route has finished its run. Routes seeded
+    // with looping buses never finish.
+    pub fn route_finished(&self, route: BusRouteID) -> bool {
+        if let Some(r) = self.routes.get(&route) {
+            r.buses.is_empty() && self.scheduled_buses.values().all(|b| b.route != route)
+        } else {
+            false
+        }
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         self.events.drain(..).collect()
     }
@@ -267,6 +466,10 @@ impl TransitSimState {
         &self.buses[&bus].passengers
     }
 
+    pub fn get_capacity(&self, bus: CarID) -> usize {
+        self.buses[&bus].capacity
+    }
+
     pub fn bus_route(&self, bus: CarID) -> BusRouteID {
         self.buses[&bus].route
     }
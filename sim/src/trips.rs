@@ -1,12 +1,14 @@
 use crate::{
-    AgentID, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal, Event, ParkingSimState,
-    ParkingSpot, PedestrianID, Scheduler, SidewalkPOI, SidewalkSpot, TransitSimState, TripID,
-    Vehicle, VehicleType, WalkingSimState,
+    Activity, AgentID, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal, Event,
+    ParkingSimState, ParkingSpot, PedestrianID, PersonID, PersonSpec, Scheduler, SidewalkPOI,
+    SidewalkSpot, TransitSimState, TripID, Vehicle, VehicleType, WalkingSimState,
+    DEFAULT_PARKING_STAY,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
-use geom::{Speed, Time};
+use geom::{Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
+    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, Path, PathConstraints, PathRequest,
+    PathStep, Position,
 };
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, VecDeque};
@@ -22,6 +24,13 @@ pub struct TripManager {
     active_trip_mode: BTreeMap<AgentID, TripID>,
     num_bus_trips: usize,
     unfinished_trips: usize,
+    people: Vec<Person>,
+    // StartPersonTrip commands in the scheduler. Their trips don't exist yet, but the simulation
+    // isn't done.
+    pending_person_trips: usize,
+    // Iterative traffic assignment keeps some trips on the route they drove last time, instead of
+    // pathfinding again.
+    fixed_car_routes: BTreeMap<TripID, Vec<PathStep>>,
 
     events: Vec<Event>,
 }
@@ -33,6 +42,9 @@ impl TripManager {
             active_trip_mode: BTreeMap::new(),
             num_bus_trips: 0,
             unfinished_trips: 0,
+            people: Vec::new(),
+            pending_person_trips: 0,
+            fixed_car_routes: BTreeMap::new(),
             events: Vec::new(),
         }
     }
@@ -87,6 +99,7 @@ impl TripManager {
             spawned_at,
             finished_at: None,
             aborted: false,
+            person: None,
             mode,
             legs: VecDeque::from(legs),
             start,
@@ -99,6 +112,14 @@ impl TripManager {
         id
     }
 
+    pub fn set_fixed_car_routes(&mut self, routes: BTreeMap<TripID, Vec<PathStep>>) {
+        self.fixed_car_routes = routes;
+    }
+
+    pub fn fixed_car_route(&self, trip: TripID, req: &PathRequest, map: &Map) -> Option<Path> {
+        fixed_car_route(&self.fixed_car_routes, trip, req, map)
+    }
+
     pub fn dynamically_override_legs(&mut self, id: TripID, legs: Vec<TripLeg>) {
         let trip = &mut self.trips[id.0];
         trip.legs = VecDeque::from(legs);
@@ -132,6 +153,9 @@ impl TripManager {
             Some(TripLeg::Drive(vehicle, DrivingGoal::ParkNear(_))) => assert_eq!(car, vehicle.id),
             _ => unreachable!(),
         };
+        if let Some(p) = trip.person {
+            self.people[p.0].car = Some(car);
+        }
 
         match &trip.legs[0] {
             TripLeg::Walk(_, _, to) => match (spot, &to.connection) {
@@ -146,6 +170,9 @@ impl TripManager {
                         trip.mode,
                         now - trip.spawned_at,
                     ));
+                    if let Some(p) = trip.person {
+                        self.person_reached_building(now, p, b1, scheduler);
+                    }
                     return;
                 }
                 _ => {}
@@ -173,11 +200,12 @@ impl TripManager {
         scheduler: &mut Scheduler,
     ) {
         self.events.push(Event::PedReachedParkingSpot(ped, spot));
-        let trip = &mut self.trips[self
+        let id = self
             .active_trip_mode
             .remove(&AgentID::Pedestrian(ped))
-            .unwrap()
-            .0];
+            .unwrap();
+        let stay = self.expected_stay(id);
+        let trip = &mut self.trips[id.0];
 
         trip.assert_walking_leg(ped, SidewalkSpot::parking_spot(spot, map, parking));
         let (car, drive_to) = match trip.legs[0] {
@@ -198,7 +226,9 @@ impl TripManager {
             end,
             constraints: PathConstraints::Car,
         };
-        let path = if let Some(p) = map.pathfind(req.clone()) {
+        let path = if let Some(p) = fixed_car_route(&self.fixed_car_routes, id, &req, map)
+            .or_else(|| map.pathfind_at(req.clone(), now))
+        {
             p
         } else {
             println!(
@@ -211,7 +241,7 @@ impl TripManager {
             return;
         };
 
-        let router = drive_to.make_router(path, map, parked_car.vehicle.vehicle_type);
+        let router = drive_to.make_router(path, map, parked_car.vehicle.vehicle_type, stay);
         scheduler.push(
             now,
             Command::SpawnCar(
@@ -235,11 +265,12 @@ impl TripManager {
         map: &Map,
         scheduler: &mut Scheduler,
     ) {
-        let trip = &mut self.trips[self
+        let id = self
             .active_trip_mode
             .remove(&AgentID::Pedestrian(ped))
-            .unwrap()
-            .0];
+            .unwrap();
+        let stay = self.expected_stay(id);
+        let trip = &mut self.trips[id.0];
 
         trip.assert_walking_leg(ped, spot.clone());
         let (vehicle, drive_to) = match trip.legs[0] {
@@ -257,7 +288,7 @@ impl TripManager {
             end,
             constraints: PathConstraints::Bike,
         };
-        let path = if let Some(p) = map.pathfind(req.clone()) {
+        let path = if let Some(p) = map.pathfind_at(req.clone(), now) {
             p
         } else {
             println!(
@@ -270,7 +301,7 @@ impl TripManager {
             return;
         };
 
-        let router = drive_to.make_router(path, map, vehicle.vehicle_type);
+        let router = drive_to.make_router(path, map, vehicle.vehicle_type, stay);
         scheduler.push(
             now,
             Command::SpawnCar(
@@ -298,6 +329,10 @@ impl TripManager {
             Some(TripLeg::Drive(vehicle, DrivingGoal::ParkNear(_))) => assert_eq!(vehicle.id, bike),
             _ => unreachable!(),
         };
+        // The bike stays here until the person's next biking trip.
+        if let Some(p) = trip.person {
+            self.people[p.0].bike_rack = Some(bike_rack.clone());
+        }
 
         if !trip.spawn_ped(now, bike_rack, map, scheduler) {
             self.unfinished_trips -= 1;
@@ -310,6 +345,7 @@ impl TripManager {
         ped: PedestrianID,
         bldg: BuildingID,
         map: &Map,
+        scheduler: &mut Scheduler,
     ) {
         self.events.push(Event::PedReachedBuilding(ped, bldg));
         let trip = &mut self.trips[self
@@ -327,9 +363,13 @@ impl TripManager {
             trip.mode,
             now - trip.spawned_at,
         ));
+        if let Some(p) = trip.person {
+            self.person_reached_building(now, p, bldg, scheduler);
+        }
     }
 
-    // If no route is returned, the pedestrian boarded a bus immediately.
+    // If no route is returned, the pedestrian boarded a bus immediately, or no more buses are
+    // coming and they'll walk the rest of the way instead.
     pub fn ped_reached_bus_stop(
         &mut self,
         now: Time,
@@ -337,6 +377,7 @@ impl TripManager {
         stop: BusStopID,
         map: &Map,
         transit: &mut TransitSimState,
+        scheduler: &mut Scheduler,
     ) -> Option<BusRouteID> {
         let trip = &mut self.trips[self.active_trip_mode[&AgentID::Pedestrian(ped)].0];
         match trip.legs[0] {
@@ -349,6 +390,10 @@ impl TripManager {
         match trip.legs[1] {
             TripLeg::RideBus(_, route, stop2) => {
                 self.events.push(Event::PedReachedBusStop(ped, stop, route));
+                if transit.route_finished(route) {
+                    self.walk_instead_of_bus(now, ped, stop, map, scheduler);
+                    return None;
+                }
                 self.events.push(Event::TripPhaseStarting(
                     trip.id,
                     None,
@@ -373,10 +418,13 @@ impl TripManager {
         trip.id
     }
 
+    // Usually the pedestrian gets off where they planned, but a bus finishing its run drops
+    // everybody off at the last stop.
     pub fn ped_left_bus(
         &mut self,
         now: Time,
         ped: PedestrianID,
+        stop: BusStopID,
         map: &Map,
         scheduler: &mut Scheduler,
     ) {
@@ -385,16 +433,63 @@ impl TripManager {
             .remove(&AgentID::Pedestrian(ped))
             .unwrap()
             .0];
-        let start = match trip.legs.pop_front().unwrap() {
-            TripLeg::RideBus(_, _, stop) => SidewalkSpot::bus_stop(stop, map),
+        match trip.legs.pop_front().unwrap() {
+            TripLeg::RideBus(_, _, _) => {}
             _ => unreachable!(),
         };
+        let start = SidewalkSpot::bus_stop(stop, map);
 
         if !trip.spawn_ped(now, start, map, scheduler) {
             self.unfinished_trips -= 1;
         }
     }
 
+    // The last scheduled bus for the route already finished its run.
+    pub fn ped_stranded_at_bus_stop(
+        &mut self,
+        now: Time,
+        ped: PedestrianID,
+        stop: BusStopID,
+        map: &Map,
+        walking: &mut WalkingSimState,
+        scheduler: &mut Scheduler,
+    ) {
+        walking.ped_stopped_waiting_for_bus(ped);
+        self.walk_instead_of_bus(now, ped, stop, map, scheduler);
+    }
+
+    // Skip the bus ride and walk from the stop straight to wherever the bus would've gone.
+    fn walk_instead_of_bus(
+        &mut self,
+        now: Time,
+        ped: PedestrianID,
+        stop: BusStopID,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        let trip = &mut self.trips[self
+            .active_trip_mode
+            .remove(&AgentID::Pedestrian(ped))
+            .unwrap()
+            .0];
+        trip.assert_walking_leg(ped, SidewalkSpot::bus_stop(stop, map));
+        match trip.legs.pop_front() {
+            Some(TripLeg::RideBus(_, _, _)) => {}
+            _ => unreachable!(),
+        };
+        if !trip.spawn_ped(now, SidewalkSpot::bus_stop(stop, map), map, scheduler) {
+            self.unfinished_trips -= 1;
+        }
+    }
+
+    // Bus trips don't count as finished or unfinished trips.
+    pub fn bus_finished_run(&mut self, now: Time, bus: CarID) {
+        let trip = &mut self.trips[self.active_trip_mode.remove(&AgentID::Car(bus)).unwrap().0];
+        assert!(trip.is_bus_trip());
+        trip.legs.pop_front();
+        trip.finished_at = Some(now);
+    }
+
     pub fn ped_reached_border(
         &mut self,
         now: Time,
@@ -438,6 +533,158 @@ impl TripManager {
         ));
     }
 
+    pub fn new_person(
+        &mut self,
+        spec: &PersonSpec,
+        ped: PedestrianID,
+        ped_speed: Speed,
+        bike: Option<Vehicle>,
+        scheduler: &mut Scheduler,
+    ) -> PersonID {
+        let id = PersonID(self.people.len());
+        self.people.push(Person {
+            id,
+            ped,
+            ped_speed,
+            bldg: spec.home,
+            car: None,
+            bike,
+            bike_rack: None,
+            schedule: spec.activities.iter().cloned().collect(),
+            staying_for: Duration::ZERO,
+        });
+        if !spec.activities.is_empty() {
+            self.pending_person_trips += 1;
+            scheduler.push(spec.depart, Command::StartPersonTrip(id));
+        }
+        id
+    }
+
+    // Leave for the next activity. If anything goes wrong, the trip is aborted and the person
+    // doesn't do anything else that day.
+    pub fn start_person_trip(
+        &mut self,
+        now: Time,
+        id: PersonID,
+        map: &Map,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+    ) {
+        self.pending_person_trips -= 1;
+        let person = &mut self.people[id.0];
+        let activity = person.schedule.pop_front().unwrap();
+        person.staying_for = activity.duration;
+
+        let ped = person.ped;
+        let speed = person.ped_speed;
+        let from = person.bldg;
+        let start = SidewalkSpot::building(person.bldg, map);
+        let goal = SidewalkSpot::building(activity.bldg, map);
+        let walk_there = vec![TripLeg::Walk(ped, speed, goal.clone())];
+        let mut reserved_car = None;
+        let legs = match activity.mode {
+            TripMode::Walk => walk_there,
+            TripMode::Drive => {
+                let drive_to = DrivingGoal::ParkNear(activity.bldg);
+                // The car is parked wherever the last driving trip ended. The first time, borrow
+                // any car owned by the starting building.
+                if let Some(parked_car) = person
+                    .car
+                    .and_then(|c| parking.dynamically_reserve_specific_car(c))
+                {
+                    let legs = vec![
+                        TripLeg::Walk(
+                            ped,
+                            speed,
+                            SidewalkSpot::parking_spot(parked_car.spot, map, parking),
+                        ),
+                        TripLeg::Drive(parked_car.vehicle.clone(), drive_to),
+                        TripLeg::Walk(ped, speed, goal),
+                    ];
+                    reserved_car = Some(parked_car);
+                    legs
+                } else {
+                    vec![TripLeg::Walk(
+                        ped,
+                        speed,
+                        SidewalkSpot::deferred_parking_spot(person.bldg, drive_to, map),
+                    )]
+                }
+            }
+            TripMode::Bike => {
+                let drive_to = DrivingGoal::ParkNear(activity.bldg);
+                let last_lane = drive_to.goal_pos(PathConstraints::Bike, map).lane();
+                let rack = person
+                    .bike_rack
+                    .clone()
+                    .or_else(|| SidewalkSpot::bike_from_bike_rack(start.sidewalk_pos.lane(), map));
+                match (person.bike.clone(), rack) {
+                    (Some(bike), Some(rack))
+                        if rack.sidewalk_pos.lane() != goal.sidewalk_pos.lane()
+                            && map
+                                .get_parent(last_lane)
+                                .bike_to_sidewalk(last_lane)
+                                .is_some() =>
+                    {
+                        vec![
+                            TripLeg::Walk(ped, speed, rack),
+                            TripLeg::Drive(bike, drive_to),
+                            TripLeg::Walk(ped, speed, goal),
+                        ]
+                    }
+                    // Just walk instead
+                    _ => walk_there,
+                }
+            }
+            TripMode::Transit => {
+                if let Some((stop1, stop2, route)) =
+                    map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
+                {
+                    vec![
+                        TripLeg::Walk(ped, speed, SidewalkSpot::bus_stop(stop1, map)),
+                        TripLeg::RideBus(ped, route, stop2),
+                        TripLeg::Walk(ped, speed, goal),
+                    ]
+                } else {
+                    walk_there
+                }
+            }
+        };
+
+        let trip = self.new_trip(now, TripStart::Bldg(from), legs);
+        self.trips[trip.0].person = Some(id);
+        if !self.trips[trip.0].spawn_ped(now, start, map, scheduler) {
+            if let Some(parked_car) = reserved_car {
+                parking.dynamically_return_car(parked_car);
+            }
+            self.abort_trip_failed_start(trip);
+        }
+    }
+
+    fn person_reached_building(
+        &mut self,
+        now: Time,
+        id: PersonID,
+        bldg: BuildingID,
+        scheduler: &mut Scheduler,
+    ) {
+        let person = &mut self.people[id.0];
+        person.bldg = bldg;
+        if !person.schedule.is_empty() {
+            self.pending_person_trips += 1;
+            scheduler.push(now + person.staying_for, Command::StartPersonTrip(id));
+        }
+    }
+
+    // How long the driver on this trip expects to park at the end. People know from their
+    // schedule; for everybody else, it's a guess.
+    pub fn expected_stay(&self, id: TripID) -> Duration {
+        match self.trips[id.0].person {
+            Some(p) => self.people[p.0].staying_for,
+            None => DEFAULT_PARKING_STAY,
+        }
+    }
+
     pub fn abort_trip_failed_start(&mut self, id: TripID) {
         self.trips[id.0].aborted = true;
         if !self.trips[id.0].is_bus_trip() {
@@ -446,7 +693,9 @@ impl TripManager {
         self.events.push(Event::TripAborted(id));
     }
 
-    pub fn abort_trip_impossible_parking(&mut self, car: CarID) {
+    // The car is about to be deleted mid-trip, like when it can't park or live edits leave it no
+    // way to its goal.
+    pub fn abort_car_trip(&mut self, car: CarID) {
         let trip = self.active_trip_mode.remove(&AgentID::Car(car)).unwrap();
         assert!(!self.trips[trip.0].is_bus_trip());
         self.trips[trip.0].aborted = true;
@@ -513,7 +762,7 @@ impl TripManager {
     }
 
     pub fn is_done(&self) -> bool {
-        self.unfinished_trips == 0
+        self.unfinished_trips == 0 && self.pending_person_trips == 0
     }
 
     pub fn collect_events(&mut self) -> Vec<Event> {
@@ -588,6 +837,27 @@ struct Trip {
     mode: TripMode,
     start: TripStart,
     end: TripEnd,
+    person: Option<PersonID>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+struct Person {
+    id: PersonID,
+    ped: PedestrianID,
+    ped_speed: Speed,
+    // Where the person is now, or where they last left from
+    bldg: BuildingID,
+    // Once known, the person keeps using the same car. It's usually parked wherever the last
+    // driving trip ended.
+    car: Option<CarID>,
+    bike: Option<Vehicle>,
+    // Where the bike was left after the last biking trip. Before that, it's at the starting
+    // building.
+    bike_rack: Option<SidewalkSpot>,
+    // Activities not started yet
+    schedule: VecDeque<Activity>,
+    // How long to stay at the current activity
+    staying_for: Duration,
 }
 
 impl Trip {
@@ -666,6 +936,17 @@ impl Trip {
     }
 }
 
+// None if the trip has no fixed route, or it no longer fits, like when the car is parked somewhere
+// else this time. Then the caller should just pathfind like usual.
+fn fixed_car_route(
+    fixed_car_routes: &BTreeMap<TripID, Vec<PathStep>>,
+    trip: TripID,
+    req: &PathRequest,
+    map: &Map,
+) -> Option<Path> {
+    Path::reuse(req, fixed_car_routes.get(&trip)?.clone(), map)
+}
+
 // These don't specify where the leg starts, since it might be unknown -- like when we drive and
 // don't know where we'll wind up parking.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
@@ -700,7 +981,7 @@ impl TripMode {
             AgentID::Car(id) => match id.1 {
                 VehicleType::Car => TripMode::Drive,
                 VehicleType::Bike => TripMode::Bike,
-                VehicleType::Bus => TripMode::Transit,
+                VehicleType::Bus | VehicleType::Train => TripMode::Transit,
             },
         }
     }
@@ -1,5 +1,5 @@
 use crate::runner::TestRunner;
-use geom::{Duration, Line, PolyLine, Pt2D};
+use geom::{Duration, Line, PolyLine, Pt2D, Speed};
 
 #[allow(clippy::unreadable_literal)]
 pub fn run(t: &mut TestRunner) {
@@ -40,6 +40,12 @@ pub fn run(t: &mut TestRunner) {
         pl.get_slice_ending_at(pt);
     });
 
+    t.run_fast("speed_units", |_| {
+        assert!((Speed::miles_per_hour(25.0).to_miles_per_hour() - 25.0).abs() < 0.001);
+        assert!((Speed::km_per_hour(100.0).to_miles_per_hour() - 62.137).abs() < 0.001);
+        assert_eq!(Speed::miles_per_hour(25.0).to_string(), "25 mph");
+    });
+
     t.run_fast("time_parsing", |_| {
         assert_eq!(Duration::parse("2.3"), Ok(Duration::seconds(2.3)));
         assert_eq!(Duration::parse("02.3"), Ok(Duration::seconds(2.3)));
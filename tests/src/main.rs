@@ -4,6 +4,7 @@ mod parking;
 mod runner;
 mod sim_completion;
 mod sim_determinism;
+mod traffic_signals;
 mod transit;
 mod trips;
 
@@ -34,6 +35,7 @@ fn main() {
     parking::run(t.suite("parking"));
     sim_completion::run(t.suite("sim_completion"));
     sim_determinism::run(t.suite("sim_determinism"));
+    traffic_signals::run(t.suite("traffic_signals"));
     transit::run(t.suite("transit"));
     trips::run(t.suite("trips"));
 
@@ -1,4 +1,9 @@
 use crate::runner::TestRunner;
+use abstutil::Timer;
+use geom::{Duration, Speed, Time};
+use map_model::raw::RawMap;
+use map_model::{osm, LaneID, LaneType, Map, TurnType};
+use std::collections::{BTreeMap, BTreeSet};
 
 pub fn run(t: &mut TestRunner) {
     t.run_slow("convert_osm_twice", |_| {
@@ -10,6 +15,7 @@ pub fn run(t: &mut TestRunner) {
             gtfs: Some("../data/input/google_transit_2018_18_08".to_string()),
             neighborhoods: Some("../data/input/neighborhoods.geojson".to_string()),
             clip: Some(abstutil::path_polygon("montlake")),
+            speed_limits: None,
             output: "convert_osm_twice.bin".to_string(),
         };
 
@@ -24,6 +30,29 @@ pub fn run(t: &mut TestRunner) {
         }
     });
 
+    t.run_slow("convert_osm_pbf_matches_xml", |_| {
+        let mut flags = convert_osm::Flags {
+            osm: "../data/input/osm/montlake.osm".to_string(),
+            parking_shapes: None,
+            offstreet_parking: None,
+            sidewalks: None,
+            gtfs: None,
+            neighborhoods: None,
+            clip: Some(abstutil::path_polygon("montlake")),
+            speed_limits: None,
+            output: "convert_osm_pbf_matches_xml.bin".to_string(),
+        };
+        let from_xml = convert_osm::convert(&flags, &mut abstutil::Timer::throwaway());
+        flags.osm = "../data/input/osm/montlake.osm.pbf".to_string();
+        let from_pbf = convert_osm::convert(&flags, &mut abstutil::Timer::throwaway());
+
+        if abstutil::to_json(&from_xml) != abstutil::to_json(&from_pbf) {
+            abstutil::write_json("from_xml.json".to_string(), &from_xml);
+            abstutil::write_json("from_pbf.json".to_string(), &from_pbf);
+            panic!("from_xml.json and from_pbf.json differ");
+        }
+    });
+
     t.run_slow("raw_to_map_twice", |_| {
         let map1 = map_model::Map::new(
             abstutil::path_raw_map("montlake"),
@@ -59,4 +88,289 @@ pub fn run(t: &mut TestRunner) {
             &mut abstutil::Timer::throwaway(),
         );
     });
+
+    t.run_fast("conditional_restrictions", |_| {
+        let parsed = map_model::parse_conditional(
+            "no_left_turn @ (Mo-Fr 07:00-09:00,16:00-18:30); no_u_turn @ (Sa 22:00-02:00)",
+        );
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].0, "no_left_turn");
+        assert_eq!(parsed[1].0, "no_u_turn");
+
+        // The simulation happens on a weekday.
+        let peak = &parsed[0].1;
+        assert!(peak.contains(Time::START_OF_DAY + Duration::hours(8)));
+        assert!(peak.contains(Time::START_OF_DAY + Duration::hours(17)));
+        assert!(!peak.contains(Time::START_OF_DAY + Duration::hours(12)));
+        assert!(!peak.contains(Time::START_OF_DAY + Duration::hours(9)));
+        assert!(!parsed[1]
+            .1
+            .contains(Time::START_OF_DAY + Duration::hours(23)));
+
+        assert!(map_model::TimeWindows::parse("Mo-Fr 25:00-26:00").is_err());
+        assert!(map_model::raw::RestrictionType::new("no_entry").is_none());
+    });
+
+    t.run_fast("reversible_schedule", |_| {
+        let mut tags = BTreeMap::new();
+        tags.insert("oneway".to_string(), "reversible".to_string());
+        tags.insert("lanes".to_string(), "2".to_string());
+        tags.insert(
+            "oneway:conditional".to_string(),
+            "yes @ (Mo-Fr 05:00-11:00); -1 @ (Mo-Fr 11:30-23:00)".to_string(),
+        );
+        let schedule = map_model::ReversibleSchedule::parse(&tags).unwrap();
+        let morning = Time::START_OF_DAY + Duration::hours(8);
+        assert!(schedule.is_open(true, morning));
+        assert!(!schedule.is_open(false, morning));
+        // Closed both ways while the lanes switch over
+        let switchover = Time::START_OF_DAY + Duration::hours(11) + Duration::minutes(15);
+        assert!(!schedule.is_open(true, switchover));
+        assert!(!schedule.is_open(false, switchover));
+        assert!(schedule.is_open(false, Time::START_OF_DAY + Duration::hours(17)));
+
+        assert!(!schedule.is_contraflow());
+        assert_eq!(
+            schedule.next_change(morning),
+            Time::START_OF_DAY + Duration::hours(11)
+        );
+        // Past the last window, wait for the next morning
+        assert_eq!(
+            schedule.next_change(Time::START_OF_DAY + Duration::hours(23)),
+            Time::START_OF_DAY + Duration::hours(29)
+        );
+
+        tags.remove("oneway:conditional");
+        assert!(map_model::ReversibleSchedule::parse(&tags).is_none());
+
+        // Contraflow lanes on a two-way road
+        let mut tags = BTreeMap::new();
+        tags.insert("lanes:both_ways".to_string(), "1".to_string());
+        tags.insert(
+            "lanes:both_ways:conditional".to_string(),
+            "yes @ (06:00-10:00); -1 @ (15:00-19:00)".to_string(),
+        );
+        assert!(map_model::ReversibleSchedule::parse(&tags)
+            .unwrap()
+            .is_contraflow());
+    });
+
+    t.run_slow("turn_lanes_tags", |_| {
+        let mut timer = Timer::throwaway();
+        let orig_map = Map::new(abstutil::path_map("montlake"), true, &mut timer);
+        // Going straight into a road with fewer lanes is a lane change.
+        let types_from = |map: &Map, l: LaneID| -> BTreeSet<TurnType> {
+            map.get_turns_from_lane(l)
+                .into_iter()
+                .map(|t| match t.turn_type {
+                    TurnType::LaneChangeLeft | TurnType::LaneChangeRight => TurnType::Straight,
+                    tt => tt,
+                })
+                .collect()
+        };
+        // A road with one lane going into an intersection where it can turn every way
+        let road = orig_map
+            .all_roads()
+            .iter()
+            .find(|r| {
+                r.osm_tags.contains_key(osm::ENDPT_FWD)
+                    && !r.osm_tags.contains_key(osm::SYNTHETIC_LANES)
+                    && !r.osm_tags.keys().any(|k| k.starts_with("turn:lanes"))
+                    && r.children_forwards
+                        .iter()
+                        .filter(|(_, lt)| *lt != LaneType::Sidewalk && *lt != LaneType::Parking)
+                        .count()
+                        == 1
+                    && r.children_forwards[0].1 == LaneType::Driving
+                    && vec![TurnType::Left, TurnType::Straight, TurnType::Right]
+                        .into_iter()
+                        .all(|tt| types_from(&orig_map, r.children_forwards[0].0).contains(&tt))
+            })
+            .expect("No road can turn every way");
+        let orig_id = road.orig_id;
+
+        // Give it two lanes, with left on its own and through or right on the other.
+        let make_map = |turn_lanes: &str, timer: &mut Timer| -> Map {
+            let mut raw: RawMap = abstutil::read_binary(abstutil::path_raw_map("montlake"), timer);
+            raw.apply_all_fixes(timer);
+            let road = raw.roads.get_mut(&orig_id).unwrap();
+            road.osm_tags
+                .insert("lanes:forward".to_string(), "2".to_string());
+            road.osm_tags
+                .insert("turn:lanes:forward".to_string(), turn_lanes.to_string());
+            convert_osm::check_turn_lanes(0, road, timer);
+            let expect_kept = turn_lanes.split('|').count() == 2;
+            assert_eq!(
+                road.osm_tags.contains_key("turn:lanes:forward"),
+                expect_kept
+            );
+
+            let raw_path = "turn_lanes_tags.json";
+            abstutil::write_json(raw_path.to_string(), &raw);
+            let map = Map::new(raw_path.to_string(), false, timer);
+            std::fs::remove_file(raw_path).unwrap();
+            map
+        };
+        let driving_lanes = |map: &Map| -> Vec<LaneID> {
+            let r = map
+                .all_roads()
+                .iter()
+                .find(|r| r.orig_id == orig_id)
+                .unwrap();
+            r.children_forwards
+                .iter()
+                .filter(|(_, lt)| *lt == LaneType::Driving)
+                .map(|(l, _)| *l)
+                .collect()
+        };
+
+        let map = make_map("left|through;right", &mut timer);
+        let lanes = driving_lanes(&map);
+        assert_eq!(lanes.len(), 2);
+        let left = types_from(&map, lanes[0]);
+        assert!(left.contains(&TurnType::Left));
+        assert!(!left.contains(&TurnType::Straight));
+        assert!(!left.contains(&TurnType::Right));
+        let other = types_from(&map, lanes[1]);
+        assert!(!other.contains(&TurnType::Left));
+        assert!(other.contains(&TurnType::Straight));
+        assert!(other.contains(&TurnType::Right));
+
+        // Three lanes of tags for two lanes get dropped, and the usual guess takes over: left from
+        // the leftmost lane, right from the rightmost, and straight from both.
+        let map = make_map("left|through|right", &mut timer);
+        let lanes = driving_lanes(&map);
+        assert_eq!(lanes.len(), 2);
+        let left = types_from(&map, lanes[0]);
+        assert!(left.contains(&TurnType::Left));
+        assert!(left.contains(&TurnType::Straight));
+        assert!(!left.contains(&TurnType::Right));
+        let right = types_from(&map, lanes[1]);
+        assert!(!right.contains(&TurnType::Left));
+        assert!(right.contains(&TurnType::Straight));
+        assert!(right.contains(&TurnType::Right));
+    });
+
+    t.run_slow("export_geojson", |_| {
+        let map = Map::new(
+            abstutil::path_map("montlake"),
+            true,
+            &mut Timer::throwaway(),
+        );
+        let dir = "export_geojson_test";
+        map.export_geojson(dir);
+        let read = |layer: &str| -> Vec<serde_json::Value> {
+            let path = format!("{}/{}.geojson", dir, layer);
+            let json: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+            json["features"].as_array().unwrap().clone()
+        };
+
+        let lanes = read("lanes");
+        assert_eq!(lanes.len(), map.all_lanes().len());
+        assert_eq!(lanes[0]["geometry"]["type"], "LineString");
+
+        let roads = read("roads");
+        assert_eq!(roads.len(), map.all_roads().len());
+        for road in &roads {
+            let r = map.get_r(map_model::RoadID(
+                road["properties"]["id"].as_u64().unwrap() as usize,
+            ));
+            assert_eq!(
+                road["properties"]["speed_limit_mph"].as_f64().unwrap(),
+                r.get_speed_limit().to_miles_per_hour()
+            );
+        }
+
+        // Every turn has its own ID, and it says what the turn connects.
+        let turns = read("turns");
+        assert_eq!(turns.len(), map.all_turns().len());
+        let mut ids = BTreeSet::new();
+        for turn in &turns {
+            let props = &turn["properties"];
+            let id = props["id"].as_str().unwrap().to_string();
+            assert_eq!(
+                id,
+                format!(
+                    "{}_{}_{}",
+                    props["intersection"], props["src"], props["dst"]
+                )
+            );
+            assert!(ids.insert(id));
+        }
+
+        for (layer, count) in vec![
+            ("intersections", map.all_intersections().len()),
+            ("buildings", map.all_buildings().len()),
+            ("bus_stops", map.all_bus_stops().len()),
+            ("areas", map.all_areas().len()),
+        ] {
+            assert_eq!(read(layer).len(), count);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    });
+
+    t.run_fast("parse_turn_lanes", |_| {
+        let lanes = map_model::parse_turn_lanes("left|left;through|none|right").unwrap();
+        assert_eq!(lanes.len(), 4);
+        let left_only = lanes[0].as_ref().unwrap();
+        assert!(left_only.contains(&TurnType::Left));
+        assert!(!left_only.contains(&TurnType::Straight));
+        let shared = lanes[1].as_ref().unwrap();
+        assert!(shared.contains(&TurnType::Left) && shared.contains(&TurnType::Straight));
+        assert!(lanes[2].is_none());
+        assert!(map_model::parse_turn_lanes("left|hovercraft").is_none());
+    });
+
+    t.run_fast("parse_maxspeed", |_| {
+        let defaults = map_model::SpeedLimitDefaults::for_region("de").unwrap();
+        assert_eq!(
+            defaults.parse_maxspeed("50"),
+            Some(Speed::km_per_hour(50.0))
+        );
+        assert_eq!(
+            defaults.parse_maxspeed("30 mph"),
+            Some(Speed::miles_per_hour(30.0))
+        );
+        assert_eq!(
+            defaults.parse_maxspeed("30mph"),
+            Some(Speed::miles_per_hour(30.0))
+        );
+        assert_eq!(
+            defaults.parse_maxspeed("DE:urban"),
+            Some(Speed::km_per_hour(50.0))
+        );
+        assert_eq!(
+            defaults.parse_maxspeed("DE:zone30"),
+            Some(Speed::km_per_hour(30.0))
+        );
+        assert_eq!(
+            defaults.parse_maxspeed("signals;80"),
+            Some(Speed::km_per_hour(80.0))
+        );
+        assert!(defaults.parse_maxspeed("walk").is_some());
+        assert!(defaults.parse_maxspeed("none").is_some());
+        assert!(defaults.parse_maxspeed("signals").is_none());
+
+        let mut tags = BTreeMap::new();
+        tags.insert("highway".to_string(), "primary_link".to_string());
+        assert_eq!(defaults.get(&tags), Speed::km_per_hour(50.0));
+        tags.insert("maxspeed:forward".to_string(), "70".to_string());
+        tags.insert("maxspeed:backward".to_string(), "60".to_string());
+        assert_eq!(defaults.get(&tags), Speed::km_per_hour(60.0));
+        // Faster than the cap for maxspeed=none still counts
+        tags.insert("maxspeed:forward".to_string(), "150".to_string());
+        tags.insert("maxspeed:backward".to_string(), "140".to_string());
+        assert_eq!(defaults.get(&tags), Speed::km_per_hour(140.0));
+
+        // Raw maps from before there were defaults assume the US
+        let raw = map_model::raw::RawMap::blank("speed_limit_defaults".to_string());
+        let mut json = serde_json::to_value(&raw).unwrap();
+        json.as_object_mut().unwrap().remove("speed_limits");
+        let old: map_model::raw::RawMap = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            old.speed_limits,
+            map_model::SpeedLimitDefaults::for_region("us").unwrap()
+        );
+    });
 }
@@ -1,14 +1,62 @@
 use crate::runner::TestRunner;
-/*use abstutil::Timer;
-use geom::Duration;
-use sim::{DrivingGoal, Event, ParkingSpot, Scenario, SidewalkSpot, SimFlags, TripSpec};*/
+use abstutil::Timer;
+use geom::{Distance, Duration, Time};
+use map_model::{BuildingID, EditCmd, LaneID, LaneType, Map, ParkingPolicy, Position};
+use rand_xorshift::XorShiftRng;
+use sim::{
+    Activity, DrivingGoal, ParkingSpot, PersonSpec, Scenario, Sim, SimFlags, TripMode, TripSpec,
+};
+/*use sim::{Event, SidewalkSpot};*/
 
 // TODO park in a garage, then walk somewhere else
 // TODO park in a garage that's also the trip destination
 // TODO ped walks to a garage to start driving somewhere else
 // TODO two peds leave same bldg at around the same time, contend for owned cars
 
-pub fn run(_t: &mut TestRunner) {
+pub fn run(t: &mut TestRunner) {
+    t.run_slow("avoid_expensive_parking", |h| {
+        // Normally the driver parks right by the building.
+        let (map, mut sim, mut rng, goal, parking_lane) = setup("avoid_expensive_parking", None);
+        drive_to(&map, &mut sim, &mut rng, goal);
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::minutes(30)));
+        assert_eq!(parked_lane(&sim, goal), parking_lane);
+
+        // Priced out of that lane, they park somewhere else.
+        let (map, mut sim, mut rng, goal, parking_lane) = setup(
+            "avoid_expensive_parking",
+            Some(ParkingPolicy {
+                hourly_price_cents: 5000,
+                ..ParkingPolicy::default()
+            }),
+        );
+        drive_to(&map, &mut sim, &mut rng, goal);
+        sim.just_run_until_done(&map, Some(Duration::minutes(30)));
+        assert_ne!(parked_lane(&sim, goal), parking_lane);
+    });
+
+    t.run_slow("time_limited_parking_for_short_stays", |h| {
+        let limit = ParkingPolicy {
+            max_stay: Some(Duration::hours(1)),
+            ..ParkingPolicy::default()
+        };
+
+        // Somebody staying for 20 minutes can use the time-limited spots...
+        let (map, mut sim, mut rng, goal, parking_lane) =
+            setup("time_limited_parking_for_short_stays", Some(limit.clone()));
+        let home = visit(&map, &mut sim, &mut rng, goal, Duration::minutes(20));
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::hours(1)));
+        assert_eq!(parked_lane(&sim, home), parking_lane);
+
+        // ... but somebody staying for 3 hours can't.
+        let (map, mut sim, mut rng, goal, parking_lane) =
+            setup("time_limited_parking_for_short_stays", Some(limit));
+        let home = visit(&map, &mut sim, &mut rng, goal, Duration::hours(3));
+        sim.just_run_until_done(&map, Some(Duration::hours(1)));
+        assert_ne!(parked_lane(&sim, home), parking_lane);
+    });
+
     // TODO Lots of boilerplate between these two. Can we do better?
 
     /*t.run_slow("park_on_goal_st", |h| {
@@ -30,7 +78,7 @@ pub fn run(_t: &mut TestRunner) {
                 start: SidewalkSpot::building(south_bldg, &map),
                 spot,
                 goal: DrivingGoal::ParkNear(north_bldg),
-                ped_speed: Scenario::rand_ped_speed(&mut rng),
+                ped_speed: Scenario::rand_ped_speed(rng),
             },
             &map,
         );
@@ -68,7 +116,7 @@ pub fn run(_t: &mut TestRunner) {
                 start: SidewalkSpot::building(south_bldg, &map),
                 spot,
                 goal: DrivingGoal::ParkNear(north_bldg),
-                ped_speed: Scenario::rand_ped_speed(&mut rng),
+                ped_speed: Scenario::rand_ped_speed(rng),
             },
             &map,
         );
@@ -86,3 +134,130 @@ pub fn run(_t: &mut TestRunner) {
         sim.just_run_until_done(&map, Some(Duration::minutes(1)));
     });*/
 }
+
+// Finds a building with on-street parking along the lane drivers use to reach it, optionally
+// changing that parking lane's policy. Returns the building and the parking lane.
+fn setup(
+    run_name: &str,
+    policy: Option<ParkingPolicy>,
+) -> (Map, Sim, XorShiftRng, BuildingID, LaneID) {
+    let (mut map, sim, rng) = SimFlags::for_test(run_name).load(&mut Timer::throwaway());
+    let (goal, parking_lane) = map
+        .all_buildings()
+        .iter()
+        .find_map(|b| {
+            let driving_lane = map.find_driving_lane_near_building(b.id);
+            let parking_lane = map
+                .find_closest_lane(driving_lane, vec![LaneType::Parking])
+                .ok()?;
+            // Garages along the way might be closer.
+            let no_garages = map.all_buildings().iter().all(|other| match other.parking {
+                Some(ref p) => p.driving_pos.lane() != driving_lane,
+                None => true,
+            });
+            if map.get_l(parking_lane).number_parking_spots() >= 5
+                && no_garages
+                && approach(&map, driving_lane).is_some()
+            {
+                Some((b.id, parking_lane))
+            } else {
+                None
+            }
+        })
+        .expect("No building has parking along its driving lane");
+
+    if let Some(policy) = policy {
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeLaneParkingPolicy {
+            id: parking_lane,
+            policy,
+            orig_policy: map.get_l(parking_lane).parking_policy.clone(),
+        });
+        map.apply_edits(edits, &mut Timer::throwaway());
+    }
+    (map, sim, rng, goal, parking_lane)
+}
+
+// Some other driving lane leading to this one, long enough to start a car on
+fn approach(map: &Map, l: LaneID) -> Option<LaneID> {
+    map.get_turns_to_lane(l)
+        .into_iter()
+        .map(|t| t.id.src)
+        .find(|src| {
+            map.get_l(*src).is_driving()
+                && map.get_l(*src).parent != map.get_l(l).parent
+                && map.get_l(*src).length() > Distance::meters(20.0)
+        })
+}
+
+// A car appears just before the building's driving lane. It'll be owned by the goal.
+fn drive_to(map: &Map, sim: &mut Sim, rng: &mut XorShiftRng, goal: BuildingID) {
+    let start = approach(map, map.find_driving_lane_near_building(goal)).unwrap();
+    sim.schedule_trip(
+        Time::START_OF_DAY,
+        TripSpec::CarAppearing {
+            start_pos: Position::new(start, Distance::meters(15.0)),
+            goal: DrivingGoal::ParkNear(goal),
+            vehicle_spec: Scenario::rand_car(rng),
+            ped_speed: Scenario::rand_ped_speed(rng),
+        },
+        map,
+    );
+    sim.spawn_all_trips(map, &mut Timer::throwaway(), false);
+}
+
+// Somebody drives their own car from a nearby home to the goal and stays there for a while.
+// Returns the home, which owns the car.
+fn visit(
+    map: &Map,
+    sim: &mut Sim,
+    rng: &mut XorShiftRng,
+    goal: BuildingID,
+    stay: Duration,
+) -> BuildingID {
+    let goal_road = map.get_parent(map.find_driving_lane_near_building(goal)).id;
+    let (home, home_parking) = map
+        .all_buildings()
+        .iter()
+        .find_map(|b| {
+            let driving_lane = map.find_driving_lane_near_building(b.id);
+            if map.get_l(driving_lane).parent == goal_road || b.parking.is_some() {
+                return None;
+            }
+            let parking_lane = map
+                .find_closest_lane(driving_lane, vec![LaneType::Parking])
+                .ok()?;
+            if map.get_l(parking_lane).number_parking_spots() > 0 {
+                Some((b.id, parking_lane))
+            } else {
+                None
+            }
+        })
+        .unwrap();
+    sim.seed_parked_car(
+        Scenario::rand_car(rng),
+        ParkingSpot::Onstreet(home_parking, 0),
+        Some(home),
+    );
+    sim.schedule_person(
+        &PersonSpec {
+            home,
+            depart: Time::START_OF_DAY,
+            activities: vec![Activity {
+                bldg: goal,
+                mode: TripMode::Drive,
+                duration: stay,
+            }],
+        },
+        Scenario::rand_ped_speed(rng),
+        None,
+    );
+    home
+}
+
+fn parked_lane(sim: &Sim, owner: BuildingID) -> LaneID {
+    match sim.get_parked_cars_by_owner(owner)[0].spot {
+        ParkingSpot::Onstreet(l, _) => l,
+        ParkingSpot::Offstreet(_, _) => panic!("{} parked offstreet", owner),
+    }
+}
@@ -1,7 +1,19 @@
 use crate::runner::TestRunner;
 use abstutil::Timer;
-use geom::Duration;
-use sim::{Scenario, SimFlags};
+use geom::{Distance, Duration, Speed, Time};
+use map_model::raw::{RawMap, RestrictionType};
+use map_model::{
+    Building, EditCmd, IntersectionID, LaneID, LaneType, Map, Path, PathConstraints, PathRequest,
+    PathStep, Position, TimeWindows, TravelTimes, Traversable, TurnID, TurnPriority, TurnType,
+};
+use rand::Rng;
+use sim::{
+    Admission, AgentID, DrivingGoal, Event, EventLog, FreeformPolicy, IntersectionController,
+    IntersectionState, Request, Scenario, SidewalkSpot, Sim, SimFlags, TrajectoryRecorder, TripID,
+    TripMode, TripSpec,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
 
 pub fn run(t: &mut TestRunner) {
     t.run_slow("small_spawn_completes", |h| {
@@ -12,4 +24,824 @@ pub fn run(t: &mut TestRunner) {
         h.setup_done(&mut sim);
         sim.just_run_until_done(&map, Some(Duration::minutes(70)));
     });
+
+    t.run_slow("small_spawn_completes_with_rerouting", |h| {
+        let mut flags = SimFlags::for_test("small_spawn_completes_with_rerouting");
+        flags.opts.reroute_blocked_after = Some(Duration::seconds(30.0));
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+        Scenario::small_run(&map).instantiate(&mut sim, &map, &mut rng, &mut Timer::throwaway());
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::minutes(70)));
+    });
+
+    t.run_slow("reroute_around_live_closure", |h| {
+        let mut flags = SimFlags::for_test("reroute_around_live_closure");
+        flags.opts.reroute_around_closures = true;
+        let (mut map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+        // Everybody, including pedestrians and buses, which can't reroute
+        Scenario::small_run(&map).instantiate(&mut sim, &map, &mut rng, &mut Timer::throwaway());
+        h.setup_done(&mut sim);
+        sim.timed_step(&map, Duration::minutes(5), &mut Timer::throwaway());
+
+        // Closures that would strand somebody are refused.
+        let agents = sim.active_agents();
+        let ped_crossing = agents
+            .iter()
+            .filter_map(|a| match a {
+                AgentID::Pedestrian(_) => sim.get_path(*a),
+                AgentID::Car(_) => None,
+            })
+            .flat_map(|path| path.get_steps().iter().skip(2))
+            .find_map(|step| match step {
+                PathStep::Turn(t) if !map.get_i(t.parent).is_border() => Some(t.parent),
+                _ => None,
+            })
+            .expect("Nobody is walking through an intersection");
+        assert!(sim.can_close_intersection_live(ped_crossing, &map).is_err());
+
+        // Close a traffic signal that some car is headed through and that nobody else depends on.
+        let (car, i) = agents
+            .iter()
+            .filter(|a| match a {
+                AgentID::Car(_) => true,
+                AgentID::Pedestrian(_) => false,
+            })
+            .flat_map(|a| {
+                sim.get_path(*a)
+                    .map(|path| {
+                        path.get_steps()
+                            .iter()
+                            .skip(2)
+                            .filter_map(|step| match step {
+                                PathStep::Turn(t) => Some((*a, t.parent)),
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_else(Vec::new)
+            })
+            .find(|(_, i)| {
+                map.get_i(*i).is_traffic_signal()
+                    && sim.can_close_intersection_live(*i, &map).is_ok()
+            })
+            .expect("No car is headed through a traffic signal that can be closed");
+
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::CloseIntersection {
+            id: i,
+            orig_it: map.get_i(i).intersection_type,
+        });
+        map.apply_edits(edits, &mut Timer::throwaway());
+        map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+        sim.handle_live_edits(&map);
+
+        assert!(!path_crosses(&sim, car, i));
+        // Trips that haven't started yet avoid it too, and nobody gets stuck there.
+        sim.just_run_until_done(&map, Some(Duration::minutes(70)));
+        assert!(sim.get_accepted_agents(i).is_empty());
+    });
+
+    t.run_slow("event_log_replays", |_| {
+        for path in vec!["event_log_replays.jsonl", "event_log_replays.bin"] {
+            let mut flags = SimFlags::for_test("event_log_replays");
+            flags.opts.event_log = Some(path.to_string());
+            let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+            Scenario::small_run(&map).instantiate(
+                &mut sim,
+                &map,
+                &mut rng,
+                &mut Timer::throwaway(),
+            );
+            sim.just_run_until_done(&map, Some(Duration::minutes(70)));
+
+            let replayed = EventLog::replay(path, &map, &mut Timer::throwaway()).unwrap();
+            assert_eq!(
+                abstutil::to_json(&sim.get_analytics().finished_trips),
+                abstutil::to_json(&replayed.finished_trips)
+            );
+            assert_eq!(
+                abstutil::to_json(&sim.get_analytics().intersection_delays),
+                abstutil::to_json(&replayed.intersection_delays)
+            );
+            std::fs::remove_file(path).unwrap();
+        }
+    });
+
+    t.run_slow("trajectories_recorded", |_| {
+        let path = "trajectories_recorded.bin";
+        let mut flags = SimFlags::for_test("trajectories_recorded");
+        flags.opts.record_trajectories = Some((path.to_string(), Duration::seconds(10.0)));
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+        Scenario::small_run(&map).instantiate(&mut sim, &map, &mut rng, &mut Timer::throwaway());
+        sim.just_run_until_done(&map, Some(Duration::minutes(70)));
+
+        let frames = TrajectoryRecorder::read(path).unwrap();
+        assert!(frames.iter().any(|f| !f.agents.is_empty()));
+        for pair in frames.windows(2) {
+            assert_eq!(pair[1].time - pair[0].time, Duration::seconds(10.0));
+        }
+        for f in &frames {
+            assert_eq!(f.agents.len(), f.lon.len());
+            assert_eq!(f.agents.len(), f.speed.len());
+        }
+        std::fs::remove_file(path).unwrap();
+    });
+
+    t.run_slow("reversible_lanes_switch", |h| {
+        let log_path = "reversible_lanes_switch.jsonl";
+        let mut flags = SimFlags::for_test("reversible_lanes_switch");
+        flags.opts.event_log = Some(log_path.to_string());
+        let orig_map = Map::new(flags.load.clone(), true, &mut Timer::throwaway());
+
+        // Find a plain two-way road that the bus takes between its first two stops.
+        let route = orig_map.get_bus_route("49").unwrap();
+        let stop1 = orig_map.get_bs(route.stops[0]).driving_pos;
+        let stop2 = orig_map.get_bs(route.stops[1]).driving_pos;
+        let bus_lane = orig_map
+            .pathfind(PathRequest {
+                start: stop1,
+                end: stop2,
+                constraints: PathConstraints::Bus,
+            })
+            .unwrap()
+            .get_steps()
+            .iter()
+            .filter_map(|step| match step {
+                PathStep::Lane(l) if *l != stop1.lane() && *l != stop2.lane() => Some(*l),
+                _ => None,
+            })
+            .find(|l| {
+                let r = orig_map.get_parent(*l);
+                let driving = |lanes: &Vec<(LaneID, LaneType)>| {
+                    lanes.iter().any(|(_, lt)| *lt == LaneType::Driving)
+                };
+                driving(&r.children_forwards)
+                    && driving(&r.children_backwards)
+                    && r.all_lanes().into_iter().all(|l| {
+                        let lane = orig_map.get_l(l);
+                        lane.is_driving() || lane.is_sidewalk()
+                    })
+                    && !orig_map.get_i(r.src_i).is_border()
+                    && !orig_map.get_i(r.dst_i).is_border()
+            })
+            .expect("Bus route 49 doesn't use a plain two-way road");
+        let orig_id = orig_map.get_parent(bus_lane).orig_id;
+        let bus_forwards = orig_map.get_parent(bus_lane).is_forwards(bus_lane);
+
+        // Make it reversible. The other direction is open first, and the bus's direction opens 10
+        // minutes in, with no gap in between.
+        let mut raw: RawMap =
+            abstutil::read_binary(abstutil::path_raw_map("montlake"), &mut Timer::throwaway());
+        raw.apply_all_fixes(&mut Timer::throwaway());
+        let tags = &mut raw.roads.get_mut(&orig_id).unwrap().osm_tags;
+        tags.insert("oneway".to_string(), "reversible".to_string());
+        let (bus_side, other_side) = if bus_forwards {
+            ("yes", "-1")
+        } else {
+            ("-1", "yes")
+        };
+        tags.insert(
+            "oneway:conditional".to_string(),
+            format!(
+                "{} @ (00:00-00:10); {} @ (00:10-23:00)",
+                other_side, bus_side
+            ),
+        );
+        let raw_path = "reversible_lanes_switch.json";
+        abstutil::write_json(raw_path.to_string(), &raw);
+        let map = Map::new(raw_path.to_string(), false, &mut Timer::throwaway());
+        std::fs::remove_file(raw_path).unwrap();
+        let road = map
+            .all_roads()
+            .iter()
+            .find(|r| r.orig_id == orig_id)
+            .unwrap();
+        assert!(road.reversible.is_some());
+        // Both sides are the same pavement, so the road isn't any wider than one side.
+        let driving = |lanes: &Vec<(LaneID, LaneType)>| -> Vec<LaneID> {
+            lanes
+                .iter()
+                .filter(|(_, lt)| *lt == LaneType::Driving)
+                .map(|(l, _)| *l)
+                .collect()
+        };
+        let (fwd, back) = (
+            driving(&road.children_forwards),
+            driving(&road.children_backwards),
+        );
+        assert_eq!(fwd.len(), back.len());
+        assert!(map.get_l(fwd[0]).lane_center_pts.first_pt().approx_eq(
+            map.get_l(*back.last().unwrap()).lane_center_pts.last_pt(),
+            Distance::meters(0.1)
+        ));
+
+        let mut sim = Sim::new(&map, flags.opts.clone(), &mut Timer::throwaway());
+        let mut rng = flags.make_rng();
+        let route = map.get_bus_route("49").unwrap();
+        let bus = sim.seed_bus_route(route, &map, &mut Timer::throwaway())[0];
+
+        // Send cars through both ways, before and after the switch.
+        for children in vec![&road.children_forwards, &road.children_backwards] {
+            let l = children
+                .iter()
+                .find(|(_, lt)| *lt == LaneType::Driving)
+                .map(|(l, _)| l)
+                .unwrap();
+            let start = map
+                .get_turns_to_lane(*l)
+                .into_iter()
+                .map(|t| t.id.src)
+                .find(|src| {
+                    map.get_l(*src).is_driving()
+                        && map.get_l(*src).parent != road.id
+                        && map.get_l(*src).length() > Distance::meters(20.0)
+                })
+                .unwrap();
+            let goal = map
+                .get_turns_from_lane(*l)
+                .into_iter()
+                .map(|t| map.get_parent(t.id.dst))
+                .filter(|r| r.id != road.id)
+                .flat_map(|r| r.all_lanes())
+                .find_map(|l| map.get_l(l).building_paths.first().cloned())
+                .unwrap();
+            for i in 0..20 {
+                sim.schedule_trip(
+                    Time::START_OF_DAY
+                        + Duration::minutes(5)
+                        + Duration::seconds(30.0) * (i as f64),
+                    TripSpec::CarAppearing {
+                        start_pos: Position::new(start, Distance::meters(15.0)),
+                        goal: DrivingGoal::ParkNear(goal),
+                        vehicle_spec: Scenario::rand_car(&mut rng),
+                        ped_speed: Scenario::rand_ped_speed(&mut rng),
+                    },
+                    &map,
+                );
+            }
+        }
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::minutes(60)));
+
+        // Nobody enters the road while somebody is still going the other way on it.
+        let mut inside: BTreeMap<AgentID, bool> = BTreeMap::new();
+        let mut directions_used = BTreeSet::new();
+        let mut bus_arrived = false;
+        for (time, ev) in EventLog::read(log_path).unwrap() {
+            match ev {
+                Event::AgentEntersTraversable(a, on) => {
+                    inside.remove(&a);
+                    if let Traversable::Lane(l) = on {
+                        if map.get_l(l).parent == road.id {
+                            let forwards = road.is_forwards(l);
+                            assert!(
+                                inside.values().all(|x| *x == forwards),
+                                "{:?} entered {} head-on at {}",
+                                a,
+                                l,
+                                time
+                            );
+                            inside.insert(a, forwards);
+                            directions_used.insert(forwards);
+                        }
+                    }
+                }
+                Event::BusArrivedAtStop(b, _, stop) if b == bus && stop == route.stops[1] => {
+                    bus_arrived = true;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(directions_used.len(), 2);
+        assert!(bus_arrived);
+        std::fs::remove_file(log_path).unwrap();
+    });
+    t.run_slow("contraflow_lane", |_| {
+        let flags = SimFlags::for_test("contraflow_lane");
+        let orig_map = Map::new(flags.load.clone(), true, &mut Timer::throwaway());
+        let orig_road = orig_map
+            .all_roads()
+            .iter()
+            .find(|r| {
+                let driving = |lanes: &Vec<(LaneID, LaneType)>| {
+                    lanes.iter().any(|(_, lt)| *lt == LaneType::Driving)
+                };
+                driving(&r.children_forwards)
+                    && driving(&r.children_backwards)
+                    && r.all_lanes()
+                        .into_iter()
+                        .all(|l| orig_map.get_l(l).lane_type != LaneType::SharedLeftTurn)
+                    && !orig_map.get_i(r.src_i).is_border()
+                    && !orig_map.get_i(r.dst_i).is_border()
+            })
+            .expect("No two-way road");
+        let orig_id = orig_road.orig_id;
+        let orig_width = orig_road.get_thick_polyline(&orig_map).unwrap().1;
+
+        // The center lane goes forwards in the morning and backwards in the evening.
+        let mut raw: RawMap =
+            abstutil::read_binary(abstutil::path_raw_map("montlake"), &mut Timer::throwaway());
+        raw.apply_all_fixes(&mut Timer::throwaway());
+        let tags = &mut raw.roads.get_mut(&orig_id).unwrap().osm_tags;
+        tags.insert("lanes:both_ways".to_string(), "1".to_string());
+        tags.insert(
+            "lanes:both_ways:conditional".to_string(),
+            "yes @ (06:00-10:00); -1 @ (15:00-19:00)".to_string(),
+        );
+        let raw_path = "contraflow_lane.json";
+        abstutil::write_json(raw_path.to_string(), &raw);
+        let map = Map::new(raw_path.to_string(), false, &mut Timer::throwaway());
+        std::fs::remove_file(raw_path).unwrap();
+        let road = map
+            .all_roads()
+            .iter()
+            .find(|r| r.orig_id == orig_id)
+            .unwrap();
+
+        // Only the center lane on each side switches, and it's one lane wide.
+        let fwd = road.children_forwards[0].0;
+        let back = road.children_backwards[0].0;
+        for l in road.all_lanes() {
+            assert_eq!(road.is_reversible(l), l == fwd || l == back);
+        }
+        assert!(map.get_l(fwd).lane_center_pts.first_pt().approx_eq(
+            map.get_l(back).lane_center_pts.last_pt(),
+            Distance::meters(0.1)
+        ));
+        let width = road.get_thick_polyline(&map).unwrap().1;
+        assert!((width - orig_width - map.get_l(fwd).width).abs() < Distance::meters(0.01));
+
+        // The other lanes are always open.
+        let morning = Time::START_OF_DAY + Duration::hours(8);
+        let evening = Time::START_OF_DAY + Duration::hours(17);
+        let into = |l: LaneID| map.get_turns_to_lane(l)[0].id;
+        for (l, open) in vec![
+            (fwd, vec![true, false]),
+            (back, vec![false, true]),
+            (road.children_forwards[1].0, vec![true, true]),
+        ] {
+            assert_eq!(
+                vec![morning, evening]
+                    .into_iter()
+                    .map(|time| map.is_turn_allowed_at(into(l), PathConstraints::Car, time))
+                    .collect::<Vec<_>>(),
+                open
+            );
+        }
+        assert_eq!(
+            map.next_turn_change(into(fwd), PathConstraints::Car, morning),
+            Some(Time::START_OF_DAY + Duration::hours(10))
+        );
+    });
+    t.run_slow("conditional_turn_ban", |h| {
+        let log_path = "conditional_turn_ban.jsonl";
+        let mut flags = SimFlags::for_test("conditional_turn_ban");
+        flags.opts.event_log = Some(log_path.to_string());
+        let orig_map = Map::new(flags.load.clone(), true, &mut Timer::throwaway());
+
+        let (start_pos, goal, req) = cross_map_trip(&orig_map);
+
+        // Ban one of its turns at a big intersection during the morning peak.
+        let turn = orig_map
+            .pathfind(req.clone())
+            .unwrap()
+            .get_steps()
+            .iter()
+            .find_map(|step| match step {
+                PathStep::Turn(t)
+                    if orig_map.get_i(t.parent).roads.len() >= 4
+                        && orig_map.get_l(t.src).parent != orig_map.get_l(t.dst).parent =>
+                {
+                    Some(*t)
+                }
+                _ => None,
+            })
+            .expect("The trip doesn't turn at any big intersection");
+        let from = orig_map.get_parent(turn.src).orig_id;
+        let to = orig_map.get_parent(turn.dst).orig_id;
+        let mut raw: RawMap =
+            abstutil::read_binary(abstutil::path_raw_map("montlake"), &mut Timer::throwaway());
+        raw.apply_all_fixes(&mut Timer::throwaway());
+        raw.roads
+            .get_mut(&from)
+            .unwrap()
+            .conditional_turn_restrictions
+            .push((
+                RestrictionType::BanTurns,
+                to,
+                TimeWindows::parse("07:00-09:00").unwrap(),
+            ));
+        let raw_path = "conditional_turn_ban.json";
+        abstutil::write_json(raw_path.to_string(), &raw);
+        let map = Map::new(raw_path.to_string(), false, &mut Timer::throwaway());
+        std::fs::remove_file(raw_path).unwrap();
+        let is_banned = |t: TurnID| {
+            map.get_parent(t.src).orig_id == from && map.get_parent(t.dst).orig_id == to
+        };
+        let uses_ban = |path: Path| {
+            path.get_steps().iter().any(|step| match step {
+                PathStep::Turn(t) => is_banned(*t),
+                _ => false,
+            })
+        };
+
+        let peak = Time::START_OF_DAY + Duration::hours(8);
+        let noon = Time::START_OF_DAY + Duration::hours(12);
+        assert!(!uses_ban(map.pathfind_at(req.clone(), peak).unwrap()));
+        assert!(uses_ban(map.pathfind_at(req.clone(), noon).unwrap()));
+
+        // Drivers in the simulation go around it too, but only during the peak.
+        let mut sim = Sim::new(&map, flags.opts.clone(), &mut Timer::throwaway());
+        let mut rng = flags.make_rng();
+        let mut cars = Vec::new();
+        for depart in vec![peak, noon] {
+            cars.push(
+                sim.schedule_trip(
+                    depart,
+                    TripSpec::CarAppearing {
+                        start_pos,
+                        goal: goal.clone(),
+                        vehicle_spec: Scenario::rand_car(&mut rng),
+                        ped_speed: Scenario::rand_ped_speed(&mut rng),
+                    },
+                    &map,
+                )
+                .1
+                .unwrap(),
+            );
+        }
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::hours(14)));
+
+        let mut took_ban = BTreeSet::new();
+        for (_, ev) in EventLog::read(log_path).unwrap() {
+            if let Event::AgentEntersTraversable(AgentID::Car(car), Traversable::Turn(t)) = ev {
+                if is_banned(t) {
+                    took_ban.insert(car);
+                }
+            }
+        }
+        assert!(!took_ban.contains(&cars[0]));
+        assert!(took_ban.contains(&cars[1]));
+        std::fs::remove_file(log_path).unwrap();
+    });
+
+    t.run_slow("time_dependent_car_route", |_| {
+        let flags = SimFlags::for_test("time_dependent_car_route");
+        let (mut map, _, _) = flags.load(&mut Timer::throwaway());
+        let (_, _, req) = cross_map_trip(&map);
+        let free_flow: Vec<PathStep> = map
+            .pathfind(req.clone())
+            .unwrap()
+            .get_steps()
+            .iter()
+            .cloned()
+            .collect();
+
+        // Jam the whole usual route during the morning peak, except where it starts and ends.
+        let peak = Time::START_OF_DAY + Duration::hours(8);
+        let night = Time::START_OF_DAY + Duration::hours(3);
+        let mut travel_times = TravelTimes::new(Duration::minutes(15));
+        for step in &free_flow {
+            if let PathStep::Lane(l) = step {
+                if *l != req.start.lane() && *l != req.end.lane() {
+                    travel_times.set(peak, *l, Duration::hours(1));
+                }
+            }
+        }
+        let cost = |steps: &Vec<PathStep>, departure: Time, map: &Map| {
+            travel_times.path_cost(steps, departure, map)
+        };
+        let route_at = |map: &Map, departure: Time| -> Vec<PathStep> {
+            map.pathfind_at(req.clone(), departure)
+                .unwrap()
+                .get_steps()
+                .iter()
+                .cloned()
+                .collect()
+        };
+
+        // Setting the same times twice shouldn't change anything.
+        for _ in 0..2 {
+            map.set_travel_times(Some(travel_times.clone()), &mut Timer::throwaway());
+            let at_peak = route_at(&map, peak);
+            assert_ne!(at_peak, free_flow);
+            assert!(cost(&at_peak, peak, &map) < cost(&free_flow, peak, &map));
+            // Buckets without observations use free-flow costs.
+            assert_eq!(
+                cost(&route_at(&map, night), night, &map),
+                cost(&free_flow, night, &map)
+            );
+        }
+
+        // Back to free-flow costs, which the night bucket measures
+        map.set_travel_times(None, &mut Timer::throwaway());
+        assert_eq!(
+            cost(&route_at(&map, peak), night, &map),
+            cost(&free_flow, night, &map)
+        );
+    });
+
+    t.run_slow("traffic_assignment_converges", |_| {
+        let flags = SimFlags::for_test("traffic_assignment_converges");
+        let (mut map, _, _) = flags.load(&mut Timer::throwaway());
+        let scenario = Scenario::small_run(&map);
+
+        // Like headless --assignment: after each run, half of the car trips re-plan with the
+        // travel times just observed, and the rest keep their route.
+        let mut replan_rng = flags.make_rng();
+        let mut fixed_routes: BTreeMap<TripID, Vec<PathStep>> = BTreeMap::new();
+        let mut prev_drive_time: Option<Duration> = None;
+        let mut converged = false;
+        for _ in 0..10 {
+            let mut sim = Sim::new(&map, flags.opts.clone(), &mut Timer::throwaway());
+            sim.set_fixed_car_routes(std::mem::replace(&mut fixed_routes, BTreeMap::new()));
+            scenario.instantiate(
+                &mut sim,
+                &map,
+                &mut flags.make_rng(),
+                &mut Timer::throwaway(),
+            );
+            sim.just_run_until_done(&map, Some(Duration::minutes(70)));
+
+            let analytics = sim.get_analytics();
+            let mut drive_time = Duration::ZERO;
+            for (_, _, mode, dt) in &analytics.finished_trips {
+                if *mode == Some(TripMode::Drive) {
+                    drive_time += *dt;
+                }
+            }
+            assert!(drive_time > Duration::ZERO);
+            if let Some(prev) = prev_drive_time {
+                if ((drive_time - prev) / prev).abs() < 0.05 {
+                    converged = true;
+                    break;
+                }
+            }
+            prev_drive_time = Some(drive_time);
+
+            map.set_travel_times(
+                Some(analytics.travel_times(Duration::minutes(15))),
+                &mut Timer::throwaway(),
+            );
+            for (trip, (_, _, steps)) in &analytics.car_routes {
+                if !replan_rng.gen_bool(0.5) {
+                    fixed_routes.insert(*trip, steps.clone());
+                }
+            }
+        }
+        assert!(converged, "Total driving time never settled within 5%");
+    });
+
+    t.run_slow("part_time_bus_lane", |_| {
+        let orig_map = Map::new(
+            abstutil::path_raw_map("montlake"),
+            true,
+            &mut Timer::throwaway(),
+        );
+        let (_, _, req) = cross_map_trip(&orig_map);
+
+        // Find a plain road with one lane each way along the trip.
+        let orig_id = orig_map
+            .pathfind(req.clone())
+            .unwrap()
+            .get_steps()
+            .iter()
+            .filter_map(|step| match step {
+                PathStep::Lane(l) if *l != req.start.lane() && *l != req.end.lane() => {
+                    Some(orig_map.get_parent(*l))
+                }
+                _ => None,
+            })
+            .find(|r| {
+                let (fwd, back) = r.get_lane_types();
+                fwd == vec![LaneType::Driving, LaneType::Sidewalk]
+                    && back == vec![LaneType::Driving, LaneType::Sidewalk]
+                    && !orig_map.get_i(r.src_i).is_border()
+                    && !orig_map.get_i(r.dst_i).is_border()
+            })
+            .expect("The trip doesn't use a plain two-way road")
+            .orig_id;
+
+        // Only buses can use it during the morning peak.
+        let mut raw: RawMap =
+            abstutil::read_binary(abstutil::path_raw_map("montlake"), &mut Timer::throwaway());
+        raw.apply_all_fixes(&mut Timer::throwaway());
+        raw.roads.get_mut(&orig_id).unwrap().osm_tags.insert(
+            "bus:lanes:conditional".to_string(),
+            "designated @ (07:00-09:00)".to_string(),
+        );
+        let raw_path = "part_time_bus_lane.json";
+        abstutil::write_json(raw_path.to_string(), &raw);
+        let map = Map::new(raw_path.to_string(), false, &mut Timer::throwaway());
+        std::fs::remove_file(raw_path).unwrap();
+        let road = map
+            .all_roads()
+            .iter()
+            .find(|r| r.orig_id == orig_id)
+            .unwrap();
+        for l in road.all_lanes() {
+            let lane = map.get_l(l);
+            assert!(lane.is_sidewalk() || (lane.is_bus() && lane.bus_hours.is_some()));
+        }
+        let uses_road = |path: Path| {
+            path.get_steps().iter().any(|step| match step {
+                PathStep::Lane(l) => map.get_l(*l).parent == road.id,
+                _ => false,
+            })
+        };
+
+        let peak = Time::START_OF_DAY + Duration::hours(8);
+        let noon = Time::START_OF_DAY + Duration::hours(12);
+        assert!(!uses_road(map.pathfind_at(req.clone(), peak).unwrap()));
+        assert!(uses_road(map.pathfind_at(req.clone(), noon).unwrap()));
+    });
+    t.run_slow("pedestrian_crowding", |h| {
+        let log_path = "pedestrian_crowding.jsonl";
+        let mut flags = SimFlags::for_test("pedestrian_crowding");
+        flags.opts.event_log = Some(log_path.to_string());
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+
+        // Walk across the map, around at least one corner.
+        let start = map.all_buildings()[0].id;
+        let pt = map.get_b(start).label_center;
+        let goal = map
+            .all_buildings()
+            .iter()
+            .max_by_key(|b| b.label_center.dist_to(pt).inner_meters() as usize)
+            .unwrap()
+            .id;
+        let is_corner = |t: TurnID| map.get_t(t).turn_type == TurnType::SharedSidewalkCorner;
+        assert!(map
+            .pathfind(PathRequest {
+                start: SidewalkSpot::building(start, &map).sidewalk_pos,
+                end: SidewalkSpot::building(goal, &map).sidewalk_pos,
+                constraints: PathConstraints::Pedestrian,
+            })
+            .unwrap()
+            .get_steps()
+            .iter()
+            .any(|step| match step {
+                PathStep::Turn(t) => is_corner(*t),
+                _ => false,
+            }));
+
+        // Two friends walk together, then much later, a big crowd.
+        let crowd_leaves = Time::START_OF_DAY + Duration::hours(1);
+        for (depart, num_peds) in vec![(Time::START_OF_DAY, 2), (crowd_leaves, 100)] {
+            for _ in 0..num_peds {
+                sim.schedule_trip(
+                    depart,
+                    TripSpec::JustWalking {
+                        start: SidewalkSpot::building(start, &map),
+                        goal: SidewalkSpot::building(goal, &map),
+                        ped_speed: Scenario::rand_ped_speed(&mut rng),
+                    },
+                    &map,
+                );
+            }
+        }
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::hours(3)));
+
+        // Corners are never crowded, but the big crowd is on the sidewalks.
+        let mut crowded_sidewalk = false;
+        for (time, ev) in EventLog::read(log_path).unwrap() {
+            match ev {
+                Event::PedestrianCrowding(Traversable::Turn(t), _) => {
+                    assert!(!is_corner(t));
+                }
+                Event::PedestrianCrowding(Traversable::Lane(_), _) => {
+                    if time >= crowd_leaves {
+                        crowded_sidewalk = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        assert!(crowded_sidewalk);
+        std::fs::remove_file(log_path).unwrap();
+    });
+    t.run_slow("custom_intersection_controller", |h| {
+        let flags = SimFlags::for_test("custom_intersection_controller");
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+        let (start_pos, goal, _) = cross_map_trip(&map);
+        let car = sim
+            .schedule_trip(
+                Time::START_OF_DAY,
+                TripSpec::CarAppearing {
+                    start_pos,
+                    goal,
+                    vehicle_spec: Scenario::rand_car(&mut rng),
+                    ped_speed: Scenario::rand_ped_speed(&mut rng),
+                },
+                &map,
+            )
+            .1
+            .unwrap();
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+        // The car has to get through the end of its first lane.
+        let i = map.get_l(start_pos.lane()).dst_i;
+        sim.set_intersection_controller(i, Arc::new(RedLight), &map);
+        h.setup_done(&mut sim);
+
+        sim.step(&map, Duration::minutes(10));
+        assert!(path_crosses(&sim, AgentID::Car(car), i));
+
+        // The controller survives a savestate, as long as the caller can rebuild it.
+        let ss = sim.save();
+        assert!(Sim::load_savestate(ss.clone(), &map, &mut Timer::throwaway()).is_err());
+        let mut loaded = Sim::load_savestate_with_controllers(
+            ss.clone(),
+            &map,
+            &|name| {
+                if name == "red light" {
+                    Some(Arc::new(RedLight))
+                } else {
+                    None
+                }
+            },
+            &mut Timer::throwaway(),
+        )
+        .unwrap();
+        std::fs::remove_file(ss).unwrap();
+        loaded.step(&map, Duration::minutes(10));
+        assert!(path_crosses(&loaded, AgentID::Car(car), i));
+
+        // Swapping the controller lets the waiting car go.
+        sim.set_intersection_controller(i, Arc::new(FreeformPolicy), &map);
+        sim.just_run_until_done(&map, Some(Duration::hours(1)));
+        assert!(!path_crosses(&sim, AgentID::Car(car), i));
+    });
+}
+
+// Nobody ever gets through
+struct RedLight;
+
+impl IntersectionController for RedLight {
+    fn name(&self) -> String {
+        "red light".to_string()
+    }
+
+    fn maybe_start_turn(
+        &self,
+        _: &IntersectionState,
+        _: &Request,
+        _: Speed,
+        _: Time,
+        _: &Map,
+    ) -> Admission {
+        Admission::Deny
+    }
+
+    fn wakeup_priority(
+        &self,
+        _: &IntersectionState,
+        _: &Request,
+        _: Time,
+        _: &Map,
+    ) -> TurnPriority {
+        TurnPriority::Banned
+    }
+}
+
+// Drive across the map, from a border to the farthest building
+fn cross_map_trip(map: &Map) -> (Position, DrivingGoal, PathRequest) {
+    let start = map
+        .all_incoming_borders()
+        .into_iter()
+        .flat_map(|i| i.outgoing_lanes.clone())
+        .find(|l| map.get_l(*l).is_driving() && map.get_l(*l).length() > Distance::meters(20.0))
+        .unwrap();
+    let start_pos = Position::new(start, Distance::meters(15.0));
+    let goal = DrivingGoal::ParkNear(
+        map.all_buildings()
+            .iter()
+            .max_by(|b1, b2| {
+                let dist = |b: &Building| b.label_center.dist_to(start_pos.pt(map));
+                dist(b1).partial_cmp(&dist(b2)).unwrap()
+            })
+            .unwrap()
+            .id,
+    );
+    let req = PathRequest {
+        start: start_pos,
+        end: goal.goal_pos(PathConstraints::Car, map),
+        constraints: PathConstraints::Car,
+    };
+    (start_pos, goal, req)
+}
+
+fn path_crosses(sim: &Sim, agent: AgentID, i: IntersectionID) -> bool {
+    sim.get_path(agent)
+        .map(|path| {
+            path.get_steps().iter().any(|step| match step {
+                PathStep::Turn(t) => t.parent == i,
+                _ => false,
+            })
+        })
+        .unwrap_or(false)
 }
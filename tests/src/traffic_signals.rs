@@ -0,0 +1,178 @@
+use crate::runner::TestRunner;
+use abstutil::Timer;
+use geom::{Distance, Duration, Time};
+use map_model::{
+    ControlTrafficSignal, EditCmd, IntersectionID, Map, PathConstraints, PathRequest, PathStep,
+    PhaseType, Position, TurnGroupID, TurnID, TurnPriority,
+};
+use sim::{DrivingGoal, Scenario, Sim, SimFlags, TripSpec};
+
+const MIN_GREEN: Duration = Duration::const_seconds(5.0);
+const MAX_GREEN: Duration = Duration::const_seconds(30.0);
+const EXTENSION: Duration = Duration::const_seconds(2.0);
+const OTHER_PHASES: Duration = Duration::const_seconds(10.0);
+
+pub fn run(t: &mut TestRunner) {
+    t.run_slow("actuated_phase_gaps_out", |h| {
+        // Nobody's around, so the actuated phase only lasts min_green.
+        let (map, mut sim, i, phase) = setup("actuated_phase_gaps_out", false, false);
+        h.setup_done(&mut sim);
+        let runs = phase_runs(&map, &mut sim, i, Duration::minutes(2));
+        let durations = durations_of(&runs, phase);
+        assert!(!durations.is_empty());
+        // Sampling every second might catch the change a bit late.
+        for d in durations {
+            assert!(d >= MIN_GREEN && d <= MIN_GREEN + Duration::seconds(1.0));
+        }
+    });
+
+    t.run_slow("actuated_phase_extends_to_max_green", |h| {
+        // A steady stream of cars keeps the phase going, but never past max_green.
+        let (map, mut sim, i, phase) = setup("actuated_phase_extends_to_max_green", false, true);
+        h.setup_done(&mut sim);
+        // Let the queue build up first.
+        sim.step(&map, Duration::minutes(1));
+        let runs = phase_runs(&map, &mut sim, i, Duration::minutes(3));
+        let durations = durations_of(&runs, phase);
+        assert!(!durations.is_empty());
+        for d in durations {
+            assert!(d > MIN_GREEN + EXTENSION && d <= MAX_GREEN + Duration::seconds(1.0));
+        }
+    });
+
+    t.run_slow("actuated_phases_skipped_without_demand", |h| {
+        // Every phase is actuated, and the cars only want the one phase. The others get skipped.
+        let (map, mut sim, i, phase) = setup("actuated_phases_skipped_without_demand", true, true);
+        h.setup_done(&mut sim);
+        sim.step(&map, Duration::minutes(1));
+        let runs = phase_runs(&map, &mut sim, i, Duration::minutes(2));
+        assert!(runs.iter().all(|(idx, _)| *idx == phase));
+    });
+}
+
+// Finds a traffic signal with a vehicle movement that only one phase allows, makes that phase
+// actuated, and maybe sends a stream of cars through the movement. If all_actuated, the other
+// phases are actuated too; otherwise they're fixed. Returns the signal and the phase.
+fn setup(
+    run_name: &str,
+    all_actuated: bool,
+    with_traffic: bool,
+) -> (Map, Sim, IntersectionID, usize) {
+    let flags = SimFlags::for_test(run_name);
+    let (mut map, _, mut rng) = flags.load(&mut Timer::throwaway());
+    let (i, phase, turn, goal) = map
+        .all_intersections()
+        .iter()
+        .filter(|i| i.is_traffic_signal())
+        .find_map(|i| {
+            let signal = map.get_traffic_signal(i.id);
+            signal.turn_groups.values().find_map(|g| {
+                let phase = only_phase(signal, g.id)?;
+                let turn = *g.members.iter().find(|t| {
+                    let src = map.get_l(t.src);
+                    src.is_driving() && src.length() > Distance::meters(30.0)
+                })?;
+                let goal = goal_through(&map, turn)?;
+                Some((i.id, phase, turn, goal))
+            })
+        })
+        .expect("No traffic signal has a movement that only one phase allows");
+
+    let mut signal = map.get_traffic_signal(i).clone();
+    for (idx, p) in signal.phases.iter_mut().enumerate() {
+        p.phase_type = if idx == phase || all_actuated {
+            PhaseType::Actuated {
+                min_green: MIN_GREEN,
+                max_green: MAX_GREEN,
+                extension: EXTENSION,
+            }
+        } else {
+            PhaseType::Fixed(OTHER_PHASES)
+        };
+    }
+    let mut edits = map.get_edits().clone();
+    edits.commands.push(EditCmd::ChangeTrafficSignal(signal));
+    map.apply_edits(edits, &mut Timer::throwaway());
+    map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+
+    let mut sim = Sim::new(&map, flags.opts.clone(), &mut Timer::throwaway());
+    if with_traffic {
+        for idx in 0..120 {
+            sim.schedule_trip(
+                Time::START_OF_DAY + Duration::seconds(2.0) * (idx as f64),
+                TripSpec::CarAppearing {
+                    start_pos: Position::new(turn.src, Distance::meters(15.0)),
+                    goal: goal.clone(),
+                    vehicle_spec: Scenario::rand_car(&mut rng),
+                    ped_speed: Scenario::rand_ped_speed(&mut rng),
+                },
+                &map,
+            );
+        }
+    }
+    sim.spawn_all_trips(&map, &mut Timer::throwaway(), true);
+    (map, sim, i, phase)
+}
+
+// The only phase that doesn't ban this group
+fn only_phase(signal: &ControlTrafficSignal, g: TurnGroupID) -> Option<usize> {
+    if g.crosswalk.is_some() {
+        return None;
+    }
+    let allowed: Vec<usize> = signal
+        .phases
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.get_priority_of_group(g) != TurnPriority::Banned)
+        .map(|(idx, _)| idx)
+        .collect();
+    if allowed.len() == 1 && signal.phases.len() > 1 {
+        Some(allowed[0])
+    } else {
+        None
+    }
+}
+
+// Somewhere to drive to that goes through the turn
+fn goal_through(map: &Map, turn: TurnID) -> Option<DrivingGoal> {
+    let road = map.get_parent(turn.dst);
+    road.all_lanes()
+        .into_iter()
+        .flat_map(|l| map.get_l(l).building_paths.clone())
+        .map(DrivingGoal::ParkNear)
+        .find(|goal| {
+            map.pathfind(PathRequest {
+                start: Position::new(turn.src, Distance::meters(15.0)),
+                end: goal.goal_pos(PathConstraints::Car, map),
+                constraints: PathConstraints::Car,
+            })
+            .map(|path| path.get_steps().contains(&PathStep::Turn(turn)))
+            .unwrap_or(false)
+        })
+}
+
+// Steps through the simulation a second at a time, returning each run of the same phase and when
+// it started.
+fn phase_runs(map: &Map, sim: &mut Sim, i: IntersectionID, dt: Duration) -> Vec<(usize, Time)> {
+    let end = sim.time() + dt;
+    let mut runs: Vec<(usize, Time)> = Vec::new();
+    while sim.time() < end {
+        let (idx, _) = sim.current_signal_phase(i, map);
+        if runs.last().map(|(last, _)| *last != idx).unwrap_or(true) {
+            runs.push((idx, sim.time()));
+        }
+        sim.step(map, Duration::seconds(1.0));
+    }
+    runs
+}
+
+// How long each complete run of the phase lasted. The first and last runs might've been cut off.
+fn durations_of(runs: &Vec<(usize, Time)>, phase: usize) -> Vec<Duration> {
+    let mut durations = Vec::new();
+    for idx in 1..runs.len().saturating_sub(1) {
+        if runs[idx].0 == phase {
+            durations.push(runs[idx + 1].1 - runs[idx].1);
+        }
+    }
+    durations
+}
@@ -1,9 +1,290 @@
 use crate::runner::TestRunner;
 use abstutil::Timer;
-use geom::{Duration, Time};
-use sim::{Event, Scenario, SidewalkSpot, SimFlags, TripSpec};
+use geom::{Distance, Duration, LonLat, Time};
+use map_model::raw::{OriginalRoad, RawMap};
+use map_model::{osm, BusRoute, LaneType, Map, PathConstraints, PathRequest, Position, Road};
+use sim::{
+    BusTimetable, CarID, Event, EventLog, PedestrianID, Scenario, SidewalkSpot, SimFlags, TripSpec,
+};
+use std::collections::{BTreeMap, BTreeSet};
 
 pub fn run(t: &mut TestRunner) {
+    t.run_fast("rail_route_types", |_| {
+        let route = |route_type: &str| gtfs::Route {
+            name: "1".to_string(),
+            route_type: route_type.to_string(),
+            patterns: Vec::new(),
+            calendars: BTreeMap::new(),
+        };
+        for rail in vec!["LightRail", "Subway", "Rail"] {
+            assert!(route(rail).is_rail());
+        }
+        for not_rail in vec!["Bus", "Ferry"] {
+            assert!(!route(not_rail).is_rail());
+        }
+    });
+
+    t.run_fast("gtfs_import", |_| {
+        let dir = "gtfs_import";
+        write_gtfs_fixture(dir);
+        let routes = gtfs::load(dir);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(routes.len(), 2);
+        let streetcar = routes.iter().find(|r| r.name == "S").unwrap();
+        assert!(streetcar.is_rail());
+        // Only the services its trips use
+        assert_eq!(
+            streetcar.calendars.keys().cloned().collect::<Vec<_>>(),
+            vec!["WK".to_string()]
+        );
+
+        let route = routes.iter().find(|r| r.name == "49").unwrap();
+        assert!(!route.is_rail());
+        // The pattern with the most trips in each direction comes first, forwards first.
+        let stop_ids = |idx: usize| -> Vec<&str> {
+            route.patterns[idx]
+                .stops
+                .iter()
+                .map(|s| s.id.as_str())
+                .collect()
+        };
+        assert_eq!(route.patterns.len(), 3);
+        assert!(route.patterns[0].forwards);
+        assert_eq!(stop_ids(0), vec!["S1", "S2", "S3"]);
+        assert_eq!(route.patterns[0].trips.len(), 3);
+        assert!(route.patterns[1].forwards);
+        assert_eq!(stop_ids(1), vec!["S1", "S2"]);
+        assert!(!route.patterns[2].forwards);
+        assert_eq!(stop_ids(2), vec!["S3", "S2", "S1"]);
+
+        // Stop times keep arrival and departure, and can go past midnight.
+        let main = &route.patterns[0];
+        let trip = |id: &str| main.trips.iter().find(|t| t.id == id).unwrap();
+        assert_eq!(
+            trip("T1").stop_times[1].arrival,
+            Duration::hours(8) + Duration::minutes(5)
+        );
+        assert_eq!(
+            trip("T1").stop_times[1].departure,
+            Duration::hours(8) + Duration::minutes(6)
+        );
+        assert_eq!(
+            trip("T2").stop_times[2].arrival,
+            Duration::hours(24) + Duration::minutes(10)
+        );
+
+        // Shapes are put in order.
+        assert_eq!(
+            main.shape.clone().unwrap(),
+            main.stops.iter().map(|s| s.pos).collect::<Vec<LonLat>>()
+        );
+
+        // Tuesday is 1
+        assert!(route.runs_on(trip("T1"), 1));
+        assert!(!route.runs_on(trip("T3"), 1));
+        assert!(route.runs_on(trip("T3"), 5));
+    });
+
+    t.run_slow("clip_gtfs_routes", |_| {
+        let mut raw: RawMap =
+            abstutil::read_binary(abstutil::path_raw_map("montlake"), &mut Timer::throwaway());
+        let center = raw.boundary_polygon.center();
+        let inside1 = center.to_gps(&raw.gps_bounds).unwrap();
+        let inside2 = center.offset(10.0, 10.0).to_gps(&raw.gps_bounds).unwrap();
+        let outside = LonLat::new(0.0, 0.0);
+        raw.bus_routes = vec![
+            fake_route("partly inside", vec![inside1, outside, inside2]),
+            fake_route("outside", vec![outside, outside]),
+            fake_route("one stop inside", vec![inside1, outside]),
+        ];
+        convert_osm::clip_bus_routes(&mut raw, &mut Timer::throwaway());
+
+        // Only the stops inside are left, with the stop times and shape points to match.
+        assert_eq!(raw.bus_routes.len(), 1);
+        let pattern = &raw.bus_routes[0].patterns[0];
+        assert_eq!(
+            pattern.stops.iter().map(|s| s.pos).collect::<Vec<LonLat>>(),
+            vec![inside1, inside2]
+        );
+        assert_eq!(
+            pattern.trips[0]
+                .stop_times
+                .iter()
+                .map(|st| st.arrival)
+                .collect::<Vec<Duration>>(),
+            vec![Duration::minutes(0), Duration::minutes(2)]
+        );
+        assert_eq!(pattern.shape.clone().unwrap(), vec![inside1, inside2]);
+    });
+
+    t.run_slow("gtfs_weekday_schedule", |_| {
+        // Replace the real schedule for one route with a few trips.
+        let mut raw: RawMap =
+            abstutil::read_binary(abstutil::path_raw_map("montlake"), &mut Timer::throwaway());
+        raw.apply_all_fixes(&mut Timer::throwaway());
+        let route = raw.bus_routes.iter_mut().find(|r| r.name == "49").unwrap();
+        let main_pattern = |forwards: bool| {
+            route
+                .patterns
+                .iter()
+                .filter(|p| p.forwards == forwards)
+                .max_by_key(|p| p.trips.len())
+                .unwrap()
+                .clone()
+        };
+        let mut fwd = main_pattern(true);
+        let mut back = main_pattern(false);
+        let num_stops = fwd.stops.len();
+        let times = |start: Duration, stops: Vec<usize>| -> Vec<gtfs::StopTime> {
+            stops
+                .into_iter()
+                .map(|idx| gtfs::StopTime {
+                    arrival: start + Duration::minutes(2 * idx),
+                    departure: start + Duration::minutes(2 * idx),
+                })
+                .collect()
+        };
+        let trip = |id: &str, service: &str, stop_times: Vec<gtfs::StopTime>| gtfs::Trip {
+            id: id.to_string(),
+            service_id: service.to_string(),
+            stop_times,
+        };
+        fwd.trips = vec![
+            trip(
+                "weekday",
+                "WK",
+                times(Duration::hours(8), (0..num_stops).collect()),
+            ),
+            trip(
+                "saturday",
+                "SA",
+                times(Duration::hours(10), (0..num_stops).collect()),
+            ),
+        ];
+        // Skips every stop in between
+        let mut express = fwd.clone();
+        express.stops = vec![fwd.stops[0].clone(), fwd.stops[num_stops - 1].clone()];
+        express.trips = vec![trip(
+            "express",
+            "WK",
+            times(Duration::hours(9), vec![0, num_stops - 1]),
+        )];
+        back.trips.clear();
+        route.patterns = vec![fwd, express, back];
+        let days = |weekday: bool| gtfs::Calendar {
+            days: [weekday, weekday, weekday, weekday, weekday, !weekday, false],
+            start_date: "2018-01-01".to_string(),
+            end_date: "2018-12-31".to_string(),
+        };
+        route.calendars = vec![
+            ("WK".to_string(), days(true)),
+            ("SA".to_string(), days(false)),
+        ]
+        .into_iter()
+        .collect();
+        let raw_path = "gtfs_weekday_schedule.json";
+        abstutil::write_json(raw_path.to_string(), &raw);
+        let map = Map::new(raw_path.to_string(), false, &mut Timer::throwaway());
+        std::fs::remove_file(raw_path).unwrap();
+
+        // The Saturday trip doesn't run. The express trip is interpolated along the stops it
+        // skips, so it's exactly an hour behind the weekday trip.
+        let schedule = map.get_bus_route("49").unwrap().schedule.clone().unwrap();
+        assert_eq!(schedule.trips.len(), 2);
+        let (weekday, express) = (&schedule.trips[0], &schedule.trips[1]);
+        assert!(weekday.iter().filter(|t| t.is_some()).count() >= 2);
+        for (t1, t2) in weekday.iter().zip(express.iter()) {
+            match (t1, t2) {
+                (Some(t1), Some(t2)) => {
+                    assert!((*t2 - *t1 - Duration::hours(1)).inner_seconds().abs() < 0.1)
+                }
+                (None, None) => {}
+                _ => panic!("The express trip doesn't cover the same stops"),
+            }
+        }
+    });
+
+    t.run_slow("light_rail_tracks", |_| {
+        // Turn two roads meeting somewhere into tram tracks.
+        let orig_map = Map::new(
+            abstutil::path_raw_map("montlake"),
+            true,
+            &mut Timer::throwaway(),
+        );
+        let tracks: Vec<OriginalRoad> = orig_map
+            .all_intersections()
+            .iter()
+            .find(|i| !i.is_border() && i.roads.len() >= 3)
+            .unwrap()
+            .roads
+            .iter()
+            .take(2)
+            .map(|r| orig_map.get_r(*r).orig_id)
+            .collect();
+        let mut raw: RawMap =
+            abstutil::read_binary(abstutil::path_raw_map("montlake"), &mut Timer::throwaway());
+        raw.apply_all_fixes(&mut Timer::throwaway());
+        for id in &tracks {
+            let tags = &mut raw.roads.get_mut(id).unwrap().osm_tags;
+            tags.remove(osm::HIGHWAY);
+            tags.remove("oneway");
+            tags.insert("railway".to_string(), "tram".to_string());
+        }
+        let raw_path = "light_rail_tracks.json";
+        abstutil::write_json(raw_path.to_string(), &raw);
+        let map = Map::new(raw_path.to_string(), false, &mut Timer::throwaway());
+        std::fs::remove_file(raw_path).unwrap();
+
+        // Only tracks, one each way
+        let roads: Vec<&Road> = tracks
+            .iter()
+            .map(|id| map.all_roads().iter().find(|r| r.orig_id == *id).unwrap())
+            .collect();
+        for r in &roads {
+            let (fwd, back) = r.get_lane_types();
+            assert_eq!(fwd, vec![LaneType::LightRail]);
+            assert_eq!(back, vec![LaneType::LightRail]);
+        }
+
+        // Trains can go from one track to the other, but nothing else can use them.
+        let (from, to) = roads[0]
+            .all_lanes()
+            .into_iter()
+            .find_map(|l1| {
+                roads[1]
+                    .all_lanes()
+                    .into_iter()
+                    .find(|l2| map.get_l(l1).dst_i == map.get_l(*l2).src_i)
+                    .map(|l2| (l1, l2))
+            })
+            .unwrap();
+        assert!(map
+            .pathfind(PathRequest {
+                start: Position::new(from, Distance::ZERO),
+                end: Position::new(to, map.get_l(to).length() / 2.0),
+                constraints: PathConstraints::Train,
+            })
+            .is_some());
+        for constraints in vec![
+            PathConstraints::Pedestrian,
+            PathConstraints::Car,
+            PathConstraints::Bike,
+            PathConstraints::Bus,
+        ] {
+            assert!(!constraints.can_use(map.get_l(from), &map));
+        }
+        // No turns between tracks and anything else
+        for l in &map.get_i(map.get_l(from).dst_i).incoming_lanes {
+            for turn in map.get_turns_from_lane(*l) {
+                assert_eq!(
+                    map.get_l(turn.id.src).is_light_rail(),
+                    map.get_l(turn.id.dst).is_light_rail()
+                );
+            }
+        }
+    });
+
     t.run_slow("bus_reaches_stops", |h| {
         let mut flags = SimFlags::for_test("bus_reaches_stops");
         flags.opts.savestate_every = Some(Duration::seconds(30.0));
@@ -79,4 +360,359 @@ pub fn run(t: &mut TestRunner) {
             Duration::minutes(9),
         );
     });
+    t.run_slow("full_bus_leaves_riders_behind", |h| {
+        let log_path = "full_bus_leaves_riders_behind.jsonl";
+        let mut flags = SimFlags::for_test("full_bus_leaves_riders_behind");
+        flags.opts.event_log = Some(log_path.to_string());
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+        // A tiny bus, so the third rider has to wait for the next one
+        let route = map.get_bus_route("49").unwrap();
+        let route = BusRoute {
+            id: route.id,
+            name: route.name.clone(),
+            stops: route.stops.clone(),
+            schedule: route.schedule.clone(),
+            route_type: route.route_type,
+            vehicle_length: route.vehicle_length,
+            capacity: 2,
+        };
+        let buses = sim
+            .dispatch_bus_route(
+                &route,
+                &BusTimetable {
+                    route_name: route.name.clone(),
+                    departures: vec![
+                        Time::START_OF_DAY + Duration::minutes(5),
+                        Time::START_OF_DAY + Duration::minutes(15),
+                    ],
+                    headways: Vec::new(),
+                    time_to_stop: (0..route.stops.len()).map(Duration::minutes).collect(),
+                    runs: Vec::new(),
+                    timepoints: BTreeSet::new(),
+                },
+                &map,
+                &mut Timer::throwaway(),
+            )
+            .unwrap();
+        let (stop1, stop2) = (route.stops[1], route.stops[2]);
+        let start_bldg = *map
+            .get_l(map.get_bs(stop1).sidewalk_pos.lane())
+            .building_paths
+            .last()
+            .unwrap();
+        let goal_bldg = map
+            .get_l(map.get_bs(stop2).sidewalk_pos.lane())
+            .building_paths[0];
+        for _ in 0..3 {
+            sim.schedule_trip(
+                Time::START_OF_DAY,
+                TripSpec::UsingTransit {
+                    start: SidewalkSpot::building(start_bldg, &map),
+                    route: route.id,
+                    stop1,
+                    stop2,
+                    goal: SidewalkSpot::building(goal_bldg, &map),
+                    ped_speed: Scenario::rand_ped_speed(&mut rng),
+                },
+                &map,
+            );
+        }
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::hours(1)));
+
+        let mut boarded: BTreeMap<CarID, Vec<PedestrianID>> = BTreeMap::new();
+        let mut denied = Vec::new();
+        for (_, ev) in EventLog::read(log_path).unwrap() {
+            match ev {
+                Event::PedEntersBus(ped, bus, _) => {
+                    boarded.entry(bus).or_insert_with(Vec::new).push(ped);
+                }
+                Event::PedDeniedBoarding(ped, bus, stop) => {
+                    assert_eq!(bus, buses[0]);
+                    assert_eq!(stop, stop1);
+                    denied.push(ped);
+                }
+                _ => {}
+            }
+        }
+        std::fs::remove_file(log_path).unwrap();
+        assert_eq!(boarded[&buses[0]].len(), 2);
+        assert_eq!(denied.len(), 1);
+        assert_eq!(boarded[&buses[1]], denied);
+    });
+    t.run_slow("bus_follows_timetable", |h| {
+        let flags = SimFlags::for_test("bus_follows_timetable");
+        let (map, mut sim, _) = flags.load(&mut Timer::throwaway());
+        let route = map.get_bus_route("49").unwrap();
+        let buses = sim
+            .dispatch_bus_route(
+                route,
+                &BusTimetable {
+                    route_name: route.name.clone(),
+                    departures: vec![Time::START_OF_DAY + Duration::minutes(2)],
+                    headways: Vec::new(),
+                    time_to_stop: (0..route.stops.len()).map(Duration::minutes).collect(),
+                    runs: Vec::new(),
+                    timepoints: BTreeSet::new(),
+                },
+                &map,
+                &mut Timer::throwaway(),
+            )
+            .unwrap();
+        let bus = buses[0];
+        h.setup_done(&mut sim);
+
+        let last_stop = *route.stops.last().unwrap();
+        sim.run_until_expectations_met(
+            &map,
+            vec![
+                Event::BusArrivedAtStop(bus, route.id, route.stops[0]),
+                Event::BusDepartedFromStop(bus, route.id, route.stops[0]),
+                Event::BusArrivedAtStop(bus, route.id, last_stop),
+            ],
+            Duration::minutes(20),
+        );
+        // The bus disappears at the end of its run, instead of looping.
+        sim.just_run_until_done(&map, Some(Duration::minutes(1)));
+    });
+
+    t.run_slow("bus_holds_at_timepoint", |h| {
+        let log_path = "bus_holds_at_timepoint.jsonl";
+        let mut flags = SimFlags::for_test("bus_holds_at_timepoint");
+        flags.opts.event_log = Some(log_path.to_string());
+        let (map, mut sim, _) = flags.load(&mut Timer::throwaway());
+        let route = map.get_bus_route("49").unwrap();
+        // The bus has plenty of time to reach the second stop, so it's early there and has to
+        // wait.
+        let departure = Time::START_OF_DAY + Duration::minutes(2);
+        let held_until = departure + Duration::minutes(15);
+        let bus = sim
+            .dispatch_bus_route(
+                route,
+                &BusTimetable {
+                    route_name: route.name.clone(),
+                    departures: vec![departure],
+                    headways: Vec::new(),
+                    time_to_stop: (0..route.stops.len())
+                        .map(|idx| {
+                            if idx == 0 {
+                                Duration::ZERO
+                            } else {
+                                Duration::minutes(15) + Duration::minutes(2 * (idx - 1))
+                            }
+                        })
+                        .collect(),
+                    runs: Vec::new(),
+                    timepoints: vec![1].into_iter().collect(),
+                },
+                &map,
+                &mut Timer::throwaway(),
+            )
+            .unwrap()[0];
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::hours(1)));
+
+        let mut arrived = None;
+        let mut departed = None;
+        let (mut early, mut on_time, mut late) = (0, 0, 0);
+        for (time, ev) in EventLog::read(log_path).unwrap() {
+            match ev {
+                Event::BusArrivedAtStop(b, _, stop) if b == bus && stop == route.stops[1] => {
+                    arrived = Some(time);
+                }
+                Event::BusDepartedFromStop(b, _, stop) if b == bus && stop == route.stops[1] => {
+                    departed = Some(time);
+                }
+                Event::ScheduledBusArrival(b, _, _, scheduled) if b == bus => {
+                    if time < scheduled - Duration::minutes(1) {
+                        early += 1;
+                    } else if time > scheduled + Duration::minutes(5) {
+                        late += 1;
+                    } else {
+                        on_time += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        std::fs::remove_file(log_path).unwrap();
+        assert!(arrived.unwrap() < held_until - Duration::minutes(1));
+        let departed = departed.unwrap();
+        assert!(departed >= held_until && departed - held_until < Duration::seconds(1.0));
+
+        let otp = sim
+            .get_analytics()
+            .on_time_performance(sim.time(), Some(route.id));
+        assert!(otp.early >= 1);
+        assert_eq!((otp.early, otp.on_time, otp.late), (early, on_time, late));
+        assert_eq!(
+            otp.percent_on_time(),
+            Some(100.0 * (on_time as f64) / ((early + on_time + late) as f64))
+        );
+    });
+
+    t.run_slow("ped_walks_after_last_bus", |h| {
+        let flags = SimFlags::for_test("ped_walks_after_last_bus");
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+        let route = map.get_bus_route("49").unwrap();
+        sim.dispatch_bus_route(
+            route,
+            &BusTimetable {
+                route_name: route.name.clone(),
+                departures: vec![Time::START_OF_DAY + Duration::minutes(1)],
+                headways: Vec::new(),
+                time_to_stop: (0..route.stops.len()).map(Duration::minutes).collect(),
+                runs: Vec::new(),
+                timepoints: BTreeSet::new(),
+            },
+            &map,
+            &mut Timer::throwaway(),
+        )
+        .unwrap();
+        let ped_stop1 = route.stops[1];
+        let ped_stop2 = route.stops[2];
+        let start_bldg = *map
+            .get_l(map.get_bs(ped_stop1).sidewalk_pos.lane())
+            .building_paths
+            .last()
+            .unwrap();
+        let goal_bldg = map
+            .get_l(map.get_bs(ped_stop2).sidewalk_pos.lane())
+            .building_paths[0];
+        // Long after the only bus finished its run
+        let ped = sim
+            .schedule_trip(
+                Time::START_OF_DAY + Duration::minutes(30),
+                TripSpec::UsingTransit {
+                    start: SidewalkSpot::building(start_bldg, &map),
+                    route: route.id,
+                    stop1: ped_stop1,
+                    stop2: ped_stop2,
+                    goal: SidewalkSpot::building(goal_bldg, &map),
+                    ped_speed: Scenario::rand_ped_speed(&mut rng),
+                },
+                &map,
+            )
+            .0
+            .unwrap();
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+        h.setup_done(&mut sim);
+
+        // Nobody waits forever for a bus that isn't coming; the pedestrian walks instead.
+        sim.run_until_expectations_met(
+            &map,
+            vec![
+                Event::PedReachedBusStop(ped, ped_stop1, route.id),
+                Event::PedReachedBuilding(ped, goal_bldg),
+            ],
+            Duration::minutes(60),
+        );
+        sim.just_run_until_done(&map, Some(Duration::minutes(1)));
+    });
+}
+
+// One pattern through these stops, with one trip 2 minutes between each
+fn fake_route(name: &str, pts: Vec<LonLat>) -> gtfs::Route {
+    gtfs::Route {
+        name: name.to_string(),
+        route_type: "Bus".to_string(),
+        patterns: vec![gtfs::Pattern {
+            forwards: true,
+            stops: pts
+                .iter()
+                .enumerate()
+                .map(|(idx, pt)| gtfs::Stop {
+                    id: idx.to_string(),
+                    name: idx.to_string(),
+                    pos: *pt,
+                })
+                .collect(),
+            trips: vec![gtfs::Trip {
+                id: "trip".to_string(),
+                service_id: "WK".to_string(),
+                stop_times: (0..pts.len())
+                    .map(|idx| gtfs::StopTime {
+                        arrival: Duration::minutes(2 * idx),
+                        departure: Duration::minutes(2 * idx),
+                    })
+                    .collect(),
+            }],
+            shape: Some(pts),
+        }],
+        calendars: BTreeMap::new(),
+    }
+}
+
+// A tiny feed with two routes. Route 49 has a main pattern with a weekday trip, a trip running
+// past midnight, and a Saturday trip; a short-turn; and one trip going backwards. The streetcar
+// just has one trip.
+fn write_gtfs_fixture(dir: &str) {
+    std::fs::create_dir_all(dir).unwrap();
+    let files = vec![
+        (
+            "routes.txt",
+            "route_id,agency_id,route_short_name,route_long_name,route_desc,route_type,route_url,\
+             route_color,route_text_color\n\
+             R1,A,49,Forty-nine,,3,,,\n\
+             R2,A,S,Streetcar,,0,,,\n",
+        ),
+        (
+            "stops.txt",
+            "stop_id,stop_code,stop_name,stop_desc,stop_lat,stop_lon,zone_id,stop_url,\
+             location_type,parent_station,stop_timezone,wheelchair_boarding\n\
+             S1,,First,,47.640,-122.300,,,0,,,0\n\
+             S2,,Second,,47.641,-122.300,,,0,,,0\n\
+             S3,,Third,,47.642,-122.300,,,0,,,0\n",
+        ),
+        (
+            "calendar.txt",
+            "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,\
+             end_date\n\
+             WK,1,1,1,1,1,0,0,20180101,20181231\n\
+             SA,0,0,0,0,0,1,0,20180101,20181231\n",
+        ),
+        (
+            "shapes.txt",
+            "shape_id,shape_pt_lat,shape_pt_lon,shape_pt_sequence,shape_dist_traveled\n\
+             SH1,47.642,-122.300,3,\n\
+             SH1,47.640,-122.300,1,\n\
+             SH1,47.641,-122.300,2,\n",
+        ),
+        (
+            "trips.txt",
+            "route_id,service_id,trip_id,trip_headsign,trip_short_name,direction_id,block_id,\
+             shape_id,wheelchair_accessible,bikes_allowed\n\
+             R1,WK,T1,,,0,,SH1,0,0\n\
+             R1,WK,T2,,,0,,SH1,0,0\n\
+             R1,SA,T3,,,0,,SH1,0,0\n\
+             R1,WK,T4,,,0,,,0,0\n\
+             R1,WK,T5,,,1,,,0,0\n\
+             R2,WK,T6,,,0,,,0,0\n",
+        ),
+        (
+            "stop_times.txt",
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence,stop_headsign,pickup_type,\
+             drop_off_type,shape_dist_traveled,timepoint\n\
+             T1,08:00:00,08:00:00,S1,1,,0,0,,1\n\
+             T1,08:05:00,08:06:00,S2,2,,0,0,,1\n\
+             T1,08:10:00,08:10:00,S3,3,,0,0,,1\n\
+             T2,23:50:00,23:50:00,S1,1,,0,0,,1\n\
+             T2,24:00:00,24:00:00,S2,2,,0,0,,1\n\
+             T2,24:10:00,24:10:00,S3,3,,0,0,,1\n\
+             T3,10:00:00,10:00:00,S1,1,,0,0,,1\n\
+             T3,10:05:00,10:05:00,S2,2,,0,0,,1\n\
+             T3,10:10:00,10:10:00,S3,3,,0,0,,1\n\
+             T4,09:00:00,09:00:00,S1,1,,0,0,,1\n\
+             T4,09:05:00,09:05:00,S2,2,,0,0,,1\n\
+             T5,11:00:00,11:00:00,S3,1,,0,0,,1\n\
+             T5,11:05:00,11:05:00,S2,2,,0,0,,1\n\
+             T5,11:10:00,11:10:00,S1,3,,0,0,,1\n\
+             T6,12:00:00,12:00:00,S1,1,,0,0,,1\n\
+             T6,12:10:00,12:10:00,S3,2,,0,0,,1\n",
+        ),
+    ];
+    for (name, contents) in files {
+        std::fs::write(format!("{}/{}", dir, name), contents).unwrap();
+    }
 }
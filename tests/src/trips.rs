@@ -1,8 +1,13 @@
 use crate::runner::TestRunner;
 use abstutil::Timer;
-use geom::{Duration, Time};
-use map_model::{BuildingID, IntersectionID};
-use sim::{DrivingGoal, Event, Scenario, SidewalkSpot, SimFlags, TripSpec};
+use geom::{Distance, Duration, Pt2D, Time};
+use map_model::{Building, BuildingID, IntersectionID, LaneType, Map, PathConstraints};
+use popdat::psrc::{Mode, Purpose};
+use popdat::{Trip, TripEndpt};
+use sim::{
+    Activity, DrivingGoal, Event, EventLog, PersonSpec, Scenario, SidewalkSpot, SimFlags, TripMode,
+    TripSpec,
+};
 
 pub fn run(t: &mut TestRunner) {
     t.run_slow("bike_from_border", |h| {
@@ -37,4 +42,213 @@ pub fn run(t: &mut TestRunner) {
         );
         sim.just_run_until_done(&map, Some(Duration::minutes(1)));
     });
+
+    t.run_slow("person_reuses_car", |h| {
+        let log_path = "person_reuses_car.jsonl";
+        let mut flags = SimFlags::for_test("person_reuses_car");
+        flags.opts.event_log = Some(log_path.to_string());
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+
+        // The car starts parked on the street near home.
+        let (home, spot) = map
+            .all_buildings()
+            .iter()
+            .find_map(|b| {
+                let parking = map
+                    .find_closest_lane(
+                        map.find_driving_lane_near_building(b.id),
+                        vec![LaneType::Parking],
+                    )
+                    .ok()?;
+                Some((b.id, *sim.get_free_spots(parking).get(0)?))
+            })
+            .unwrap();
+        let car = sim.seed_parked_car(Scenario::rand_car(&mut rng), spot, Some(home));
+        let work = farthest_building(&map, home, |_| true);
+        sim.schedule_person(
+            &PersonSpec {
+                home,
+                depart: Time::START_OF_DAY,
+                activities: vec![
+                    Activity {
+                        bldg: work,
+                        mode: TripMode::Drive,
+                        duration: Duration::minutes(30),
+                    },
+                    Activity {
+                        bldg: home,
+                        mode: TripMode::Drive,
+                        duration: Duration::minutes(30),
+                    },
+                ],
+            },
+            Scenario::rand_ped_speed(&mut rng),
+            None,
+        );
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::hours(3)));
+
+        // Both trips drive the same car, and the trip home starts when the person is done at work.
+        let mut arrived_at_work = None;
+        let mut trips_started = Vec::new();
+        let mut car_trips = Vec::new();
+        for (time, ev) in EventLog::read(log_path).unwrap() {
+            match ev {
+                Event::PedReachedBuilding(_, b) if b == work && arrived_at_work.is_none() => {
+                    arrived_at_work = Some(time);
+                }
+                Event::TripPhaseStarting(trip, _, desc) => {
+                    if !trips_started.iter().any(|(id, _)| *id == trip) {
+                        trips_started.push((trip, time));
+                    }
+                    if desc == car.to_string() {
+                        car_trips.push(trip);
+                    }
+                }
+                _ => {}
+            }
+        }
+        std::fs::remove_file(log_path).unwrap();
+        assert_eq!(trips_started.len(), 2);
+        assert_eq!(car_trips, vec![trips_started[0].0, trips_started[1].0]);
+        let leave_work = trips_started[1].1;
+        let expected = arrived_at_work.unwrap() + Duration::minutes(30);
+        assert!(leave_work >= expected && leave_work <= expected + Duration::seconds(1.0));
+    });
+
+    t.run_slow("person_returns_to_bike", |h| {
+        let log_path = "person_returns_to_bike.jsonl";
+        let mut flags = SimFlags::for_test("person_returns_to_bike");
+        flags.opts.event_log = Some(log_path.to_string());
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+
+        // Bike to work, walk to lunch nearby, walk back to work, then bike home from where the
+        // bike was left.
+        let can_bike_from = |b: &Building| {
+            SidewalkSpot::bike_from_bike_rack(b.sidewalk(), &map).is_some()
+                && can_bike_to(&map, b.id)
+        };
+        let home = map
+            .all_buildings()
+            .iter()
+            .find(|b| can_bike_from(b))
+            .unwrap()
+            .id;
+        let work = farthest_building(&map, home, |b| can_bike_from(b));
+        let lunch = map
+            .all_buildings()
+            .iter()
+            .filter(|b| {
+                map.get_l(b.sidewalk()).parent != map.get_l(map.get_b(work).sidewalk()).parent
+            })
+            .min_by_key(|b| {
+                b.label_center
+                    .dist_to(map.get_b(work).label_center)
+                    .inner_meters() as usize
+            })
+            .unwrap()
+            .id;
+        let activity = |bldg, mode| Activity {
+            bldg,
+            mode,
+            duration: Duration::minutes(30),
+        };
+        sim.schedule_person(
+            &PersonSpec {
+                home,
+                depart: Time::START_OF_DAY,
+                activities: vec![
+                    activity(work, TripMode::Bike),
+                    activity(lunch, TripMode::Walk),
+                    activity(home, TripMode::Bike),
+                ],
+            },
+            Scenario::rand_ped_speed(&mut rng),
+            Some(Scenario::rand_bike(&mut rng)),
+        );
+        h.setup_done(&mut sim);
+        sim.just_run_until_done(&map, Some(Duration::hours(4)));
+
+        let mut bike_parked_at = Vec::new();
+        let mut bike_starts = Vec::new();
+        for (_, ev) in EventLog::read(log_path).unwrap() {
+            match ev {
+                Event::BikeStoppedAtSidewalk(_, sidewalk) => {
+                    bike_parked_at.push(map.get_l(sidewalk).parent);
+                }
+                Event::TripPhaseStarting(_, Some(req), _)
+                    if req.constraints == PathConstraints::Bike =>
+                {
+                    bike_starts.push(map.get_l(req.start.lane()).parent);
+                }
+                _ => {}
+            }
+        }
+        std::fs::remove_file(log_path).unwrap();
+        assert_eq!(bike_parked_at.len(), 2);
+        assert_eq!(bike_starts.len(), 2);
+        // The ride home starts from where the bike was left at work, not from lunch.
+        assert_eq!(bike_starts[1], bike_parked_at[0]);
+    });
+
+    t.run_fast("group_trips_by_person", |_| {
+        let trip = |person, from, to, depart_at| Trip {
+            from,
+            to,
+            depart_at: Time::START_OF_DAY + Duration::hours(depart_at),
+            purpose: (Purpose::Home, Purpose::Work),
+            mode: Mode::Drive,
+            person,
+            trip_time: Duration::minutes(10),
+            trip_dist: Distance::meters(1000.0),
+        };
+        let border = || TripEndpt::Border(IntersectionID(0), Pt2D::new(0.0, 0.0));
+
+        let (people, individ_trips) = popdat::group_by_person(&vec![
+            // A chain of activities
+            trip(
+                (0, 0),
+                TripEndpt::Building(BuildingID(1)),
+                TripEndpt::Building(BuildingID(2)),
+                8,
+            ),
+            trip(
+                (0, 0),
+                TripEndpt::Building(BuildingID(2)),
+                TripEndpt::Building(BuildingID(1)),
+                17,
+            ),
+            // The last trip leaves the map, so there's nowhere to do the last activity.
+            trip(
+                (0, 1),
+                TripEndpt::Building(BuildingID(1)),
+                TripEndpt::Building(BuildingID(3)),
+                8,
+            ),
+            trip((0, 1), TripEndpt::Building(BuildingID(3)), border(), 17),
+        ]);
+        assert_eq!(people.len(), 1);
+        assert_eq!(people[0].home, BuildingID(1));
+        assert_eq!(people[0].activities.len(), 2);
+        assert_eq!(individ_trips.len(), 2);
+        assert!(individ_trips.iter().all(|t| t.person == (0, 1)));
+    });
+}
+
+fn farthest_building<F: Fn(&Building) -> bool>(map: &Map, from: BuildingID, ok: F) -> BuildingID {
+    let pt = map.get_b(from).label_center;
+    map.all_buildings()
+        .iter()
+        .filter(|b| ok(b))
+        .max_by_key(|b| b.label_center.dist_to(pt).inner_meters() as usize)
+        .unwrap()
+        .id
+}
+
+// Bikes have to be able to stop near the building.
+fn can_bike_to(map: &Map, b: BuildingID) -> bool {
+    let lane = DrivingGoal::ParkNear(b)
+        .goal_pos(PathConstraints::Bike, map)
+        .lane();
+    map.get_parent(lane).bike_to_sidewalk(lane).is_some()
 }